@@ -0,0 +1,259 @@
+//! Turns assembly back into a ROM: the Cowgod mnemonics the disassembler
+//! prints, one instruction a line, with labels and data.
+//!
+//! ```text
+//! start:
+//!     LD V0, 0x05
+//!     LD I, sprite      ; labels work wherever an address does
+//!     DRW V0, V0, 5
+//!     JP start
+//! sprite:
+//!     DB 0xF0, 0x90, 0x90, 0x90, 0xF0
+//! ```
+//!
+//! A label ends in a colon and names the address of whatever follows it.
+//! `DB` lays down bytes and `DW` big-endian words, which may be labels.
+//! Numbers are decimal, or hex and binary with `0x` and `0b`. Mnemonics
+//! and registers can be any case, and `;` starts a comment. A line of a
+//! disassembly listing starts with its address and opcode, which are
+//! skipped, so `disasm` output assembles back to the same ROM.
+
+use std::collections::HashMap;
+
+use crate::disassembler::PROGRAM_OFFSET;
+use crate::instructions::Instruction;
+
+// Operands that are neither registers nor values
+const NAMES: [&str; 10] = ["I", "[I]", "DT", "ST", "K", "F", "B", "HF", "R", "LONG"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operand {
+    Register(u8),
+    Name(&'static str),
+    Value(u32),
+}
+
+struct Line<'a> {
+    number: usize,
+    mnemonic: String,
+    operands: Vec<&'a str>,
+}
+
+pub fn assemble(source: &str) -> Result<Vec<u8>, String> {
+    // The first pass sizes every line, to know where the labels are
+    let mut labels = HashMap::new();
+    let mut lines = Vec::new();
+    let mut address = PROGRAM_OFFSET as u32;
+    for (number, text) in source.lines().enumerate() {
+        let error = |message: String| format!("line {}: {}", number + 1, message);
+        let mut text = skip_listing_columns(text.split(';').next().unwrap().trim());
+        loop {
+            let (first, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+            let label = match first.strip_suffix(':') {
+                Some(label) => label,
+                None => break,
+            };
+            if !is_label(label) {
+                return Err(error(format!("`{}` isn't a valid label", label)));
+            }
+            if labels.insert(label, address).is_some() {
+                return Err(error(format!("`{}` is already defined", label)));
+            }
+            text = rest.trim_start();
+        }
+        if text.is_empty() {
+            continue;
+        }
+
+        let (mnemonic, operands) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+        let mnemonic = mnemonic.to_ascii_uppercase();
+        let separators: &[char] = if mnemonic == "SAVE" || mnemonic == "LOAD" { &[',', '-'] } else { &[','] };
+        let operands: Vec<&str> = match operands.trim() {
+            "" => Vec::new(),
+            operands => operands.split(separators).map(str::trim).collect(),
+        };
+        if operands.iter().any(|operand| operand.is_empty()) {
+            return Err(error(String::from("missing an operand")));
+        }
+        address += match mnemonic.as_str() {
+            "DB" => operands.len() as u32,
+            "DW" => 2 * operands.len() as u32,
+            _ => 2,
+        };
+        lines.push(Line { number, mnemonic, operands });
+    }
+
+    let mut rom = Vec::new();
+    for line in &lines {
+        let error = |message: String| format!("line {}: {}", line.number + 1, message);
+        let operands = line.operands.iter().map(|token| operand(token, &labels)).collect::<Result<Vec<_>, _>>()
+                                                                                .map_err(error)?;
+        match line.mnemonic.as_str() {
+            "DB" => for operand in &operands {
+                rom.push(value(operand, 8).map_err(error)? as u8);
+            },
+            "DW" => for operand in &operands {
+                rom.extend_from_slice(&value(operand, 16).map_err(error)?.to_be_bytes());
+            },
+            mnemonic => {
+                let instruction = instruction(mnemonic, &operands).map_err(error)?;
+                rom.extend_from_slice(&instruction.encode().to_be_bytes());
+            },
+        }
+    }
+    Ok(rom)
+}
+
+/// `0x200: 00E0  CLS` is `CLS`. Labels can't start with a digit, so an
+/// address followed by a colon is always a listing's.
+fn skip_listing_columns(text: &str) -> &str {
+    let mut tokens = text.split_whitespace();
+    let is_address = tokens.next().and_then(|token| token.strip_suffix(':')).and_then(number).is_some();
+    let is_opcode = tokens.next().is_some_and(|token| token.chars().all(|c| c.is_ascii_hexdigit()));
+    if !is_address || !is_opcode {
+        return text;
+    }
+    // Past the address, the spaces after it, the opcode and the spaces after that
+    let rest = text.split_once(':').unwrap().1.trim_start();
+    rest.trim_start_matches(|c: char| c.is_ascii_hexdigit()).trim_start()
+}
+
+fn is_label(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && operand(name, &HashMap::new()).is_err()
+}
+
+fn number(token: &str) -> Option<u32> {
+    let lower = token.to_ascii_lowercase();
+    if let Some(hex) = lower.strip_prefix("0x") {
+        u32::from_str_radix(hex, 16).ok()
+    } else if let Some(binary) = lower.strip_prefix("0b") {
+        u32::from_str_radix(binary, 2).ok()
+    } else {
+        lower.parse().ok()
+    }
+}
+
+fn operand(token: &str, labels: &HashMap<&str, u32>) -> Result<Operand, String> {
+    let upper = token.to_ascii_uppercase();
+    if let Some(register) = upper.strip_prefix('V').filter(|register| register.len() == 1) {
+        if let Ok(register) = u8::from_str_radix(register, 16) {
+            return Ok(Operand::Register(register));
+        }
+    }
+    if let Some(&name) = NAMES.iter().find(|&&name| name == upper) {
+        return Ok(Operand::Name(name));
+    }
+    number(token).or_else(|| labels.get(token).copied()).map(Operand::Value)
+                 .ok_or_else(|| format!("`{}` isn't a register, number or label", token))
+}
+
+fn value(operand: &Operand, bits: u32) -> Result<u16, String> {
+    match *operand {
+        Operand::Value(value) if value < 1 << bits => Ok(value as u16),
+        Operand::Value(value) => Err(format!("{:#X} doesn't fit in {} bits", value, bits)),
+        Operand::Register(register) => Err(format!("expected a value, not V{:X}", register)),
+        Operand::Name(name) => Err(format!("expected a value, not {}", name)),
+    }
+}
+
+fn instruction(mnemonic: &str, operands: &[Operand]) -> Result<Instruction, String> {
+    use Operand::{Name, Register};
+
+    let address = |operand| value(operand, 12);
+    let byte = |operand| value(operand, 8).map(|value| value as u8);
+    let nibble = |operand| value(operand, 4).map(|value| value as u8);
+    Ok(match (mnemonic, operands) {
+        ("CLS", []) => Instruction::ClearScreen,
+        ("RET", []) => Instruction::Return,
+        ("SYS", [target]) => Instruction::NOP(address(target)?),
+        ("JP", [Register(0), target]) => Instruction::JumpFromOffset(address(target)?),
+        ("JP", [target]) => Instruction::Jump(address(target)?),
+        ("CALL", [target]) => Instruction::Call(address(target)?),
+        ("SE", [Register(x), Register(y)]) => Instruction::SkipIfEQRegister(*x, *y),
+        ("SE", [Register(x), data]) => Instruction::SkipIfEQData(*x, byte(data)?),
+        ("SNE", [Register(x), Register(y)]) => Instruction::SkipIfNERegister(*x, *y),
+        ("SNE", [Register(x), data]) => Instruction::SkipIfNEData(*x, byte(data)?),
+        ("LD", [Register(x), Register(y)]) => Instruction::LoadRegister(*x, *y),
+        ("LD", [Register(x), Name("DT")]) => Instruction::SetRegisterFromDelay(*x),
+        ("LD", [Register(x), Name("K")]) => Instruction::AwaitPress(*x),
+        ("LD", [Register(x), Name("[I]")]) => Instruction::ReadRegisters(*x),
+        ("LD", [Register(x), Name("R")]) => Instruction::ReadFlags(*x),
+        ("LD", [Register(x), data]) => Instruction::LoadData(*x, byte(data)?),
+        ("LD", [Name("I"), Name("LONG")]) => Instruction::LongSetI,
+        ("LD", [Name("I"), target]) => Instruction::SetI(address(target)?),
+        ("LD", [Name("DT"), Register(x)]) => Instruction::SetDelayFromRegister(*x),
+        ("LD", [Name("ST"), Register(x)]) => Instruction::SetSoundFromRegister(*x),
+        ("LD", [Name("F"), Register(x)]) => Instruction::LoadSprite(*x),
+        ("LD", [Name("HF"), Register(x)]) => Instruction::LoadLargeSprite(*x),
+        ("LD", [Name("B"), Register(x)]) => Instruction::SetBCDRepresentation(*x),
+        ("LD", [Name("[I]"), Register(x)]) => Instruction::StoreRegisters(*x),
+        ("LD", [Name("R"), Register(x)]) => Instruction::StoreFlags(*x),
+        ("ADD", [Name("I"), Register(x)]) => Instruction::AddI(*x),
+        ("ADD", [Register(x), Register(y)]) => Instruction::Add(*x, *y),
+        ("ADD", [Register(x), data]) => Instruction::AddData(*x, byte(data)?),
+        ("OR", [Register(x), Register(y)]) => Instruction::Or(*x, *y),
+        ("AND", [Register(x), Register(y)]) => Instruction::And(*x, *y),
+        ("XOR", [Register(x), Register(y)]) => Instruction::Xor(*x, *y),
+        ("SUB", [Register(x), Register(y)]) => Instruction::Sub(*x, *y),
+        ("SUBN", [Register(x), Register(y)]) => Instruction::NegatedSub(*x, *y),
+        // Shifting a register by itself does the same under either shift quirk
+        ("SHR", [Register(x)]) => Instruction::ShiftRight(*x, *x),
+        ("SHR", [Register(x), Register(y)]) => Instruction::ShiftRight(*x, *y),
+        ("SHL", [Register(x)]) => Instruction::ShiftLeft(*x, *x),
+        ("SHL", [Register(x), Register(y)]) => Instruction::ShiftLeft(*x, *y),
+        ("RND", [Register(x), data]) => Instruction::Random(*x, byte(data)?),
+        ("DRW", [Register(x), Register(y), rows]) => Instruction::Draw(*x, *y, nibble(rows)?),
+        ("SKP", [Register(x)]) => Instruction::SkipIfPressed(*x),
+        ("SKNP", [Register(x)]) => Instruction::SkipIfNotPressed(*x),
+        ("SCD", [rows]) => Instruction::ScrollDown(nibble(rows)?),
+        ("SCU", [rows]) => Instruction::ScrollUp(nibble(rows)?),
+        ("SCR", []) => Instruction::ScrollRight,
+        ("SCL", []) => Instruction::ScrollLeft,
+        ("EXIT", []) => Instruction::Exit,
+        ("LOW", []) => Instruction::LowRes,
+        ("HIGH", []) => Instruction::HiRes,
+        ("SAVE", [Register(x), Register(y)]) => Instruction::SaveRange(*x, *y),
+        ("LOAD", [Register(x), Register(y)]) => Instruction::LoadRange(*x, *y),
+        ("PLANE", [planes]) => Instruction::SelectPlanes(nibble(planes)?),
+        _ => return Err(format!("no {} instruction takes {}", mnemonic, describe(operands))),
+    })
+}
+
+fn describe(operands: &[Operand]) -> String {
+    let operands: Vec<String> = operands.iter().map(|operand| match *operand {
+        Operand::Register(_) => String::from("a register"),
+        Operand::Name(name) => name.to_string(),
+        Operand::Value(_) => String::from("a value"),
+    }).collect();
+    if operands.is_empty() { String::from("no operands") } else { operands.join(", ") }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disassembler;
+    use crate::instructions;
+
+    #[test]
+    fn every_mnemonic_assembles_to_its_opcode() {
+        for opcode in 0..=u16::MAX {
+            let text = instructions::parse_opcode(opcode).to_string();
+            assert_eq!(assemble(&text), Ok(opcode.to_be_bytes().to_vec()), "{}", text);
+        }
+    }
+
+    #[test]
+    fn listings_assemble_to_the_rom() {
+        let source = "start: LD V0, 5\n  LD I, sprite ; a comment\nloop:\n DRW V0, v0, 5\n jp loop\n\
+                      sprite: db 0xF0, 0b10010000, 144\n dw start";
+        let rom = assemble(source).unwrap();
+        assert_eq!(rom, [0x60, 0x05, 0xA2, 0x08, 0xD0, 0x05, 0x12, 0x04, 0xF0, 0x90, 0x90, 0x02, 0x00]);
+        assert_eq!(assemble(&disassembler::listing(&rom, true).join("\n")), Ok(rom));
+
+        assert_eq!(assemble("JP nowhere"), Err(String::from("line 1: `nowhere` isn't a register, number or label")));
+        assert_eq!(assemble("a:\na: CLS"), Err(String::from("line 2: `a` is already defined")));
+        assert_eq!(assemble("LD V0, 0x100"), Err(String::from("line 1: 0x100 doesn't fit in 8 bits")));
+    }
+}
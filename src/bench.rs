@@ -0,0 +1,99 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::chip8::Chip8;
+use crate::error::Chip8Error;
+use crate::stats::Profile;
+
+/*
+    `yac8 bench`: run the core flat out, with no window and no waiting on the
+    clock, and report how fast it went and where the time went. The
+    machine runs in whole frames as it would headless, so timers and
+    display waits behave, but nothing paces it.
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Length {
+    Instructions(u64),
+    Seconds(f64),
+}
+
+impl Length {
+    // A number of instructions, or of seconds with an `s` after it
+    pub fn parse(value: &str) -> Result<Length, String> {
+        let parsed = match value.strip_suffix('s') {
+            Some(seconds) => seconds.parse().ok().filter(|&seconds: &f64| seconds > 0.0).map(Length::Seconds),
+            None => value.parse().ok().filter(|&instructions| instructions > 0).map(Length::Instructions),
+        };
+        parsed.ok_or_else(|| format!("expected a number of instructions or of seconds like `5s`, got `{}`", value))
+    }
+}
+
+pub struct Report {
+    instructions: u64,
+    frames: u64,
+    elapsed: Duration,
+    profile: Profile,
+    // Why the run ended before its length, if it did
+    stopped: Option<&'static str>,
+}
+
+// Frames run between checks of the clock
+const FRAMES_PER_CHECK: u64 = 10;
+
+pub fn run(machine: &mut Chip8, length: Length) -> Result<Report, Chip8Error> {
+    machine.enable_profile();
+    if let Length::Instructions(instructions) = length {
+        machine.set_limits(Some(machine.instructions_executed() + instructions), None);
+    }
+    let (instructions, frames) = (machine.instructions_executed(), machine.frames_elapsed());
+
+    let started = Instant::now();
+    let stopped = loop {
+        machine.run_frames(FRAMES_PER_CHECK)?;
+        if machine.limit_reached() {
+            break None;
+        }
+        if let Length::Seconds(seconds) = length {
+            if started.elapsed().as_secs_f64() >= seconds {
+                break None;
+            }
+        }
+        // Nothing will press it
+        if machine.waiting_on_key() {
+            break Some("the program is waiting for a key");
+        }
+        if machine.paused() {
+            break Some("a watch paused the machine");
+        }
+    };
+
+    Ok(Report {
+        instructions: machine.instructions_executed() - instructions,
+        frames: machine.frames_elapsed() - frames,
+        elapsed: started.elapsed(),
+        profile: *machine.profile().unwrap(),
+        stopped,
+    })
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let seconds = self.elapsed.as_secs_f64();
+        writeln!(f, "=> Benchmark: {} instructions and {} frames in {:.3}s.", self.instructions, self.frames, seconds)?;
+        if let Some(reason) = self.stopped {
+            writeln!(f, "=> Stopped early: {}.", reason)?;
+        }
+        writeln!(f, "  instructions/s  {:.0}", self.instructions as f64 / seconds)?;
+        writeln!(f, "  frames/s        {:.0}", self.frames as f64 / seconds)?;
+
+        // Whatever isn't fetch, decode or execute is the scheduler, timers, history and the like
+        let profiled = self.profile.fetch + self.profile.decode + self.profile.execute;
+        let phases = [("fetch", self.profile.fetch), ("decode", self.profile.decode), ("execute", self.profile.execute),
+                      ("other", self.elapsed.saturating_sub(profiled))];
+        for (name, time) in phases.iter() {
+            writeln!(f, "  {:<8} {:>5.1}%  {:>7.1} ns/instruction", name, 100.0 * time.as_secs_f64() / seconds,
+                     time.as_nanos() as f64 / self.instructions.max(1) as f64)?;
+        }
+        Ok(())
+    }
+}
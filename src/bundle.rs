@@ -0,0 +1,212 @@
+use crate::quirks::Quirks;
+use crate::variant::Variant;
+
+/*
+    CHIP-8 binary bundles (`.c8b`), which pack a ROM together with metadata
+    and the platform and colors it wants, so it runs right without any
+    flags. All numbers are big-endian and offsets count from the start of
+    the file:
+
+    "CBF"    magic
+    u8       format version, 0
+    u16      offset of the properties table
+    ...      bytecode table: (u8 platform, u16 offset, u16 length) entries,
+             ending at a 0 platform
+    ...      properties table: (u8 key, u16 offset) entries, ending at a 0 key
+
+    A bundle can hold builds for several platforms. The first one yac8 can
+    run is loaded and selects the matching variant and quirks. Text
+    properties are NUL-terminated and colors are a count followed by that
+    many RGB triples: background, foreground, then XO-CHIP's extra plane
+    colors. Properties yac8 has no use for are skipped.
+*/
+pub struct Bundle {
+    pub rom: Vec<u8>,
+    pub variant: Option<Variant>,
+    pub quirks: Option<Quirks>,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub author: Option<String>,
+    pub colors: Vec<[u8; 3]>,
+}
+
+const MAGIC: &[u8] = b"CBF";
+const VERSION: u8 = 0;
+
+const PROPERTY_NAME: u8 = 0x01;
+const PROPERTY_DESCRIPTION: u8 = 0x02;
+const PROPERTY_AUTHOR: u8 = 0x03;
+const PROPERTY_COLORS: u8 = 0x05;
+
+impl Bundle {
+    pub fn is_bundle(data: &[u8]) -> bool {
+        data.starts_with(MAGIC)
+    }
+
+    /*
+        A plain ROM file, with nothing to apply.
+    */
+    pub fn plain(rom: Vec<u8>) -> Bundle {
+        Bundle { rom, variant: None, quirks: None, name: None, description: None, author: None, colors: Vec::new() }
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Bundle, String> {
+        if !Bundle::is_bundle(data) {
+            return Err(String::from("not a CHIP-8 bundle"));
+        }
+        let version = byte(data, 3)?;
+        if version != VERSION {
+            return Err(format!("unsupported bundle version {}", version));
+        }
+
+        let properties_offset = word(data, 4)? as usize;
+        let mut program = None;
+        let mut position = 6;
+        loop {
+            let platform = byte(data, position)?;
+            if platform == 0 {
+                break;
+            }
+            let offset = word(data, position + 1)? as usize;
+            let length = word(data, position + 3)? as usize;
+            position += 5;
+
+            if program.is_none() {
+                if let Some((variant, quirks)) = platform_settings(platform) {
+                    let rom = data.get(offset..offset + length).ok_or("bytecode runs past the end of the bundle")?;
+                    program = Some((rom.to_vec(), variant, quirks));
+                }
+            }
+        }
+        let (rom, variant, quirks) = program.ok_or("no bytecode for a platform yac8 supports")?;
+
+        let mut bundle = Bundle::plain(rom);
+        bundle.variant = Some(variant);
+        bundle.quirks = Some(quirks);
+        let mut position = properties_offset;
+        loop {
+            let key = byte(data, position)?;
+            if key == 0 {
+                break;
+            }
+            let offset = word(data, position + 1)? as usize;
+            position += 3;
+
+            match key {
+                PROPERTY_NAME => bundle.name = Some(text(data, offset)?),
+                PROPERTY_DESCRIPTION => bundle.description = Some(text(data, offset)?),
+                PROPERTY_AUTHOR => bundle.author = Some(text(data, offset)?),
+                PROPERTY_COLORS => {
+                    let count = byte(data, offset)? as usize;
+                    let colors = data.get(offset + 1..offset + 1 + count * 3).ok_or("colors run past the end of the bundle")?;
+                    bundle.colors = colors.chunks(3).map(|rgb| [rgb[0], rgb[1], rgb[2]]).collect();
+                },
+                _ => {},
+            }
+        }
+        Ok(bundle)
+    }
+}
+
+/*
+    The variant and quirks for a bytecode table platform, or None if yac8
+    can't run it.
+*/
+fn platform_settings(platform: u8) -> Option<(Variant, Quirks)> {
+    match platform {
+        0x01 => Some((Variant::Chip8, Variant::Chip8.quirks())),
+        0x02 => Some((Variant::Chip8, Quirks::default())),
+        0x03 => Some((Variant::SuperChip, Variant::SuperChip.quirks())),
+        0x04 => Some((Variant::XoChip, Variant::XoChip.quirks())),
+        _ => None,
+    }
+}
+
+fn byte(data: &[u8], offset: usize) -> Result<u8, String> {
+    data.get(offset).copied().ok_or_else(|| format!("truncated bundle, nothing at {:#06X}", offset))
+}
+
+fn word(data: &[u8], offset: usize) -> Result<u16, String> {
+    Ok(u16::from_be_bytes([byte(data, offset)?, byte(data, offset + 1)?]))
+}
+
+fn text(data: &[u8], offset: usize) -> Result<String, String> {
+    let bytes = data.get(offset..).ok_or("text runs past the end of the bundle")?;
+    let end = bytes.iter().position(|&byte| byte == 0).ok_or("unterminated text in the bundle")?;
+    Ok(String::from_utf8_lossy(&bytes[..end]).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A bundle with an unknown platform's build, then a SUPER-CHIP one, a name, colors and an unknown property
+    fn bundle() -> Vec<u8> {
+        let mut data = b"CBF\x00\x00\x11".to_vec();
+        data.extend([0x7F, 0x00, 0x1B, 0x00, 0x02]);
+        data.extend([0x03, 0x00, 0x1D, 0x00, 0x04]);
+        data.push(0x00);
+        // The properties table, at 0x11
+        data.extend([PROPERTY_NAME, 0x00, 0x21, PROPERTY_COLORS, 0x00, 0x26, 0x7E, 0x00, 0x00]);
+        data.push(0x00);
+        // At 0x1B, the two builds
+        data.extend([0xFF, 0xFF, 0x00, 0xFF, 0x12, 0x02]);
+        // At 0x21, the name and colors
+        data.extend(b"Pong\x00");
+        data.extend([0x02, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0xFF]);
+        data
+    }
+
+    #[test]
+    fn the_first_supported_build_is_loaded_with_its_properties() {
+        let bundle = Bundle::parse(&bundle()).unwrap();
+        assert_eq!(bundle.rom, vec![0x00, 0xFF, 0x12, 0x02]);
+        assert_eq!(bundle.variant, Some(Variant::SuperChip));
+        assert_eq!(bundle.quirks, Some(Variant::SuperChip.quirks()));
+        assert_eq!(bundle.name.as_deref(), Some("Pong"));
+        assert_eq!(bundle.author, None);
+        assert_eq!(bundle.colors, vec![[0x00, 0x00, 0x00], [0xFF, 0xFF, 0xFF]]);
+    }
+
+    #[test]
+    fn platforms_select_their_variant() {
+        assert_eq!(platform_settings(0x01).map(|(variant, _)| variant), Some(Variant::Chip8));
+        assert_eq!(platform_settings(0x02).map(|(variant, _)| variant), Some(Variant::Chip8));
+        assert_eq!(platform_settings(0x03).map(|(variant, _)| variant), Some(Variant::SuperChip));
+        assert_eq!(platform_settings(0x04).map(|(variant, _)| variant), Some(Variant::XoChip));
+        assert!(platform_settings(0x7F).is_none());
+    }
+
+    #[test]
+    fn truncated_bundles_are_errors() {
+        let data = bundle();
+        for length in 0..data.len() - 1 {
+            assert!(Bundle::parse(&data[..length]).is_err(), "{} bytes parsed", length);
+        }
+    }
+
+    #[test]
+    fn invalid_bundles_are_errors() {
+        let mut data = bundle();
+        data[3] = 1;
+        assert_eq!(Bundle::parse(&data).err().unwrap(), "unsupported bundle version 1");
+
+        // The SUPER-CHIP build runs off the end
+        let mut data = bundle();
+        data[14] = 0xFF;
+        assert!(Bundle::parse(&data).is_err());
+
+        // Only the unknown platform is left
+        let mut data = bundle();
+        data[11] = 0x7E;
+        assert_eq!(Bundle::parse(&data).err().unwrap(), "no bytecode for a platform yac8 supports");
+
+        // The name loses its terminator
+        let mut data = bundle();
+        data.truncate(0x26);
+        data[0x26 - 1] = b'!';
+        assert!(Bundle::parse(&data).is_err());
+
+        assert!(Bundle::parse(b"ROM!").is_err());
+    }
+}
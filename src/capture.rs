@@ -0,0 +1,352 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::checksum;
+
+/*
+    Dependency-free captures of the display buffer: PNG and plain PBM
+    images, which any image tool can open, ASCII art for terminals and bug
+    reports, and animated GIFs of whole runs.
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CaptureFormat {
+    Png,
+    Pbm,
+    Ascii,
+}
+
+impl CaptureFormat {
+    /*
+        `.png` and `.pbm` files get images; anything else gets ASCII art.
+    */
+    pub fn from_path(path: &Path) -> CaptureFormat {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some(extension) if extension.eq_ignore_ascii_case("png") => CaptureFormat::Png,
+            Some(extension) if extension.eq_ignore_ascii_case("pbm") => CaptureFormat::Pbm,
+            _ => CaptureFormat::Ascii,
+        }
+    }
+}
+
+pub fn encode(format: CaptureFormat, buffer: &[u8], width: usize, height: usize) -> Vec<u8> {
+    match format {
+        CaptureFormat::Png => to_png(buffer, width, height),
+        CaptureFormat::Pbm => to_pbm(buffer, width, height).into_bytes(),
+        CaptureFormat::Ascii => to_ascii(buffer.chunks(width)).into_bytes(),
+    }
+}
+
+/*
+    A plain (P1) PBM image, where 1 is a lit pixel.
+*/
+pub fn to_pbm(buffer: &[u8], width: usize, height: usize) -> String {
+    let mut pbm = format!("P1\n{} {}\n", width, height);
+    for row in buffer.chunks(width) {
+        let pixels: Vec<&str> = row.iter().map(|&pixel| if pixel != 0 { "1" } else { "0" }).collect();
+        pbm.push_str(&pixels.join(" "));
+        pbm.push('\n');
+    }
+    pbm
+}
+
+pub fn to_ascii<'a>(rows: impl Iterator<Item = &'a [u8]>) -> String {
+    let mut ascii = String::new();
+    for row in rows {
+        ascii.extend(row.iter().map(|&pixel| if pixel != 0 { '#' } else { '.' }));
+        ascii.push('\n');
+    }
+    ascii
+}
+
+/*
+    The buffer blown up `scale` times in each direction, for captures big
+    enough to look at.
+*/
+pub fn scale(buffer: &[u8], width: usize, scale: usize) -> Vec<u8> {
+    let mut scaled = Vec::with_capacity(buffer.len() * scale * scale);
+    for row in buffer.chunks(width) {
+        let scaled_row: Vec<u8> = row.iter().flat_map(|&pixel| std::iter::repeat_n(pixel, scale)).collect();
+        for _ in 0..scale {
+            scaled.extend_from_slice(&scaled_row);
+        }
+    }
+    scaled
+}
+
+/*
+    An 8-bit grayscale PNG where lit pixels are white. The image data is
+    zlib-wrapped but left uncompressed (stored deflate blocks), which keeps
+    the encoder tiny; a 64x32 display is only a couple of kilobytes anyway.
+*/
+pub fn to_png(buffer: &[u8], width: usize, height: usize) -> Vec<u8> {
+    let mut scanlines = Vec::with_capacity((width + 1) * height);
+    for row in buffer.chunks(width) {
+        scanlines.push(0);  // no filter
+        scanlines.extend(row.iter().map(|&pixel| if pixel != 0 { 0xFF } else { 0x00 }));
+    }
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    header.extend_from_slice(&[8, 0, 0, 0, 0]);  // 8-bit depth, grayscale, deflate, no filter, no interlace
+
+    let mut png = vec![0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+    write_png_chunk(&mut png, b"IHDR", &header);
+    write_png_chunk(&mut png, b"IDAT", &zlib_stored(&scanlines));
+    write_png_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn write_png_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = checksum::crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    const MAX_BLOCK: usize = 0xFFFF;
+
+    let mut zlib = vec![0x78, 0x01];
+    let mut blocks = data.chunks(MAX_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        zlib.extend_from_slice(&[0x01, 0x00, 0x00, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        let is_final = blocks.peek().is_none();
+        let length = block.len() as u16;
+        zlib.push(is_final as u8);
+        zlib.extend_from_slice(&length.to_le_bytes());
+        zlib.extend_from_slice(&(!length).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+    zlib.extend_from_slice(&adler32(data).to_be_bytes());
+    zlib
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+/*
+    An animated GIF built up a frame at a time as the machine runs. GIF
+    delays are in hundredths of a second, which 60hz frames don't divide
+    into, so each frame's delay is rounded against the total so far and
+    the animation keeps time. Runs of identical frames become one longer
+    frame. The image is the size of the first frame times `scale`, and
+    frames from the other resolution are resampled to it.
+*/
+pub struct GifRecorder {
+    width: usize,
+    height: usize,
+    scale: usize,
+    gif: Vec<u8>,
+    // The frame waiting for its delay to be known, and how many 60hz frames it has lasted
+    pending: Option<(Vec<u8>, u64)>,
+    frames_written: u64,
+}
+
+// Black, then white for the first plane, light grey for the second and dark grey for both
+const GIF_PALETTE: [[u8; 3]; 4] = [[0x00, 0x00, 0x00], [0xFF, 0xFF, 0xFF], [0xAA, 0xAA, 0xAA], [0x55, 0x55, 0x55]];
+
+impl GifRecorder {
+    const FRAMES_PER_SECOND: u64 = 60;
+
+    pub fn new(width: usize, height: usize, scale: usize) -> GifRecorder {
+        let mut gif = b"GIF89a".to_vec();
+        gif.extend_from_slice(&((width * scale) as u16).to_le_bytes());
+        gif.extend_from_slice(&((height * scale) as u16).to_le_bytes());
+        gif.extend_from_slice(&[0x81, 0, 0]);  // a global color table of 4 entries
+        for color in GIF_PALETTE.iter() {
+            gif.extend_from_slice(color);
+        }
+        // Loop forever
+        gif.extend_from_slice(&[0x21, 0xFF, 0x0B]);
+        gif.extend_from_slice(b"NETSCAPE2.0");
+        gif.extend_from_slice(&[0x03, 0x01, 0x00, 0x00, 0x00]);
+        GifRecorder { width, height, scale, gif, pending: None, frames_written: 0 }
+    }
+
+    // The display at the end of a frame
+    pub fn add_frame(&mut self, buffer: &[u8], width: usize, height: usize) {
+        let frame: Vec<u8> = (0..self.height).flat_map(|y| (0..self.width).map(move |x| (x, y)))
+                                             .map(|(x, y)| buffer[y * height / self.height * width + x * width / self.width])
+                                             .collect();
+        match self.pending.as_mut() {
+            Some((pending, frames)) if *pending == frame => *frames += 1,
+            _ => {
+                self.write_pending();
+                self.pending = Some((frame, 1));
+            },
+        }
+    }
+
+    pub fn finish(mut self) -> Vec<u8> {
+        self.write_pending();
+        self.gif.push(0x3B);
+        self.gif
+    }
+
+    fn write_pending(&mut self) {
+        let (frame, frames) = match self.pending.take() {
+            Some(pending) => pending,
+            None => return,
+        };
+        let centiseconds = |frames: u64| (frames * 100 + GifRecorder::FRAMES_PER_SECOND / 2) / GifRecorder::FRAMES_PER_SECOND;
+        let delay = centiseconds(self.frames_written + frames) - centiseconds(self.frames_written);
+        self.frames_written += frames;
+
+        self.gif.extend_from_slice(&[0x21, 0xF9, 0x04, 0x00]);
+        self.gif.extend_from_slice(&(delay.min(u16::MAX as u64) as u16).to_le_bytes());
+        self.gif.extend_from_slice(&[0x00, 0x00]);
+
+        self.gif.push(0x2C);
+        self.gif.extend_from_slice(&[0, 0, 0, 0]);
+        self.gif.extend_from_slice(&((self.width * self.scale) as u16).to_le_bytes());
+        self.gif.extend_from_slice(&((self.height * self.scale) as u16).to_le_bytes());
+        self.gif.push(0x00);
+
+        let pixels = scale(&frame, self.width, self.scale);
+        self.gif.push(GIF_MIN_CODE_SIZE);
+        for block in lzw(&pixels).chunks(255) {
+            self.gif.push(block.len() as u8);
+            self.gif.extend_from_slice(block);
+        }
+        self.gif.push(0x00);
+    }
+}
+
+// Four colors need two bits
+const GIF_MIN_CODE_SIZE: u8 = 2;
+const MAX_CODE_SIZE: u32 = 12;
+
+/*
+    GIF's variable-width LZW, codes packed low bit first. The table starts
+    over with a clear code whenever it fills up.
+*/
+fn lzw(pixels: &[u8]) -> Vec<u8> {
+    let clear = 1u16 << GIF_MIN_CODE_SIZE;
+    let end = clear + 1;
+    let mut out = Vec::new();
+    let (mut bits, mut bit_count) = (0u32, 0u32);
+    let mut emit = |code: u16, size: u32, out: &mut Vec<u8>| {
+        bits |= (code as u32) << bit_count;
+        bit_count += size;
+        while bit_count >= 8 {
+            out.push(bits as u8);
+            bits >>= 8;
+            bit_count -= 8;
+        }
+    };
+
+    let mut table: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut size = GIF_MIN_CODE_SIZE as u32 + 1;
+    let mut next = end + 1;
+    emit(clear, size, &mut out);
+
+    let mut pixels = pixels.iter();
+    let mut prefix = match pixels.next() {
+        Some(&pixel) => pixel as u16,
+        None => {
+            emit(end, size, &mut out);
+            return flush(out, bits, bit_count);
+        },
+    };
+    for &pixel in pixels {
+        if let Some(&code) = table.get(&(prefix, pixel)) {
+            prefix = code;
+            continue;
+        }
+        emit(prefix, size, &mut out);
+        table.insert((prefix, pixel), next);
+        next += 1;
+        if next as u32 > 1 << size && size < MAX_CODE_SIZE {
+            size += 1;
+        }
+        if next as u32 == 1 << MAX_CODE_SIZE {
+            emit(clear, size, &mut out);
+            table.clear();
+            size = GIF_MIN_CODE_SIZE as u32 + 1;
+            next = end + 1;
+        }
+        prefix = pixel as u16;
+    }
+    emit(prefix, size, &mut out);
+    emit(end, size, &mut out);
+    flush(out, bits, bit_count)
+}
+
+fn flush(mut out: Vec<u8>, bits: u32, bit_count: u32) -> Vec<u8> {
+    if bit_count > 0 {
+        out.push(bits as u8);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A plain GIF LZW decoder, to check the encoder against
+    fn unlzw(data: &[u8]) -> Vec<u8> {
+        let clear = 1u16 << GIF_MIN_CODE_SIZE;
+        let roots: Vec<Vec<u8>> = (0..clear).map(|pixel| vec![pixel as u8]).collect();
+        let (mut table, mut size, mut previous): (Vec<Vec<u8>>, u32, Option<u16>) = (Vec::new(), 0, None);
+        let (mut out, mut bit) = (Vec::new(), 0);
+        loop {
+            let read_size = if table.is_empty() { GIF_MIN_CODE_SIZE as u32 + 1 } else { size };
+            let code = (0..read_size).map(|offset| ((data[(bit + offset as usize) / 8] >> ((bit + offset as usize) % 8)) & 1) as u16)
+                                     .enumerate().fold(0, |code, (offset, value)| code | value << offset);
+            bit += read_size as usize;
+            if code == clear {
+                table = roots.clone();
+                table.extend([Vec::new(), Vec::new()]);
+                size = GIF_MIN_CODE_SIZE as u32 + 1;
+                previous = None;
+                continue;
+            }
+            if code == clear + 1 {
+                return out;
+            }
+            let entry = match (table.get(code as usize), previous) {
+                (Some(entry), _) => entry.clone(),
+                (None, Some(previous)) => {
+                    let mut entry = table[previous as usize].clone();
+                    entry.push(entry[0]);
+                    entry
+                },
+                (None, None) => panic!("bad code {}", code),
+            };
+            if let Some(previous) = previous {
+                if table.len() < 1 << MAX_CODE_SIZE {
+                    let mut added = table[previous as usize].clone();
+                    added.push(entry[0]);
+                    table.push(added);
+                    if table.len() == 1 << size && size < MAX_CODE_SIZE {
+                        size += 1;
+                    }
+                }
+            }
+            out.extend_from_slice(&entry);
+            previous = Some(code);
+        }
+    }
+
+    #[test]
+    fn lzw_round_trips_through_a_full_table() {
+        let mut seed = 0xC8u32;
+        let pixels: Vec<u8> = (0..40_000).map(|_| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            (seed >> 16) as u8 & 0x3
+        }).collect();
+        assert_eq!(unlzw(&lzw(&pixels)), pixels);
+        assert_eq!(unlzw(&lzw(&[1; 3000])), vec![1; 3000]);
+    }
+}
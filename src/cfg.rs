@@ -0,0 +1,98 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use crate::disassembler::{self, PROGRAM_OFFSET};
+use crate::instructions::{self, Instruction};
+
+/*
+    The control-flow graph of the code reachable from the entry point, as
+    Graphviz DOT: one box per basic block listing its instructions, with
+    edges for jumps, calls, skips and falling through. Render it with
+    `dot -Tsvg rom.dot -o rom.svg`.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Edge {
+    FallThrough,
+    Jump,
+    Call,
+    Skip,
+}
+
+impl Edge {
+    fn attributes(&self) -> &'static str {
+        match self {
+            Edge::FallThrough => "",
+            Edge::Jump => " [color=blue]",
+            Edge::Call => " [style=dashed, label=\"call\"]",
+            Edge::Skip => " [color=darkgreen, label=\"skip\"]",
+        }
+    }
+}
+
+struct Block {
+    instructions: Vec<(u16, u16)>,
+    edges: BTreeSet<(u16, Edge)>,
+}
+
+fn edges(rom: &[u8], address: u16, opcode: u16) -> Vec<(u16, Edge)> {
+    let successors = disassembler::successors(rom, address, opcode);
+    match instructions::parse_opcode(opcode) {
+        Instruction::Jump(target) => vec![(target, Edge::Jump)],
+        Instruction::Call(target) => vec![(target, Edge::Call), (successors[1], Edge::FallThrough)],
+        _ if successors.len() == 2 => vec![(successors[0], Edge::FallThrough), (successors[1], Edge::Skip)],
+        _ => successors.into_iter().map(|next| (next, Edge::FallThrough)).collect(),
+    }
+}
+
+fn blocks(rom: &[u8]) -> BTreeMap<u16, Block> {
+    let reachable = disassembler::reachable(rom);
+    let opcode = |address: u16| disassembler::opcode_at(rom, address).expect("Reachable addresses are inside the ROM.");
+
+    // A block starts at the entry point, at every branch target and after every branch
+    let mut leaders = BTreeSet::new();
+    leaders.insert(PROGRAM_OFFSET);
+    for &address in &reachable {
+        let edges = edges(rom, address, opcode(address));
+        if edges.len() != 1 || edges[0].1 != Edge::FallThrough {
+            leaders.extend(edges.iter().map(|&(target, _)| target));
+        }
+    }
+
+    let mut blocks = BTreeMap::new();
+    for &leader in leaders.iter().filter(|leader| reachable.contains(leader)) {
+        let mut block = Block { instructions: Vec::new(), edges: BTreeSet::new() };
+        let mut address = leader;
+        loop {
+            block.instructions.push((address, opcode(address)));
+            let edges = edges(rom, address, opcode(address));
+            let falls_into_next = edges.len() == 1 && edges[0].1 == Edge::FallThrough
+                                  && !leaders.contains(&edges[0].0) && reachable.contains(&edges[0].0);
+            if !falls_into_next {
+                block.edges.extend(edges.into_iter().filter(|(target, _)| reachable.contains(target)));
+                break;
+            }
+            address = edges[0].0;
+        }
+        blocks.insert(leader, block);
+    }
+    blocks
+}
+
+pub fn to_dot(rom: &[u8], name: &str) -> String {
+    let mut dot = String::new();
+    writeln!(dot, "digraph \"{}\" {{", name.replace('"', "'")).unwrap();
+    writeln!(dot, "  node [shape=box, fontname=monospace];").unwrap();
+    for (leader, block) in blocks(rom) {
+        let lines: Vec<String> = block.instructions.iter()
+                                      .map(|&(address, opcode)| {
+                                          format!("{:#05X}: {}\\l", address, instructions::parse_opcode(opcode))
+                                      })
+                                      .collect();
+        writeln!(dot, "  b{:03X} [label=\"{}\"];", leader, lines.concat()).unwrap();
+        for (target, edge) in block.edges {
+            writeln!(dot, "  b{:03X} -> b{:03X}{};", leader, target, edge.attributes()).unwrap();
+        }
+    }
+    writeln!(dot, "}}").unwrap();
+    dot
+}
@@ -1,30 +1,51 @@
-use std::time::Duration;
-use rand::{thread_rng, Rng};
+use std::collections::BTreeSet;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 
 use log::{info, warn};
 
+use crate::checksum;
+use crate::coverage::Coverage;
 use crate::instructions;
 use crate::instructions::Instruction;
+use crate::lint::Linter;
+use crate::machine_state::MachineState;
 use crate::display::Display;
+use crate::dynarec::Dynarec;
+use crate::error::Chip8Error;
+use crate::font::Font;
+use crate::history::History;
+use crate::hooks::{CoreEvent, Hook};
+use crate::input::InputSource;
 use crate::main_memory::MainMemory;
+use crate::quirks::{BigSprite, Quirks};
 use crate::registers::Registers;
+use crate::scan::{self, Extension};
+use crate::scheduler::{Event, Scheduler};
+use crate::snapshot::{Journal, Snapshot};
 use crate::stack::Stack;
+use crate::stats::{Profile, Stats};
+use crate::trace::Tracer;
+use crate::variant::Variant;
+use crate::watch::Watch;
 
-/*
-    The VM proper. This holds all of the VM structures and provides a cycle
-    function for progressing the CPU. It also provides a hook for updating
-    keystroke information.
-
-    There is currently no support for alternate key mappings. The original hex
-    pad layout is mapped tp the upper left region of the keyboard as follows:
-
-    keyboard     hexpad input
-    1 2 3 4   |   1 2 3 C
-    Q W E R   |   4 5 6 D
-    A S D F   |   7 8 9 E
-    Z X C V   |   A 0 B F
-
-*/
+/// The VM proper. This holds all of the VM structures and provides a cycle
+/// function for progressing the CPU.
+///
+/// Keys are the hex pad's 16, pressed with `set_key` or polled each frame
+/// from an `InputSource`, so which keyboard keys press them is up to the
+/// frontend. The `yac8` binary's default layout, which `--keymap` changes,
+/// maps the hex pad to the upper left region of the keyboard:
+///
+/// ```text
+/// keyboard     hexpad input
+/// 1 2 3 4   |   1 2 3 C
+/// Q W E R   |   4 5 6 D
+/// A S D F   |   7 8 9 E
+/// Z X C V   |   A 0 B F
+/// ```
 pub struct Chip8 {
     // Access required for drawing to the screen
     pub display: Display,
@@ -32,327 +53,1663 @@ pub struct Chip8 {
     registers: Registers,
     stack: Stack,
     main_memory: MainMemory,
+    // The program as loaded, for `reset`
+    rom: Vec<u8>,
+    // SUPER-CHIP's FX75/FX85 storage, the HP-48's RPL user flags
+    rpl_flags: [u8; Chip8::NUM_RPL_FLAGS],
+    // The XO-CHIP bit-planes that drawing, clearing and scrolling act on
+    planes: u8,
     waiting_on_key: i8,
+    presses_while_waiting: u16,
     key_pressed: [bool; Chip8::NUM_KEYS as usize],
-    micros_per_cycle: u32,
-    micros_since_cycle: u128,
-    micros_per_timer: u32,
-    micros_since_timer: u128,
+    edge_triggered_keys: bool,
+    key_pressed_last_frame: [bool; Chip8::NUM_KEYS as usize],
+    key_edges: [bool; Chip8::NUM_KEYS as usize],
+    scheduler: Scheduler,
+    variant: Variant,
+    quirks: Quirks,
+    waiting_on_vblank: bool,
+    cycles_executed: u64,
+    frames_elapsed: u64,
+    max_instructions: Option<u64>,
+    max_frames: Option<u64>,
+    pause_at: Option<u64>,
+    watches: Vec<Watch>,
+    watch_hit: Option<usize>,
+    breakpoints: BTreeSet<u16>,
+    breakpoint_hit: Option<u16>,
+    uninitialized_reads: Vec<(u16, u16)>,
+    rng: StdRng,
+    history: Option<History>,
+    journal: Option<Journal>,
+    // Where a hash of the machine goes at every frame boundary
+    state_hashes: Option<Box<dyn Write>>,
+    // The source keys are polled from, and the keys it held at the last poll
+    input: Option<(Box<dyn InputSource>, u16)>,
+    hooks: Vec<Hook>,
+    stats: Stats,
+    profile: Option<Profile>,
+    tracer: Option<Tracer>,
+    coverage: Option<Coverage>,
+    linter: Option<Linter>,
+    dynarec: Option<Dynarec>,
 }
 
 impl Chip8 {
-    const NUM_KEYS: u8 = 16;
-    const TIMER_RATE_HZ: f64 = 60.0;
+    pub const NUM_KEYS: u8 = 16;
+    // The HP-48 had 8; XO-CHIP allows all 16 registers
+    pub const NUM_RPL_FLAGS: usize = 16;
 
     pub fn new(program_data: Vec<u8>, clock_speed_hz: f64) -> Chip8 {
-        let micros_per_cycle = ((1e6) * (1. / clock_speed_hz)).round() as u32;
-        let micros_per_timer = ((1e6) * (1. / Chip8::TIMER_RATE_HZ)).round() as u32;
-
         Chip8 {
             registers: Registers::new(),
             stack: Stack::new(),
-            main_memory: MainMemory::new(program_data),
+            main_memory: MainMemory::new(program_data.clone()),
+            rom: program_data,
             display: Display::new(),
+            rpl_flags: [0; Chip8::NUM_RPL_FLAGS],
+            planes: 0x1,
             waiting_on_key: -1,  // Stores the register where the keypress is to be stored
+            presses_while_waiting: 0,
             key_pressed: [false; Chip8::NUM_KEYS as usize],
-            micros_per_cycle: micros_per_cycle,
-            micros_since_cycle: 0,
-            micros_per_timer: micros_per_timer,
-            micros_since_timer: 0,
+            edge_triggered_keys: false,
+            key_pressed_last_frame: [false; Chip8::NUM_KEYS as usize],
+            key_edges: [false; Chip8::NUM_KEYS as usize],
+            scheduler: Scheduler::new(clock_speed_hz),
+            variant: Variant::Chip8,
+            quirks: Quirks::default(),
+            waiting_on_vblank: false,
+            cycles_executed: 0,
+            frames_elapsed: 0,
+            max_instructions: None,
+            max_frames: None,
+            pause_at: None,
+            watches: Vec::new(),
+            watch_hit: None,
+            breakpoints: BTreeSet::new(),
+            breakpoint_hit: None,
+            uninitialized_reads: Vec::new(),
+            rng: Chip8::starting_rng(),
+            history: None,
+            journal: None,
+            state_hashes: None,
+            input: None,
+            hooks: Vec::new(),
+            stats: Stats::default(),
+            profile: None,
+            tracer: None,
+            coverage: None,
+            linter: None,
+            dynarec: None,
+        }
+    }
+
+    pub fn set_tracer(&mut self, tracer: Tracer) {
+        self.tracer = Some(tracer);
+    }
+
+    /// Call `hook` with every core event from now on. Like tracing, hooks
+    /// keep the dynamic recompiler out of the way.
+    pub fn add_hook(&mut self, hook: Hook) {
+        self.hooks.push(hook);
+    }
+
+    fn emit(&mut self, event: CoreEvent) {
+        for hook in self.hooks.iter_mut() {
+            hook(&event);
+        }
+    }
+
+    /// Which extension opcodes exist. This doesn't touch the quirks, so a
+    /// variant can be run with another's behaviour.
+    pub fn set_variant(&mut self, variant: Variant) {
+        self.variant = variant;
+        self.main_memory.set_size(variant.memory_size());
+    }
+
+    pub fn variant(&self) -> Variant {
+        self.variant
+    }
+
+    pub fn set_font(&mut self, font: &Font, address: u16) -> Result<(), String> {
+        self.main_memory.set_font(font, address)
+    }
+
+    // The browser has no entropy source rand can reach without wasm-bindgen, so there the wrapper seeds it
+    #[cfg(not(target_arch = "wasm32"))]
+    fn starting_rng() -> StdRng {
+        StdRng::from_entropy()
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn starting_rng() -> StdRng {
+        StdRng::seed_from_u64(0)
+    }
+
+    /// Draw `RND`'s numbers from a fixed seed rather than the entropy the
+    /// machine starts with, so a run can be repeated exactly.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    /// Make EX9E and EXA1 see a key as pressed only on the first check
+    /// after it went down, rather than for as long as it's held. Each
+    /// frame, the keys that are down now but weren't a frame ago become
+    /// edges, and the first skip instruction to test an edge uses it up.
+    pub fn set_edge_triggered_keys(&mut self, edge_triggered_keys: bool) {
+        self.edge_triggered_keys = edge_triggered_keys;
+    }
+
+    /// Write a `frame sha1` line to `out` at every frame boundary, hashing
+    /// the whole machine, so two runs can be diffed to find the first
+    /// frame where they went different ways.
+    pub fn set_state_hashes(&mut self, out: Box<dyn Write>) {
+        self.state_hashes = Some(out);
+    }
+
+    fn write_state_hash(&mut self) {
+        if let Some(mut out) = self.state_hashes.take() {
+            match writeln!(out, "{} {}", self.frames_elapsed, checksum::to_hex(&self.snapshot().hash())) {
+                Ok(()) => self.state_hashes = Some(out),
+                Err(error) => stopped_writing("state hashes", &error),
+            }
+        }
+    }
+
+    /// Read the hex pad from `source` from now on. Keys can still be set
+    /// directly too; the machine only applies what changes in the source.
+    pub fn set_input(&mut self, source: Box<dyn InputSource>) {
+        self.input = Some((source, 0));
+    }
+
+    /// Press and release whatever keys the input source changed since it
+    /// was last polled.
+    fn poll_input(&mut self) {
+        let (source, last) = match self.input.as_mut() {
+            Some(input) => input,
+            None => return,
+        };
+        let keys = source.keys(self.frames_elapsed);
+        let changed = keys ^ *last;
+        *last = keys;
+        for key in (0..Chip8::NUM_KEYS).filter(|key| changed & (1 << key) != 0) {
+            self.set_key(key, keys & (1 << key) != 0);
+        }
+    }
+
+    /// Stop the machine after a number of executed instructions and/or
+    /// display frames, for scripted runs that need to end deterministically.
+    /// Once a limit is reached `try_cycle` does nothing further.
+    pub fn set_limits(&mut self, max_instructions: Option<u64>, max_frames: Option<u64>) {
+        self.max_instructions = max_instructions;
+        self.max_frames = max_frames;
+    }
+
+    pub fn limit_reached(&self) -> bool {
+        self.max_instructions.is_some_and(|max| self.cycles_executed >= max)
+            || self.max_frames.is_some_and(|max| self.frames_elapsed >= max)
+    }
+
+    /// Hold the machine once this many instructions have executed, e.g. to
+    /// inspect it at an exact point. Unlike a limit, the time passed to
+    /// `try_cycle` is kept, so clearing or moving the pause and calling
+    /// `try_cycle` with no further time picks up exactly where it stopped.
+    pub fn set_pause_at(&mut self, instructions: Option<u64>) {
+        self.pause_at = instructions;
+    }
+
+    pub fn paused(&self) -> bool {
+        self.pause_at.is_some_and(|at| self.cycles_executed >= at) || self.watch_hit.is_some()
+            || self.breakpoint_hit.is_some()
+    }
+
+    /// Check a watch expression after every instruction, pausing the
+    /// machine when it becomes true until `resume` is called. An expression
+    /// that is already true when added has to turn false first.
+    pub fn add_watch(&mut self, mut watch: Watch) {
+        watch.check(self);
+        self.watches.push(watch);
+    }
+
+    pub fn watch_hit(&self) -> Option<&Watch> {
+        self.watch_hit.map(|index| &self.watches[index])
+    }
+
+    /// Pause the machine whenever the program counter arrives at an
+    /// address, before the instruction there executes. Like a watch, it
+    /// holds until `resume`.
+    pub fn add_breakpoint(&mut self, address: u16) -> bool {
+        self.breakpoints.insert(address)
+    }
+
+    pub fn remove_breakpoint(&mut self, address: u16) -> bool {
+        self.breakpoints.remove(&address)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = u16> + '_ {
+        self.breakpoints.iter().cloned()
+    }
+
+    pub fn breakpoint_hit(&self) -> Option<u16> {
+        self.breakpoint_hit
+    }
+
+    pub fn resume(&mut self) {
+        self.watch_hit = None;
+        self.breakpoint_hit = None;
+    }
+
+    /// Strict memory mode: note every read of program memory that neither
+    /// the loader nor the program has written.
+    pub fn enable_strict_memory(&mut self) {
+        self.main_memory.track_uninitialized_reads();
+    }
+
+    /// The uninitialized reads since the last call, as the address of the
+    /// instruction and the address it read.
+    pub fn take_uninitialized_reads(&mut self) -> Vec<(u16, u16)> {
+        std::mem::take(&mut self.uninitialized_reads)
+    }
+
+    /// Strict mode: uninitialized reads plus runtime lints for ROM authors.
+    pub fn enable_strict(&mut self) {
+        self.enable_strict_memory();
+        self.linter = Some(Linter::new());
+    }
+
+    pub fn linter(&self) -> Option<&Linter> {
+        self.linter.as_ref()
+    }
+
+    /// Keep a journal of snapshots as the machine runs, so that it can
+    /// step back. Like the other debugging aids this runs every
+    /// instruction through the interpreter.
+    pub fn enable_journal(&mut self) {
+        self.journal = Some(Journal::new());
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            registers: self.registers.clone(),
+            stack: self.stack.clone(),
+            main_memory: self.main_memory.clone(),
+            display: self.display.clone(),
+            rpl_flags: self.rpl_flags,
+            planes: self.planes,
+            waiting_on_key: self.waiting_on_key,
+            presses_while_waiting: self.presses_while_waiting,
+            key_pressed: self.key_pressed,
+            key_pressed_last_frame: self.key_pressed_last_frame,
+            key_edges: self.key_edges,
+            scheduler: self.scheduler.clone(),
+            waiting_on_vblank: self.waiting_on_vblank,
+            cycles_executed: self.cycles_executed,
+            frames_elapsed: self.frames_elapsed,
+            rng: self.rng.clone(),
+            variant: self.variant,
+            quirks: self.quirks,
+        }
+    }
+
+    /// What the machine looks like right now, for dumps, debuggers and
+    /// tests. Memory and the display come along only when asked for.
+    pub fn state(&self, with_memory: bool) -> MachineState {
+        MachineState {
+            variant: self.variant,
+            instructions: self.cycles_executed,
+            frames: self.frames_elapsed,
+            v: *self.registers.data(),
+            i: self.registers.i_register,
+            pc: self.program_counter(),
+            delay_timer: self.registers.delay_timer,
+            sound_timer: self.registers.sound_timer,
+            stack: self.stack.frames().to_vec(),
+            keys: (0..Chip8::NUM_KEYS).filter(|&key| self.key_pressed[key as usize])
+                                      .fold(0, |keys, key| keys | 1 << key),
+            waiting_on_key: self.waiting_on_key(),
+            memory: with_memory.then(|| (0..self.main_memory.size()).map(|address| self.main_memory.peek(address as u16).unwrap_or(0))
+                                                                    .collect()),
+            display: with_memory.then(|| self.display.pixels().to_vec()),
+        }
+    }
+
+    /// Put the machine into `state`, the other way from `state`: the
+    /// registers, timers, program counter, stack and keys, plus program
+    /// memory (from 0x200) and the display when it has them. Counters,
+    /// the variant and any key wait are left alone. For setting up tests
+    /// rather than restoring a run, which is what snapshots are for.
+    pub fn set_state(&mut self, state: &MachineState) -> Result<(), Chip8Error> {
+        for (register, &value) in state.v.iter().enumerate() {
+            self.registers.write_data_register(register as u8, value)?;
+        }
+        self.registers.i_register = state.i;
+        self.registers.delay_timer = state.delay_timer;
+        self.registers.sound_timer = state.sound_timer;
+        self.main_memory.set_program_counter(state.pc)?;
+        self.stack = Stack::new();
+        for &frame in &state.stack {
+            self.stack.push(frame)?;
+        }
+        for key in 0..Chip8::NUM_KEYS {
+            self.set_key(key, state.key_held(key));
+        }
+        if let Some(memory) = &state.memory {
+            for (address, &byte) in memory.iter().enumerate().skip(0x200) {
+                self.main_memory.write_address(address as u16, byte)?;
+            }
+        }
+        if let Some(display) = &state.display {
+            if display.len() == Display::HIRES_SIZE && !self.display.hires() {
+                self.display.set_hires(true);
+            }
+            for (num, &pixel) in display.iter().enumerate() {
+                self.display.set(num % self.display.width(), num / self.display.width(), pixel);
+            }
+        }
+        Ok(())
+    }
+
+    /// Start the program over, as a fresh `Chip8` with the same ROM would:
+    /// memory, registers, stack, display, timers and counters go back to
+    /// how they were at boot. The variant, quirks, font and clock speed
+    /// stay, as does anything attached, such as the input, hooks, watches,
+    /// breakpoints and limits. Random numbers carry on rather than repeat.
+    pub fn reset(&mut self) {
+        self.main_memory.reload(self.rom.clone());
+        if let Some(dynarec) = self.dynarec.as_mut() {
+            dynarec.invalidate_all();
+        }
+        self.registers = Registers::new();
+        self.stack = Stack::new();
+        self.display.replace(Display::new());
+        self.rpl_flags = [0; Chip8::NUM_RPL_FLAGS];
+        self.planes = 0x1;
+        self.waiting_on_key = -1;
+        self.presses_while_waiting = 0;
+        self.key_pressed = [false; Chip8::NUM_KEYS as usize];
+        self.key_pressed_last_frame = [false; Chip8::NUM_KEYS as usize];
+        self.key_edges = [false; Chip8::NUM_KEYS as usize];
+        // So that keys still held are pressed again at the next poll
+        if let Some((_, held)) = self.input.as_mut() {
+            *held = 0;
+        }
+        self.scheduler = Scheduler::new(self.scheduler.clock_speed());
+        self.waiting_on_vblank = false;
+        self.cycles_executed = 0;
+        self.frames_elapsed = 0;
+        self.watch_hit = None;
+        self.breakpoint_hit = None;
+        self.uninitialized_reads.clear();
+        self.stats = Stats::default();
+        // There's no stepping back past a reset
+        if self.history.is_some() {
+            self.history = Some(History::new());
+        }
+        if self.journal.is_some() {
+            self.journal = Some(Journal::new());
+        }
+    }
+
+    pub fn restore(&mut self, snapshot: Snapshot) {
+        // Compiled code survives where memory is the same, as it is for run-ahead's restore every frame
+        if let Some(dynarec) = self.dynarec.as_mut() {
+            match self.main_memory.changes_from(&snapshot.main_memory) {
+                Some(changes) => changes.iter().for_each(|&(address, length)| dynarec.invalidate(address, length)),
+                None => dynarec.invalidate_all(),
+            }
         }
+        self.registers = snapshot.registers;
+        self.stack = snapshot.stack;
+        self.main_memory = snapshot.main_memory;
+        self.display.replace(snapshot.display);
+        self.rpl_flags = snapshot.rpl_flags;
+        self.planes = snapshot.planes;
+        self.waiting_on_key = snapshot.waiting_on_key;
+        self.presses_while_waiting = snapshot.presses_while_waiting;
+        self.key_pressed = snapshot.key_pressed;
+        self.key_pressed_last_frame = snapshot.key_pressed_last_frame;
+        self.key_edges = snapshot.key_edges;
+        self.scheduler = snapshot.scheduler;
+        self.waiting_on_vblank = snapshot.waiting_on_vblank;
+        self.cycles_executed = snapshot.cycles_executed;
+        self.frames_elapsed = snapshot.frames_elapsed;
+        self.rng = snapshot.rng;
+        self.variant = snapshot.variant;
+        self.quirks = snapshot.quirks;
     }
 
-    pub fn scan_program(&mut self) {
+    /// Go back to just after the previous instruction, by restoring the
+    /// newest journal snapshot before it and running forward again: first
+    /// on the scheduler's clock, then a step at a time for instructions a
+    /// debugger stepped while the clock was stopped. The replay uses the
+    /// keys as they were at the snapshot, so if the program waited on a key
+    /// in between it can't get past it, and the machine is left as it was.
+    pub fn step_back(&mut self) -> Result<(), String> {
+        let target = self.cycles_executed.checked_sub(1).ok_or("Nothing has run yet.")?;
+        let snapshot = self.journal.as_mut()
+                                   .ok_or("Stepping back needs the journal.")?
+                                   .rewind(target)
+                                   .ok_or("The journal doesn't go back that far.")?;
+        let current = self.snapshot();
+        let now = self.scheduler.now();
+        self.restore(snapshot);
+        self.scheduler.advance(Duration::from_nanos((now - self.scheduler.now()) as u64));
+
+        // Replay quietly, stopping right after the target instruction
+        let tracer = self.tracer.take();
+        let coverage = self.coverage.take();
+        let linter = self.linter.take();
+        let hooks = std::mem::take(&mut self.hooks);
+        let stats = self.stats;
+        let input = self.input.take();
+        let pause_at = self.pause_at.replace(target);
+        let mut replayed = self.try_cycle(Duration::from_secs(0)).map(|_| ());
+        if replayed.is_ok() && self.cycles_executed == target {
+            replayed = self.catch_up_without_executing();
+        }
+        while replayed.is_ok() && self.cycles_executed < target && !self.waiting_on_key() {
+            replayed = self.step();
+        }
+        self.tracer = tracer;
+        self.coverage = coverage;
+        self.linter = linter;
+        self.hooks = hooks;
+        self.stats = stats;
+        self.input = input;
+        self.pause_at = pause_at;
+
+        match replayed {
+            Ok(_) if self.cycles_executed == target => Ok(()),
+            _ => {
+                self.restore(current);
+                Err(String::from("The replay didn't arrive at the previous instruction; a key probably changed."))
+            },
+        }
+    }
+
+    /// The display as it will be `frames` frames from now if the keys stay
+    /// as they are, for run-ahead: showing it hides the frame or so a
+    /// program takes to react to a key. The machine runs ahead quietly and
+    /// is put back as it was, so nothing it did along the way counts. A
+    /// fault on the way shows the display as it stood.
+    pub fn run_ahead(&mut self, frames: u64) -> Display {
+        let current = self.snapshot();
+        let tracer = self.tracer.take();
+        let coverage = self.coverage.take();
+        let linter = self.linter.take();
+        let journal = self.journal.take();
+        let state_hashes = self.state_hashes.take();
+        let hooks = std::mem::take(&mut self.hooks);
+        let watches = std::mem::take(&mut self.watches);
+        let history = self.history.take();
+        let stats = self.stats;
+        let input = self.input.take();
+        let uninitialized_reads = self.uninitialized_reads.len();
+
+        let ahead = match self.run_frames(frames) {
+            Ok(_) => self.display.clone(),
+            Err(_) => current.display.clone(),
+        };
 
-        for _ in 0..self.main_memory.program_length {
-            let opcode = self.main_memory.fetch_opcode();
-            match opcode {
-                Some(opcode) => {
-                    let instruction = instructions::parse_opcode(opcode);
-                    println!("{:#06X} => {:X?}", opcode, instruction);
+        self.tracer = tracer;
+        self.coverage = coverage;
+        self.linter = linter;
+        self.journal = journal;
+        self.state_hashes = state_hashes;
+        self.hooks = hooks;
+        self.watches = watches;
+        self.history = history;
+        self.stats = stats;
+        self.input = input;
+        self.uninitialized_reads.truncate(uninitialized_reads);
+        self.restore(current);
+        ahead
+    }
+
+    /// Handle the interrupts and idle cycles that are due before the next
+    /// instruction, as running on would have before reaching it.
+    fn catch_up_without_executing(&mut self) -> Result<(), Chip8Error> {
+        while let Some(event) = self.scheduler.peek_event() {
+            if event == Event::Cycle && self.waiting_on_key == -1 && !self.waiting_on_vblank {
+                break;
+            }
+            self.scheduler.next_event();
+            match event {
+                Event::Cycle => self.finish_key_wait()?,
+                Event::VBlank => {
+                    self.vblank();
+                    self.frames_elapsed += 1;
                 },
+            }
+        }
+        Ok(())
+    }
+
+    pub fn instructions_executed(&self) -> u64 {
+        self.cycles_executed
+    }
+
+    pub fn frames_elapsed(&self) -> u64 {
+        self.frames_elapsed
+    }
+
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    /// Time the fetch, decode and execute of every instruction. Compiled
+    /// blocks have no fetch or decode, so with the dynarec their time all
+    /// counts as execution.
+    pub fn enable_profile(&mut self) {
+        self.profile = Some(Profile::default());
+    }
+
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+
+    pub fn enable_coverage(&mut self) {
+        self.coverage = Some(Coverage::new(self.main_memory.program_length));
+    }
+
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    // Keep the last instructions executed, to show what led up to a fault
+    pub fn enable_history(&mut self) {
+        self.history = Some(History::new());
+    }
+
+    /// Run hot code through the experimental dynamic recompiler. Compiled
+    /// blocks only run while nothing needs to see single instructions, so
+    /// tracing, coverage, strict mode, watches, instruction limits and
+    /// pauses all quietly fall back to the interpreter.
+    pub fn enable_dynarec(&mut self) {
+        self.dynarec = Some(Dynarec::new());
+    }
+
+    pub fn dynarec(&self) -> Option<&Dynarec> {
+        self.dynarec.as_ref()
+    }
+
+    /// Run the machine forward by `elapsed_time`, returning the number of
+    /// display interrupts (frames) that fired. Frontends only need to redraw
+    /// when this is non-zero. The machine stops at a faulty program's
+    /// failing instruction and returns the fault.
+    pub fn try_cycle(&mut self, elapsed_time: Duration) -> Result<u32, Chip8Error> {
+        self.scheduler.advance(elapsed_time);
+        self.poll_input();
+
+        let mut frames = 0;
+        while !self.limit_reached() && !self.paused() {
+            if self.journal.as_ref().is_some_and(|journal| journal.is_due(self.cycles_executed)) {
+                let snapshot = self.snapshot();
+                self.journal.as_mut().unwrap().record(snapshot);
+            }
+            let event = match self.scheduler.next_event() {
+                Some(event) => event,
                 None => break,
             };
+            match event {
+                Event::Cycle => {
+                    self.finish_key_wait()?;
+                    if self.waiting_on_key == -1 && !self.waiting_on_vblank && !self.run_block()? {
+                        self.step()?;
+                    }
+                },
+                Event::VBlank => {
+                    self.vblank();
+                    self.frames_elapsed += 1;
+                    frames += 1;
+                    self.write_state_hash();
+                    self.poll_input();
+                },
+            }
+        }
+        Ok(frames)
+    }
+
+    /// `try_cycle` for a frontend with nowhere to report a fault, which
+    /// panics with it after the instructions that led up to it. Nothing in
+    /// the core calls this.
+    pub fn cycle(&mut self, elapsed_time: Duration) -> u32 {
+        self.try_cycle(elapsed_time).unwrap_or_else(|error| match &self.history {
+            Some(history) => panic!("{}{}", history, error),
+            None => panic!("{}", error),
+        })
+    }
+
+    /// Run `frames` frames of emulated time as fast as the host allows,
+    /// with the instructions and timer ticks each would get in real time
+    /// but no waiting on the clock. For tools that run programs rather
+    /// than show them. Stops early at a limit or a pause and returns how
+    /// many frames ran.
+    pub fn run_frames(&mut self, frames: u64) -> Result<u64, Chip8Error> {
+        let mut ran = 0;
+        while ran < frames && !self.limit_reached() && !self.paused() {
+            ran += self.try_cycle(Scheduler::frame_duration())? as u64;
         }
+        Ok(ran)
     }
 
-    pub fn cycle(&mut self, elapsed_time: Duration) {
-        self.micros_since_cycle += elapsed_time.as_micros();
-        self.micros_since_timer += elapsed_time.as_micros();
+    /// Run the compiled block at the program counter, if there is one and
+    /// the rest of its cycles are due before anything else happens. The
+    /// first cycle has already been taken by the caller.
+    fn run_block(&mut self) -> Result<bool, Chip8Error> {
+        let single_stepping = self.tracer.is_some() || self.coverage.is_some() || self.linter.is_some() || self.journal.is_some()
+                              || !self.watches.is_empty() || !self.breakpoints.is_empty() || !self.hooks.is_empty()
+                              || self.max_instructions.is_some() || self.pause_at.is_some();
+        let dynarec = match self.dynarec.as_mut() {
+            Some(dynarec) if !single_stepping => dynarec,
+            _ => return Ok(false),
+        };
+
+        let pc = self.main_memory.peek_program_counter() as u16;
+        let block = match dynarec.enter(pc, &self.main_memory) {
+            Some(block) => block,
+            None => return Ok(false),
+        };
+        if !self.scheduler.take_cycles(block.len() as u64 - 1) {
+            return Ok(false);
+        }
 
-        if self.micros_since_cycle > self.micros_per_cycle as u128 {
-            let cycles = self.micros_since_cycle / (self.micros_per_cycle as u128);
-            for _ in 0..cycles {
-                if self.waiting_on_key == -1 {
-                    let instr = self.fetch();
-                    self.execute(instr);
+        for (offset, &instruction) in block.iter().enumerate() {
+            let instruction_pc = pc + 2 * offset as u16;
+            self.skip_instruction();
+            let before = self.history.is_some().then(|| self.registers.clone());
+            let mut lap = self.profile.as_ref().map(|_| Instant::now());
+            let result = self.execute(instruction_pc, instruction);
+            self.record_lap(&mut lap, |profile| &mut profile.execute);
+            if let (Some(history), Some(before)) = (self.history.as_mut(), before) {
+                history.record(self.cycles_executed, instruction_pc, instruction, before, &self.registers);
+            }
+            if let Err(error) = result {
+                // Leave the machine as the interpreter would have, with only what ran counted
+                self.scheduler.return_cycles((block.len() - offset - 1) as u64);
+                if let Some(dynarec) = self.dynarec.as_mut() {
+                    dynarec.record_run(offset);
                 }
+                return Err(error);
+            }
+            for address in self.main_memory.take_uninitialized_reads() {
+                self.uninitialized_reads.push((instruction_pc, address));
             }
-            self.micros_since_cycle = self.micros_since_cycle % (self.micros_per_cycle as u128);
+            self.cycles_executed += 1;
+            self.stats.instructions += 1;
+        }
+        if let Some(dynarec) = self.dynarec.as_mut() {
+            dynarec.record_run(block.len());
         }
+        Ok(true)
+    }
 
-        if self.micros_since_timer > self.micros_per_timer as u128 {
-            if self.registers.delay_timer > 0 {
-                self.registers.delay_timer -= 1;
+    fn vblank(&mut self) {
+        self.stats.frames += 1;
+        if self.registers.delay_timer > 0 {
+            self.registers.delay_timer -= 1;
+        }
+        if self.registers.sound_timer > 0 {
+            self.registers.sound_timer -= 1;
+            if self.registers.sound_timer == 0 {
+                self.emit(CoreEvent::SoundStopped);
             }
-            if self.registers.sound_timer > 0 {
-                self.registers.sound_timer -= 1;
+        }
+        self.waiting_on_vblank = false;
+
+        for key in 0..Chip8::NUM_KEYS as usize {
+            self.key_edges[key] = self.key_pressed[key] && !self.key_pressed_last_frame[key];
+        }
+        self.key_pressed_last_frame = self.key_pressed;
+    }
+
+    /// Execute exactly one instruction, whether or not the machine is
+    /// waiting on a key or the display, without touching the timers. A
+    /// faulty program is reported as an error rather than a panic, which
+    /// makes this the entry point for fuzzing the core. After an error the
+    /// machine is still safe to inspect, but the program is lost.
+    pub fn step(&mut self) -> Result<(), Chip8Error> {
+        let pc = self.main_memory.peek_program_counter() as u16;
+        let mut lap = self.profile.as_ref().map(|_| Instant::now());
+        let opcode = self.main_memory.fetch_opcode()?;
+        self.record_lap(&mut lap, |profile| &mut profile.fetch);
+        let instruction = instructions::parse_opcode(opcode);
+        info!("{:#06X} => {:X?}", opcode, instruction);
+        self.record_lap(&mut lap, |profile| &mut profile.decode);
+        let i_before = self.registers.i_register;
+
+        if let Some(coverage) = self.coverage.as_mut() {
+            coverage.record(pc, &instruction);
+        }
+
+        // Only the tracer and history need the registers as they were
+        let before = (self.tracer.is_some() || self.history.is_some()).then(|| self.registers.clone());
+        let sound_before = self.registers.sound_timer;
+        let result = self.execute(pc, instruction);
+        self.record_lap(&mut lap, |profile| &mut profile.execute);
+        if let (Ok(()), Some(tracer), Some(before)) = (&result, self.tracer.as_mut(), before.as_ref()) {
+            if let Err(error) = tracer.record(self.cycles_executed, pc, &instruction, before, &self.registers) {
+                stopped_writing("the trace", &error);
+                self.tracer = None;
             }
-            self.micros_since_timer = self.micros_since_timer % (self.micros_per_timer as u128);
         }
+        if let (Some(history), Some(before)) = (self.history.as_mut(), before) {
+            history.record(self.cycles_executed, pc, instruction, before, &self.registers);
+        }
+        result?;
+
+        if let Some(linter) = self.linter.as_mut() {
+            linter.check(pc, &instruction, i_before, self.registers.i_register, self.stack.frames().len(),
+                         self.main_memory.size());
+        }
+
+        for address in self.main_memory.take_uninitialized_reads() {
+            self.uninitialized_reads.push((pc, address));
+        }
+        if !self.hooks.is_empty() {
+            self.emit_events(pc, instruction, sound_before);
+        }
+        self.cycles_executed += 1;
+        self.stats.instructions += 1;
+        self.check_watches();
+        let pc = self.main_memory.peek_program_counter() as u16;
+        if self.breakpoints.contains(&pc) {
+            self.breakpoint_hit = Some(pc);
+        }
+        Ok(())
     }
 
-    fn fetch(&mut self) -> Instruction {
-        let opcode = self.main_memory.fetch_opcode();
-        let instruction = match opcode {
-            Some(opcode) => {
-                let instruction = instructions::parse_opcode(opcode);
-                info!("{:#06X} => {:X?}", opcode, instruction);
-                instruction
+    fn emit_events(&mut self, pc: u16, instruction: Instruction, sound_before: u8) {
+        self.emit(CoreEvent::InstructionExecuted { pc, instruction });
+        match instruction {
+            Instruction::Draw(..) => {
+                self.emit(CoreEvent::Drew { pc, collision: self.registers.data()[0xF] });
             },
-            None => panic!("End of ROM."),
-        };
-        instruction
+            Instruction::AwaitPress(register) => self.emit(CoreEvent::KeyWaitEntered { register }),
+            Instruction::Call(address) => {
+                self.emit(CoreEvent::SubroutineCalled { address, return_address: pc.wrapping_add(2) });
+            },
+            _ => {},
+        }
+        match (sound_before, self.registers.sound_timer) {
+            (0, 1..=u8::MAX) => self.emit(CoreEvent::SoundStarted),
+            (1..=u8::MAX, 0) => self.emit(CoreEvent::SoundStopped),
+            _ => {},
+        }
+    }
+
+    fn check_watches(&mut self) {
+        let mut watches = std::mem::take(&mut self.watches);
+        for (index, watch) in watches.iter_mut().enumerate() {
+            if watch.check(self) && self.watch_hit.is_none() {
+                self.watch_hit = Some(index);
+            }
+        }
+        self.watches = watches;
+    }
+
+    // Add the time since the last lap to one phase of the profile, if there is one
+    fn record_lap(&mut self, lap: &mut Option<Instant>, phase: fn(&mut Profile) -> &mut Duration) {
+        if let (Some(started), Some(profile)) = (lap.as_mut(), self.profile.as_mut()) {
+            let now = Instant::now();
+            *phase(profile) += now - *started;
+            *started = now;
+        }
     }
 
-    fn execute(&mut self, instruction: Instruction) {
+    fn execute(&mut self, pc: u16, instruction: Instruction) -> Result<(), Chip8Error> {
         match instruction {
             Instruction::ClearScreen => {
-                self.display.clear();
+                self.display.clear(self.planes);
             },
             Instruction::Return => {
-                let address = self.stack.pop();
-                self.main_memory.set_program_counter(address);
+                let address = self.stack.pop()?;
+                self.main_memory.set_program_counter(address)?;
             },
             Instruction::Jump(address) => {
-                self.main_memory.set_program_counter(address);
+                self.main_memory.set_program_counter(address)?;
             },
             Instruction::Call(address) => {
-                let pc = self.main_memory.peek_program_counter();
-                self.stack.push(pc as u16);
-                self.main_memory.set_program_counter(address);
+                let return_address = self.main_memory.peek_program_counter();
+                self.stack.push(return_address as u16)?;
+                self.stats.stack_high_water = self.stats.stack_high_water.max(self.stack.frames().len());
+                self.main_memory.set_program_counter(address)?;
             },
             Instruction::SkipIfEQData(register, data) => {
-                if self.registers.read_data_register(register) == data {
-                    self.main_memory.skip_instruction();
+                if self.registers.read_data_register(register)? == data {
+                    self.skip_instruction();
                 }
             },
             Instruction::SkipIfNEData(register, data) => {
-                if self.registers.read_data_register(register) != data {
-                    self.main_memory.skip_instruction();
+                if self.registers.read_data_register(register)? != data {
+                    self.skip_instruction();
                 }
             },
             Instruction::SkipIfEQRegister(register_1, register_2) => {
-                if self.registers.read_data_register(register_1) == self.registers.read_data_register(register_2) {
-                    self.main_memory.skip_instruction();
+                if self.registers.read_data_register(register_1)? == self.registers.read_data_register(register_2)? {
+                    self.skip_instruction();
                 }
             },
             Instruction::LoadData(register, data) => {
-                self.registers.write_data_register(register, data)
+                self.registers.write_data_register(register, data)?
             },
             Instruction::AddData(register, data) => {
-                let register_data = self.registers.read_data_register(register);
-                self.registers.write_data_register(register, register_data.wrapping_add(data));
+                let register_data = self.registers.read_data_register(register)?;
+                self.registers.write_data_register(register, register_data.wrapping_add(data))?;
             },
             Instruction::LoadRegister(register_1, register_2) => {
-                let data = self.registers.read_data_register(register_2);
-                self.registers.write_data_register(register_1, data);
+                let data = self.registers.read_data_register(register_2)?;
+                self.registers.write_data_register(register_1, data)?;
             },
             Instruction::Or(register_1, register_2) => {
-                self.registers.write_data_register(register_1,
-                    self.registers.read_data_register(register_1) | self.registers.read_data_register(register_2));
+                let data = self.registers.read_data_register(register_1)? | self.registers.read_data_register(register_2)?;
+                self.registers.write_data_register(register_1, data)?;
+                self.reset_vf_after_logic()?;
             },
             Instruction::And(register_1, register_2) => {
-                self.registers.write_data_register(register_1, self.registers.read_data_register(register_1)
-                                                               & self.registers.read_data_register(register_2));
+                let data = self.registers.read_data_register(register_1)? & self.registers.read_data_register(register_2)?;
+                self.registers.write_data_register(register_1, data)?;
+                self.reset_vf_after_logic()?;
             },
             Instruction::Xor(register_1, register_2) => {
-                self.registers.write_data_register(register_1,
-                    self.registers.read_data_register(register_1) ^ self.registers.read_data_register(register_2));
+                let data = self.registers.read_data_register(register_1)? ^ self.registers.read_data_register(register_2)?;
+                self.registers.write_data_register(register_1, data)?;
+                self.reset_vf_after_logic()?;
             },
             Instruction::Add(register_1, register_2) => {
-                let register_1_data = self.registers.read_data_register(register_1) as u16;
-                let register_2_data = self.registers.read_data_register(register_2) as u16;
+                let register_1_data = self.registers.read_data_register(register_1)? as u16;
+                let register_2_data = self.registers.read_data_register(register_2)? as u16;
                 let sum = register_1_data + register_2_data;
-                self.registers.write_data_register(0xF, (sum > 255) as u8);
-                self.registers.write_data_register(register_1, sum as u8)
+                self.registers.write_data_register(0xF, (sum > 255) as u8)?;
+                self.registers.write_data_register(register_1, sum as u8)?
 
             },
             Instruction::Sub(register_1, register_2) => {
-                let register_1_data = self.registers.read_data_register(register_1);
-                let register_2_data = self.registers.read_data_register(register_2);
-                self.registers.write_data_register(0xF, (register_1_data > register_2_data) as u8);
-                self.registers.write_data_register(register_1, register_1_data.wrapping_sub(register_2_data));
+                let register_1_data = self.registers.read_data_register(register_1)?;
+                let register_2_data = self.registers.read_data_register(register_2)?;
+                self.registers.write_data_register(0xF, (register_1_data > register_2_data) as u8)?;
+                self.registers.write_data_register(register_1, register_1_data.wrapping_sub(register_2_data))?;
             },
-            Instruction::ShiftRight(register) => {
-                let data = self.registers.read_data_register(register);
-                self.registers.write_data_register(0xF, data & 0x1);
-                self.registers.write_data_register(register, data >> 1);
+            Instruction::ShiftRight(register, source) => {
+                let data = self.registers.read_data_register(self.shift_source(register, source))?;
+                self.registers.write_data_register(0xF, data & 0x1)?;
+                self.registers.write_data_register(register, data >> 1)?;
             },
             Instruction::NegatedSub(register_1, register_2) => {
-                let register_1_data = self.registers.read_data_register(register_1);
-                let register_2_data = self.registers.read_data_register(register_2);
-                self.registers.write_data_register(0xF, (register_2_data > register_1_data) as u8);
-                self.registers.write_data_register(register_1, register_2_data.wrapping_sub(register_1_data));
+                let register_1_data = self.registers.read_data_register(register_1)?;
+                let register_2_data = self.registers.read_data_register(register_2)?;
+                self.registers.write_data_register(0xF, (register_2_data > register_1_data) as u8)?;
+                self.registers.write_data_register(register_1, register_2_data.wrapping_sub(register_1_data))?;
             },
-            Instruction::ShiftLeft(register) => {
-                let data = self.registers.read_data_register(register);
-                self.registers.write_data_register(0xF, data >> 7);
-                self.registers.write_data_register(register, data << 1);
+            Instruction::ShiftLeft(register, source) => {
+                let data = self.registers.read_data_register(self.shift_source(register, source))?;
+                self.registers.write_data_register(0xF, data >> 7)?;
+                self.registers.write_data_register(register, data << 1)?;
             },
             Instruction::SkipIfNERegister(register_1, register_2) => {
-                let register_1_data = self.registers.read_data_register(register_1);
-                let register_2_data = self.registers.read_data_register(register_2);
+                let register_1_data = self.registers.read_data_register(register_1)?;
+                let register_2_data = self.registers.read_data_register(register_2)?;
                 if register_1_data != register_2_data {
-                    self.main_memory.skip_instruction();
+                    self.skip_instruction();
                 }
             },
             Instruction::SetI(value) => {
                 self.registers.i_register = value;
             },
             Instruction::JumpFromOffset(address) => {
-                let offset = self.registers.read_data_register(0x0);
-                self.main_memory.set_program_counter(offset as u16 + address);
+                let offset_register = if self.quirks.jump_uses_vx { (address >> 8) as u8 } else { 0x0 };
+                let offset = self.registers.read_data_register(offset_register)?;
+                self.main_memory.set_program_counter(offset as u16 + address)?;
             },
             Instruction::Random(register, data) => {
-                let mut rng = thread_rng();
-                let n: u8 = rng.gen_range(0, 255);
-                self.registers.write_data_register(register, n & data);
+                let n: u8 = self.rng.gen_range(0, 255);
+                self.stats.random_calls += 1;
+                self.registers.write_data_register(register, n & data)?;
             },
             Instruction::Draw(x, y, data) => {
-                let start_sprite = self.registers.i_register;
-                let end_sprite = start_sprite + (data as u16);
-                let collision = self.display.draw(self.registers.read_data_register(x),
-                                                  self.registers.read_data_register(y),
-                                                  self.main_memory.slice_program(start_sprite,
-                                                                                 end_sprite));
-                self.registers.write_data_register(0xF, collision as u8);
+                // DXY0 draws a big sprite on some platforms, and always in hi-res
+                let (length, bytes_per_row) = match (data, self.quirks.big_sprite) {
+                    (0, _) if self.display.hires() => (32, 2),
+                    (0, BigSprite::Tall) => (16, 1),
+                    (0, BigSprite::Large) => (32, 2),
+                    _ => (data as u16, 1),
+                };
+                // Each selected plane has a sprite of its own, one after the other
+                let length = length * self.planes.count_ones() as u16;
+                let base = self.registers.i_register;
+                let sprite = (0..length).map(|offset| self.main_memory.load_address(base.wrapping_add(offset)))
+                                        .collect::<Result<Vec<u8>, Chip8Error>>()?;
+                let drawn = self.display.draw(self.registers.read_data_register(x)?,
+                                              self.registers.read_data_register(y)?,
+                                              &sprite,
+                                              bytes_per_row,
+                                              self.planes,
+                                              self.quirks.wrap_sprites);
+                let collision = if self.quirks.collision_row_count && self.display.hires() {
+                    drawn.erased + drawn.clipped
+                } else {
+                    (drawn.erased > 0) as u8
+                };
+                self.registers.write_data_register(0xF, collision)?;
+                self.stats.draws += 1;
+                self.stats.collisions += (drawn.erased > 0) as u64;
+                self.waiting_on_vblank = self.quirks.display_wait;
             },
             Instruction::SkipIfPressed(register) => {
-                let key = self.registers.read_data_register(register);
-                if self.key_pressed[key as usize] == true {
-                    self.main_memory.skip_instruction();
+                let key = self.registers.read_data_register(register)?;
+                if key >= Chip8::NUM_KEYS {
+                    return Err(Chip8Error::InvalidKey(key));
+                }
+                if self.check_key(key) {
+                    self.skip_instruction();
                 }
             },
             Instruction::SkipIfNotPressed(register) => {
-                let key = self.registers.read_data_register(register);
-                if key < Chip8::NUM_KEYS {
-                    if !(self.key_pressed[key as usize]) {
-                        self.main_memory.skip_instruction();
-                    }
-                } else {
-                    panic!("Invalid key expected");
+                let key = self.registers.read_data_register(register)?;
+                if key >= Chip8::NUM_KEYS {
+                    return Err(Chip8Error::InvalidKey(key));
+                }
+                if !self.check_key(key) {
+                    self.skip_instruction();
                 }
             },
             Instruction::SetRegisterFromDelay(register) => {
-                self.registers.write_data_register(register, self.registers.delay_timer)
+                self.registers.write_data_register(register, self.registers.delay_timer)?
             },
             Instruction::AwaitPress(register) => {
                 self.waiting_on_key = register as i8;
+                self.presses_while_waiting = 0;
             },
             Instruction::SetDelayFromRegister(register) => {
-                self.registers.delay_timer = self.registers.read_data_register(register);
+                self.registers.delay_timer = self.registers.read_data_register(register)?;
             },
-            Instruction::SetSoundFromRegister(_) => {
-                warn!("Sound is not implemented.");
+            Instruction::SetSoundFromRegister(register) => {
+                self.registers.sound_timer = self.registers.read_data_register(register)?;
             },
             Instruction::AddI(register) => {
-                self.registers.i_register += self.registers.read_data_register(register) as u16;
+                self.registers.i_register = self.registers.i_register.wrapping_add(self.registers.read_data_register(register)? as u16);
             },
             Instruction::LoadSprite(register) => {
-                self.registers.i_register = 5 * self.registers.read_data_register(register) as u16;
+                self.registers.i_register = self.main_memory.digit_address(self.registers.read_data_register(register)?);
             },
             Instruction::SetBCDRepresentation(register) => {
-                let data = self.registers.read_data_register(register);
-                self.main_memory.write_address(self.registers.i_register, (data / 100) % 10);
-                self.main_memory.write_address(self.registers.i_register + 1, (data / 10) % 10);
-                self.main_memory.write_address(self.registers.i_register + 2, data % 10);
+                let data = self.registers.read_data_register(register)?;
+                let base = self.registers.i_register;
+                self.main_memory.write_address(base, (data / 100) % 10)?;
+                self.main_memory.write_address(base.wrapping_add(1), (data / 10) % 10)?;
+                self.main_memory.write_address(base.wrapping_add(2), data % 10)?;
+                self.invalidate_compiled(base, 3);
             },
             Instruction::StoreRegisters(high_register) => {
                 // info!("{:X?}", instruction);
                 let base = self.registers.i_register;
                 for register in 0..(high_register + 1) {
-                    self.main_memory.write_address(base + register as u16,
-                                                   self.registers.read_data_register(register))
+                    self.main_memory.write_address(base.wrapping_add(register as u16),
+                                                   self.registers.read_data_register(register)?)?;
                 }
+                self.invalidate_compiled(base, high_register as u16 + 1);
+                self.increment_i_after_load_store(high_register);
             },
             Instruction::ReadRegisters(high_register) => {
                 let base = self.registers.i_register;
                 for register in 0..(high_register + 1) {
-                    self.registers.write_data_register(register, self.main_memory.load_address(base + register as u16))
+                    let data = self.main_memory.load_address(base.wrapping_add(register as u16))?;
+                    self.registers.write_data_register(register, data)?;
                 }
+                self.increment_i_after_load_store(high_register);
             },
-            Instruction::NOP(_) => {},
-            Instruction::UNKNOWN(data) => panic!("Unknown instruction encountered: {:X?}", data),
-        }
-    }
-
-    pub fn update_key(&mut self, key: String, is_pressed: bool) {
-        info!("Parsing keystroke {}, is_pressed: {}", key, is_pressed);
-        let keycode = Chip8::match_key(key);
-        match keycode {
-            Some(code) => {
-                self.key_pressed[code as usize] = is_pressed;
-                if self.waiting_on_key != -1 && is_pressed {
-                    self.registers.write_data_register(self.waiting_on_key as u8, code);
-                    self.waiting_on_key = -1;
-                }
-            },
-            None => {}
-        }
-    }
-
-    pub fn match_key(key: String) -> Option<u8> {
-        match key.as_str() {
-            "1" => {
-                Some(0x1)
+            Instruction::ScrollDown(rows) => {
+                self.require(Extension::SuperChip, pc, instruction)?;
+                self.display.scroll_down(rows as usize, self.planes);
             },
-            "2" => {
-                Some(0x2)
+            Instruction::ScrollRight => {
+                self.require(Extension::SuperChip, pc, instruction)?;
+                self.display.scroll_right(4, self.planes);
             },
-            "3" => {
-                Some(0x3)
+            Instruction::ScrollLeft => {
+                self.require(Extension::SuperChip, pc, instruction)?;
+                self.display.scroll_left(4, self.planes);
             },
-            "4" => {
-                Some(0xC)
+            Instruction::Exit => {
+                // Stay on this instruction for good, leaving the last frame up
+                self.require(Extension::SuperChip, pc, instruction)?;
+                self.main_memory.set_program_counter(pc)?;
             },
-            "Q" => {
-                Some(0x4)
+            Instruction::LowRes => {
+                self.require(Extension::SuperChip, pc, instruction)?;
+                self.display.set_hires(false);
             },
-            "W" => {
-                Some(0x5)
+            Instruction::HiRes => {
+                self.require(Extension::SuperChip, pc, instruction)?;
+                self.display.set_hires(true);
             },
-            "E" => {
-                Some(0x6)
+            Instruction::LoadLargeSprite(register) => {
+                self.require(Extension::SuperChip, pc, instruction)?;
+                // Fonts without big digits fall back to the small ones
+                let digit = self.registers.read_data_register(register)?;
+                self.registers.i_register = self.main_memory.big_digit_address(digit)
+                                                            .unwrap_or_else(|| self.main_memory.digit_address(digit));
             },
-            "R" => {
-                Some(0xD)
-            },
-            "A" => {
-                Some(0x7)
+            Instruction::StoreFlags(high_register) => {
+                self.require(Extension::SuperChip, pc, instruction)?;
+                for register in 0..(high_register + 1) {
+                    self.rpl_flags[register as usize] = self.registers.read_data_register(register)?;
+                }
             },
-            "S" => {
-                Some(0x8)
+            Instruction::ReadFlags(high_register) => {
+                self.require(Extension::SuperChip, pc, instruction)?;
+                for register in 0..(high_register + 1) {
+                    self.registers.write_data_register(register, self.rpl_flags[register as usize])?;
+                }
             },
-            "D" => {
-                Some(0x9)
+            Instruction::ScrollUp(rows) => {
+                self.require(Extension::XoChip, pc, instruction)?;
+                self.display.scroll_up(rows as usize, self.planes);
             },
-            "F" => {
-                Some(0xE)
+            Instruction::SaveRange(register_1, register_2) => {
+                self.require(Extension::XoChip, pc, instruction)?;
+                let base = self.registers.i_register;
+                for (offset, register) in Chip8::register_range(register_1, register_2).into_iter().enumerate() {
+                    self.main_memory.write_address(base.wrapping_add(offset as u16),
+                                                   self.registers.read_data_register(register)?)?;
+                }
+                self.invalidate_compiled(base, register_1.abs_diff(register_2) as u16 + 1);
             },
-            "Z" => {
-                Some(0xA)
+            Instruction::LoadRange(register_1, register_2) => {
+                self.require(Extension::XoChip, pc, instruction)?;
+                let base = self.registers.i_register;
+                for (offset, register) in Chip8::register_range(register_1, register_2).into_iter().enumerate() {
+                    let data = self.main_memory.load_address(base.wrapping_add(offset as u16))?;
+                    self.registers.write_data_register(register, data)?;
+                }
             },
-            "X" => {
-                Some(0x0)
+            Instruction::LongSetI => {
+                self.require(Extension::XoChip, pc, instruction)?;
+                self.registers.i_register = self.main_memory.fetch_opcode()?;
             },
-            "C" => {
-                Some(0xB)
+            Instruction::SelectPlanes(planes) => {
+                self.require(Extension::XoChip, pc, instruction)?;
+                self.planes = planes & 0x3;
             },
-            "V" => {
-                Some(0xF)
+            Instruction::NOP(_) => {},
+            Instruction::UNKNOWN(opcode) => {
+                return Err(match scan::extension_pattern(opcode) {
+                    Some((extension, _)) if !self.variant.supports(extension) => {
+                        Chip8Error::UnsupportedInstruction { address: pc, opcode, variant: self.variant, extension }
+                    },
+                    _ => Chip8Error::UnknownInstruction { address: pc, opcode },
+                });
             },
-            _ => { None }
         }
+        Ok(())
+    }
+
+    // Skips step over all four bytes of XO-CHIP's F000 NNNN
+    fn skip_instruction(&mut self) {
+        let next = self.main_memory.peek_program_counter() as u16;
+        self.main_memory.skip_instruction();
+        if self.variant.supports(Extension::XoChip) && self.main_memory.peek_opcode(next) == Some(0xF000) {
+            self.main_memory.skip_instruction();
+        }
+    }
+
+    // 5XY2 and 5XY3 go from X to Y, backwards if Y is lower
+    fn register_range(from: u8, to: u8) -> Vec<u8> {
+        if from <= to { (from..=to).collect() } else { (to..=from).rev().collect() }
+    }
+
+    // Extension instructions are an error on variants without the extension
+    fn require(&self, extension: Extension, pc: u16, instruction: Instruction) -> Result<(), Chip8Error> {
+        if self.variant.supports(extension) {
+            Ok(())
+        } else {
+            Err(Chip8Error::UnsupportedInstruction { address: pc, opcode: instruction.encode(), variant: self.variant, extension })
+        }
+    }
+
+    // Whether EX9E/EXA1 see `key` as pressed, using up its edge if edge-triggered
+    fn check_key(&mut self, key: u8) -> bool {
+        if self.edge_triggered_keys {
+            std::mem::replace(&mut self.key_edges[key as usize], false)
+        } else {
+            self.key_pressed[key as usize]
+        }
+    }
+
+    fn invalidate_compiled(&mut self, address: u16, length: u16) {
+        if let Some(dynarec) = self.dynarec.as_mut() {
+            dynarec.invalidate(address, length);
+        }
+    }
+
+    fn shift_source(&self, register: u8, source: u8) -> u8 {
+        if self.quirks.shift_uses_vy { source } else { register }
+    }
+
+    fn reset_vf_after_logic(&mut self) -> Result<(), Chip8Error> {
+        if self.quirks.logic_resets_vf {
+            self.registers.write_data_register(0xF, 0)?;
+        }
+        Ok(())
+    }
+
+    fn increment_i_after_load_store(&mut self, high_register: u8) {
+        if self.quirks.load_store_increments_i {
+            self.registers.i_register = self.registers.i_register.wrapping_add(high_register as u16 + 1);
+        }
+    }
+
+    /// Read-only views of the core's state for debuggers, overlays and
+    /// tests. Nothing outside the core writes through these; changing the
+    /// machine goes through `set_key`, `restore` and friends.
+    pub fn registers(&self) -> &Registers {
+        &self.registers
+    }
+
+    pub fn stack(&self) -> &Stack {
+        &self.stack
+    }
+
+    pub fn history(&self) -> Option<&History> {
+        self.history.as_ref()
+    }
+
+    pub fn memory(&self) -> &MainMemory {
+        &self.main_memory
+    }
+
+    pub fn program_counter(&self) -> u16 {
+        self.main_memory.peek_program_counter() as u16
+    }
+
+    /// Run one 60hz frame of emulated time, as `run_frames(1)` does,
+    /// returning whether the display changed during it. A frontend that
+    /// drives the machine a frame at a time only needs to redraw when it
+    /// did; the timers tick every frame either way.
+    pub fn step_frame(&mut self) -> Result<bool, Chip8Error> {
+        self.run_frames(1)?;
+        Ok(self.display.take_changed())
+    }
+
+    pub fn clock_speed(&self) -> f64 {
+        self.scheduler.clock_speed()
+    }
+
+    /// Run the CPU at `clock_speed_hz` from now on. The timers and frames
+    /// keep to 60hz.
+    pub fn set_clock_speed(&mut self, clock_speed_hz: f64) {
+        self.scheduler.set_clock_speed(clock_speed_hz);
+    }
+
+    /// How much more time `try_cycle` needs before the next frame ends, for
+    /// frontends to sleep until then rather than spin.
+    pub fn until_next_frame(&self) -> Duration {
+        self.scheduler.until_vblank()
+    }
+
+    pub fn waiting_on_key(&self) -> bool {
+        self.waiting_on_key != -1
+    }
+
+    /// Which of the 16 keys are held down, indexed by key.
+    pub fn keys_pressed(&self) -> &[bool] {
+        &self.key_pressed
+    }
+
+    pub fn sound_active(&self) -> bool {
+        self.registers.sound_timer > 0
+    }
+
+    /// Press or release a hex pad key directly. Scripts do; frontends
+    /// usually feed an `InputSource` instead.
+    pub fn set_key(&mut self, code: u8, is_pressed: bool) {
+        if self.waiting_on_key != -1 && is_pressed && !self.key_pressed[code as usize] {
+            self.presses_while_waiting |= 1 << code;
+        }
+        self.key_pressed[code as usize] = is_pressed;
+    }
+
+    /// FX0A is satisfied by a key going down while it waits; a key already
+    /// held when the wait began doesn't count. Presses are collected
+    /// between instructions and settled here, before the next one, so keys
+    /// that go down together always resolve to the lowest key code however
+    /// the frontend happened to order their events. A key tapped and
+    /// released between two instructions still counts.
+    fn finish_key_wait(&mut self) -> Result<(), Chip8Error> {
+        if self.waiting_on_key == -1 || self.presses_while_waiting == 0 {
+            return Ok(());
+        }
+        let key = self.presses_while_waiting.trailing_zeros() as u8;
+        self.registers.write_data_register(self.waiting_on_key as u8, key)?;
+        self.waiting_on_key = -1;
+        self.presses_while_waiting = 0;
+        Ok(())
+    }
+}
+
+/// A trace or hash file that can't be written to any more is dropped
+/// rather than taking the machine down with it. A broken pipe only means
+/// the reader has seen enough, as with a trace piped into `head`, so it
+/// goes unremarked.
+fn stopped_writing(what: &str, error: &io::Error) {
+    if error.kind() != io::ErrorKind::BrokenPipe {
+        warn!("Stopped writing {}: {}", what, error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::savestate::StateWriter;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn step_never_panics_on_random_programs() {
+        let mut rng = StdRng::seed_from_u64(0xC8);
+        for _ in 0..500 {
+            let program: Vec<u8> = (0..rng.gen_range(0, 512)).map(|_| rng.gen()).collect();
+            let mut machine = Chip8::new(program, 700.0);
+            for _ in 0..2000 {
+                let key = rng.gen_range(0, Chip8::NUM_KEYS) as usize;
+                machine.key_pressed[key] = !machine.key_pressed[key];
+                if machine.step().is_err() {
+                    break;
+                }
+            }
+        }
+    }
+
+    // LD V1, K then spin
+    fn waiting_machine() -> Chip8 {
+        let mut machine = Chip8::new(vec![0xF1, 0x0A, 0x12, 0x02], 700.0);
+        machine.step().unwrap();
+        assert!(machine.waiting_on_key());
+        machine
+    }
+
+    #[test]
+    fn breakpoints_pause_before_the_instruction() {
+        // LD V0, 1; ADD V0, 1; JP 0x202
+        let mut machine = Chip8::new(vec![0x60, 0x01, 0x70, 0x01, 0x12, 0x02], 700.0);
+        machine.add_breakpoint(0x204);
+        machine.try_cycle(Duration::from_millis(100)).unwrap();
+        assert_eq!(machine.breakpoint_hit(), Some(0x204));
+        assert_eq!(machine.program_counter(), 0x204);
+        assert_eq!(machine.registers().read_data_register(0).unwrap(), 2);
+
+        machine.resume();
+        machine.try_cycle(Duration::from_secs(0)).unwrap();
+        assert_eq!(machine.breakpoint_hit(), Some(0x204));
+        assert_eq!(machine.registers().read_data_register(0).unwrap(), 3);
+    }
+
+    #[test]
+    fn the_same_seed_draws_the_same_numbers() {
+        // RND V0, 0xFF; RND V1, 0xFF; RND V2, 0xFF
+        let run = |seed| {
+            let mut machine = Chip8::new(vec![0xC0, 0xFF, 0xC1, 0xFF, 0xC2, 0xFF], 700.0);
+            machine.set_seed(seed);
+            (0..3).for_each(|_| machine.step().unwrap());
+            (0..3).map(|register| machine.registers().read_data_register(register).unwrap()).collect::<Vec<u8>>()
+        };
+        assert_eq!(run(42), run(42));
+        assert_ne!(run(42), run(43));
+    }
+
+    #[test]
+    fn simultaneous_presses_resolve_to_the_lowest_key() {
+        let mut machine = waiting_machine();
+        machine.set_key(0xB, true);
+        machine.set_key(0x3, true);
+        machine.set_key(0x7, true);
+        machine.try_cycle(Duration::from_millis(5)).unwrap();
+        assert!(!machine.waiting_on_key());
+        assert_eq!(machine.registers().read_data_register(1).unwrap(), 0x3);
+    }
+
+    #[test]
+    fn key_held_before_the_wait_does_not_count() {
+        let mut machine = Chip8::new(vec![0xF1, 0x0A, 0x12, 0x02], 700.0);
+        machine.set_key(0x2, true);
+        machine.step().unwrap();
+        machine.set_key(0x2, true);
+        machine.try_cycle(Duration::from_millis(5)).unwrap();
+        assert!(machine.waiting_on_key());
+
+        machine.set_key(0x9, true);
+        machine.try_cycle(Duration::from_millis(5)).unwrap();
+        assert!(!machine.waiting_on_key());
+        assert_eq!(machine.registers().read_data_register(1).unwrap(), 0x9);
+    }
+
+    #[test]
+    fn tap_between_instructions_is_not_lost() {
+        let mut machine = waiting_machine();
+        machine.set_key(0xE, true);
+        machine.set_key(0xE, false);
+        machine.try_cycle(Duration::from_millis(5)).unwrap();
+        assert!(!machine.waiting_on_key());
+        assert_eq!(machine.registers().read_data_register(1).unwrap(), 0xE);
+    }
+
+    #[test]
+    fn input_source_is_polled_every_frame() {
+        let mut machine = waiting_machine();
+        // A bot that presses A from frame 2 on
+        machine.set_input(Box::new(|frame: u64| if frame >= 2 { 1 << 0xA } else { 0 }));
+        machine.try_cycle(Scheduler::frame_duration()).unwrap();
+        assert!(machine.waiting_on_key());
+        machine.try_cycle(Scheduler::frame_duration()).unwrap();
+        machine.try_cycle(Scheduler::frame_duration()).unwrap();
+        assert!(!machine.waiting_on_key());
+        assert_eq!(machine.registers().read_data_register(1).unwrap(), 0xA);
+    }
+
+    #[test]
+    fn run_frames_keeps_instructions_and_timers_in_step() {
+        // LD V0, 0xFF; LD DT, V0; then spin
+        let mut machine = Chip8::new(vec![0x60, 0xFF, 0xF0, 0x15, 0x12, 0x04], 600.0);
+        assert_eq!(machine.run_frames(60).unwrap(), 60);
+        assert_eq!(machine.frames_elapsed(), 60);
+        assert_eq!(machine.instructions_executed(), 600);
+        assert_eq!(machine.registers().delay_timer, 0xFF - 60);
+    }
+
+    #[test]
+    fn state_reflects_an_executed_instruction() {
+        // LD V3, 0x2A then CALL 0x206
+        let mut machine = Chip8::new(vec![0x63, 0x2A, 0x22, 0x06, 0x00, 0x00, 0x00, 0xE0], 700.0);
+        let before = machine.state(false);
+        machine.step().unwrap();
+        machine.step().unwrap();
+        machine.set_key(0x5, true);
+
+        let mut v = before.v;
+        v[3] = 0x2A;
+        assert_eq!(machine.state(false), MachineState {
+            instructions: 2,
+            v,
+            pc: 0x206,
+            stack: vec![0x204],
+            keys: 1 << 5,
+            ..before
+        });
+        assert_eq!(machine.state(true).memory.unwrap()[0x200..0x202], [0x63, 0x2A]);
+    }
+
+    #[test]
+    fn hooks_see_core_events_in_order() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        // CALL 0x204, then LD V0, 0x02; LD ST, V0; LD V1, K
+        let mut machine = Chip8::new(vec![0x22, 0x04, 0x00, 0x00, 0x60, 0x02, 0xF0, 0x18, 0xF1, 0x0A], 700.0);
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let seen = events.clone();
+        machine.add_hook(Box::new(move |event| if !matches!(event, CoreEvent::InstructionExecuted { .. }) {
+            seen.borrow_mut().push(*event);
+        }));
+        machine.run_frames(3).unwrap();
+
+        assert_eq!(*events.borrow(), vec![
+            CoreEvent::SubroutineCalled { address: 0x204, return_address: 0x202 },
+            CoreEvent::SoundStarted,
+            CoreEvent::KeyWaitEntered { register: 1 },
+            CoreEvent::SoundStopped,
+        ]);
+    }
+
+    #[test]
+    fn the_clock_can_change_mid_run() {
+        // Jump in place
+        let mut machine = Chip8::new(vec![0x12, 0x00], 600.0);
+        machine.run_frames(6).unwrap();
+        assert_eq!(machine.instructions_executed(), 60);
+
+        machine.set_clock_speed(1200.0);
+        assert_eq!(machine.clock_speed(), 1200.0);
+        machine.run_frames(6).unwrap();
+        assert_eq!(machine.instructions_executed(), 60 + 120);
+        assert_eq!(machine.frames_elapsed(), 12);
+    }
+
+    #[test]
+    fn step_frame_reports_whether_the_display_changed() {
+        // CLS; LD F, V0; DRW V0, V0, 5; then jump in place
+        let mut machine = Chip8::new(vec![0x00, 0xE0, 0xF0, 0x29, 0xD0, 0x05, 0x12, 0x06], 700.0);
+        assert!(machine.display.take_changed());
+        assert_eq!(machine.step_frame(), Ok(true));
+        assert_eq!(machine.step_frame(), Ok(false));
+
+        // Clearing a blank screen changes nothing, but restoring a different one does
+        let drawn = machine.snapshot();
+        let mut machine = Chip8::new(vec![0x00, 0xE0, 0x12, 0x00], 700.0);
+        machine.display.take_changed();
+        assert_eq!(machine.step_frame(), Ok(false));
+        machine.restore(drawn);
+        assert!(machine.display.take_changed());
+    }
+
+    #[test]
+    fn coverage_counts_every_byte_of_an_odd_length_rom() {
+        // Jump in place, then a byte of data
+        let mut machine = Chip8::new(vec![0x12, 0x00, 0xAB], 700.0);
+        machine.enable_coverage();
+        machine.step().unwrap();
+        let coverage = machine.coverage().unwrap();
+        assert_eq!(coverage.executed_bytes(), (2, 3));
+        assert_eq!(coverage.unexecuted_ranges(), vec![(0x202, 0x202)]);
+    }
+
+    // Run `program` for `frames`, returning how it ended, its saved state and the instructions run compiled
+    fn run_with_dynarec(program: &[u8], frames: u64, dynarec: bool) -> (Result<u64, Chip8Error>, Vec<u8>, u64) {
+        let mut machine = Chip8::new(program.to_vec(), 700.0);
+        machine.set_seed(7);
+        if dynarec {
+            machine.enable_dynarec();
+        }
+        let result = machine.run_frames(frames);
+        let mut out = StateWriter::new();
+        machine.snapshot().save(&mut out);
+        (result, out.into_bytes(), machine.dynarec().map_or(0, Dynarec::instructions_compiled))
+    }
+
+    #[test]
+    fn the_dynarec_ends_up_where_the_interpreter_does() {
+        let program = [0x70, 0x01,  // V0 += 1
+                       0x81, 0x04,  // V1 += V0
+                       0x82, 0x16,  // V2 = V1 >> 1
+                       0xA3, 0x00,  // I = 0x300
+                       0xF1, 0x1E,  // I += V1
+                       0xC4, 0xFF,  // V4 = random
+                       0xF3, 0x15,  // delay timer = V3
+                       0x12, 0x00]; // jump back
+        let (interpreted, interpreted_state, _) = run_with_dynarec(&program, 60, false);
+        let (compiled, compiled_state, ran_compiled) = run_with_dynarec(&program, 60, true);
+        assert_eq!(interpreted, Ok(60));
+        assert_eq!(compiled, Ok(60));
+        assert!(ran_compiled > 0);
+        assert!(interpreted_state == compiled_state);
+    }
+
+    #[test]
+    fn a_block_that_faults_partway_counts_only_what_ran() {
+        let program = [0x73, 0x01,  // V3 += 1
+                       0xAF, 0x00,  // I = 0xF00
+                       0xF3, 0x1E,  // I += V3
+                       0xF1, 0x65,  // V0, V1 = [I], [I + 1], past the end of memory once V3 is 0xFF
+                       0x72, 0x01,  // V2 += 3, so the fault lands in a compiled block
+                       0x72, 0x01,
+                       0x72, 0x01,
+                       0x12, 0x00]; // jump back
+        let (interpreted, interpreted_state, _) = run_with_dynarec(&program, 300, false);
+        let (compiled, compiled_state, ran_compiled) = run_with_dynarec(&program, 300, true);
+        assert_eq!(interpreted, Err(Chip8Error::InvalidAddress(0x1000)));
+        assert_eq!(compiled, interpreted);
+        assert!(ran_compiled > 0);
+        assert!(interpreted_state == compiled_state);
+    }
+
+    #[test]
+    fn resetting_reloads_the_rom_and_keeps_the_settings() {
+        // V0 = 5, store it over the 0x00 at 0x20A, HIGH, then spin
+        let program = vec![0x60, 0x05, 0xA2, 0x0A, 0xF0, 0x55, 0x00, 0xFF, 0x12, 0x08, 0x00];
+        let mut machine = Chip8::new(program, 700.0);
+        machine.set_variant(Variant::SuperChip);
+        machine.set_quirks(Quirks::preset("schip").unwrap());
+        machine.set_font(&Font::builtin("default").unwrap(), 0x010).unwrap();
+        (0..5).for_each(|_| machine.step().unwrap());
+        assert_eq!(machine.memory().peek(0x20A), Some(0x05));
+        assert!(machine.display.hires());
+
+        machine.reset();
+        assert_eq!(machine.memory().peek(0x20A), Some(0x00));
+        assert_eq!((machine.program_counter(), machine.registers().read_data_register(0)), (0x200, Ok(0)));
+        assert_eq!((machine.instructions_executed(), machine.frames_elapsed()), (0, 0));
+        assert!(!machine.display.hires());
+        assert_eq!(machine.variant(), Variant::SuperChip);
+        assert_eq!(machine.snapshot().quirks, Quirks::preset("schip").unwrap());
+        assert_eq!(machine.memory().digit_address(1), 0x015);
+
+        // And it runs the same way again
+        (0..5).for_each(|_| machine.step().unwrap());
+        assert_eq!(machine.memory().peek(0x20A), Some(0x05));
+    }
+
+    #[test]
+    fn restoring_keeps_compiled_blocks_whose_code_is_unchanged() {
+        // V0 += 1, V1 += V0, I = 0x300, jump back
+        let program = vec![0x70, 0x01, 0x81, 0x04, 0xA3, 0x00, 0x12, 0x00];
+        let mut machine = Chip8::new(program.clone(), 700.0);
+        machine.enable_dynarec();
+        machine.run_frames(30).unwrap();
+        machine.run_ahead(1);
+        machine.run_ahead(1);
+        assert_eq!(machine.dynarec().unwrap().blocks_invalidated(), 0);
+
+        // The same, but V1 += V0 is now V1 += 2
+        let mut changed = program;
+        changed[2..4].copy_from_slice(&[0x71, 0x02]);
+        machine.restore(Chip8::new(changed, 700.0).snapshot());
+        assert!(machine.dynarec().unwrap().blocks_invalidated() > 0);
+    }
+
+    #[test]
+    fn variant_and_keys_can_be_read_back() {
+        let mut machine = Chip8::new(vec![0x12, 0x00], 700.0);
+        machine.set_variant(Variant::SuperChip);
+        machine.set_key(0xA, true);
+        assert_eq!(machine.variant(), Variant::SuperChip);
+        assert_eq!(machine.keys_pressed().iter().position(|&pressed| pressed), Some(0xA));
+        assert_eq!(machine.keys_pressed().len(), Chip8::NUM_KEYS as usize);
+    }
+
+    #[test]
+    fn history_is_only_kept_when_asked_for() {
+        // V0 = 5, then jump in place
+        let mut machine = Chip8::new(vec![0x60, 0x05, 0x12, 0x02], 700.0);
+        machine.step().unwrap();
+        assert!(machine.history().is_none());
+        machine.enable_history();
+        machine.step().unwrap();
+        assert_eq!(machine.history().unwrap().to_string().lines().count(), 2);
+    }
+
+    #[test]
+    fn run_ahead_shows_the_next_frame_and_leaves_the_machine_alone() {
+        // Draw the digits 0, 1, 2... one after another, clearing in between
+        let mut machine = Chip8::new(vec![0x60, 0x00, 0xF0, 0x29, 0x00, 0xE0, 0xD0, 0x05, 0x70, 0x01, 0x12, 0x02], 700.0);
+        machine.run_frames(2).unwrap();
+        let before = machine.state(true);
+        let stats = *machine.stats();
+
+        let ahead = machine.run_ahead(1);
+        assert_eq!(machine.state(true), before);
+        assert_eq!(*machine.stats(), stats);
+        assert_ne!(ahead.pixels(), machine.display.pixels());
+
+        machine.run_frames(1).unwrap();
+        assert_eq!(ahead.pixels(), machine.display.pixels());
+    }
+
+    #[test]
+    fn sprites_clip_at_the_edges_unless_they_wrap() {
+        // Two full rows at (62, 31), from the data after the draw
+        let program = vec![0x60, 0x3E, 0x61, 0x1F, 0xA2, 0x08, 0xD0, 0x12, 0xFF, 0xFF];
+        let lit = |wrap_sprites| {
+            let mut machine = Chip8::new(program.clone(), 700.0);
+            machine.set_quirks(Quirks { wrap_sprites, ..Quirks::default() });
+            (0..4).for_each(|_| machine.step().unwrap());
+            assert!(machine.display.get(62, 31) && machine.display.get(63, 31));
+            assert_eq!(machine.display.get(0, 0), wrap_sprites);
+            machine.display.pixels().iter().filter(|&&pixel| pixel != 0).count()
+        };
+        assert_eq!(lit(false), 2);
+        assert_eq!(lit(true), 16);
+    }
+
+    #[test]
+    fn super_chip_counts_rows_clipped_off_the_bottom_in_hi_res() {
+        // HIGH, draw a 16x16 sprite from 0x20C at (0, 56), then spin
+        let mut program = vec![0x00, 0xFF, 0x60, 0x00, 0x61, 0x38, 0xA2, 0x0C, 0xD0, 0x10, 0x12, 0x0A];
+        program.extend([0xFF; 32]);
+        let mut machine = Chip8::new(program, 700.0);
+        machine.set_variant(Variant::SuperChip);
+        machine.set_quirks(Quirks::preset("schip").unwrap());
+        (0..5).for_each(|_| machine.step().unwrap());
+        assert_eq!(machine.registers().read_data_register(0xF), Ok(8));
+        assert_eq!(machine.stats().collisions, 0);
+        assert!(machine.display.get(15, 63));
+
+        // Low-res only reports whether anything was erased
+        let mut machine = Chip8::new(vec![0x60, 0x00, 0x61, 0x1C, 0xA2, 0x0A, 0xD0, 0x18, 0x12, 0x08, 0xFF],
+                                     700.0);
+        machine.set_quirks(Quirks::preset("schip").unwrap());
+        (0..4).for_each(|_| machine.step().unwrap());
+        assert_eq!(machine.registers().read_data_register(0xF), Ok(0));
+    }
+
+    #[test]
+    fn super_chip_draws_and_scrolls_in_hi_res() {
+        // HIGH, draw the big 0 at (0, 0), SCD 2, SCR, then spin
+        let program = vec![0x00, 0xFF, 0x60, 0x00, 0xF0, 0x30, 0xD0, 0x0A, 0x00, 0xC2, 0x00, 0xFB, 0x12, 0x0C];
+        let mut machine = Chip8::new(program.clone(), 700.0);
+        assert!(matches!(machine.step(), Err(Chip8Error::UnsupportedInstruction { extension: Extension::SuperChip, .. })));
+
+        let mut machine = Chip8::new(program, 700.0);
+        machine.set_variant(Variant::SuperChip);
+        for _ in 0..6 {
+            machine.step().unwrap();
+        }
+        assert_eq!((machine.display.width(), machine.display.height()), (128, 64));
+        assert!(!machine.display.get(0, 0) && !machine.display.get(3, 2));
+        assert!(machine.display.get(4, 2) && machine.display.get(11, 2));
+        assert!(!machine.display.get(12, 2));
+    }
+
+    #[test]
+    fn xo_chip_draws_on_both_planes_and_skips_long_loads() {
+        let program = vec![0xF3, 0x01,                  // PLANE 3
+                           0xF0, 0x00, 0x02, 0x14,      // LD I, LONG 0x214
+                           0x60, 0x00,
+                           0xD0, 0x01,                  // One row on each plane
+                           0x30, 0x00,                  // Skip all of the next long load
+                           0xF0, 0x00, 0x00, 0x00,
+                           0x12, 0x10,
+                           0x00, 0x00,
+                           0xF0, 0x3C];
+        let mut machine = Chip8::new(program, 700.0);
+        machine.set_variant(Variant::XoChip);
+        for _ in 0..5 {
+            machine.step().unwrap();
+        }
+        assert_eq!(machine.program_counter(), 0x210);
+        assert_eq!(machine.registers().i_register, 0x214);
+        assert_eq!(machine.display.pixels()[..8], [1, 1, 3, 3, 2, 2, 0, 0]);
     }
 }
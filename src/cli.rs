@@ -0,0 +1,723 @@
+use std::fmt::Display;
+use std::str::FromStr;
+
+use clap::{App, AppSettings, Arg, SubCommand};
+
+use crate::bench;
+use crate::quirks::Quirks;
+use crate::test_pattern;
+use crate::variant::Variant;
+use crate::watch::Watch;
+
+/*
+    The command line interface. Each mode of operation is a subcommand with
+    its own flags, so new tools can grow options without clashing:
+
+    yac8 run ROM       run a program in a window
+    yac8 bench ROM N   run a program flat out and report how fast it went
+    yac8 test ROM S    run a program headless under a script of assertions
+    yac8 scan ROM      print raw opcodes and decoded instructions
+    yac8 scan DIR      write reports for every ROM in a directory
+    yac8 disasm ROM    print an address-annotated disassembly
+    yac8 compat ROM    check which variants a program runs under
+    yac8 lint ROM      check a program for likely mistakes
+    yac8 audio-devices list the audio output devices
+    yac8 vectors       write or check golden test vectors for the core
+*/
+pub fn app() -> App<'static, 'static> {
+    App::new("yac8")
+        .version("0.1.0")
+        .author("halfhorst")
+        .about("Yet another CHIP-8 emulator")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .arg(Arg::with_name("verbose")
+                .short("v")
+                .long("verbose")
+                .help("Verbose logging to the terminal.")
+                .takes_value(false)
+                .global(true)
+                .required(false))
+        .arg(Arg::with_name("rom_db")
+                .long("rom-db")
+                .value_name("DATABASE_FILE")
+                .help("A file of known ROM dumps to check ROMs against. Each line is `sha1<TAB>good|bad<TAB>name`.")
+                .takes_value(true)
+                .global(true)
+                .required(false))
+        .subcommand(SubCommand::with_name("run")
+                .about("Run a CHIP-8 program.")
+                .arg(program_file().required_unless("test_pattern"))
+                .arg(Arg::with_name("test_pattern")
+                        .long("test-pattern")
+                        .help("Run a built-in diagnostic instead of a ROM, for checking scaling, the palette, refresh and the key mapping on a new setup.")
+                        .value_name("PATTERN")
+                        .possible_values(&test_pattern::NAMES)
+                        .conflicts_with("program_file")
+                        .takes_value(true)
+                        .required(false))
+                .arg(clock_speed())
+                .arg(seed())
+                .arg(Arg::with_name("rotate")
+                        .long("rotate")
+                        .help("Rotate the display clockwise by the given degrees, for ROMs designed for portrait screens.")
+                        .value_name("DEGREES")
+                        .possible_values(&["0", "90", "180", "270"])
+                        .takes_value(true)
+                        .required(false))
+                .arg(Arg::with_name("scale")
+                        .long("scale")
+                        .help("The initial window size as a multiple of the display resolution, from 1 to 64. Defaults to 10.")
+                        .value_name("SCALE")
+                        .validator(in_range(1, 64))
+                        .takes_value(true)
+                        .required(false))
+                .arg(Arg::with_name("scale_mode")
+                        .long("scale-mode")
+                        .help("How the display fills the window: whole-pixel `integer` scaling with letterboxing (the default), aspect-preserving `fit`, or `stretch`.")
+                        .value_name("MODE")
+                        .possible_values(&["integer", "fit", "stretch"])
+                        .takes_value(true)
+                        .required(false))
+                .arg(Arg::with_name("theme")
+                        .long("theme")
+                        .help("Dress the window in a named theme: a palette plus the color of the border around the game and a thin frame. `classic` is plain black and white.")
+                        .value_name("THEME")
+                        .possible_values(&["classic", "slate", "amber", "green", "lcd"])
+                        .takes_value(true)
+                        .required(false))
+                .arg(Arg::with_name("palette")
+                        .long("palette")
+                        .help("Color the game with a named palette, keeping the border: `monochrome` white on black, `amber` or `green` phosphor, or `lcd`. Replaces a theme's palette.")
+                        .value_name("PALETTE")
+                        .possible_values(&["monochrome", "amber", "green", "lcd"])
+                        .takes_value(true)
+                        .required(false))
+                .arg(palette_color("bg", "bg", "The color of unlit pixels, as RRGGBB hex, over any palette or theme."))
+                .arg(palette_color("fg", "fg", "The color of lit pixels, as RRGGBB hex, over any palette or theme. On XO-CHIP, pixels lit on the first plane."))
+                .arg(palette_color("fg2", "fg2", "The color of XO-CHIP pixels lit on only the second plane, as RRGGBB hex."))
+                .arg(palette_color("fg_both", "fg-both", "The color of XO-CHIP pixels lit on both planes, as RRGGBB hex."))
+                .arg(Arg::with_name("keymap")
+                        .long("keymap")
+                        .value_name("KEYMAP")
+                        .help("Which keys press the hex pad: `qwerty` (the default), `arrows`, or a file of `KEY = \"SCANCODE\"` lines.")
+                        .takes_value(true)
+                        .required(false))
+                .arg(Arg::with_name("border")
+                        .long("border")
+                        .help("The color of the border around the game, as RRGGBB hex. Defaults to black, or the theme's border.")
+                        .value_name("COLOR")
+                        .validator(rgb_color)
+                        .takes_value(true)
+                        .required(false))
+                .arg(Arg::with_name("fullscreen")
+                        .short("f")
+                        .long("fullscreen")
+                        .help("Start fullscreen, either borderless `desktop` or `exclusive`. F11 toggles fullscreen at any time.")
+                        .value_name("MODE")
+                        .possible_values(&["desktop", "exclusive"])
+                        .takes_value(true)
+                        .required(false))
+                .arg(Arg::with_name("stats")
+                        .long("stats")
+                        .help("Print counters for the run when the VM exits: instructions, frames, draws, collisions, the deepest the stack got and calls to RND.")
+                        .takes_value(false)
+                        .required(false))
+                .arg(Arg::with_name("frame_stats")
+                        .long("frame-stats")
+                        .help("Print frame pacing statistics when the window closes: present intervals, jitter and time spent emulating and rendering.")
+                        .takes_value(false)
+                        .required(false))
+                .arg(Arg::with_name("always_on_top")
+                        .long("always-on-top")
+                        .help("Keep the window above other windows, say beside an editor while writing a ROM. The pause menu toggles it.")
+                        .takes_value(false)
+                        .required(false))
+                .arg(Arg::with_name("run_ahead")
+                        .long("run-ahead")
+                        .help("Show the display this many frames ahead of the machine, from 1 to 4, assuming the keys stay as they are, to hide the frames a game takes to react to a key.")
+                        .value_name("FRAMES")
+                        .validator(in_range(1, 4))
+                        .takes_value(true)
+                        .required(false))
+                .arg(Arg::with_name("minimized")
+                        .long("minimized")
+                        .help("While the window is minimized, either keep running without drawing and with little CPU (`throttle`, the default) or `pause` emulation.")
+                        .value_name("MODE")
+                        .possible_values(&["throttle", "pause"])
+                        .takes_value(true)
+                        .required(false))
+                .arg(Arg::with_name("escape")
+                        .long("escape")
+                        .help("What Escape does: open the pause menu (`menu`, the default), ask whether to quit (`confirm`) or `quit` straight away.")
+                        .value_name("ACTION")
+                        .possible_values(&["menu", "confirm", "quit"])
+                        .takes_value(true)
+                        .required(false))
+                .arg(Arg::with_name("debug")
+                        .long("debug")
+                        .help("Start paused with a debugger prompt on the terminal: breakpoints, single-stepping and the registers, timers and stack.")
+                        .takes_value(false)
+                        .required(false))
+                .arg(Arg::with_name("history")
+                        .long("history")
+                        .help("Keep the last 256 instructions and the registers each one changed, and print them if the program crashes. On with --debug and --web-debugger.")
+                        .takes_value(false)
+                        .required(false))
+                .arg(Arg::with_name("debug_window")
+                        .long("debug-window")
+                        .help("Open a second window showing registers, the stack, disassembly and memory. F12 toggles it at any time.")
+                        .takes_value(false)
+                        .required(false))
+                .arg(Arg::with_name("debug_panel")
+                        .long("debug-panel")
+                        .help("Widen the window so its right third shows the registers, timers and stack, updated every frame beside the game.")
+                        .takes_value(false)
+                        .required(false))
+                .arg(Arg::with_name("load_state")
+                        .long("load-state")
+                        .value_name("STATE_FILE")
+                        .help("Start from a state saved with --save-state for the same ROM.")
+                        .takes_value(true)
+                        .required(false))
+                .arg(Arg::with_name("save_state")
+                        .long("save-state")
+                        .value_name("STATE_FILE")
+                        .help("Save the machine's state to a file when the VM exits.")
+                        .takes_value(true)
+                        .required(false))
+                .arg(Arg::with_name("web_debugger")
+                        .long("web-debugger")
+                        .value_name("PORT")
+                        .help("Serve a debugger at http://localhost:PORT/ with the display, registers, disassembly and pause/step buttons.")
+                        .validator(in_range(1, 65535))
+                        .takes_value(true)
+                        .required(false))
+                .arg(Arg::with_name("mirror")
+                        .long("mirror")
+                        .help("Open a second, overlay-free window mirroring the display at the given scale (1 to 64), for screen capture.")
+                        .value_name("SCALE")
+                        .validator(in_range(1, 64))
+                        .takes_value(true)
+                        .required(false))
+                .arg(Arg::with_name("volume")
+                        .long("volume")
+                        .help("The buzzer volume as a percentage, from 0 to 100. Defaults to 25.")
+                        .value_name("PERCENT")
+                        .validator(in_range(0, 100))
+                        .takes_value(true)
+                        .required(false))
+                .arg(Arg::with_name("min_beep")
+                        .long("min-beep")
+                        .help("The shortest time in ms the buzzer sounds for, however short the sound timer, from 0 to 1000. Defaults to 33ms.")
+                        .value_name("MS")
+                        .validator(in_range(0, 1000))
+                        .takes_value(true)
+                        .required(false))
+                .arg(Arg::with_name("audio_rate")
+                        .long("audio-rate")
+                        .help("The audio sample rate to request from SDL in hz, from 8000 to 192000. Defaults to 44100hz.")
+                        .value_name("HZ")
+                        .validator(in_range(8_000, 192_000))
+                        .takes_value(true)
+                        .required(false))
+                .arg(Arg::with_name("audio_buffer")
+                        .long("audio-buffer")
+                        .help("The audio buffer size to request from SDL in samples, a power of two from 64 to 16384. Smaller is lower latency, larger avoids crackling. Defaults to 1024.")
+                        .value_name("SAMPLES")
+                        .validator(power_of_two(64, 16_384))
+                        .takes_value(true)
+                        .required(false))
+                .arg(Arg::with_name("audio_device")
+                        .long("audio-device")
+                        .help("The audio output device to play the buzzer on, by name as `yac8 audio-devices` lists them. Defaults to the system's default device.")
+                        .value_name("NAME")
+                        .takes_value(true)
+                        .required(false))
+                .arg(Arg::with_name("record_audio")
+                        .long("record-audio")
+                        .help("Record everything the buzzer plays, silence included, to a WAV file.")
+                        .value_name("WAV_FILE")
+                        .takes_value(true)
+                        .required(false))
+                .arg(variant())
+                .arg(Arg::with_name("font")
+                        .long("font")
+                        .value_name("FONT")
+                        .help("The hex digit font: `default`, `vip`, `dream6800`, `rounded`, or a font file of 80 bytes (240 with SUPER-CHIP's big digits).")
+                        .takes_value(true)
+                        .required(false))
+                .arg(Arg::with_name("font_address")
+                        .long("font-address")
+                        .value_name("ADDRESS")
+                        .help("Where in memory to put the font, in hex below 0x200. Defaults to 0x050, like most modern interpreters.")
+                        .validator(hex_address(0x1FF))
+                        .takes_value(true)
+                        .required(false))
+                .arg(quirks())
+                .arg(quirk())
+                .arg(Arg::with_name("display_wait")
+                        .long("display-wait")
+                        .help("Stall the CPU after each draw until the next 60hz display interrupt, like the COSMAC VIP.")
+                        .takes_value(false)
+                        .required(false))
+                .arg(Arg::with_name("edge_triggered_keys")
+                        .long("edge-triggered-keys")
+                        .help("Key skip instructions (EX9E/EXA1) see each key press once, rather than for as long as the key is held.")
+                        .takes_value(false)
+                        .required(false))
+                .arg(Arg::with_name("strict_memory")
+                        .long("strict-memory")
+                        .help("Warn, with the address of the instruction, whenever the program reads or runs memory that neither the ROM nor the program itself has written.")
+                        .takes_value(false)
+                        .required(false))
+                .arg(Arg::with_name("strict")
+                        .long("strict")
+                        .help("Everything --strict-memory does, plus a report on exit of suspicious things the program did: clobbering a value in VF with a flag, storing into code, pointing I past memory, nearly overflowing the stack.")
+                        .takes_value(false)
+                        .required(false))
+                .arg(dynarec())
+                .arg(Arg::with_name("trace")
+                        .short("t")
+                        .long("trace")
+                        .value_name("FORMAT")
+                        .help("Trace every executed instruction, one `text` or `json` line each.")
+                        .possible_values(&["text", "json"])
+                        .takes_value(true)
+                        .required(false))
+                .arg(Arg::with_name("teach")
+                        .long("teach")
+                        .help("Step through the program slowly for learning CHIP-8, printing each instruction with a plain-English explanation and the registers it changed. Runs at 2hz unless --clock says otherwise.")
+                        .takes_value(false)
+                        .conflicts_with("trace")
+                        .required(false))
+                .arg(Arg::with_name("state_hashes")
+                        .long("state-hashes")
+                        .value_name("FILE")
+                        .help("Write a SHA-1 of the whole machine at every frame to a file, one `frame hash` line each, so two runs can be diffed to prove they behaved identically.")
+                        .takes_value(true)
+                        .required(false))
+                .arg(Arg::with_name("trace_file")
+                        .long("trace-file")
+                        .value_name("TRACE_FILE")
+                        .help("Write the instruction trace to a file instead of stdout.")
+                        .takes_value(true)
+                        .requires("trace")
+                        .required(false))
+                .arg(Arg::with_name("script")
+                        .long("script")
+                        .value_name("SCRIPT_FILE")
+                        .help("Drive the program with a script of timed key presses, screenshots and assertions, e.g. `at frame 60: press 5 for 3 frames`. A failed assertion exits with status 1.")
+                        .takes_value(true)
+                        .required(false))
+                .arg(Arg::with_name("watch")
+                        .long("watch")
+                        .value_name("EXPRESSION")
+                        .help("Pause when this expression becomes true, checked after every instruction, e.g. `V3 + V4 > 10` or `[0x400] != 0`. F5 resumes; headless runs only report it. May be repeated.")
+                        .validator(|expression| Watch::parse(&expression).map(|_| ()))
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .required(false))
+                .arg(Arg::with_name("max_instructions")
+                        .long("max-instructions")
+                        .help("Stop after executing this many instructions, exiting with status 0.")
+                        .value_name("COUNT")
+                        .validator(in_range(1, u64::MAX))
+                        .takes_value(true)
+                        .required(false))
+                .arg(Arg::with_name("max_frames")
+                        .long("max-frames")
+                        .help("Stop after this many 60hz frames of emulated time, exiting with status 0.")
+                        .value_name("COUNT")
+                        .validator(in_range(1, u64::MAX))
+                        .takes_value(true)
+                        .required(false))
+                .arg(Arg::with_name("timeout")
+                        .long("timeout")
+                        .help("Stop after this many seconds of wall time, exiting with status 124.")
+                        .value_name("SECONDS")
+                        .validator(in_range(0.0, 31_536_000.0))
+                        .takes_value(true)
+                        .required(false))
+                .arg(Arg::with_name("watchdog")
+                        .long("watchdog")
+                        .help("Abort with status 3 if the program counter and display haven't changed for this many seconds of emulated time, e.g. after a crash into a tight loop.")
+                        .value_name("SECONDS")
+                        .validator(in_range(0.1, 86_400.0))
+                        .takes_value(true)
+                        .required(false))
+                .arg(Arg::with_name("dump_screen")
+                        .long("dump-screen")
+                        .value_name("FILE")
+                        .help("Save the display when the VM exits, as a PNG or PBM image for `.png` and `.pbm` files or ASCII art otherwise. F2 prints the display as ASCII art at any time.")
+                        .takes_value(true)
+                        .required(false))
+                .arg(Arg::with_name("record_gif")
+                        .long("record-gif")
+                        .value_name("FILE")
+                        .help("Record every frame of the run to an animated GIF, written when the VM exits. F4 starts and stops a recording at any time.")
+                        .takes_value(true)
+                        .required(false))
+                .arg(Arg::with_name("capture_scale")
+                        .long("capture-scale")
+                        .value_name("SCALE")
+                        .help("How many times to enlarge F3 screenshots and GIF recordings, from 1 to 16. Defaults to 4.")
+                        .validator(in_range(1, 16))
+                        .takes_value(true)
+                        .required(false))
+                .arg(Arg::with_name("tui")
+                        .long("tui")
+                        .help("Draw the display in the terminal with block characters and read keys from it, instead of opening a window. Works over SSH. Ctrl-C quits.")
+                        .conflicts_with_all(&["headless", "debug"])
+                        .takes_value(false)
+                        .required(false))
+                .arg(Arg::with_name("headless")
+                        .long("headless")
+                        .help("Run without a window or audio, as fast as possible in emulated time. Stops after the last scheduled screenshot, state dump or script action, or on a limit, and needs one of them unless a debugger is attached.")
+                        .takes_value(false)
+                        .required(false))
+                .arg(Arg::with_name("screenshot_at")
+                        .long("screenshot-at")
+                        .value_name("FRAME")
+                        .help("Save the display once this many frames have elapsed, to the matching --out file. May be repeated.")
+                        .validator(in_range(0, u64::MAX))
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .requires("out")
+                        .required(false))
+                .arg(Arg::with_name("out")
+                        .long("out")
+                        .value_name("FILE")
+                        .help("Where to save a --screenshot-at capture, in the same formats as --dump-screen. Given once per --screenshot-at, in the same order.")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .requires("screenshot_at")
+                        .required(false))
+                .arg(Arg::with_name("dump_at")
+                        .long("dump-at")
+                        .value_name("INSTRUCTION")
+                        .help("Write a text dump of the machine state once this many instructions have executed, stopping at exactly that point. May be repeated.")
+                        .validator(in_range(0, u64::MAX))
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .required(false))
+                .arg(Arg::with_name("dump_dir")
+                        .long("dump-dir")
+                        .value_name("DIR")
+                        .help("Where --dump-at writes its `state-<INSTRUCTION>.txt` files. Defaults to the current directory.")
+                        .takes_value(true)
+                        .requires("dump_at")
+                        .required(false))
+                .arg(Arg::with_name("coverage")
+                        .long("coverage")
+                        .help("Print an instruction coverage report when the VM exits.")
+                        .takes_value(false)
+                        .required(false))
+                .arg(Arg::with_name("coverage_file")
+                        .long("coverage-file")
+                        .value_name("COVERAGE_FILE")
+                        .help("Write the coverage report to a file instead of stdout.")
+                        .takes_value(true)
+                        .requires("coverage")
+                        .required(false))
+                .arg(Arg::with_name("report")
+                        .long("report")
+                        .value_name("FILE")
+                        .help("Write a report of the run when the VM exits: the ROM, settings, counters, instructions executed, coverage and the final screen. HTML for `.html`, Markdown otherwise. Turns off --dynarec, as coverage does.")
+                        .takes_value(true)
+                        .required(false)))
+        .subcommand(SubCommand::with_name("bench")
+                .about("Run a program without a window as fast as the host allows, then report instructions per second and the time spent in fetch, decode and execute. A program that waits for a key ends the run early.")
+                .arg(program_file())
+                .arg(Arg::with_name("bench")
+                        .value_name("LENGTH")
+                        .help("How long to run: a number of instructions, or of seconds with an `s` after it, e.g. `5000000` or `10s`.")
+                        .validator(|length| bench::Length::parse(&length).map(|_| ()))
+                        .takes_value(true)
+                        .required(true))
+                .arg(clock_speed())
+                .arg(seed())
+                .arg(variant())
+                .arg(quirks())
+                .arg(quirk())
+                .arg(dynarec()))
+        .subcommand(SubCommand::with_name("test")
+                .about("Run a program headless under a script of timed key presses, screenshots and assertions, stopping after the last action. Exits with status 1 if an assertion fails, 2 if the program crashes.")
+                .arg(program_file())
+                .arg(Arg::with_name("script")
+                        .value_name("SCRIPT_FILE")
+                        .help("The script to drive the program with, as for `run --script`.")
+                        .takes_value(true)
+                        .required(true))
+                .arg(clock_speed())
+                .arg(seed())
+                .arg(variant())
+                .arg(quirks())
+                .arg(quirk())
+                .arg(Arg::with_name("timeout")
+                        .long("timeout")
+                        .help("Stop after this many seconds of wall time, exiting with status 124.")
+                        .value_name("SECONDS")
+                        .validator(in_range(0.0, 31_536_000.0))
+                        .takes_value(true)
+                        .required(false)))
+        .subcommand(SubCommand::with_name("scan")
+                .about("Scan a program without running it, printing its disassembly, with unreachable bytes as data, and what kind of ROM it is. Given a directory, scans every ROM in it into report files.")
+                .arg(Arg::with_name("program_file")
+                        .value_name("PROGRAM_FILE")
+                        .help("A CHIP-8 ROM filepath, or a directory of ROMs.")
+                        .takes_value(true)
+                        .required(true))
+                .arg(Arg::with_name("out_dir")
+                        .long("out-dir")
+                        .value_name("DIR")
+                        .help("Where a directory scan writes its per-ROM reports and index. Defaults to `yac8-scan` inside the scanned directory.")
+                        .takes_value(true)
+                        .required(false))
+                .arg(explain()))
+        .subcommand(SubCommand::with_name("disasm")
+                .about("Disassemble the code reachable from 0x200 into an address-annotated listing, with everything else as data.")
+                .arg(program_file())
+                .arg(Arg::with_name("dot")
+                        .long("dot")
+                        .help("Print the control-flow graph of the reachable code as Graphviz DOT instead.")
+                        .takes_value(false)
+                        .conflicts_with("html")
+                        .required(false))
+                .arg(Arg::with_name("html")
+                        .long("html")
+                        .help("Print a standalone HTML page instead, with linked jump targets and sprite previews.")
+                        .takes_value(false)
+                        .required(false))
+                .arg(Arg::with_name("out")
+                        .long("out")
+                        .value_name("FILE")
+                        .help("Write the disassembly to FILE instead of printing it.")
+                        .takes_value(true)
+                        .required(false))
+                .arg(explain()))
+        .subcommand(SubCommand::with_name("asm")
+                .about("Assemble a source file of CHIP-8 mnemonics, labels and data into a ROM. Disassembly listings assemble back into their ROM.")
+                .arg(Arg::with_name("source_file")
+                        .value_name("SOURCE_FILE")
+                        .help("The assembly source filepath.")
+                        .takes_value(true)
+                        .required(true))
+                .arg(Arg::with_name("out")
+                        .long("out")
+                        .value_name("FILE")
+                        .help("Where to write the ROM. Defaults to the source filepath with a `.ch8` extension.")
+                        .takes_value(true)
+                        .required(false)))
+        .subcommand(SubCommand::with_name("lint")
+                .about("Check a program for likely mistakes without running it. Exits with status 1 if there are errors.")
+                .arg(program_file())
+                .arg(Arg::with_name("variant")
+                        .long("variant")
+                        .value_name("VARIANT")
+                        .help("The interpreter the program is written for, which decides the opcodes it may use. Defaults to a bundle's platform, or plain CHIP-8.")
+                        .possible_values(&Variant::NAMES)
+                        .takes_value(true)
+                        .required(false))
+                .arg(Arg::with_name("format")
+                        .long("format")
+                        .value_name("FORMAT")
+                        .help("Print each finding as a `text` line, the default, or a `json` line.")
+                        .possible_values(&["text", "json"])
+                        .takes_value(true)
+                        .required(false)))
+        .subcommand(SubCommand::with_name("compat")
+                .about("Run a program headless as each variant and report which ones it appears to work under.")
+                .arg(program_file())
+                .arg(clock_speed())
+                .arg(Arg::with_name("frames")
+                        .long("frames")
+                        .help("How many 60hz frames to run each preset for. Defaults to 600, ten seconds.")
+                        .value_name("FRAMES")
+                        .validator(in_range(1, 1_000_000))
+                        .takes_value(true)
+                        .required(false)))
+        .subcommand(SubCommand::with_name("audio-devices")
+                .about("List the audio output devices that `run --audio-device` can pick from."))
+        .subcommand(SubCommand::with_name("vectors")
+                .about("Print JSON Lines test vectors recording what every instruction does to random machine states, or check a file of them against this build. Exits with status 1 if any changed.")
+                .arg(Arg::with_name("check")
+                        .long("check")
+                        .value_name("VECTOR_FILE")
+                        .help("Check vectors written earlier instead of printing new ones.")
+                        .takes_value(true)
+                        .required(false))
+                .arg(Arg::with_name("variant")
+                        .long("variant")
+                        .value_name("VARIANT")
+                        .help("Record the behaviour of this variant's quirks instead of yac8's defaults.")
+                        .possible_values(&Variant::NAMES)
+                        .takes_value(true)
+                        .conflicts_with("check")
+                        .required(false))
+                .arg(Arg::with_name("count")
+                        .long("count")
+                        .value_name("COUNT")
+                        .help("How many vectors to write per opcode pattern. Defaults to 8.")
+                        .validator(in_range(1, 10_000))
+                        .takes_value(true)
+                        .conflicts_with("check")
+                        .required(false))
+                .arg(Arg::with_name("seed")
+                        .long("seed")
+                        .value_name("SEED")
+                        .help("The seed the random states are drawn from. Defaults to 0.")
+                        .validator(in_range(0, u64::MAX))
+                        .takes_value(true)
+                        .conflicts_with("check")
+                        .required(false)))
+}
+
+fn program_file() -> Arg<'static, 'static> {
+    Arg::with_name("program_file")
+        .value_name("PROGRAM_FILE")
+        .help("A CHIP-8 ROM filepath, or a `.c8b` bundle.")
+        .takes_value(true)
+        .required(true)
+}
+
+fn clock_speed() -> Arg<'static, 'static> {
+    Arg::with_name("clock_speed")
+        .short("c")
+        .long("clock")
+        .help("The clock speed to run the CPU at in hz, from 1 to 1000000. Defaults to 700hz.")
+        .value_name("HZ")
+        .validator(in_range(1.0, 1_000_000.0))
+        .takes_value(true)
+        .required(false)
+}
+
+fn seed() -> Arg<'static, 'static> {
+    Arg::with_name("seed")
+        .long("seed")
+        .value_name("SEED")
+        .help("Seed the random number generator, so every run of the program draws the same random numbers.")
+        .validator(in_range(0, u64::MAX))
+        .takes_value(true)
+        .required(false)
+}
+
+fn variant() -> Arg<'static, 'static> {
+    Arg::with_name("variant")
+        .long("variant")
+        .value_name("VARIANT")
+        .help("The interpreter the program was written for, which decides the extension opcodes it may use and how ambiguous instructions behave. Without it, plain CHIP-8 with yac8's own behaviour.")
+        .possible_values(&Variant::NAMES)
+        .takes_value(true)
+        .required(false)
+}
+
+fn quirks() -> Arg<'static, 'static> {
+    Arg::with_name("quirks")
+        .long("quirks")
+        .value_name("PRESET")
+        .help("Make the ambiguous instructions behave like this interpreter's, without changing which opcodes are allowed. Overrides the variant's and a bundle's quirks.")
+        .possible_values(&Quirks::PRESETS)
+        .takes_value(true)
+        .required(false)
+}
+
+fn quirk() -> Arg<'static, 'static> {
+    Arg::with_name("quirk")
+        .long("quirk")
+        .value_name("NAME[=VALUE]")
+        .help("Change one quirk on top of the preset: `shift-vy`, `load-store-i`, `jump-vx`, `logic-vf`, `display-wait`, `collision-rows` or `wrap-sprites` with `=on` (the default) or `=off`, or `big-sprite=nothing|tall|large`. May be repeated.")
+        .validator(|setting| Quirks::default().apply(&setting))
+        .takes_value(true)
+        .multiple(true)
+        .number_of_values(1)
+        .required(false)
+}
+
+fn dynarec() -> Arg<'static, 'static> {
+    Arg::with_name("dynarec")
+        .long("dynarec")
+        .help("Experimental: compile hot straight-line code into threaded code instead of interpreting it, and report what it did on exit.")
+        .takes_value(false)
+        .required(false)
+}
+
+fn explain() -> Arg<'static, 'static> {
+    Arg::with_name("explain")
+        .long("explain")
+        .help("Follow each instruction with a short plain-English description of what it does.")
+        .takes_value(false)
+        .required(false)
+}
+
+/*
+    Validators for numeric arguments, so bad values are rejected by clap
+    with a usage message rather than panicking later.
+*/
+fn in_range<T>(min: T, max: T) -> impl Fn(String) -> Result<(), String>
+    where T: FromStr + PartialOrd + Display + 'static
+{
+    move |value| match value.parse::<T>() {
+        Ok(n) if n >= min && n <= max => Ok(()),
+        _ => Err(format!("expected a number from {} to {}, got `{}`", min, max, value)),
+    }
+}
+
+fn hex_address(max: u16) -> impl Fn(String) -> Result<(), String> {
+    move |value| match parse_hex_address(&value) {
+        Some(address) if address <= max => Ok(()),
+        _ => Err(format!("expected a hex address up to {:#05X}, got `{}`", max, value)),
+    }
+}
+
+// Hex digits, optionally after 0x or 0X
+pub fn parse_hex_address(value: &str) -> Option<u16> {
+    let digits = value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")).unwrap_or(value);
+    u16::from_str_radix(digits, 16).ok()
+}
+
+fn palette_color(name: &'static str, long: &'static str, help: &'static str) -> Arg<'static, 'static> {
+    Arg::with_name(name)
+        .long(long)
+        .help(help)
+        .value_name("COLOR")
+        .validator(rgb_color)
+        .takes_value(true)
+        .required(false)
+}
+
+fn rgb_color(value: String) -> Result<(), String> {
+    // The same check as interface::parse_rgb, which needs SDL
+    let hex = value.trim_start_matches('#');
+    if hex.len() == 6 && hex.chars().all(|digit| digit.is_ascii_hexdigit()) {
+        Ok(())
+    } else {
+        Err(format!("expected a color as RRGGBB hex, got `{}`", value))
+    }
+}
+
+fn power_of_two(min: u32, max: u32) -> impl Fn(String) -> Result<(), String> {
+    move |value| match value.parse::<u32>() {
+        Ok(n) if n >= min && n <= max && n.is_power_of_two() => Ok(()),
+        _ => Err(format!("expected a power of two from {} to {}, got `{}`", min, max, value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_addresses_take_either_prefix() {
+        for value in ["0x1a0", "0X1A0", "1a0"] {
+            assert_eq!(parse_hex_address(value), Some(0x1A0));
+            assert_eq!(hex_address(0x1FF)(String::from(value)), Ok(()));
+        }
+        assert_eq!(parse_hex_address("0x0x1a0"), None);
+        assert_eq!(parse_hex_address("0x"), None);
+        assert!(hex_address(0x1FF)(String::from("0x200")).is_err());
+    }
+}
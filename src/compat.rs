@@ -0,0 +1,120 @@
+use std::fmt;
+
+use crate::checksum;
+use crate::chip8::Chip8;
+use crate::error::Chip8Error;
+use crate::quirks::Quirks;
+use crate::variant::Variant;
+use crate::watchdog::Watchdog;
+
+/// Answers "which settings make this game work?" by running a ROM headless
+/// under yac8's defaults and as every variant, with no keys pressed, for
+/// the same number of emulated frames. A profile looks
+/// compatible if the ROM ran without faulting and left something on the
+/// display. Profiles that end on the same display hash behaved the same
+/// way, which usually means the quirks they differ in don't matter to the
+/// ROM. ROMs that use random numbers can differ from run to run.
+pub const DEFAULT_PROFILE: &str = "yac8";
+
+// A ROM whose pc and display don't change for this long has halted
+const HALT_SECONDS: f64 = 1.0;
+
+pub enum Outcome {
+    Running,
+    WaitingOnKey,
+    Halted(u64),
+    Crashed(u64, Chip8Error),
+}
+
+pub struct ProfileResult {
+    pub profile: &'static str,
+    pub outcome: Outcome,
+    pub frames: u64,
+    pub instructions: u64,
+    pub display_crc: u32,
+    pub lit_pixels: usize,
+}
+
+impl ProfileResult {
+    pub fn is_compatible(&self) -> bool {
+        !matches!(self.outcome, Outcome::Crashed(..)) && self.lit_pixels > 0
+    }
+}
+
+pub fn run_profile(rom: &[u8], clock_speed: f64, profile: &'static str,
+                   variant: Variant, quirks: Quirks, frames: u64) -> ProfileResult {
+    let mut machine = Chip8::new(rom.to_vec(), clock_speed);
+    machine.set_variant(variant);
+    machine.set_quirks(quirks);
+    let mut watchdog = Watchdog::new(HALT_SECONDS);
+
+    let mut outcome = Outcome::Running;
+    while machine.frames_elapsed() < frames {
+        let elapsed = match machine.run_frames(1) {
+            Ok(elapsed) => elapsed,
+            Err(error) => {
+                outcome = Outcome::Crashed(machine.frames_elapsed(), error);
+                break;
+            },
+        };
+        if watchdog.observe(elapsed as u32, machine.program_counter(), machine.waiting_on_key(), machine.display.pixels()) {
+            outcome = Outcome::Halted(machine.frames_elapsed());
+            break;
+        }
+    }
+    if let Outcome::Running = outcome {
+        if machine.waiting_on_key() {
+            outcome = Outcome::WaitingOnKey;
+        }
+    }
+
+    ProfileResult {
+        profile,
+        outcome,
+        frames: machine.frames_elapsed(),
+        instructions: machine.instructions_executed(),
+        display_crc: checksum::crc32(machine.display.pixels()),
+        lit_pixels: machine.display.pixels().iter().filter(|&&pixel| pixel != 0).count(),
+    }
+}
+
+/// Every profile's result, the default first and then each variant with
+/// its quirks preset.
+pub fn check(rom: &[u8], clock_speed: f64, frames: u64) -> Vec<ProfileResult> {
+    let mut results = vec![run_profile(rom, clock_speed, DEFAULT_PROFILE, Variant::Chip8, Quirks::default(), frames)];
+    for &variant in Variant::ALL.iter() {
+        results.push(run_profile(rom, clock_speed, variant.name(), variant, variant.quirks(), frames));
+    }
+    results
+}
+
+pub struct Report(pub Vec<ProfileResult>);
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{:<8} {:<10} {:>7} {:>12} {:>8}  {:<8}  result",
+                 "profile", "compatible", "frames", "instructions", "pixels", "display")?;
+        for result in &self.0 {
+            let outcome = match result.outcome {
+                Outcome::Running => String::from("still running"),
+                Outcome::WaitingOnKey => String::from("waiting on a key"),
+                Outcome::Halted(frame) => format!("halted by frame {}", frame),
+                Outcome::Crashed(frame, error) => format!("crashed in frame {}: {}", frame, error),
+            };
+            writeln!(f, "{:<8} {:<10} {:>7} {:>12} {:>8}  {:08x}  {}",
+                     result.profile,
+                     if result.is_compatible() { "yes" } else { "no" },
+                     result.frames,
+                     result.instructions,
+                     result.lit_pixels,
+                     result.display_crc,
+                     outcome)?;
+        }
+
+        let compatible: Vec<&str> = self.0.iter().filter(|result| result.is_compatible()).map(|result| result.profile).collect();
+        match compatible.len() {
+            0 => writeln!(f, "\nThe ROM doesn't appear to work under any profile."),
+            _ => writeln!(f, "\nThe ROM appears compatible with: {}", compatible.join(", ")),
+        }
+    }
+}
@@ -0,0 +1,150 @@
+//! A small LZSS compressor for save states. Machine state is mostly long
+//! runs of zeros and repeated sprite data, which even a simple dictionary
+//! coder shrinks to a fraction of its size, and it keeps yac8 free of
+//! native dependencies.
+//!
+//! The stream starts with the uncompressed length as a big-endian u32.
+//! After that, each flag byte describes the next eight items, low bit
+//! first: 0 for a literal byte, 1 for a two-byte match of 12 bits of
+//! distance back (1-4096) and 4 bits of length (3-18).
+
+const WINDOW: usize = 4096;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 18;
+const HASH_SIZE: usize = 1 << 12;
+// How many earlier positions with the same hash to try
+const MAX_CANDIDATES: usize = 32;
+// The most output one byte of input can stand for: half of a two-byte match
+const MAX_EXPANSION: usize = MAX_MATCH / 2;
+
+fn hash(data: &[u8]) -> usize {
+    ((data[0] as usize) << 8 ^ (data[1] as usize) << 4 ^ data[2] as usize) % HASH_SIZE
+}
+
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    let mut out = (data.len() as u32).to_be_bytes().to_vec();
+    // The most recent position for each hash, and the one before each position
+    let mut head = vec![usize::MAX; HASH_SIZE];
+    let mut previous = vec![usize::MAX; data.len()];
+
+    let mut position = 0;
+    let mut flags_at = 0;
+    let mut items = 8;
+    while position < data.len() {
+        if items == 8 {
+            flags_at = out.len();
+            out.push(0);
+            items = 0;
+        }
+
+        let (mut best_length, mut best_distance) = (0, 0);
+        if position + MIN_MATCH <= data.len() {
+            let mut candidate = head[hash(&data[position..])];
+            for _ in 0..MAX_CANDIDATES {
+                if candidate == usize::MAX || position - candidate > WINDOW {
+                    break;
+                }
+                let length = data[candidate..].iter()
+                                              .zip(&data[position..])
+                                              .take(MAX_MATCH)
+                                              .take_while(|(a, b)| a == b)
+                                              .count();
+                if length > best_length {
+                    best_length = length;
+                    best_distance = position - candidate;
+                }
+                candidate = previous[candidate];
+            }
+        }
+
+        let advance = if best_length >= MIN_MATCH {
+            out[flags_at] |= 1 << items;
+            let token = ((best_distance - 1) << 4) | (best_length - MIN_MATCH);
+            out.extend_from_slice(&(token as u16).to_be_bytes());
+            best_length
+        } else {
+            out.push(data[position]);
+            1
+        };
+        for _ in 0..advance {
+            if position + MIN_MATCH <= data.len() {
+                let bucket = hash(&data[position..]);
+                previous[position] = head[bucket];
+                head[bucket] = position;
+            }
+            position += 1;
+        }
+        items += 1;
+    }
+    out
+}
+
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>, String> {
+    let truncated = || String::from("truncated compressed data");
+    let length = data.get(..4).ok_or_else(truncated)?;
+    let length = u32::from_be_bytes([length[0], length[1], length[2], length[3]]) as usize;
+    // Checked before allocating, so a corrupt length can't ask for gigabytes
+    if length > (data.len() - 4).saturating_mul(MAX_EXPANSION) {
+        return Err(truncated());
+    }
+    let mut out = Vec::with_capacity(length);
+
+    let mut input = data[4..].iter();
+    while out.len() < length {
+        let flags = *input.next().ok_or_else(truncated)?;
+        for item in 0..8 {
+            if out.len() == length {
+                break;
+            }
+            if flags & (1 << item) == 0 {
+                out.push(*input.next().ok_or_else(truncated)?);
+                continue;
+            }
+            let token = u16::from_be_bytes([*input.next().ok_or_else(truncated)?, *input.next().ok_or_else(truncated)?]);
+            let distance = (token >> 4) as usize + 1;
+            let match_length = (token & 0xF) as usize + MIN_MATCH;
+            let start = out.len().checked_sub(distance).ok_or("a match reaches back before the start")?;
+            for offset in 0..match_length {
+                out.push(out[start + offset]);
+            }
+        }
+    }
+    if out.len() != length {
+        return Err(String::from("a match runs past the end of the data"));
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    #[test]
+    fn compressed_data_comes_back_the_same() {
+        let mut rng = StdRng::seed_from_u64(0x1A);
+        let random: Vec<u8> = (0..5000).map(|_| rng.gen()).collect();
+        let sprites: Vec<u8> = [0xF0, 0x90, 0x90, 0x90, 0xF0].iter().cycle().take(6000).cloned().collect();
+        let mut state = vec![0; 4096];
+        state[0x200..0x200 + 100].copy_from_slice(&random[..100]);
+        for data in [Vec::new(), vec![7], vec![0; 70_000], random, sprites, state] {
+            let compressed = compress(&data);
+            assert_eq!(decompress(&compressed), Ok(data));
+        }
+    }
+
+    #[test]
+    fn corrupt_data_is_an_error() {
+        let compressed = compress(&[0xAB; 1000]);
+        for length in 0..compressed.len() {
+            assert!(decompress(&compressed[..length]).is_err());
+        }
+        // A length far more than the rest could hold is refused before anything is allocated
+        assert!(decompress(&[0xFF, 0xFF, 0xFF, 0xFF, 0x00, 0x01]).is_err());
+        // A match with nothing before it to copy
+        assert!(decompress(&[0x00, 0x00, 0x00, 0x03, 0x01, 0x00, 0x00]).is_err());
+        // A match that runs past the length
+        assert!(decompress(&[0x00, 0x00, 0x00, 0x02, 0x02, 0x41, 0x00, 0x00]).is_err());
+    }
+}
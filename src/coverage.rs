@@ -0,0 +1,98 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::instructions;
+use crate::instructions::Instruction;
+
+/// Instruction coverage for a single run. Records which ROM addresses were
+/// executed and how often each opcode pattern was hit, so ROM authors can
+/// spot untested branches and so we can see which of our own instruction
+/// handlers a ROM actually exercised.
+pub struct Coverage {
+    executed: Vec<bool>,
+    pattern_hits: BTreeMap<&'static str, u64>,
+}
+
+impl Coverage {
+    const PROGRAM_OFFSET: usize = 0x200;
+
+    pub fn new(rom_size: usize) -> Coverage {
+        Coverage {
+            executed: vec![false; rom_size],
+            pattern_hits: BTreeMap::new(),
+        }
+    }
+
+    pub fn record(&mut self, pc: u16, instruction: &Instruction) {
+        let offset = (pc as usize).wrapping_sub(Coverage::PROGRAM_OFFSET);
+        for byte in offset..(offset + 2) {
+            if let Some(executed) = self.executed.get_mut(byte) {
+                *executed = true;
+            }
+        }
+        *self.pattern_hits.entry(instruction.pattern()).or_insert(0) += 1;
+    }
+
+    // How many ROM bytes executed, out of how many
+    pub fn executed_bytes(&self) -> (usize, usize) {
+        (self.executed.iter().filter(|&&executed| executed).count(), self.executed.len())
+    }
+
+    // Executions per opcode pattern, e.g. "DXYN"
+    pub fn pattern_hits(&self) -> &BTreeMap<&'static str, u64> {
+        &self.pattern_hits
+    }
+
+    /// Inclusive address ranges of ROM bytes that never executed. These are
+    /// either dead code or data (sprites, tables) the program reads.
+    pub fn unexecuted_ranges(&self) -> Vec<(u16, u16)> {
+        let mut ranges = Vec::new();
+        let mut start = None;
+
+        for (offset, &executed) in self.executed.iter().enumerate() {
+            match (executed, start) {
+                (false, None) => start = Some(offset),
+                (true, Some(first)) => {
+                    ranges.push(Coverage::to_address_range(first, offset - 1));
+                    start = None;
+                },
+                _ => {},
+            }
+        }
+        if let Some(first) = start {
+            ranges.push(Coverage::to_address_range(first, self.executed.len() - 1));
+        }
+
+        ranges
+    }
+
+    fn to_address_range(first: usize, last: usize) -> (u16, u16) {
+        ((first + Coverage::PROGRAM_OFFSET) as u16, (last + Coverage::PROGRAM_OFFSET) as u16)
+    }
+}
+
+impl fmt::Display for Coverage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (executed, total) = self.executed_bytes();
+        let percent = if total == 0 { 0.0 } else { 100.0 * executed as f64 / total as f64 };
+
+        writeln!(f, "=> Coverage report")?;
+        writeln!(f, "ROM bytes executed: {} of {} ({:.1}%)", executed, total, percent)?;
+
+        writeln!(f, "Never executed:")?;
+        for (first, last) in self.unexecuted_ranges() {
+            writeln!(f, "  {:#06X}-{:#06X} ({} bytes)", first, last, last - first + 1)?;
+        }
+
+        writeln!(f, "Opcode patterns hit:")?;
+        for (pattern, hits) in self.pattern_hits.iter() {
+            writeln!(f, "  {} {:>10}", pattern, hits)?;
+        }
+
+        let missed: Vec<&str> = instructions::PATTERNS.iter()
+                                                      .filter(|pattern| !self.pattern_hits.contains_key(*pattern))
+                                                      .cloned()
+                                                      .collect();
+        writeln!(f, "Opcode patterns never hit: {}", missed.join(" "))
+    }
+}
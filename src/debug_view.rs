@@ -0,0 +1,94 @@
+use crate::chip8::Chip8;
+use crate::instructions;
+use crate::registers::Registers;
+use crate::stack::Stack;
+
+/*
+    A text dump of the machine for the debugger window: registers, timers,
+    the stack, the disassembly around the program counter and the memory
+    that I points at.
+*/
+pub const COLUMNS: usize = 40;
+pub const ROWS: usize = 32;
+
+// The side panel's width in characters
+const PANEL_COLUMNS: usize = 15;
+const STACK_FRAMES_PER_PANEL_ROW: usize = 4;
+
+const INSTRUCTIONS_BEFORE_PC: u16 = 4;
+const INSTRUCTIONS_AFTER_PC: u16 = 8;
+const MEMORY_ROWS: u16 = 4;
+const BYTES_PER_MEMORY_ROW: u16 = 8;
+
+pub fn render(machine: &Chip8) -> Vec<String> {
+    let registers = machine.registers();
+    let pc = machine.program_counter();
+    let mut lines = Vec::new();
+
+    lines.push(format!("PC {:#06X}  I {:#06X}  DT {:02X}  ST {:02X}",
+                       pc, registers.i_register, registers.delay_timer, registers.sound_timer));
+    for row in 0..(Registers::NUM_DATA_REGISTERS / 4) {
+        let cells: Vec<String> = (0..4).map(|column| row * 4 + column)
+                                       .map(|register| format!("V{:X} {:02X}", register, registers.data()[register as usize]))
+                                       .collect();
+        lines.push(cells.join("  "));
+    }
+
+    let frames: Vec<String> = machine.stack().frames().iter().map(|frame| format!("{:03X}", frame)).collect();
+    lines.push(format!("STACK {}", frames.join(" ")));
+    lines.push(String::new());
+
+    let memory = machine.memory();
+    let first = pc.saturating_sub(2 * INSTRUCTIONS_BEFORE_PC);
+    for address in (first..(pc + 2 * INSTRUCTIONS_AFTER_PC)).step_by(2) {
+        let marker = if address == pc { '>' } else { ' ' };
+        match memory.peek_opcode(address) {
+            Some(opcode) => {
+                lines.push(format!("{}{:#06X} {:04X} {}", marker, address, opcode, instructions::parse_opcode(opcode)));
+            },
+            None => lines.push(format!("{}{:#06X} ----", marker, address)),
+        }
+    }
+    lines.push(String::new());
+
+    lines.push(String::from("MEMORY AT I"));
+    for row in 0..MEMORY_ROWS {
+        let base = registers.i_register.wrapping_add(row * BYTES_PER_MEMORY_ROW);
+        let bytes: Vec<String> = (0..BYTES_PER_MEMORY_ROW).map(|offset| match memory.peek(base.wrapping_add(offset)) {
+                                                                 Some(byte) => format!("{:02X}", byte),
+                                                                 None => String::from("--"),
+                                                             })
+                                                             .collect();
+        lines.push(format!("{:#06X} {}", base, bytes.join(" ")));
+    }
+
+    lines
+}
+
+/*
+    The registers, timers and stack for the side panel beside the game,
+    narrow enough to sit in a third of the window. It's always the same
+    size, so the text doesn't jump about as the stack fills.
+*/
+pub fn panel(machine: &Chip8) -> Vec<String> {
+    let registers = machine.registers();
+    let mut lines = vec![format!("PC {:#06X}", machine.program_counter()),
+                         format!("I  {:#06X}", registers.i_register),
+                         format!("DT {:02X}  ST {:02X}", registers.delay_timer, registers.sound_timer),
+                         String::new()];
+    for row in 0..(Registers::NUM_DATA_REGISTERS / 2) {
+        lines.push(format!("V{:X} {:02X}  V{:X} {:02X}", row * 2, registers.data()[row as usize * 2],
+                           row * 2 + 1, registers.data()[row as usize * 2 + 1]));
+    }
+    lines.push(String::new());
+
+    lines.push(String::from("STACK"));
+    let frames: Vec<String> = machine.stack().frames().iter().map(|frame| format!("{:03X}", frame)).collect();
+    for row in 0..(Stack::NUM_FRAMES / STACK_FRAMES_PER_PANEL_ROW) {
+        let start = (row * STACK_FRAMES_PER_PANEL_ROW).min(frames.len());
+        let end = ((row + 1) * STACK_FRAMES_PER_PANEL_ROW).min(frames.len());
+        lines.push(frames[start..end].join(" "));
+    }
+
+    lines.iter().map(|line| format!("{:<width$}", line, width = PANEL_COLUMNS)).collect()
+}
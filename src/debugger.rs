@@ -0,0 +1,180 @@
+use std::io::{self, BufRead, Write};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+use crate::chip8::Chip8;
+use crate::debug_view;
+use crate::instructions;
+
+/*
+    A debugger on the terminal for --debug. Commands are read from stdin on
+    a thread of their own and polled from the main loop between cycles, like
+    the web debugger, so the window keeps drawing while the prompt waits.
+    The machine starts paused so breakpoints can be set before anything
+    runs, and a breakpoint or a watch hands control back to the prompt.
+*/
+pub struct Debugger {
+    commands: Receiver<String>,
+    paused: bool,
+    quit: bool,
+    last_error: Option<String>,
+}
+
+const HELP: &str = "\
+break ADDR     (b)  pause when the program counter reaches ADDR
+delete ADDR    (d)  remove the breakpoint at ADDR
+breakpoints    (l)  list the breakpoints
+step [COUNT]   (s)  execute one or COUNT instructions
+continue       (c)  run until the next breakpoint or watch
+pause          (p)  hold the machine
+registers      (r)  show V0-VF, I, the timers, the stack and the code at PC
+quit           (q)  stop the emulator";
+
+impl Debugger {
+    pub fn start() -> Debugger {
+        let (sender, commands) = mpsc::channel();
+        thread::spawn(move || {
+            for line in io::stdin().lock().lines().map_while(Result::ok) {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        println!("=> Debugger ready, the machine is paused. Type `help` for commands.");
+        prompt();
+        Debugger { commands, paused: true, quit: false, last_error: None }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn quit_requested(&self) -> bool {
+        self.quit
+    }
+
+    /*
+        Take over from a breakpoint or watch that has paused the machine.
+        The caller resumes the machine itself, which now stays held by the
+        debugger instead.
+    */
+    pub fn break_in(&mut self, machine: &Chip8) {
+        if let Some(address) = machine.breakpoint_hit() {
+            println!("=> Breakpoint at {:#06X}.", address);
+        }
+        self.paused = true;
+        self.last_error = None;
+        print_location(machine);
+        prompt();
+    }
+
+    /*
+        Run every command that's been typed, without blocking when there
+        are none. Returns true if a command stepped the machine.
+    */
+    pub fn poll(&mut self, machine: &mut Chip8) -> bool {
+        let mut stepped = false;
+        loop {
+            let line = match self.commands.try_recv() {
+                Ok(line) => line,
+                Err(TryRecvError::Empty) => break,
+                // With stdin closed nobody can resume the machine, so let it run
+                Err(TryRecvError::Disconnected) => {
+                    self.paused = false;
+                    break;
+                },
+            };
+            match self.execute(line.trim(), machine) {
+                Ok(step) => stepped |= step,
+                Err(error) => println!("=> {}", error),
+            }
+            if self.quit {
+                break;
+            }
+            prompt();
+        }
+        stepped
+    }
+
+    fn execute(&mut self, line: &str, machine: &mut Chip8) -> Result<bool, String> {
+        let mut words = line.split_whitespace();
+        let command = match words.next() {
+            Some(command) => command,
+            None => return Ok(false),
+        };
+        let argument = words.next();
+
+        match command {
+            "b" | "break" => {
+                let address = parse_address(argument)?;
+                if machine.add_breakpoint(address) {
+                    println!("=> Breakpoint set at {:#06X}.", address);
+                }
+            },
+            "d" | "delete" => {
+                let address = parse_address(argument)?;
+                if !machine.remove_breakpoint(address) {
+                    return Err(format!("There's no breakpoint at {:#06X}.", address));
+                }
+            },
+            "l" | "breakpoints" => {
+                let addresses: Vec<String> = machine.breakpoints().map(|address| format!("{:#06X}", address)).collect();
+                println!("=> Breakpoints: {}.", if addresses.is_empty() { String::from("none") } else { addresses.join(", ") });
+            },
+            "s" | "step" => {
+                let count = match argument {
+                    Some(count) => count.parse::<u32>().map_err(|_| format!("`{}` isn't a number of instructions.", count))?,
+                    None => 1,
+                };
+                self.paused = true;
+                if let Some(error) = &self.last_error {
+                    return Err(format!("The program stopped: {}.", error));
+                }
+                for _ in 0..count {
+                    if let Err(error) = machine.step() {
+                        self.last_error = Some(error.to_string());
+                        return Err(format!("The program stopped: {}.", error));
+                    }
+                }
+                print_location(machine);
+                return Ok(true);
+            },
+            "c" | "continue" => {
+                self.paused = false;
+                self.last_error = None;
+            },
+            "p" | "pause" => {
+                self.paused = true;
+                print_location(machine);
+            },
+            "r" | "registers" => {
+                for line in debug_view::render(machine) {
+                    println!("{}", line);
+                }
+            },
+            "q" | "quit" => self.quit = true,
+            "h" | "help" => println!("{}", HELP),
+            _ => return Err(format!("Unknown command `{}`. Type `help` for commands.", command)),
+        }
+        Ok(false)
+    }
+}
+
+fn parse_address(argument: Option<&str>) -> Result<u16, String> {
+    let argument = argument.ok_or("The command needs an address.")?;
+    u16::from_str_radix(argument.trim_start_matches("0x").trim_start_matches("0X"), 16)
+        .map_err(|_| format!("`{}` isn't a hex address.", argument))
+}
+
+fn print_location(machine: &Chip8) {
+    let pc = machine.program_counter();
+    match machine.memory().peek_opcode(pc) {
+        Some(opcode) => println!("{:#06X} {:04X} {}", pc, opcode, instructions::parse_opcode(opcode)),
+        None => println!("{:#06X} ----", pc),
+    }
+}
+
+fn prompt() {
+    print!("(yac8) ");
+    io::stdout().flush().expect("Cannot write to stdout.");
+}
@@ -0,0 +1,154 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::instructions::{self, Instruction};
+
+/// Turns ROM bytes into an assembly listing. The code reachable from the
+/// entry point is disassembled an instruction a line, and everything else
+/// is data, a word or less of `DB` a line. Every line that code jumps or
+/// calls to gets a label, and the jumps and calls name their targets by it:
+///
+/// 0x200: 2204  CALL sub_204
+/// loc_202:
+/// 0x202: 1202  JP loc_202
+/// sub_204:
+/// 0x204: 00EE  RET
+/// 0x206: F090  DB 0xF0, 0x90
+pub const PROGRAM_OFFSET: u16 = 0x200;
+
+pub fn listing(rom: &[u8], explain: bool) -> Vec<String> {
+    let code = reachable(rom);
+    let labels = labels(rom);
+    let mut lines = Vec::new();
+    let mut offset = 0;
+    while offset < rom.len() {
+        let address = PROGRAM_OFFSET + offset as u16;
+        if let Some(label) = labels.get(&address) {
+            lines.push(format!("{}:", label));
+        }
+        let (raw, text, explanation) = match opcode_at(rom, address).filter(|_| code.contains(&address)) {
+            Some(opcode) => {
+                let instruction = instructions::parse_opcode(opcode);
+                (format!("{:04X}", opcode), with_labels(instruction, &labels), instruction.explain())
+            },
+            None => {
+                // Up to a word, stopping short of any code that starts on the second byte
+                let length = if offset + 1 < rom.len() && !code.contains(&(address + 1)) { 2 } else { 1 };
+                let bytes = &rom[offset..offset + length];
+                (bytes.iter().map(|byte| format!("{:02X}", byte)).collect(),
+                 format!("DB {}", bytes.iter().map(|byte| format!("{:#04X}", byte)).collect::<Vec<_>>().join(", ")),
+                 String::from("Data: no path from the entry point runs it"))
+            },
+        };
+        offset += raw.len() / 2;
+        lines.push(if explain {
+            format!("{:#05X}: {:<4}  {:<16}  ; {}", address, raw, text, explanation)
+        } else {
+            format!("{:#05X}: {:<4}  {}", address, raw, text)
+        });
+    }
+    lines
+}
+
+/// A name for every address of reachable code that other code jumps or
+/// calls to: `sub_` and the address for subroutines, `loc_` for the rest.
+pub fn labels(rom: &[u8]) -> BTreeMap<u16, String> {
+    let code = reachable(rom);
+    let mut labels = BTreeMap::new();
+    for &address in &code {
+        match instructions::parse_opcode(opcode_at(rom, address).unwrap()) {
+            Instruction::Call(target) if code.contains(&target) => {
+                labels.insert(target, format!("sub_{:03X}", target));
+            },
+            Instruction::Jump(target) | Instruction::JumpFromOffset(target) if code.contains(&target) => {
+                labels.entry(target).or_insert_with(|| format!("loc_{:03X}", target));
+            },
+            _ => {},
+        }
+    }
+    labels
+}
+
+fn with_labels(instruction: Instruction, labels: &BTreeMap<u16, String>) -> String {
+    match instruction {
+        Instruction::Jump(target) if labels.contains_key(&target) => format!("JP {}", labels[&target]),
+        Instruction::Call(target) if labels.contains_key(&target) => format!("CALL {}", labels[&target]),
+        Instruction::JumpFromOffset(target) if labels.contains_key(&target) => format!("JP V0, {}", labels[&target]),
+        _ => instruction.to_string(),
+    }
+}
+
+/// The opcode at `address`, if the whole word is inside the ROM.
+pub fn opcode_at(rom: &[u8], address: u16) -> Option<u16> {
+    let offset = address.checked_sub(PROGRAM_OFFSET)? as usize;
+    match rom.get(offset..offset + 2)? {
+        [high, low] => Some(((*high as u16) << 8) | *low as u16),
+        _ => None,
+    }
+}
+
+/// How many bytes the instruction starting with `opcode` takes. XO-CHIP's
+/// F000 NNNN is the only one longer than a word.
+fn length(opcode: u16) -> u16 {
+    if opcode == 0xF000 { 4 } else { 2 }
+}
+
+/// Where control can go after the instruction at `address`. Returns and
+/// the end of the program go nowhere, and neither does BNNN, whose target
+/// depends on a register. Neither do unknown opcodes, since whatever
+/// follows is probably data.
+pub fn successors(rom: &[u8], address: u16, opcode: u16) -> Vec<u16> {
+    let next = address + length(opcode);
+    match instructions::parse_opcode(opcode) {
+        Instruction::Return | Instruction::JumpFromOffset(_) | Instruction::UNKNOWN(_) => vec![],
+        Instruction::Exit => vec![],
+        Instruction::Jump(target) => vec![target],
+        Instruction::Call(target) => vec![target, next],
+        Instruction::SkipIfEQData(..) | Instruction::SkipIfNEData(..) | Instruction::SkipIfEQRegister(..)
+        | Instruction::SkipIfNERegister(..) | Instruction::SkipIfPressed(_) | Instruction::SkipIfNotPressed(_) => {
+            let skipped = opcode_at(rom, next).map_or(2, length);
+            vec![next, next + skipped]
+        },
+        _ => vec![next],
+    }
+}
+
+/// Recursive descent: the address of every instruction reachable from the
+/// entry point, following jumps, calls and both sides of skips. Whatever
+/// is left over is data, or code only reached through BNNN.
+pub fn reachable(rom: &[u8]) -> BTreeSet<u16> {
+    let mut visited = BTreeSet::new();
+    let mut pending = vec![PROGRAM_OFFSET];
+    while let Some(address) = pending.pop() {
+        let opcode = match opcode_at(rom, address) {
+            Some(opcode) if !visited.contains(&address) => opcode,
+            _ => continue,
+        };
+        visited.insert(address);
+        pending.extend(successors(rom, address, opcode));
+    }
+    visited
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn jumps_and_calls_name_their_targets() {
+        // CALL 0x204, JP 0x202, RET, then a byte of data and a jump past the end
+        let rom = [0x22, 0x04, 0x12, 0x02, 0x00, 0xEE, 0xF0];
+        assert_eq!(listing(&rom, false), ["0x200: 2204  CALL sub_204", "loc_202:", "0x202: 1202  JP loc_202",
+                                          "sub_204:", "0x204: 00EE  RET", "0x206: F0    DB 0xF0"]);
+        // Unreachable code gets no labels, and targets outside the ROM stay numbers
+        assert!(labels(&[0x00, 0xFD, 0x12, 0x00]).is_empty());
+        assert_eq!(listing(&[0x13, 0x00], false), ["0x200: 1300  JP 0x300"]);
+    }
+
+    #[test]
+    fn only_reachable_code_is_disassembled() {
+        // Jump over a sprite byte to code at an odd address, which returns into more data
+        let rom = [0x12, 0x03, 0xF0, 0x00, 0xEE, 0x90, 0x90, 0x60];
+        assert_eq!(listing(&rom, false), ["0x200: 1203  JP loc_203", "0x202: F0    DB 0xF0", "loc_203:",
+                                          "0x203: 00EE  RET", "0x205: 9090  DB 0x90, 0x90", "0x207: 60    DB 0x60"]);
+    }
+}
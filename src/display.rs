@@ -1,48 +1,225 @@
-/*
-    The CHIP-8 display at the original 64x48 resolution. This display supports
-    drawing binary sprite data and is used as a display buffer.
-*/
+use crate::savestate::{StateReader, StateWriter};
+
+/// The CHIP-8 display at the original 64x32 resolution, or SUPER-CHIP's
+/// 128x64 hi-res mode. This display supports drawing binary sprite data
+/// and is used as a display buffer.
+///
+/// Outside the core, the display is read through `width`, `height`, `get`,
+/// `rows` and `pixels` rather than its storage, which is free to change.
+/// `take_changed` tells a frontend whether there's anything new to draw.
+#[derive(Clone)]
 pub struct Display {
-    pub buffer: [u8; Display::SIZE],
+    buffer: Vec<u8>,
+    // SUPER-CHIP's 128x64 mode, which changes how collisions are reported
+    hires: bool,
+    // Whether any pixel changed since the last `take_changed`
+    changed: bool,
  }
 
  impl Display {
      pub const WIDTH: u16 = 64;
      pub const HEIGHT: u16 = 32;
      pub const SIZE: usize = (Display::WIDTH * Display::HEIGHT) as usize;
+     pub const HIRES_WIDTH: u16 = 128;
+     pub const HIRES_HEIGHT: u16 = 64;
+     pub const HIRES_SIZE: usize = (Display::HIRES_WIDTH * Display::HIRES_HEIGHT) as usize;
 
      pub fn new() -> Display {
          Display {
-             buffer: [0x0; Display::SIZE]
+             buffer: vec![0x0; Display::SIZE],
+             hires: false,
+             changed: true,
+         }
+     }
+
+     pub fn save(&self, out: &mut StateWriter) {
+         out.bytes(&self.buffer);
+         out.bool(self.hires);
+     }
+
+     pub fn load(input: &mut StateReader) -> Result<Display, String> {
+         let buffer = input.bytes()?;
+         let hires = input.bool()?;
+         let size = if hires { Display::HIRES_SIZE } else { Display::SIZE };
+         if buffer.len() != size {
+             return Err(format!("expected {} pixels, found {}", size, buffer.len()));
+         }
+         Ok(Display { buffer, hires, changed: true })
+     }
+
+     pub fn hires(&self) -> bool {
+         self.hires
+     }
+
+     /// Whether any pixel has changed since the last call, or since the
+     /// display was created. A new display has everything to draw.
+     pub fn take_changed(&mut self) -> bool {
+         std::mem::replace(&mut self.changed, false)
+     }
+
+     /// Take on `other`'s pixels and resolution, as restoring a snapshot
+     /// does, noting a change if they differ from what was there.
+     pub fn replace(&mut self, other: Display) {
+         let changed = self.changed || self.buffer != other.buffer;
+         *self = Display { changed, ..other };
+     }
+
+     /// Switch between 64x32 and 128x64, clearing the display as Octo and
+     /// later SUPER-CHIP interpreters do.
+     pub fn set_hires(&mut self, hires: bool) {
+         self.hires = hires;
+         self.buffer = vec![0x0; if hires { Display::HIRES_SIZE } else { Display::SIZE }];
+         self.changed = true;
+     }
+
+     pub fn width(&self) -> usize {
+         if self.hires { Display::HIRES_WIDTH as usize } else { Display::WIDTH as usize }
+     }
+
+     pub fn height(&self) -> usize {
+         if self.hires { Display::HIRES_HEIGHT as usize } else { Display::HEIGHT as usize }
+     }
+
+     /// Whether the pixel at (x, y) is lit. Pixels off the display are not.
+     pub fn get(&self, x: usize, y: usize) -> bool {
+         x < self.width() && y < self.height() && self.buffer[y * self.width() + x] != 0
+     }
+
+     /// Each row of pixels, top to bottom.
+     pub fn rows(&self) -> impl Iterator<Item = &[u8]> {
+         self.buffer.chunks(self.width())
+     }
+
+     /// Every pixel, row by row, one byte each. A pixel is 0 when unlit,
+     /// and otherwise which entry of a palette to color it with.
+     pub fn pixels(&self) -> &[u8] {
+         &self.buffer
+     }
+
+     /// Set the pixel at (x, y) to a palette entry, 0 for unlit. Pixels
+     /// off the display are ignored.
+     pub fn set(&mut self, x: usize, y: usize, value: u8) {
+         let width = self.width();
+         if x < width && y < self.height() {
+             self.changed |= self.buffer[y * width + x] != value;
+             self.buffer[y * width + x] = value;
          }
      }
 
-     pub fn clear(&mut self) {
-         self.buffer = [0x0; Display::SIZE];
+     /// Blank the bit-planes in the `planes` mask: bit 0 for the first,
+     /// which is all plain CHIP-8 has, and bit 1 for XO-CHIP's second.
+     pub fn clear(&mut self, planes: u8) {
+         self.changed |= self.buffer.iter().any(|pixel| pixel & planes != 0);
+         self.buffer.iter_mut().for_each(|pixel| *pixel &= !planes);
+     }
+
+     /// Move the `planes` down or up `rows` pixels, or right or left
+     /// `columns`, leaving blank pixels behind. Amounts are in pixels of
+     /// the current resolution, as on Octo; the HP-48 scrolled lo-res by
+     /// half as much.
+     pub fn scroll_down(&mut self, rows: usize, planes: u8) {
+         self.scroll(0, rows as isize, planes);
+     }
+
+     pub fn scroll_up(&mut self, rows: usize, planes: u8) {
+         self.scroll(0, -(rows as isize), planes);
+     }
+
+     pub fn scroll_right(&mut self, columns: usize, planes: u8) {
+         self.scroll(columns as isize, 0, planes);
      }
 
-     pub fn draw(&mut self, x: u8, y: u8, sprite_data: &[u8]) -> bool {
-         let mut erased = false;
+     pub fn scroll_left(&mut self, columns: usize, planes: u8) {
+         self.scroll(-(columns as isize), 0, planes);
+     }
 
-         for (y_iter, byte) in sprite_data.iter().enumerate() {
-             let current_y = (y + y_iter as u8) as u16 % Display::HEIGHT;
+     fn scroll(&mut self, dx: isize, dy: isize, planes: u8) {
+         let (width, height) = (self.width() as isize, self.height() as isize);
+         let old = self.buffer.clone();
+         for y in 0..height {
+             for x in 0..width {
+                 let (from_x, from_y) = (x - dx, y - dy);
+                 let moved = if (0..width).contains(&from_x) && (0..height).contains(&from_y) {
+                     old[(from_y * width + from_x) as usize] & planes
+                 } else {
+                     0
+                 };
+                 let index = (y * width + x) as usize;
+                 self.buffer[index] = old[index] & !planes | moved;
+             }
+         }
+         self.changed |= self.buffer != old;
+     }
 
-             for bit_num in 0..8 {
-                 let current_x = (x + bit_num as u8) as u16 % Display::WIDTH;
-                 let buffer_index = ((current_y * Display::WIDTH) + current_x) as usize;
+     /// XOR a sprite onto each of the `planes`, returning how many rows
+     /// erased at least one lit pixel on any of them and how many were
+     /// clipped off the bottom, which SUPER-CHIP counts as collisions in
+     /// hi-res. Sprites are 8 pixels
+     /// wide, one byte per row, or 16 wide with two bytes per row. With
+     /// both planes selected, `sprite_data` holds the first plane's sprite
+     /// followed by the second's. The sprite's position wraps onto the
+     /// screen, and with `wrap` so do its pixels past the right or bottom
+     /// edge; otherwise they're clipped.
+     pub fn draw(&mut self, x: u8, y: u8, sprite_data: &[u8], bytes_per_row: usize, planes: u8, wrap: bool) -> DrawnRows {
+         let (width, height) = (self.width() as u16, self.height() as u16);
+         let (x, y) = (x as u16 % width, y as u16 % height);
+         let selected: Vec<u8> = [1, 2].iter().copied().filter(|plane| planes & plane != 0).collect();
+         if selected.is_empty() {
+             return DrawnRows::default();
+         }
+         let plane_length = sprite_data.len() / selected.len();
+         let rows = plane_length / bytes_per_row;
+         let mut erased = vec![false; rows];
+         let clipped = if wrap { 0 } else { rows.saturating_sub((height - y) as usize) };
 
-                 let old_pixel = self.buffer[buffer_index];
-                 let current_bit = (byte >> (7 - bit_num)) & 1;  // isolate the nth bit
-                 let new_pixel = current_bit ^ old_pixel;
+         for (plane, sprite) in selected.iter().zip(sprite_data.chunks(plane_length.max(1))) {
+             for (y_iter, row) in sprite.chunks(bytes_per_row).enumerate() {
+                 let current_y = y + y_iter as u16;
+                 if current_y >= height && !wrap {
+                     break;
+                 }
+                 let current_y = current_y % height;
 
-                 self.buffer[buffer_index] = new_pixel;
+                 for (byte_num, byte) in row.iter().enumerate() {
+                     for bit_num in 0..8 {
+                         let current_x = x + 8 * byte_num as u16 + bit_num as u16;
+                         if current_x >= width && !wrap {
+                             break;
+                         }
+                         let current_x = current_x % width;
+                         let buffer_index = ((current_y * width) + current_x) as usize;
 
-                 if old_pixel == 1 && new_pixel == 0 {
-                     erased = true;
+                         let current_bit = (byte >> (7 - bit_num)) & 1;  // isolate the nth bit
+                         if current_bit == 0 {
+                             continue;
+                         }
+                         if self.buffer[buffer_index] & plane != 0 {
+                             erased[y_iter] = true;
+                         }
+                         self.buffer[buffer_index] ^= plane;
+                         self.changed = true;
+                     }
                  }
              }
          }
 
-         erased
+         DrawnRows {
+             erased: erased.iter().filter(|&&erased| erased).count() as u8,
+             clipped: clipped as u8,
+         }
+     }
+ }
+
+/// The rows of a drawn sprite that erased a lit pixel, and that didn't fit
+/// above the bottom of the screen.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct DrawnRows {
+    pub erased: u8,
+    pub clipped: u8,
+}
+
+ impl Default for Display {
+     fn default() -> Display {
+         Display::new()
      }
  }
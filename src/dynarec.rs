@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::instructions::{self, Instruction};
+use crate::main_memory::MainMemory;
+
+/// An experimental dynamic recompiler, mostly as a showcase and for
+/// benchmarking. It counts how often execution arrives at each address and
+/// once one gets hot, compiles the straight-line run of instructions
+/// starting there into threaded code: the instructions decoded once up
+/// front, so the block can run back to back without fetching or decoding.
+///
+/// Blocks stop before anything that branches, waits, draws or writes
+/// memory, so a block never modifies itself while running. A write
+/// anywhere in a compiled block throws the block away and execution falls
+/// back to the interpreter until the new code gets hot in turn.
+pub type Block = Rc<[Instruction]>;
+
+pub struct Dynarec {
+    hits: Vec<u32>,
+    blocks: HashMap<u16, Option<Block>>,
+    blocks_compiled: u64,
+    blocks_invalidated: u64,
+    instructions_compiled: u64,
+}
+
+impl Dynarec {
+    // Executions of an address before the block starting there is compiled
+    const HOT_THRESHOLD: u32 = 50;
+    // Shorter runs aren't worth leaving the interpreter for
+    const MIN_BLOCK_LENGTH: usize = 2;
+    const MAX_BLOCK_LENGTH: usize = 64;
+
+    pub fn new() -> Dynarec {
+        Dynarec {
+            hits: Vec::new(),
+            blocks: HashMap::new(),
+            blocks_compiled: 0,
+            blocks_invalidated: 0,
+            instructions_compiled: 0,
+        }
+    }
+
+    /// Called as execution arrives at `pc`, returning the compiled block
+    /// starting there if there is one, compiling it once it's hot.
+    pub fn enter(&mut self, pc: u16, memory: &MainMemory) -> Option<Block> {
+        if let Some(block) = self.blocks.get(&pc) {
+            return block.clone();
+        }
+
+        // Sized from memory here, since XO-CHIP can grow it after the dynarec is made
+        if self.hits.len() < memory.size() {
+            self.hits.resize(memory.size(), 0);
+        }
+        let hits = self.hits.get_mut(pc as usize)?;
+        *hits += 1;
+        if *hits < Dynarec::HOT_THRESHOLD {
+            return None;
+        }
+
+        let block = Dynarec::compile(pc, memory);
+        if block.is_some() {
+            self.blocks_compiled += 1;
+        }
+        // Remembering a failed compile stops it being retried on every visit
+        self.blocks.insert(pc, block.clone());
+        block
+    }
+
+    fn compile(pc: u16, memory: &MainMemory) -> Option<Block> {
+        let mut block = Vec::new();
+        let mut address = pc;
+        while block.len() < Dynarec::MAX_BLOCK_LENGTH {
+            let instruction = match memory.peek_opcode(address) {
+                Some(opcode) => instructions::parse_opcode(opcode),
+                None => break,
+            };
+            if !Dynarec::is_straight_line(&instruction) {
+                break;
+            }
+            block.push(instruction);
+            address = address.wrapping_add(2);
+        }
+
+        if block.len() < Dynarec::MIN_BLOCK_LENGTH {
+            return None;
+        }
+        Some(block.into())
+    }
+
+    fn is_straight_line(instruction: &Instruction) -> bool {
+        matches!(instruction,
+                 Instruction::LoadData(..) | Instruction::AddData(..) | Instruction::LoadRegister(..)
+                 | Instruction::Or(..) | Instruction::And(..) | Instruction::Xor(..)
+                 | Instruction::Add(..) | Instruction::Sub(..) | Instruction::NegatedSub(..)
+                 | Instruction::ShiftRight(..) | Instruction::ShiftLeft(..)
+                 | Instruction::SetI(..) | Instruction::AddI(..) | Instruction::LoadSprite(..)
+                 | Instruction::Random(..) | Instruction::ReadRegisters(..)
+                 | Instruction::SetRegisterFromDelay(..) | Instruction::SetDelayFromRegister(..)
+                 | Instruction::SetSoundFromRegister(..))
+    }
+
+    pub fn blocks_invalidated(&self) -> u64 {
+        self.blocks_invalidated
+    }
+
+    pub fn instructions_compiled(&self) -> u64 {
+        self.instructions_compiled
+    }
+
+    /// Record that `count` instructions of a compiled block ran, all of
+    /// them unless one faulted.
+    pub fn record_run(&mut self, count: usize) {
+        self.instructions_compiled += count as u64;
+    }
+
+    /// Throw away every block that overlaps the `length` bytes written at
+    /// `address`, and let their addresses heat up again from scratch.
+    pub fn invalidate(&mut self, address: u16, length: u16) {
+        let written = address as u32..address as u32 + length as u32;
+        let stale: Vec<u16> = self.blocks.iter()
+                                         .filter(|(&start, block)| {
+                                             let end = start as u32 + 2 * block.as_ref().map_or(1, |block| block.len()) as u32;
+                                             written.start < end && (start as u32) < written.end
+                                         })
+                                         .map(|(&start, _)| start)
+                                         .collect();
+        for start in stale {
+            if let Some(Some(_)) = self.blocks.remove(&start) {
+                self.blocks_invalidated += 1;
+            }
+            self.hits[start as usize] = 0;
+        }
+    }
+
+    // Throw away every block, for when all of memory may have changed
+    pub fn invalidate_all(&mut self) {
+        self.blocks_invalidated += self.blocks.values().filter(|block| block.is_some()).count() as u64;
+        self.blocks.clear();
+        self.hits.iter_mut().for_each(|hits| *hits = 0);
+    }
+}
+
+impl Default for Dynarec {
+    fn default() -> Dynarec {
+        Dynarec::new()
+    }
+}
+
+impl fmt::Display for Dynarec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} blocks compiled, {} invalidated, {} instructions ran compiled",
+               self.blocks_compiled, self.blocks_invalidated, self.instructions_compiled)
+    }
+}
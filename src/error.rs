@@ -0,0 +1,46 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::scan::Extension;
+use crate::variant::Variant;
+
+/// The ways a program can crash the machine. These are faults in the ROM,
+/// not in the emulator, so they are reported rather than panicking: the
+/// caller decides whether to stop, show the error or keep fuzzing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Chip8Error {
+    EndOfMemory(u16),
+    UnknownInstruction { address: u16, opcode: u16 },
+    UnsupportedInstruction { address: u16, opcode: u16, variant: Variant, extension: Extension },
+    StackOverflow,
+    StackUnderflow,
+    InvalidAddress(u16),
+    InvalidKey(u8),
+    InvalidRegister(u8),
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Chip8Error::EndOfMemory(address) => write!(f, "Ran off the end of memory at {:#06X}.", address),
+            Chip8Error::UnknownInstruction { address, opcode } => {
+                write!(f, "Unknown instruction {:#06X} at {:#06X}.", opcode, address)
+            },
+            Chip8Error::UnsupportedInstruction { address, opcode, variant, extension } => {
+                let needs = match extension {
+                    Extension::SuperChip => Variant::SuperChip,
+                    Extension::XoChip => Variant::XoChip,
+                };
+                write!(f, "{:#06X} at {:#06X} is a {} instruction, which {} doesn't have. Try `--variant {}`.",
+                       opcode, address, extension, variant, needs.name())
+            },
+            Chip8Error::StackOverflow => write!(f, "Stack overflow."),
+            Chip8Error::StackUnderflow => write!(f, "Return with an empty stack."),
+            Chip8Error::InvalidAddress(address) => write!(f, "Invalid memory access at {:#06X}.", address),
+            Chip8Error::InvalidKey(key) => write!(f, "Invalid key {:#04X}.", key),
+            Chip8Error::InvalidRegister(register) => write!(f, "Invalid register V{:X}.", register),
+        }
+    }
+}
+
+impl Error for Chip8Error {}
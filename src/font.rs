@@ -0,0 +1,127 @@
+/// The hex digit sprites that FX29 points I at. Every interpreter shipped
+/// its own drawing of the digits and some games are designed around one in
+/// particular, so a few well-known styles are built in and any other can
+/// be loaded from a file: 80 bytes of 5-row digits 0-F, optionally followed
+/// by 160 bytes of 10-row SUPER-CHIP digits.
+pub struct Font {
+    data: Vec<u8>,
+}
+
+impl Font {
+    pub const SMALL_SIZE: usize = 16 * 5;
+    pub const BIG_SIZE: usize = 16 * 10;
+
+    const DEFAULT: [u8; Font::SMALL_SIZE] = [0xF0, 0x90, 0x90, 0x90, 0xF0,   // 0
+                                             0x20, 0x60, 0x20, 0x20, 0x70,   // 1
+                                             0xF0, 0x10, 0xF0, 0x80, 0xF0,   // 2
+                                             0xF0, 0x10, 0xF0, 0x10, 0xF0,   // 3
+                                             0x90, 0x90, 0xF0, 0x10, 0x10,   // 4
+                                             0xF0, 0x80, 0xF0, 0x10, 0xF0,   // 5
+                                             0xF0, 0x80, 0xF0, 0x90, 0xF0,   // 6
+                                             0xF0, 0x10, 0x20, 0x40, 0x40,   // 7
+                                             0xF0, 0x90, 0xF0, 0x90, 0xF0,   // 8
+                                             0xF0, 0x90, 0xF0, 0x10, 0xF0,   // 9
+                                             0xF0, 0x90, 0xF0, 0x90, 0x90,   // A
+                                             0xE0, 0x90, 0xE0, 0x90, 0xE0,   // B
+                                             0xF0, 0x80, 0x80, 0x80, 0xF0,   // C
+                                             0xE0, 0x90, 0x90, 0x90, 0xE0,   // D
+                                             0xF0, 0x80, 0xF0, 0x80, 0xF0,   // E
+                                             0xF0, 0x80, 0xF0, 0x80, 0x80];  // F
+
+    // The COSMAC VIP's digits, from its interpreter's ROM
+    const VIP: [u8; Font::SMALL_SIZE] = [0xF0, 0x90, 0x90, 0x90, 0xF0,   // 0
+                                         0x60, 0x20, 0x20, 0x20, 0x70,   // 1
+                                         0xF0, 0x10, 0xF0, 0x80, 0xF0,   // 2
+                                         0xF0, 0x10, 0xF0, 0x10, 0xF0,   // 3
+                                         0xA0, 0xA0, 0xF0, 0x20, 0x20,   // 4
+                                         0xF0, 0x80, 0xF0, 0x10, 0xF0,   // 5
+                                         0xF0, 0x80, 0xF0, 0x90, 0xF0,   // 6
+                                         0xF0, 0x10, 0x10, 0x10, 0x10,   // 7
+                                         0xF0, 0x90, 0xF0, 0x90, 0xF0,   // 8
+                                         0xF0, 0x90, 0xF0, 0x10, 0xF0,   // 9
+                                         0xF0, 0x90, 0xF0, 0x90, 0x90,   // A
+                                         0xF0, 0x50, 0x70, 0x50, 0xF0,   // B
+                                         0xF0, 0x80, 0x80, 0x80, 0xF0,   // C
+                                         0xF0, 0x50, 0x50, 0x50, 0xF0,   // D
+                                         0xF0, 0x80, 0xF0, 0x80, 0xF0,   // E
+                                         0xF0, 0x80, 0xF0, 0x80, 0x80];  // F
+
+    // The DREAM 6800's narrower, three pixel wide digits
+    const DREAM_6800: [u8; Font::SMALL_SIZE] = [0xE0, 0xA0, 0xA0, 0xA0, 0xE0,   // 0
+                                                0x40, 0x40, 0x40, 0x40, 0x40,   // 1
+                                                0xE0, 0x20, 0xE0, 0x80, 0xE0,   // 2
+                                                0xE0, 0x20, 0xE0, 0x20, 0xE0,   // 3
+                                                0x80, 0xA0, 0xA0, 0xE0, 0x20,   // 4
+                                                0xE0, 0x80, 0xE0, 0x20, 0xE0,   // 5
+                                                0xE0, 0x80, 0xE0, 0xA0, 0xE0,   // 6
+                                                0xE0, 0x20, 0x20, 0x20, 0x20,   // 7
+                                                0xE0, 0xA0, 0xE0, 0xA0, 0xE0,   // 8
+                                                0xE0, 0xA0, 0xE0, 0x20, 0xE0,   // 9
+                                                0xE0, 0xA0, 0xE0, 0xA0, 0xA0,   // A
+                                                0xC0, 0xA0, 0xE0, 0xA0, 0xC0,   // B
+                                                0xE0, 0x80, 0x80, 0x80, 0xE0,   // C
+                                                0xC0, 0xA0, 0xA0, 0xA0, 0xC0,   // D
+                                                0xE0, 0x80, 0xE0, 0x80, 0xE0,   // E
+                                                0xE0, 0x80, 0xC0, 0x80, 0x80];  // F
+
+    // A softer modern style with rounded corners
+    const ROUNDED: [u8; Font::SMALL_SIZE] = [0x60, 0x90, 0x90, 0x90, 0x60,   // 0
+                                             0x20, 0x60, 0x20, 0x20, 0x70,   // 1
+                                             0xE0, 0x10, 0x60, 0x80, 0xF0,   // 2
+                                             0xE0, 0x10, 0x60, 0x10, 0xE0,   // 3
+                                             0x90, 0x90, 0x70, 0x10, 0x10,   // 4
+                                             0xF0, 0x80, 0xE0, 0x10, 0xE0,   // 5
+                                             0x60, 0x80, 0xE0, 0x90, 0x60,   // 6
+                                             0xF0, 0x10, 0x20, 0x40, 0x40,   // 7
+                                             0x60, 0x90, 0x60, 0x90, 0x60,   // 8
+                                             0x60, 0x90, 0x70, 0x10, 0x60,   // 9
+                                             0x60, 0x90, 0xF0, 0x90, 0x90,   // A
+                                             0xE0, 0x90, 0xE0, 0x90, 0xE0,   // B
+                                             0x70, 0x80, 0x80, 0x80, 0x70,   // C
+                                             0xE0, 0x90, 0x90, 0x90, 0xE0,   // D
+                                             0xF0, 0x80, 0xE0, 0x80, 0xF0,   // E
+                                             0xF0, 0x80, 0xE0, 0x80, 0x80];  // F
+
+    // SUPER-CHIP's 10-row digits for FX30, as Octo draws them, A-F included
+    const BIG: [u8; Font::BIG_SIZE] = [0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF,   // 0
+                                       0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF,   // 1
+                                       0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF,   // 2
+                                       0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF,   // 3
+                                       0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03,   // 4
+                                       0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF,   // 5
+                                       0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF,   // 6
+                                       0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18,   // 7
+                                       0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF,   // 8
+                                       0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF,   // 9
+                                       0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3,   // A
+                                       0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC,   // B
+                                       0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C,   // C
+                                       0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC,   // D
+                                       0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF,   // E
+                                       0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0];  // F
+
+    pub fn builtin(name: &str) -> Option<Font> {
+        let small = match name {
+            "default" => Font::DEFAULT,
+            "vip" => Font::VIP,
+            "dream6800" => Font::DREAM_6800,
+            "rounded" => Font::ROUNDED,
+            _ => return None,
+        };
+        Some(Font { data: [&small[..], &Font::BIG[..]].concat() })
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Font, String> {
+        match data.len() {
+            Font::SMALL_SIZE => Ok(Font { data: data.to_vec() }),
+            length if length == Font::SMALL_SIZE + Font::BIG_SIZE => Ok(Font { data: data.to_vec() }),
+            length => Err(format!("a font is {} bytes, or {} with big digits, not {}",
+                                  Font::SMALL_SIZE, Font::SMALL_SIZE + Font::BIG_SIZE, length)),
+        }
+    }
+
+    /// The small digits followed by the big ones, if the font has them.
+    pub fn bytes(&self) -> &[u8] {
+        &self.data
+    }
+}
@@ -0,0 +1,79 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::scheduler::Scheduler;
+
+/*
+    Frame pacing for a windowed run: how far apart frames were presented,
+    how far that strayed from the 60hz ideal (the jitter), and how long
+    each frame spent emulating and rendering. Stutter on someone's machine
+    shows up here as numbers rather than a feeling.
+*/
+pub struct FrameStats {
+    last_present: Option<Instant>,
+    // Emulation time since the last present
+    emulating: Duration,
+    intervals: Vec<Duration>,
+    emulation: Vec<Duration>,
+    rendering: Vec<Duration>,
+}
+
+impl FrameStats {
+    pub fn new() -> FrameStats {
+        FrameStats {
+            last_present: None,
+            emulating: Duration::from_secs(0),
+            intervals: Vec::new(),
+            emulation: Vec::new(),
+            rendering: Vec::new(),
+        }
+    }
+
+    pub fn record_emulation(&mut self, time: Duration) {
+        self.emulating += time;
+    }
+
+    /*
+        Record a frame that was just presented after `rendering` spent
+        drawing it.
+    */
+    pub fn record_present(&mut self, rendering: Duration) {
+        let now = Instant::now();
+        if let Some(last_present) = self.last_present {
+            self.intervals.push(now - last_present);
+        }
+        self.last_present = Some(now);
+        self.emulation.push(std::mem::replace(&mut self.emulating, Duration::from_secs(0)));
+        self.rendering.push(rendering);
+    }
+}
+
+/*
+    Average, median, 95th and 99th percentile, and worst of some timings.
+*/
+fn summary(f: &mut fmt::Formatter, name: &str, samples: &[Duration]) -> fmt::Result {
+    let mut sorted = samples.to_vec();
+    sorted.sort_unstable();
+    let millis = |duration: Duration| duration.as_secs_f64() * 1000.0;
+    let percentile = |percent: usize| millis(sorted[(sorted.len() - 1) * percent / 100]);
+    let average = millis(sorted.iter().sum::<Duration>()) / sorted.len() as f64;
+    writeln!(f, "  {:<10} avg {:6.2}ms  p50 {:6.2}ms  p95 {:6.2}ms  p99 {:6.2}ms  max {:6.2}ms",
+             name, average, percentile(50), percentile(95), percentile(99), percentile(100))
+}
+
+impl fmt::Display for FrameStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.intervals.is_empty() {
+            return writeln!(f, "=> Frame pacing: too few frames were presented to measure.");
+        }
+        let ideal = Scheduler::frame_duration();
+        let jitter: Vec<Duration> = self.intervals.iter().map(|&interval| interval.abs_diff(ideal)).collect();
+
+        writeln!(f, "=> Frame pacing over {} frames, against {:.2}ms at 60hz:", self.rendering.len(),
+                 ideal.as_secs_f64() * 1000.0)?;
+        summary(f, "interval", &self.intervals)?;
+        summary(f, "jitter", &jitter)?;
+        summary(f, "emulation", &self.emulation)?;
+        summary(f, "rendering", &self.rendering)
+    }
+}
@@ -0,0 +1,82 @@
+use std::collections::HashMap;
+
+use sdl2::controller::{Button, GameController};
+use sdl2::GameControllerSubsystem;
+
+/*
+    Game controllers, attached as they're plugged in and detached as
+    they're pulled out, including mid-game. SDL announces pads that are
+    already connected at startup the same way, so there's nothing to scan
+    for. Every pad presses the same keys, chosen so the d-pad matches the
+    W/A/S/D keys games usually move with:
+
+    d-pad up, left, down, right    5 7 8 9
+    A, B, X, Y                     6 4 1 2
+    left and right shoulder        C D
+*/
+pub struct Gamepads {
+    subsystem: GameControllerSubsystem,
+    // Open pads by joystick instance id, with the hex keys each is holding
+    attached: HashMap<u32, (GameController, u16)>,
+}
+
+impl Gamepads {
+    pub fn new(subsystem: GameControllerSubsystem) -> Gamepads {
+        Gamepads { subsystem, attached: HashMap::new() }
+    }
+
+    fn key(button: Button) -> Option<u8> {
+        match button {
+            Button::DPadUp => Some(0x5),
+            Button::DPadLeft => Some(0x7),
+            Button::DPadDown => Some(0x8),
+            Button::DPadRight => Some(0x9),
+            Button::A => Some(0x6),
+            Button::B => Some(0x4),
+            Button::X => Some(0x1),
+            Button::Y => Some(0x2),
+            Button::LeftShoulder => Some(0xC),
+            Button::RightShoulder => Some(0xD),
+            _ => None,
+        }
+    }
+
+    /*
+        Open the pad SDL just announced at `joystick_index`. Returns its
+        name, or None if it couldn't be opened or is already attached.
+    */
+    pub fn attach(&mut self, joystick_index: u32) -> Option<String> {
+        let controller = self.subsystem.open(joystick_index).ok()?;
+        let name = controller.name();
+        match self.attached.insert(controller.instance_id(), (controller, 0)) {
+            Some(_) => None,
+            None => Some(name),
+        }
+    }
+
+    /*
+        Let go of a pad that was pulled out. Returns its name and the keys
+        it was holding, which need releasing.
+    */
+    pub fn detach(&mut self, instance_id: u32) -> Option<(String, Vec<u8>)> {
+        let (controller, held) = self.attached.remove(&instance_id)?;
+        let keys = (0..16).filter(|key| held & (1 << key) != 0).collect();
+        Some((controller.name(), keys))
+    }
+
+    /*
+        The hex key a button press or release on pad `instance_id` stands
+        for, if it's bound.
+    */
+    pub fn button(&mut self, instance_id: u32, button: Button, is_pressed: bool) -> Option<u8> {
+        let key = Gamepads::key(button)?;
+        if let Some((_, held)) = self.attached.get_mut(&instance_id) {
+            if is_pressed {
+                *held |= 1 << key;
+            } else {
+                *held &= !(1 << key);
+            }
+        }
+        Some(key)
+    }
+}
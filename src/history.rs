@@ -0,0 +1,60 @@
+use std::collections::VecDeque;
+use std::fmt;
+
+use crate::instructions::Instruction;
+use crate::registers::Registers;
+use crate::trace::Tracer;
+
+/// The last instructions the machine executed, so that when a program
+/// faults the report can show how it got there rather than just the
+/// instruction that failed. Keeping it copies the registers twice an
+/// instruction, so it's only kept when asked for with
+/// `Chip8::enable_history`.
+pub struct History {
+    entries: VecDeque<Entry>,
+}
+
+struct Entry {
+    cycle: u64,
+    pc: u16,
+    instruction: Instruction,
+    before: Registers,
+    after: Registers,
+}
+
+impl History {
+    pub const LENGTH: usize = 256;
+
+    pub fn new() -> History {
+        History { entries: VecDeque::with_capacity(History::LENGTH) }
+    }
+
+    pub fn record(&mut self, cycle: u64, pc: u16, instruction: Instruction, before: Registers, after: &Registers) {
+        if self.entries.len() == History::LENGTH {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(Entry { cycle, pc, instruction, before, after: after.clone() });
+    }
+}
+
+impl Default for History {
+    fn default() -> History {
+        History::new()
+    }
+}
+
+impl fmt::Display for History {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "=> The last {} instructions, oldest first:", self.entries.len())?;
+        for entry in &self.entries {
+            let changed: Vec<String> = Tracer::register_changes(&entry.before, &entry.after)
+                                              .iter()
+                                              .map(|(name, old, new)| format!("{}: {:#X} -> {:#X}", name, old, new))
+                                              .collect();
+            let line = format!("{:>8} {:#06X} {:#06X} {:<16} {}", entry.cycle, entry.pc, entry.instruction.encode(),
+                               entry.instruction, changed.join(", "));
+            writeln!(f, "{}", line.trim_end())?;
+        }
+        Ok(())
+    }
+}
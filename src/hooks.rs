@@ -0,0 +1,37 @@
+use std::fmt;
+
+use crate::instructions::Instruction;
+
+/// Things the core tells hooks about as they happen, so profilers, scripts
+/// and frontends can follow a run without reaching into `execute`. Events
+/// come after the instruction behind them has executed, and are worked out
+/// by comparing the machine before and after it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CoreEvent {
+    InstructionExecuted { pc: u16, instruction: Instruction },
+    // VF as the draw left it
+    Drew { pc: u16, collision: u8 },
+    SoundStarted,
+    SoundStopped,
+    KeyWaitEntered { register: u8 },
+    SubroutineCalled { address: u16, return_address: u16 },
+}
+
+/// A callback for core events. Hooks run in the order they were added and
+/// can't touch the machine, only note what happened.
+pub type Hook = Box<dyn FnMut(&CoreEvent)>;
+
+impl fmt::Display for CoreEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CoreEvent::InstructionExecuted { pc, instruction } => write!(f, "{:#06X} executed {}", pc, instruction),
+            CoreEvent::Drew { pc, collision } => write!(f, "{:#06X} drew, VF = {}", pc, collision),
+            CoreEvent::SoundStarted => write!(f, "sound started"),
+            CoreEvent::SoundStopped => write!(f, "sound stopped"),
+            CoreEvent::KeyWaitEntered { register } => write!(f, "waiting for a key into V{:X}", register),
+            CoreEvent::SubroutineCalled { address, return_address } => {
+                write!(f, "called {:#06X}, returning to {:#06X}", address, return_address)
+            },
+        }
+    }
+}
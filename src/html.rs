@@ -0,0 +1,100 @@
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+use crate::disassembler::{self, PROGRAM_OFFSET};
+use crate::instructions::{self, Instruction};
+
+/*
+    A standalone HTML page of the disassembly, for browsing a ROM's code
+    without yac8. Every line is anchored by address, jump, call and `LD I`
+    operands link to their targets, words that aren't reachable code are
+    dimmed, and the data that `LD I` points at is previewed as a sprite.
+*/
+const STYLE: &str = "body { background: #111; color: #ddd; font-family: monospace; }
+a { color: #7af; text-decoration: none; }
+a:hover { text-decoration: underline; }
+.line:target { background: #335; }
+.data { color: #777; }
+.address { color: #999; }
+.opcode { color: #fc6; }
+.sprite { display: inline-block; white-space: pre; color: #eee; line-height: 1; margin: 2px 0 6px 11ch; }";
+
+// Sprite previews stop here, or at the next line something points at
+const MAX_SPRITE_ROWS: usize = 15;
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn link(address: u16) -> String {
+    // Lines are a word each, so an odd address links to the line holding it
+    format!("<a href=\"#a{:03X}\">{:#05X}</a>", address & !1, address)
+}
+
+/*
+    The mnemonic with its address operand, if any, linked to the line it
+    points at.
+*/
+fn mnemonic(instruction: &Instruction, in_rom: &dyn Fn(u16) -> bool) -> String {
+    let text = escape(&instruction.to_string());
+    match *instruction {
+        Instruction::Jump(target) | Instruction::Call(target) | Instruction::SetI(target)
+        | Instruction::JumpFromOffset(target) if in_rom(target) => {
+            text.replace(&format!("{:#05X}", target), &link(target))
+        },
+        _ => text,
+    }
+}
+
+fn sprite_preview(rom: &[u8], address: u16, referenced: &BTreeSet<u16>) -> String {
+    let start = (address - PROGRAM_OFFSET) as usize;
+    let next_reference = referenced.range(address + 1..).next().map_or(usize::MAX, |&next| (next - PROGRAM_OFFSET) as usize);
+    let end = rom.len().min(start + MAX_SPRITE_ROWS).min(next_reference);
+    let rows: Vec<String> = rom[start..end].iter()
+                                           .map(|&row| (0..8).map(|bit| if row & (0x80 >> bit) != 0 { '█' } else { '·' }).collect())
+                                           .collect();
+    format!("<span class=\"sprite\">{}</span>\n", rows.join("\n"))
+}
+
+pub fn to_html(rom: &[u8], name: &str) -> String {
+    let reachable = disassembler::reachable(rom);
+    let rom_end = PROGRAM_OFFSET as usize + rom.len();
+    let in_rom = |address: u16| (PROGRAM_OFFSET as usize..rom_end).contains(&(address as usize));
+
+    let sprites: BTreeSet<u16> = reachable.iter()
+                                          .filter_map(|&address| match disassembler::opcode_at(rom, address).map(instructions::parse_opcode) {
+                                              Some(Instruction::SetI(target)) if in_rom(target) => Some(target),
+                                              _ => None,
+                                          })
+                                          .collect();
+    // Lines that something links to, and so where a sprite preview stops
+    let mut referenced = sprites.clone();
+    for &address in &reachable {
+        let opcode = disassembler::opcode_at(rom, address).expect("Reachable addresses are inside the ROM.");
+        referenced.extend(disassembler::successors(rom, address, opcode));
+    }
+
+    let mut html = String::new();
+    writeln!(html, "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">").unwrap();
+    writeln!(html, "<title>{}</title>\n<style>\n{}\n</style>\n</head>\n<body>", escape(name), STYLE).unwrap();
+    writeln!(html, "<h1>{}</h1>\n<p>{} bytes, {} reachable instructions</p>", escape(name), rom.len(), reachable.len()).unwrap();
+    writeln!(html, "<pre>").unwrap();
+    for (num, word) in rom.chunks(2).enumerate() {
+        let address = PROGRAM_OFFSET + 2 * num as u16;
+        let class = if reachable.contains(&address) { "line" } else { "line data" };
+        let (raw, text) = match *word {
+            [high, low] => {
+                let opcode = ((high as u16) << 8) | low as u16;
+                (format!("{:04X}", opcode), mnemonic(&instructions::parse_opcode(opcode), &in_rom))
+            },
+            _ => (format!("{:02X}  ", word[0]), format!("DB {:#04X}", word[0])),
+        };
+        writeln!(html, "<span class=\"{}\" id=\"a{:03X}\"><span class=\"address\">{:#05X}:</span> <span class=\"opcode\">{}</span>  {}</span>",
+                 class, address, address, raw, text).unwrap();
+        for &sprite in sprites.range(address..address + 2) {
+            write!(html, "{}", sprite_preview(rom, sprite, &referenced)).unwrap();
+        }
+    }
+    writeln!(html, "</pre>\n</body>\n</html>").unwrap();
+    html
+}
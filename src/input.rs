@@ -0,0 +1,63 @@
+use std::cell::Cell;
+use std::rc::Rc;
+
+/// Somewhere the machine reads the hex pad from: the SDL window, a bot, a
+/// test or a recording. The machine polls its source when it starts
+/// running and after every frame, and presses or releases whatever keys
+/// changed since the last poll, so a source never has to know about the
+/// event loop and input stays in step with emulated time.
+///
+/// Keys are a bitmask with key N in bit N. Any `FnMut(frame) -> u16`
+/// closure is a source, which is the easy way to script one.
+pub trait InputSource {
+    /// The keys held as of `frame`, the number of frames elapsed so far.
+    fn keys(&mut self, frame: u64) -> u16;
+}
+
+impl<F: FnMut(u64) -> u16> InputSource for F {
+    fn keys(&mut self, frame: u64) -> u16 {
+        self(frame)
+    }
+}
+
+/// Keys held by a frontend that learns of presses from events. The
+/// frontend sets keys on its handle as events arrive and the machine polls
+/// a clone. A key pressed and released between two polls still reads as
+/// held for one, so a quick tap isn't lost.
+#[derive(Clone, Default)]
+pub struct SharedKeys {
+    held: Rc<Cell<u16>>,
+    // Pressed since the last poll
+    pressed: Rc<Cell<u16>>,
+}
+
+impl SharedKeys {
+    pub fn set(&self, key: u8, is_pressed: bool) {
+        let held = self.held.get();
+        self.held.set(if is_pressed { held | 1 << key } else { held & !(1 << key) });
+        if is_pressed {
+            self.pressed.set(self.pressed.get() | 1 << key);
+        }
+    }
+}
+
+impl InputSource for SharedKeys {
+    fn keys(&mut self, _frame: u64) -> u16 {
+        self.held.get() | self.pressed.replace(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_tap_between_polls_is_held_for_one() {
+        let keys = SharedKeys::default();
+        let mut source = keys.clone();
+        keys.set(0x5, true);
+        keys.set(0x5, false);
+        assert_eq!(source.keys(0), 1 << 0x5);
+        assert_eq!(source.keys(1), 0);
+    }
+}
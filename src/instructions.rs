@@ -1,8 +1,16 @@
+use std::fmt;
+
+#[cfg(feature = "fuzzing")]
+use rand::distributions::{Distribution, Standard};
+#[cfg(feature = "fuzzing")]
+use rand::Rng;
+
 type Register = u8;
 type Data = u8;
 type Address = u16;
 
-#[derive(Debug)]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Instruction {
     ClearScreen,
     Return,
@@ -19,9 +27,9 @@ pub enum Instruction {
     Xor(Register, Register),
     Add(Register, Register),
     Sub(Register, Register),
-    ShiftRight(Register),
+    ShiftRight(Register, Register),
     NegatedSub(Register, Register),
-    ShiftLeft(Register),
+    ShiftLeft(Register, Register),
     SkipIfNERegister(Register, Register),
     SetI(Address),
     JumpFromOffset(Address),
@@ -38,19 +46,308 @@ pub enum Instruction {
     SetBCDRepresentation(Register),
     StoreRegisters(Register),
     ReadRegisters(Register),
+    // SUPER-CHIP
+    ScrollDown(Data),
+    ScrollRight,
+    ScrollLeft,
+    Exit,
+    LowRes,
+    HiRes,
+    LoadLargeSprite(Register),
+    StoreFlags(Register),
+    ReadFlags(Register),
+    // XO-CHIP
+    ScrollUp(Data),
+    SaveRange(Register, Register),
+    LoadRange(Register, Register),
+    LongSetI,
+    SelectPlanes(Data),
     NOP(u16),
     UNKNOWN(u16),
 }
 
-/*
-    parse a big endian, 2-byte opcode into its corresponding CHIP-8
-    instruction.
-*/
+/// Every opcode pattern the interpreter understands, in opcode order,
+/// SUPER-CHIP's and XO-CHIP's included.
+pub const PATTERNS: [&str; 49] = ["0NNN", "00CN", "00DN", "00E0", "00EE", "00FB", "00FC", "00FD",
+                                  "00FE", "00FF", "1NNN", "2NNN", "3XNN", "4XNN", "5XY0", "5XY2",
+                                  "5XY3", "6XNN", "7XNN", "8XY0", "8XY1", "8XY2", "8XY3", "8XY4",
+                                  "8XY5", "8XY6", "8XY7", "8XYE", "9XY0", "ANNN", "BNNN", "CXNN",
+                                  "DXYN", "EX9E", "EXA1", "F000", "FN01", "FX07", "FX0A", "FX15",
+                                  "FX18", "FX1E", "FX29", "FX30", "FX33", "FX55", "FX65", "FX75",
+                                  "FX85"];
+
+impl Instruction {
+    /// The opcode pattern this instruction decodes from, e.g. `8XY4` for Add.
+    /// Unknown opcodes have no pattern and report `????`.
+    pub fn pattern(&self) -> &'static str {
+        match self {
+            Instruction::ClearScreen => "00E0",
+            Instruction::Return => "00EE",
+            Instruction::Jump(_) => "1NNN",
+            Instruction::Call(_) => "2NNN",
+            Instruction::SkipIfEQData(..) => "3XNN",
+            Instruction::SkipIfNEData(..) => "4XNN",
+            Instruction::SkipIfEQRegister(..) => "5XY0",
+            Instruction::LoadData(..) => "6XNN",
+            Instruction::AddData(..) => "7XNN",
+            Instruction::LoadRegister(..) => "8XY0",
+            Instruction::Or(..) => "8XY1",
+            Instruction::And(..) => "8XY2",
+            Instruction::Xor(..) => "8XY3",
+            Instruction::Add(..) => "8XY4",
+            Instruction::Sub(..) => "8XY5",
+            Instruction::ShiftRight(..) => "8XY6",
+            Instruction::NegatedSub(..) => "8XY7",
+            Instruction::ShiftLeft(..) => "8XYE",
+            Instruction::SkipIfNERegister(..) => "9XY0",
+            Instruction::SetI(_) => "ANNN",
+            Instruction::JumpFromOffset(_) => "BNNN",
+            Instruction::Random(..) => "CXNN",
+            Instruction::Draw(..) => "DXYN",
+            Instruction::SkipIfPressed(_) => "EX9E",
+            Instruction::SkipIfNotPressed(_) => "EXA1",
+            Instruction::SetRegisterFromDelay(_) => "FX07",
+            Instruction::AwaitPress(_) => "FX0A",
+            Instruction::SetDelayFromRegister(_) => "FX15",
+            Instruction::SetSoundFromRegister(_) => "FX18",
+            Instruction::AddI(_) => "FX1E",
+            Instruction::LoadSprite(_) => "FX29",
+            Instruction::SetBCDRepresentation(_) => "FX33",
+            Instruction::StoreRegisters(_) => "FX55",
+            Instruction::ReadRegisters(_) => "FX65",
+            Instruction::ScrollDown(_) => "00CN",
+            Instruction::ScrollRight => "00FB",
+            Instruction::ScrollLeft => "00FC",
+            Instruction::Exit => "00FD",
+            Instruction::LowRes => "00FE",
+            Instruction::HiRes => "00FF",
+            Instruction::LoadLargeSprite(_) => "FX30",
+            Instruction::StoreFlags(_) => "FX75",
+            Instruction::ReadFlags(_) => "FX85",
+            Instruction::ScrollUp(_) => "00DN",
+            Instruction::SaveRange(..) => "5XY2",
+            Instruction::LoadRange(..) => "5XY3",
+            Instruction::LongSetI => "F000",
+            Instruction::SelectPlanes(_) => "FN01",
+            Instruction::NOP(_) => "0NNN",
+            Instruction::UNKNOWN(_) => "????",
+        }
+    }
+
+    /// What the instruction does, in a sentence for someone learning
+    /// CHIP-8, e.g. "XOR a 5-byte sprite from I at (V0, V1), VF =
+    /// collision". Where interpreters disagree it describes the original
+    /// behaviour and says so.
+    pub fn explain(&self) -> String {
+        match *self {
+            Instruction::ClearScreen => String::from("Clear the display"),
+            Instruction::Return => String::from("Return from a subroutine to the address on top of the stack"),
+            Instruction::Jump(address) => format!("Jump to {:#05X}", address),
+            Instruction::Call(address) => format!("Call the subroutine at {:#05X}, pushing the return address", address),
+            Instruction::SkipIfEQData(x, data) => format!("Skip the next instruction if V{:X} is {:#04X}", x, data),
+            Instruction::SkipIfNEData(x, data) => format!("Skip the next instruction unless V{:X} is {:#04X}", x, data),
+            Instruction::SkipIfEQRegister(x, y) => format!("Skip the next instruction if V{:X} equals V{:X}", x, y),
+            Instruction::LoadData(x, data) => format!("Set V{:X} to {:#04X}", x, data),
+            Instruction::AddData(x, data) => format!("Add {:#04X} to V{:X}, wrapping past 0xFF without touching VF", data, x),
+            Instruction::LoadRegister(x, y) => format!("Copy V{:X} into V{:X}", y, x),
+            Instruction::Or(x, y) => format!("Set V{:X} to V{:X} OR V{:X}", x, x, y),
+            Instruction::And(x, y) => format!("Set V{:X} to V{:X} AND V{:X}", x, x, y),
+            Instruction::Xor(x, y) => format!("Set V{:X} to V{:X} XOR V{:X}", x, x, y),
+            Instruction::Add(x, y) => format!("Add V{:X} to V{:X}, VF = carry", y, x),
+            Instruction::Sub(x, y) => format!("Subtract V{:X} from V{:X}, VF = no borrow", y, x),
+            Instruction::ShiftRight(x, y) => {
+                format!("Shift V{:X} right one bit into V{:X}, VF = the bit shifted out (some interpreters shift V{:X} itself)", y, x, x)
+            },
+            Instruction::NegatedSub(x, y) => format!("Set V{:X} to V{:X} minus V{:X}, VF = no borrow", x, y, x),
+            Instruction::ShiftLeft(x, y) => {
+                format!("Shift V{:X} left one bit into V{:X}, VF = the bit shifted out (some interpreters shift V{:X} itself)", y, x, x)
+            },
+            Instruction::SkipIfNERegister(x, y) => format!("Skip the next instruction unless V{:X} equals V{:X}", x, y),
+            Instruction::SetI(address) => format!("Point I at {:#05X}", address),
+            Instruction::JumpFromOffset(address) => format!("Jump to {:#05X} plus V0", address),
+            Instruction::Random(x, data) => format!("Set V{:X} to a random byte AND {:#04X}", x, data),
+            Instruction::Draw(x, y, rows) => {
+                format!("XOR a {}-byte sprite from I at (V{:X}, V{:X}), VF = collision", rows, x, y)
+            },
+            Instruction::SkipIfPressed(x) => format!("Skip the next instruction if the key in V{:X} is held", x),
+            Instruction::SkipIfNotPressed(x) => format!("Skip the next instruction unless the key in V{:X} is held", x),
+            Instruction::SetRegisterFromDelay(x) => format!("Copy the delay timer into V{:X}", x),
+            Instruction::AwaitPress(x) => format!("Stop until a key is pressed and released, and put it in V{:X}", x),
+            Instruction::SetDelayFromRegister(x) => format!("Set the delay timer to V{:X}", x),
+            Instruction::SetSoundFromRegister(x) => format!("Sound the buzzer for V{:X} sixtieths of a second", x),
+            Instruction::AddI(x) => format!("Add V{:X} to I", x),
+            Instruction::LoadSprite(x) => format!("Point I at the font sprite for the hex digit in V{:X}", x),
+            Instruction::SetBCDRepresentation(x) => {
+                format!("Store the hundreds, tens and ones digits of V{:X} at I, I+1 and I+2", x)
+            },
+            Instruction::StoreRegisters(x) => format!("Store V0 to V{:X} in memory starting at I", x),
+            Instruction::ReadRegisters(x) => format!("Load V0 to V{:X} from memory starting at I", x),
+            Instruction::ScrollDown(rows) => format!("Scroll the display down {} pixels (SUPER-CHIP)", rows),
+            Instruction::ScrollRight => String::from("Scroll the display right 4 pixels (SUPER-CHIP)"),
+            Instruction::ScrollLeft => String::from("Scroll the display left 4 pixels (SUPER-CHIP)"),
+            Instruction::Exit => String::from("Stop the program (SUPER-CHIP)"),
+            Instruction::LowRes => String::from("Switch to the 64x32 display and clear it (SUPER-CHIP)"),
+            Instruction::HiRes => String::from("Switch to the 128x64 display and clear it (SUPER-CHIP)"),
+            Instruction::LoadLargeSprite(x) => {
+                format!("Point I at the 10-row font sprite for the digit in V{:X} (SUPER-CHIP)", x)
+            },
+            Instruction::StoreFlags(x) => format!("Save V0 to V{:X} in the HP-48's RPL flags (SUPER-CHIP)", x),
+            Instruction::ReadFlags(x) => format!("Load V0 to V{:X} from the HP-48's RPL flags (SUPER-CHIP)", x),
+            Instruction::ScrollUp(rows) => format!("Scroll the selected planes up {} pixels (XO-CHIP)", rows),
+            Instruction::SaveRange(x, y) => format!("Store V{:X} to V{:X} in memory starting at I, leaving I alone (XO-CHIP)", x, y),
+            Instruction::LoadRange(x, y) => format!("Load V{:X} to V{:X} from memory starting at I, leaving I alone (XO-CHIP)", x, y),
+            Instruction::LongSetI => String::from("Point I at the 16-bit address in the next word, and skip it (XO-CHIP)"),
+            Instruction::SelectPlanes(planes) => {
+                format!("Draw, clear and scroll bit-plane mask {} from now on (XO-CHIP)", planes)
+            },
+            Instruction::NOP(bytes) => format!("Call machine code at {:#05X}, which interpreters ignore", mask_address(bytes)),
+            Instruction::UNKNOWN(bytes) => format!("{:#06X} isn't an instruction; it's probably data", bytes),
+        }
+    }
+
+    /// The opcode this instruction decodes from; the inverse of
+    /// `parse_opcode`. Every opcode survives the round trip, including the
+    /// otherwise ignored Y of the shifts and the raw bytes of SYS and
+    /// unknown opcodes.
+    pub fn encode(&self) -> u16 {
+        match *self {
+            Instruction::ClearScreen => 0x00E0,
+            Instruction::Return => 0x00EE,
+            Instruction::Jump(address) => 0x1000 | address & 0x0FFF,
+            Instruction::Call(address) => 0x2000 | address & 0x0FFF,
+            Instruction::SkipIfEQData(x, data) => encode_register_data(0x3000, x, data),
+            Instruction::SkipIfNEData(x, data) => encode_register_data(0x4000, x, data),
+            Instruction::SkipIfEQRegister(x, y) => encode_registers(0x5000, x, y),
+            Instruction::LoadData(x, data) => encode_register_data(0x6000, x, data),
+            Instruction::AddData(x, data) => encode_register_data(0x7000, x, data),
+            Instruction::LoadRegister(x, y) => encode_registers(0x8000, x, y),
+            Instruction::Or(x, y) => encode_registers(0x8001, x, y),
+            Instruction::And(x, y) => encode_registers(0x8002, x, y),
+            Instruction::Xor(x, y) => encode_registers(0x8003, x, y),
+            Instruction::Add(x, y) => encode_registers(0x8004, x, y),
+            Instruction::Sub(x, y) => encode_registers(0x8005, x, y),
+            Instruction::ShiftRight(x, y) => encode_registers(0x8006, x, y),
+            Instruction::NegatedSub(x, y) => encode_registers(0x8007, x, y),
+            Instruction::ShiftLeft(x, y) => encode_registers(0x800E, x, y),
+            Instruction::SkipIfNERegister(x, y) => encode_registers(0x9000, x, y),
+            Instruction::SetI(address) => 0xA000 | address & 0x0FFF,
+            Instruction::JumpFromOffset(address) => 0xB000 | address & 0x0FFF,
+            Instruction::Random(x, data) => encode_register_data(0xC000, x, data),
+            Instruction::Draw(x, y, rows) => encode_registers(0xD000, x, y) | (rows & 0x0F) as u16,
+            Instruction::SkipIfPressed(x) => encode_register_data(0xE09E, x, 0),
+            Instruction::SkipIfNotPressed(x) => encode_register_data(0xE0A1, x, 0),
+            Instruction::SetRegisterFromDelay(x) => encode_register_data(0xF007, x, 0),
+            Instruction::AwaitPress(x) => encode_register_data(0xF00A, x, 0),
+            Instruction::SetDelayFromRegister(x) => encode_register_data(0xF015, x, 0),
+            Instruction::SetSoundFromRegister(x) => encode_register_data(0xF018, x, 0),
+            Instruction::AddI(x) => encode_register_data(0xF01E, x, 0),
+            Instruction::LoadSprite(x) => encode_register_data(0xF029, x, 0),
+            Instruction::SetBCDRepresentation(x) => encode_register_data(0xF033, x, 0),
+            Instruction::StoreRegisters(x) => encode_register_data(0xF055, x, 0),
+            Instruction::ReadRegisters(x) => encode_register_data(0xF065, x, 0),
+            Instruction::ScrollDown(rows) => 0x00C0 | (rows & 0x0F) as u16,
+            Instruction::ScrollRight => 0x00FB,
+            Instruction::ScrollLeft => 0x00FC,
+            Instruction::Exit => 0x00FD,
+            Instruction::LowRes => 0x00FE,
+            Instruction::HiRes => 0x00FF,
+            Instruction::LoadLargeSprite(x) => encode_register_data(0xF030, x, 0),
+            Instruction::StoreFlags(x) => encode_register_data(0xF075, x, 0),
+            Instruction::ReadFlags(x) => encode_register_data(0xF085, x, 0),
+            Instruction::ScrollUp(rows) => 0x00D0 | (rows & 0x0F) as u16,
+            Instruction::SaveRange(x, y) => encode_registers(0x5002, x, y),
+            Instruction::LoadRange(x, y) => encode_registers(0x5003, x, y),
+            Instruction::LongSetI => 0xF000,
+            Instruction::SelectPlanes(planes) => encode_register_data(0xF001, planes, 0),
+            Instruction::NOP(bytes) => bytes,
+            Instruction::UNKNOWN(bytes) => bytes,
+        }
+    }
+}
+
+/// Assembly-style mnemonics in the conventional Cowgod notation, e.g.
+/// `LD V3, 0x1F` or `DRW V0, V1, 5`.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Instruction::ClearScreen => write!(f, "CLS"),
+            Instruction::Return => write!(f, "RET"),
+            Instruction::Jump(address) => write!(f, "JP {:#05X}", address),
+            Instruction::Call(address) => write!(f, "CALL {:#05X}", address),
+            Instruction::SkipIfEQData(x, data) => write!(f, "SE V{:X}, {:#04X}", x, data),
+            Instruction::SkipIfNEData(x, data) => write!(f, "SNE V{:X}, {:#04X}", x, data),
+            Instruction::SkipIfEQRegister(x, y) => write!(f, "SE V{:X}, V{:X}", x, y),
+            Instruction::LoadData(x, data) => write!(f, "LD V{:X}, {:#04X}", x, data),
+            Instruction::AddData(x, data) => write!(f, "ADD V{:X}, {:#04X}", x, data),
+            Instruction::LoadRegister(x, y) => write!(f, "LD V{:X}, V{:X}", x, y),
+            Instruction::Or(x, y) => write!(f, "OR V{:X}, V{:X}", x, y),
+            Instruction::And(x, y) => write!(f, "AND V{:X}, V{:X}", x, y),
+            Instruction::Xor(x, y) => write!(f, "XOR V{:X}, V{:X}", x, y),
+            Instruction::Add(x, y) => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Instruction::Sub(x, y) => write!(f, "SUB V{:X}, V{:X}", x, y),
+            Instruction::ShiftRight(x, y) => write!(f, "SHR V{:X}, V{:X}", x, y),
+            Instruction::NegatedSub(x, y) => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            Instruction::ShiftLeft(x, y) => write!(f, "SHL V{:X}, V{:X}", x, y),
+            Instruction::SkipIfNERegister(x, y) => write!(f, "SNE V{:X}, V{:X}", x, y),
+            Instruction::SetI(address) => write!(f, "LD I, {:#05X}", address),
+            Instruction::JumpFromOffset(address) => write!(f, "JP V0, {:#05X}", address),
+            Instruction::Random(x, data) => write!(f, "RND V{:X}, {:#04X}", x, data),
+            Instruction::Draw(x, y, rows) => write!(f, "DRW V{:X}, V{:X}, {}", x, y, rows),
+            Instruction::SkipIfPressed(x) => write!(f, "SKP V{:X}", x),
+            Instruction::SkipIfNotPressed(x) => write!(f, "SKNP V{:X}", x),
+            Instruction::SetRegisterFromDelay(x) => write!(f, "LD V{:X}, DT", x),
+            Instruction::AwaitPress(x) => write!(f, "LD V{:X}, K", x),
+            Instruction::SetDelayFromRegister(x) => write!(f, "LD DT, V{:X}", x),
+            Instruction::SetSoundFromRegister(x) => write!(f, "LD ST, V{:X}", x),
+            Instruction::AddI(x) => write!(f, "ADD I, V{:X}", x),
+            Instruction::LoadSprite(x) => write!(f, "LD F, V{:X}", x),
+            Instruction::SetBCDRepresentation(x) => write!(f, "LD B, V{:X}", x),
+            Instruction::StoreRegisters(x) => write!(f, "LD [I], V{:X}", x),
+            Instruction::ReadRegisters(x) => write!(f, "LD V{:X}, [I]", x),
+            Instruction::ScrollDown(rows) => write!(f, "SCD {}", rows),
+            Instruction::ScrollRight => write!(f, "SCR"),
+            Instruction::ScrollLeft => write!(f, "SCL"),
+            Instruction::Exit => write!(f, "EXIT"),
+            Instruction::LowRes => write!(f, "LOW"),
+            Instruction::HiRes => write!(f, "HIGH"),
+            Instruction::LoadLargeSprite(x) => write!(f, "LD HF, V{:X}", x),
+            Instruction::StoreFlags(x) => write!(f, "LD R, V{:X}", x),
+            Instruction::ReadFlags(x) => write!(f, "LD V{:X}, R", x),
+            Instruction::ScrollUp(rows) => write!(f, "SCU {}", rows),
+            Instruction::SaveRange(x, y) => write!(f, "SAVE V{:X} - V{:X}", x, y),
+            Instruction::LoadRange(x, y) => write!(f, "LOAD V{:X} - V{:X}", x, y),
+            Instruction::LongSetI => write!(f, "LD I, LONG"),
+            Instruction::SelectPlanes(planes) => write!(f, "PLANE {}", planes),
+            Instruction::NOP(bytes) => write!(f, "SYS {:#05X}", mask_address(bytes)),
+            Instruction::UNKNOWN(bytes) => write!(f, "DW {:#06X}", bytes),
+        }
+    }
+}
+
+/// Random instructions for fuzzers and property tests, drawn uniformly from
+/// every opcode so SYS and unknown instructions turn up as often as in a
+/// hostile ROM. Enabled with the `fuzzing` feature.
+#[cfg(feature = "fuzzing")]
+impl Distribution<Instruction> for Standard {
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Instruction {
+        parse_opcode(rng.gen())
+    }
+}
+
+/// parse a big endian, 2-byte opcode into its corresponding CHIP-8
+/// instruction.
 pub fn parse_opcode(bytes: u16) -> Instruction {
     match bytes & 0xF000 {
-        0x0000 => match bytes & 0x00FF {
+        0x0000 => match bytes {
             0x00E0 => Instruction::ClearScreen,
             0x00EE => Instruction::Return,
+            0x00C0..=0x00CF => Instruction::ScrollDown(mask_data(bytes & 0x000F)),
+            0x00D0..=0x00DF => Instruction::ScrollUp(mask_data(bytes & 0x000F)),
+            0x00FB => Instruction::ScrollRight,
+            0x00FC => Instruction::ScrollLeft,
+            0x00FD => Instruction::Exit,
+            0x00FE => Instruction::LowRes,
+            0x00FF => Instruction::HiRes,
             // 0x0nnn is `jump to machine code routine`, ignored
             _ => Instruction::NOP(bytes)
         }
@@ -64,6 +361,10 @@ pub fn parse_opcode(bytes: u16) -> Instruction {
         0x5000 => match bytes & 0x000F {
             0x0000 => Instruction::SkipIfEQRegister(mask_high_register(bytes),
                                                     mask_low_register(bytes)),
+            0x0002 => Instruction::SaveRange(mask_high_register(bytes),
+                                             mask_low_register(bytes)),
+            0x0003 => Instruction::LoadRange(mask_high_register(bytes),
+                                             mask_low_register(bytes)),
             _ => Instruction::UNKNOWN(bytes),
         },
         0x6000 => Instruction::LoadData(mask_high_register(bytes),
@@ -83,10 +384,12 @@ pub fn parse_opcode(bytes: u16) -> Instruction {
                                        mask_low_register(bytes)),
             0x0005 => Instruction::Sub(mask_high_register(bytes),
                                        mask_low_register(bytes)),
-            0x0006 => Instruction::ShiftRight(mask_high_register(bytes)),
+            0x0006 => Instruction::ShiftRight(mask_high_register(bytes),
+                                              mask_low_register(bytes)),
             0x0007 => Instruction::NegatedSub(mask_high_register(bytes),
                                               mask_low_register(bytes)),
-            0x000E => Instruction::ShiftLeft(mask_high_register(bytes)),
+            0x000E => Instruction::ShiftLeft(mask_high_register(bytes),
+                                             mask_low_register(bytes)),
             _ => Instruction::UNKNOWN(bytes),
         },
         0x9000 => match bytes & 0x000F {
@@ -106,16 +409,21 @@ pub fn parse_opcode(bytes: u16) -> Instruction {
             0x00A1 => Instruction::SkipIfNotPressed(mask_high_register(bytes)),
             _ => Instruction::UNKNOWN(bytes),
         },
+        0xF000 if bytes == 0xF000 => Instruction::LongSetI,
         0xF000 => match bytes & 0x00FF {
+            0x0001 => Instruction::SelectPlanes(mask_high_register(bytes)),
             0x0007 => Instruction::SetRegisterFromDelay(mask_high_register(bytes)),
             0x000A => Instruction::AwaitPress(mask_high_register(bytes)),
             0x0015 => Instruction::SetDelayFromRegister(mask_high_register(bytes)),
             0x0018 => Instruction::SetSoundFromRegister(mask_high_register(bytes)),
             0x001E => Instruction::AddI(mask_high_register(bytes)),
             0x0029 => Instruction::LoadSprite(mask_high_register(bytes)),
+            0x0030 => Instruction::LoadLargeSprite(mask_high_register(bytes)),
             0x0033 => Instruction::SetBCDRepresentation(mask_high_register(bytes)),
             0x0055 => Instruction::StoreRegisters(mask_high_register(bytes)),
             0x0065 => Instruction::ReadRegisters(mask_high_register(bytes)),
+            0x0075 => Instruction::StoreFlags(mask_high_register(bytes)),
+            0x0085 => Instruction::ReadFlags(mask_high_register(bytes)),
             _ => Instruction::UNKNOWN(bytes),
         }
         _ => Instruction::UNKNOWN(bytes)
@@ -125,6 +433,14 @@ pub fn parse_opcode(bytes: u16) -> Instruction {
 // rylev has a super clean way of going about this
 // -> https://github.com/rylev/Rust-8/blob/master/src/instruction.rs
 
+fn encode_registers(base: u16, x: Register, y: Register) -> u16 {
+    base | ((x & 0x0F) as u16) << 8 | ((y & 0x0F) as u16) << 4
+}
+
+fn encode_register_data(base: u16, x: Register, data: Data) -> u16 {
+    base | ((x & 0x0F) as u16) << 8 | data as u16
+}
+
 fn mask_address(bytes: u16) -> Address {
     bytes & 0x0FFF
 }
@@ -140,3 +456,56 @@ fn mask_low_register(bytes: u16) -> Register {
 fn mask_data(bytes: u16) -> Data {
     (bytes & 0x00FF) as u8
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_opcode_round_trips() {
+        for opcode in 0..=u16::MAX {
+            assert_eq!(parse_opcode(opcode).encode(), opcode, "{:#06X} => {:?}", opcode, parse_opcode(opcode));
+        }
+    }
+
+    #[test]
+    fn every_variant_round_trips() {
+        let instructions = [Instruction::ClearScreen, Instruction::Return, Instruction::Jump(0x123),
+                            Instruction::Call(0x456), Instruction::SkipIfEQData(0x1, 0x23),
+                            Instruction::SkipIfNEData(0x2, 0x34), Instruction::SkipIfEQRegister(0x3, 0x4),
+                            Instruction::LoadData(0x5, 0x67), Instruction::AddData(0x6, 0x78),
+                            Instruction::LoadRegister(0x7, 0x8), Instruction::Or(0x8, 0x9),
+                            Instruction::And(0x9, 0xA), Instruction::Xor(0xA, 0xB), Instruction::Add(0xB, 0xC),
+                            Instruction::Sub(0xC, 0xD), Instruction::ShiftRight(0xD, 0xE),
+                            Instruction::NegatedSub(0xE, 0xF), Instruction::ShiftLeft(0xF, 0x0),
+                            Instruction::SkipIfNERegister(0x0, 0x1), Instruction::SetI(0x789),
+                            Instruction::JumpFromOffset(0xABC), Instruction::Random(0x1, 0xFF),
+                            Instruction::Draw(0x2, 0x3, 0xF), Instruction::SkipIfPressed(0x4),
+                            Instruction::SkipIfNotPressed(0x5), Instruction::SetRegisterFromDelay(0x6),
+                            Instruction::AwaitPress(0x7), Instruction::SetDelayFromRegister(0x8),
+                            Instruction::SetSoundFromRegister(0x9), Instruction::AddI(0xA),
+                            Instruction::LoadSprite(0xB), Instruction::SetBCDRepresentation(0xC),
+                            Instruction::StoreRegisters(0xD), Instruction::ReadRegisters(0xE),
+                            Instruction::ScrollDown(0x4), Instruction::ScrollRight, Instruction::ScrollLeft,
+                            Instruction::Exit, Instruction::LowRes, Instruction::HiRes,
+                            Instruction::LoadLargeSprite(0xF), Instruction::StoreFlags(0x7),
+                            Instruction::ReadFlags(0x3), Instruction::ScrollUp(0x2),
+                            Instruction::SaveRange(0x1, 0x5), Instruction::LoadRange(0x6, 0x2),
+                            Instruction::LongSetI, Instruction::SelectPlanes(0x3), Instruction::NOP(0x0ABC),
+                            Instruction::UNKNOWN(0x5AB1)];
+
+        for instruction in instructions.iter() {
+            assert_eq!(parse_opcode(instruction.encode()), *instruction);
+        }
+
+        // One of each pattern, plus the two variants that have none
+        assert_eq!(instructions.len(), PATTERNS.len() + 1);
+    }
+
+    #[test]
+    fn encoding_matches_the_pattern() {
+        assert_eq!(Instruction::Draw(0x1, 0x2, 0x3).encode(), 0xD123);
+        assert_eq!(Instruction::ShiftLeft(0xA, 0xB).encode(), 0x8ABE);
+        assert_eq!(Instruction::SetBCDRepresentation(0x7).encode(), 0xF733);
+    }
+}
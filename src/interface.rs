@@ -1,67 +1,891 @@
 /*
     All SDL related audio/video and windowed input.
 */
-use sdl2::pixels::Color;
+use std::fs::File;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::render::BlendMode;
 use sdl2::Sdl;
+use sdl2::sys;
 use sdl2::EventPump;
+use sdl2::rect::Rect;
 use sdl2::render;
-use sdl2::video::Window;
-use sdl2::rect::Point;
+use sdl2::video::{FullscreenType, Window};
+use sdl2::VideoSubsystem;
+use log::{info, warn};
+
+use crate::gamepads::Gamepads;
+use crate::text;
+use crate::wav::WavWriter;
+
+/*
+    Colors for each of the four values a 2-bit pixel can take. A plain
+    CHIP-8 framebuffer only ever holds 0 and 1, so it uses the first two
+    entries; XO-CHIP's two bit-planes combine into all four.
+*/
+pub type Palette = [Color; 4];
+
+pub const MONOCHROME: Palette = [Color::RGB(0, 0, 0),
+                                 Color::RGB(255, 255, 255),
+                                 Color::RGB(170, 170, 170),
+                                 Color::RGB(85, 85, 85)];
+
+const ALWAYS_ON_TOP: u32 = sys::SDL_WindowFlags::SDL_WINDOW_ALWAYS_ON_TOP as u32;
+
+/*
+    The palettes the pause menu cycles through.
+*/
+pub const PALETTES: [(&str, Palette); 4] = [
+    ("monochrome", MONOCHROME),
+    ("amber", [Color::RGB(26, 16, 0), Color::RGB(255, 176, 0), Color::RGB(191, 128, 0), Color::RGB(102, 64, 0)]),
+    ("green", [Color::RGB(0, 20, 8), Color::RGB(51, 255, 102), Color::RGB(38, 179, 77), Color::RGB(20, 102, 41)]),
+    ("lcd", [Color::RGB(155, 188, 15), Color::RGB(15, 56, 15), Color::RGB(48, 98, 48), Color::RGB(139, 172, 15)]),
+];
+
+pub fn palette(name: &str) -> Option<Palette> {
+    PALETTES.iter().find(|(palette_name, _)| *palette_name == name).map(|&(_, palette)| palette)
+}
+
+/*
+    The name of a palette in `PALETTES`, or "custom" for one that isn't.
+*/
+pub fn palette_name(palette: &Palette) -> &'static str {
+    PALETTES.iter().find(|(_, colors)| colors == palette).map_or("custom", |&(name, _)| name)
+}
+
+/*
+    The palette `offset` places along from `palette` in `PALETTES`,
+    wrapping around. A custom palette counts as sitting just before the
+    first.
+*/
+pub fn cycle_palette(palette: &Palette, offset: isize) -> Palette {
+    let count = PALETTES.len() as isize;
+    let index = match PALETTES.iter().position(|(_, colors)| colors == palette) {
+        Some(index) => index as isize + offset,
+        None if offset > 0 => offset - 1,
+        None => offset,
+    };
+    PALETTES[index.rem_euclid(count) as usize].1
+}
+
+/*
+    A look for the whole window: the palette, the color of the border
+    around the game (the letterbox bars at high scales, and what the side
+    panel sits on) and optionally a thin frame just outside the game that
+    sets it off from the border.
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Theme {
+    pub palette: Palette,
+    pub border: Color,
+    pub frame: Option<Color>,
+}
+
+pub const THEMES: [(&str, Theme); 5] = [
+    ("classic", Theme { palette: MONOCHROME, border: Color::RGB(0, 0, 0), frame: None }),
+    ("slate", Theme { palette: MONOCHROME, border: Color::RGB(38, 42, 50), frame: Some(Color::RGB(72, 78, 90)) }),
+    ("amber", Theme { palette: PALETTES[1].1, border: Color::RGB(13, 8, 0), frame: Some(Color::RGB(64, 44, 0)) }),
+    ("green", Theme { palette: PALETTES[2].1, border: Color::RGB(0, 10, 4), frame: Some(Color::RGB(13, 64, 26)) }),
+    ("lcd", Theme { palette: PALETTES[3].1, border: Color::RGB(96, 96, 108), frame: Some(Color::RGB(58, 58, 68)) }),
+];
+
+pub fn theme(name: &str) -> Option<Theme> {
+    THEMES.iter().find(|(theme_name, _)| *theme_name == name).map(|&(_, theme)| theme)
+}
+
+/*
+    A color written as RRGGBB hex, with or without a leading #.
+*/
+pub fn parse_rgb(hex: &str) -> Option<Color> {
+    let hex = hex.trim_start_matches('#');
+    if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let rgb = u32::from_str_radix(hex, 16).ok()?;
+    Some(Color::RGB((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8))
+}
+
+/*
+    A palette from RGB triples, background first. Missing entries keep the
+    monochrome colors.
+*/
+pub fn palette_from_rgb(colors: &[[u8; 3]]) -> Palette {
+    let mut palette = MONOCHROME;
+    for (entry, &[red, green, blue]) in palette.iter_mut().zip(colors) {
+        *entry = Color::RGB(red, green, blue);
+    }
+    palette
+}
+
+/*
+    Clockwise rotation of the game display, for homebrew designed to be
+    played on a screen turned on its side.
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Rotation {
+    None,
+    Quarter,
+    Half,
+    ThreeQuarter,
+}
+
+impl Rotation {
+    pub fn from_degrees(degrees: u32) -> Option<Rotation> {
+        match degrees {
+            0 => Some(Rotation::None),
+            90 => Some(Rotation::Quarter),
+            180 => Some(Rotation::Half),
+            270 => Some(Rotation::ThreeQuarter),
+            _ => None,
+        }
+    }
+
+    /*
+        The dimensions of a `width` by `height` image once rotated.
+    */
+    pub fn apply_to_size(self, width: u32, height: u32) -> (u32, u32) {
+        match self {
+            Rotation::None | Rotation::Half => (width, height),
+            Rotation::Quarter | Rotation::ThreeQuarter => (height, width),
+        }
+    }
+
+    /*
+        Where the point (x, y) of a `width` by `height` image ends up once
+        rotated.
+    */
+    pub fn apply_to_point(self, x: u32, y: u32, width: u32, height: u32) -> (u32, u32) {
+        match self {
+            Rotation::None => (x, y),
+            Rotation::Quarter => (height - 1 - y, x),
+            Rotation::Half => (width - 1 - x, height - 1 - y),
+            Rotation::ThreeQuarter => (y, width - 1 - x),
+        }
+    }
+}
+
+/*
+    How the game display fills the window. `Integer` scales by the largest
+    whole multiple that fits and letterboxes the rest, keeping every CHIP-8
+    pixel the same size. `Fit` keeps the aspect ratio but allows fractional
+    scales, and `Stretch` fills the whole window.
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScaleMode {
+    Integer,
+    Fit,
+    Stretch,
+}
+
+impl ScaleMode {
+    pub fn from_name(name: &str) -> Option<ScaleMode> {
+        match name {
+            "integer" => Some(ScaleMode::Integer),
+            "fit" => Some(ScaleMode::Fit),
+            "stretch" => Some(ScaleMode::Stretch),
+            _ => None,
+        }
+    }
+}
+
+/*
+    Desktop fullscreen is a borderless window covering the screen, which is
+    friendlier to alt-tabbing than exclusive fullscreen's mode switch.
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Fullscreen {
+    Desktop,
+    Exclusive,
+}
+
+impl Fullscreen {
+    pub fn from_name(name: &str) -> Option<Fullscreen> {
+        match name {
+            "desktop" => Some(Fullscreen::Desktop),
+            "exclusive" => Some(Fullscreen::Exclusive),
+            _ => None,
+        }
+    }
+
+    fn to_sdl(self) -> FullscreenType {
+        match self {
+            Fullscreen::Desktop => FullscreenType::Desktop,
+            Fullscreen::Exclusive => FullscreenType::True,
+        }
+    }
+}
+
+/*
+    What to do while the main window is minimized. Throttling keeps the
+    program running in real time but stops drawing and only wakes up a few
+    times a second; pausing stops emulated time until the window returns.
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Minimized {
+    Throttle,
+    Pause,
+}
+
+impl Minimized {
+    pub fn from_name(name: &str) -> Option<Minimized> {
+        match name {
+            "throttle" => Some(Minimized::Throttle),
+            "pause" => Some(Minimized::Pause),
+            _ => None,
+        }
+    }
+}
+
+/*
+    Window and rendering settings.
+*/
+pub struct VideoConfig {
+    pub rotation: Rotation,
+    pub scale_mode: ScaleMode,
+    pub window_scale: u32,
+    pub fullscreen: Option<Fullscreen>,
+    pub mirror_scale: Option<u32>,
+    pub palette: Palette,
+    pub border: Color,
+    pub frame: Option<Color>,
+    pub always_on_top: bool,
+    pub debug_panel: bool,
+}
+
+/*
+    The audio settings requested from SDL. SDL may negotiate something
+    different, which is reported in the verbose log.
+*/
+pub struct AudioConfig {
+    pub sample_rate: i32,
+    pub buffer_size: u16,
+    pub volume: f32,
+    pub min_beep: Duration,
+    pub record_to: Option<PathBuf>,
+    // None for the system default
+    pub device: Option<String>,
+}
+
+/*
+    The names of the audio output devices SDL can open.
+*/
+pub fn audio_devices() -> Result<Vec<String>, String> {
+    let audio_subsystem = sdl2::init()?.audio()?;
+    let count = audio_subsystem.num_audio_playback_devices().ok_or_else(sdl2::get_error)?;
+    (0..count).map(|index| audio_subsystem.audio_playback_device_name(index)).collect()
+}
 
 /*
     The audio-video context for the emulator. It's all SDL hidden in this
     struct.
 */
 pub struct AVInterface {
+    // Held so SDL stays initialized for as long as the interface lives.
+    #[allow(dead_code)]
     pub sdl_context: Sdl,
     pub event_pump: EventPump,
     pub canvas: render::Canvas<Window>,
+    pub buzzer: Buzzer,
+    pub gamepads: Gamepads,
+    pub palette: Palette,
+    border: Color,
+    frame: Option<Color>,
+    video_subsystem: VideoSubsystem,
+    debug_window: Option<DebugWindow>,
+    mirror_window: Option<MirrorWindow>,
+    texture: render::Texture,
+    rotation: Rotation,
+    scale_mode: ScaleMode,
+    fullscreen: Fullscreen,
+    // Whether the right third of the main window shows registers and the stack
+    debug_panel: bool,
+    // Where the game display goes in the main window
+    presentation: Rect,
     width: u32,
-    height: u32
+    height: u32,
 }
 
 impl AVInterface {
-    pub fn new(width: u32, height: u32) -> AVInterface {
+    // How opaque the box behind overlay text is, out of 255
+    const OVERLAY_ALPHA: u8 = 208;
+    const TOAST_COLUMNS: usize = 32;
+
+    pub fn new(width: u32, height: u32, video_config: VideoConfig, audio_config: AudioConfig) -> AVInterface {
         let sdl_context = sdl2::init().unwrap();
         let video_subsystem = sdl_context.video().unwrap();
 
-        let window = video_subsystem.window("yac8", width * 10, height * 10)
-                                    .position_centered()
+        let rotation = video_config.rotation;
+        let (display_width, display_height) = rotation.apply_to_size(width, height);
+
+        let window_scale = video_config.window_scale;
+        // The side panel takes a third of the window, half as wide again as the game
+        let window_width = display_width * window_scale * if video_config.debug_panel { 3 } else { 2 } / 2;
+        let mut window_builder = video_subsystem.window("yac8", window_width, display_height * window_scale);
+        window_builder.position_centered()
+                      .resizable()
+                      .opengl();
+        match video_config.fullscreen {
+            Some(Fullscreen::Desktop) => { window_builder.fullscreen_desktop(); },
+            Some(Fullscreen::Exclusive) => { window_builder.fullscreen(); },
+            None => {},
+        }
+        if video_config.always_on_top {
+            let flags = window_builder.window_flags() | ALWAYS_ON_TOP;
+            window_builder.set_window_flags(flags);
+        }
+        let window = window_builder.build().unwrap();
+
+        let mut canvas = window.into_canvas()
+                               .build()
+                               .unwrap();
+
+        canvas.set_draw_color(video_config.border);
+        canvas.clear();
+        canvas.present();
+
+        let texture = canvas.texture_creator()
+                            .create_texture_streaming(PixelFormatEnum::RGB24, display_width, display_height)
+                            .expect("Failed to create SDL2 framebuffer texture.");
+
+        let mirror_window = video_config.mirror_scale.map(|scale| {
+            MirrorWindow::new(&video_subsystem, display_width, display_height, scale)
+        });
+
+        let event_pump = sdl_context.event_pump().unwrap();
+        let buzzer = Buzzer::new(&sdl_context, &audio_config);
+        let gamepads = Gamepads::new(sdl_context.game_controller().unwrap());
+
+        let mut av_interface = AVInterface {
+            sdl_context,
+            event_pump,
+            canvas,
+            buzzer,
+            gamepads,
+            palette: video_config.palette,
+            border: video_config.border,
+            frame: video_config.frame,
+            video_subsystem,
+            debug_window: None,
+            mirror_window,
+            texture,
+            rotation,
+            scale_mode: video_config.scale_mode,
+            fullscreen: video_config.fullscreen.unwrap_or(Fullscreen::Desktop),
+            debug_panel: video_config.debug_panel,
+            presentation: Rect::new(0, 0, 1, 1),
+            width,
+            height,
+        };
+        av_interface.resized();
+        av_interface
+    }
+
+    pub fn has_debug_window(&self) -> bool {
+        self.debug_window.is_some()
+    }
+
+    /*
+        Open the debugger window, sized for a `columns` by `rows` grid of
+        text, or close it if it's already open.
+    */
+    pub fn toggle_debug_window(&mut self, columns: usize, rows: usize) {
+        self.debug_window = match self.debug_window {
+            Some(_) => None,
+            None => Some(DebugWindow::new(&self.video_subsystem, columns, rows)),
+        };
+    }
+
+    pub fn draw_debug(&mut self, lines: &[String]) {
+        let palette = &self.palette;
+        if let Some(debug_window) = self.debug_window.as_mut() {
+            debug_window.draw(lines, palette);
+        }
+    }
+
+    pub fn has_debug_panel(&self) -> bool {
+        self.debug_panel
+    }
+
+    /*
+        Fill the side panel with lines of text, as large as fits. Call it
+        after `draw`.
+    */
+    pub fn draw_panel(&mut self, lines: &[String]) {
+        let area = self.panel_rect();
+        let text_box = TextBox::new(lines);
+        let scale = (area.width() / text_box.width).min(area.height() / text_box.height).max(1);
+        let top = area.center().y() - (text_box.height * scale / 2) as i32;
+        self.draw_text_box(&text_box, area.left(), top, scale);
+    }
+
+    /*
+        The part of the main window the game display is placed in: all of
+        it, or the left two thirds beside the side panel.
+    */
+    fn game_rect(&self) -> Rect {
+        let (window_width, window_height) = self.canvas.output_size().expect("Failed to query window size.");
+        let width = if self.debug_panel { window_width * 2 / 3 } else { window_width };
+        Rect::new(0, 0, width.max(1), window_height.max(1))
+    }
+
+    fn panel_rect(&self) -> Rect {
+        let (window_width, window_height) = self.canvas.output_size().expect("Failed to query window size.");
+        let game = self.game_rect();
+        Rect::new(game.right(), 0, (window_width - game.width()).max(1), window_height.max(1))
+    }
+
+    pub fn is_main_window(&self, window_id: u32) -> bool {
+        window_id == self.canvas.window().id()
+    }
+
+    /*
+        Handle a window's close button. Closing the debugger or mirror window
+        just closes it; returns true when it was the main window, meaning quit.
+    */
+    pub fn close_window(&mut self, window_id: u32) -> bool {
+        if self.debug_window.as_ref().map(DebugWindow::id) == Some(window_id) {
+            self.debug_window = None;
+        }
+        if self.mirror_window.as_ref().map(MirrorWindow::id) == Some(window_id) {
+            self.mirror_window = None;
+        }
+        self.is_main_window(window_id)
+    }
+
+    /*
+        Switch between windowed and the configured fullscreen mode (desktop
+        fullscreen unless exclusive was asked for).
+    */
+    pub fn toggle_fullscreen(&mut self) {
+        let window = self.canvas.window_mut();
+        let target = match window.fullscreen_state() {
+            FullscreenType::Off => self.fullscreen.to_sdl(),
+            _ => FullscreenType::Off,
+        };
+        window.set_fullscreen(target).expect("Failed to change fullscreen mode.");
+    }
+
+    pub fn is_always_on_top(&self) -> bool {
+        self.canvas.window().window_flags() & ALWAYS_ON_TOP != 0
+    }
+
+    /*
+        Float the main window above others or stop. SDL only added a way to
+        change this on an open window in 2.0.16, later than the bindings,
+        so the function is looked up at runtime and older libraries get an
+        error.
+    */
+    pub fn toggle_always_on_top(&mut self) -> Result<(), String> {
+        type SetWindowAlwaysOnTop = unsafe extern "C" fn(*mut sys::SDL_Window, sys::SDL_bool);
+        let unavailable = || String::from("this needs SDL 2.0.16 or later");
+        if !cfg!(unix) {
+            return Err(unavailable());
+        }
+
+        let on_top = if self.is_always_on_top() { sys::SDL_bool::SDL_FALSE } else { sys::SDL_bool::SDL_TRUE };
+        // With no file name, SDL_LoadObject opens the running program, which SDL is loaded into
+        unsafe {
+            let program = sys::SDL_LoadObject(std::ptr::null());
+            if program.is_null() {
+                return Err(unavailable());
+            }
+            let function = sys::SDL_LoadFunction(program, b"SDL_SetWindowAlwaysOnTop\0".as_ptr() as *const _);
+            if !function.is_null() {
+                let set_always_on_top: SetWindowAlwaysOnTop = std::mem::transmute(function);
+                set_always_on_top(self.canvas.window().raw(), on_top);
+            }
+            sys::SDL_UnloadObject(program);
+            if function.is_null() {
+                return Err(unavailable());
+            }
+        }
+        Ok(())
+    }
+
+    /*
+        The main window changed size, by the user dragging it, going
+        fullscreen or moving to a screen with a different pixel density.
+        The display is placed afresh and needs redrawing.
+    */
+    pub fn resized(&mut self) {
+        self.presentation = self.presentation_rect();
+    }
+
+    fn set_resolution(&mut self, width: u32, height: u32) {
+        let (display_width, display_height) = self.rotation.apply_to_size(width, height);
+        self.texture = self.canvas.texture_creator()
+                                  .create_texture_streaming(PixelFormatEnum::RGB24, display_width, display_height)
+                                  .expect("Failed to create SDL2 framebuffer texture.");
+        if let Some(mirror_window) = self.mirror_window.as_mut() {
+            mirror_window.set_resolution(display_width, display_height);
+        }
+        self.width = width;
+        self.height = height;
+        self.resized();
+    }
+
+    /*
+        Where in the window the game display goes, given the window's
+        current size in pixels, which on high-density screens is more than
+        its size in points.
+    */
+    fn presentation_rect(&self) -> Rect {
+        let (display_width, display_height) = self.rotation.apply_to_size(self.width, self.height);
+        let (window_width, window_height) = self.game_rect().size();
+
+        let (width, height) = match self.scale_mode {
+            ScaleMode::Integer => {
+                let scale = (window_width / display_width).min(window_height / display_height).max(1);
+                (display_width * scale, display_height * scale)
+            },
+            ScaleMode::Fit => {
+                let scale = (window_width as f64 / display_width as f64).min(window_height as f64 / display_height as f64);
+                ((display_width as f64 * scale) as u32, (display_height as f64 * scale) as u32)
+            },
+            ScaleMode::Stretch => (window_width, window_height),
+        };
+
+        Rect::new((window_width as i32 - width as i32) / 2, (window_height as i32 - height as i32) / 2, width, height)
+    }
+
+    /*
+        Draw lines of text over the middle of the game display, as large as
+        fits. Call it after `draw`.
+    */
+    pub fn draw_overlay(&mut self, lines: &[String]) {
+        let text_box = TextBox::new(lines);
+        let area = self.presentation;
+        let scale = (area.width() / text_box.width).min(area.height() / text_box.height).max(1);
+        let left = area.center().x() - (text_box.width * scale / 2) as i32;
+        let top = area.center().y() - (text_box.height * scale / 2) as i32;
+        self.draw_text_box(&text_box, left, top, scale);
+    }
+
+    /*
+        Draw toasts in the bottom left corner of the game display, at a
+        size that fits `TOAST_COLUMNS` characters across it. Call it after
+        `draw`.
+    */
+    pub fn draw_toasts(&mut self, lines: &[String]) {
+        if lines.is_empty() {
+            return;
+        }
+        let text_box = TextBox::new(lines);
+        let area = self.presentation;
+        let scale = (area.width() / (AVInterface::TOAST_COLUMNS * text::CELL_WIDTH) as u32).max(1);
+        let top = area.bottom() - (text_box.height * scale) as i32;
+        self.draw_text_box(&text_box, area.left(), top, scale);
+    }
+
+    /*
+        Text in the palette's foreground color on a translucent box of its
+        background, each text pixel `scale` screen pixels square.
+    */
+    fn draw_text_box(&mut self, text_box: &TextBox, left: i32, top: i32, scale: u32) {
+        let background = self.palette[0];
+        self.canvas.set_blend_mode(BlendMode::Blend);
+        self.canvas.set_draw_color(Color::RGBA(background.r, background.g, background.b, AVInterface::OVERLAY_ALPHA));
+        self.canvas.fill_rect(Rect::new(left, top, text_box.width * scale, text_box.height * scale))
+                   .expect("Failed to draw overlay");
+
+        let ink: Vec<Rect> = text_box.pixels
+                                     .iter()
+                                     .enumerate()
+                                     .filter(|&(_, &pixel)| pixel != 0)
+                                     .map(|(num, _)| {
+                                         let (x, y) = (num as u32 % text_box.width, num as u32 / text_box.width);
+                                         Rect::new(left + (x * scale) as i32, top + (y * scale) as i32, scale, scale)
+                                     })
+                                     .collect();
+        self.canvas.set_draw_color(self.palette[1]);
+        self.canvas.fill_rects(&ink).expect("Failed to draw overlay");
+    }
+
+    /*
+        Draw a `width` by `height` framebuffer of 2-bit pixel values, one
+        per byte, mapping each through the palette and rotating it into
+        place. The window keeps its size when the resolution changes, as it
+        does going in and out of SUPER-CHIP's hi-res mode.
+    */
+    pub fn draw(&mut self, buffer: &[u8], width: u32, height: u32) {
+        if (width, height) != (self.width, self.height) {
+            self.set_resolution(width, height);
+        }
+        fill_texture(&mut self.texture, buffer, &self.palette, self.rotation, self.width, self.height);
+
+        let destination = self.presentation;
+        self.canvas.set_draw_color(self.border);
+        self.canvas.clear();
+        if let Some(frame) = self.frame {
+            // A quarter of a CHIP-8 pixel wide, around the outside of the game
+            let (display_width, _) = self.rotation.apply_to_size(self.width, self.height);
+            let thickness = (destination.width() / display_width / 4).max(1);
+            let outline = Rect::new(destination.x() - thickness as i32, destination.y() - thickness as i32,
+                                    destination.width() + 2 * thickness, destination.height() + 2 * thickness);
+            self.canvas.set_draw_color(frame);
+            self.canvas.fill_rect(outline).expect("Failed to draw the frame");
+        }
+        self.canvas.copy(&self.texture, None, destination).expect("Failed to draw");
+
+        if let Some(mirror_window) = self.mirror_window.as_mut() {
+            mirror_window.draw(buffer, &self.palette, self.rotation, self.width, self.height);
+        }
+    }
+}
+
+/*
+    Lines of text rasterized with a cell of padding all round, one byte per
+    pixel.
+*/
+struct TextBox {
+    pixels: Vec<u8>,
+    width: u32,
+    height: u32,
+}
+
+impl TextBox {
+    fn new(lines: &[String]) -> TextBox {
+        let columns = lines.iter().map(|line| line.chars().count()).max().unwrap_or(0) + 2;
+        let rows = lines.len() + 2;
+        let padded: Vec<String> = std::iter::once(String::new()).chain(lines.iter().map(|line| format!(" {}", line)))
+                                                                .collect();
+        TextBox {
+            pixels: text::rasterize(&padded, columns, rows),
+            width: (columns * text::CELL_WIDTH) as u32,
+            height: (rows * text::CELL_HEIGHT) as u32,
+        }
+    }
+}
+
+/*
+    Copy a framebuffer of pixel values, one per byte, into an RGB texture,
+    coloring each through the palette and rotating it into place.
+*/
+fn fill_texture(texture: &mut render::Texture, buffer: &[u8], palette: &Palette,
+                rotation: Rotation, width: u32, height: u32) {
+    texture.with_lock(None, |pixels: &mut [u8], pitch: usize| {
+        for (num, &value) in buffer.iter().enumerate() {
+            let color = palette[(value & 0x3) as usize];
+            let (x, y) = rotation.apply_to_point(num as u32 % width, num as u32 / width, width, height);
+            let offset = y as usize * pitch + x as usize * 3;
+            pixels[offset] = color.r;
+            pixels[offset + 1] = color.g;
+            pixels[offset + 2] = color.b;
+        }
+    }).expect("Failed to update framebuffer texture.");
+}
+
+/*
+    A clean copy of the game display in a window of its own, at a fixed
+    scale and with no overlays, for capturing in streaming software while
+    the main window carries whatever else is going on.
+*/
+struct MirrorWindow {
+    canvas: render::Canvas<Window>,
+    texture: render::Texture,
+}
+
+impl MirrorWindow {
+    fn new(video_subsystem: &VideoSubsystem, display_width: u32, display_height: u32, scale: u32) -> MirrorWindow {
+        let window = video_subsystem.window("yac8 output", display_width * scale, display_height * scale)
                                     .opengl()
                                     .build()
                                     .unwrap();
-
         let mut canvas = window.into_canvas()
                                .build()
                                .unwrap();
+        canvas.set_logical_size(display_width, display_height).expect("Failed to set logical size of SDL2 renderer.");
+
+        let texture = canvas.texture_creator()
+                            .create_texture_streaming(PixelFormatEnum::RGB24, display_width, display_height)
+                            .expect("Failed to create SDL2 mirror texture.");
+
+        MirrorWindow {
+            canvas,
+            texture,
+        }
+    }
+
+    fn id(&self) -> u32 {
+        self.canvas.window().id()
+    }
+
+    fn set_resolution(&mut self, display_width: u32, display_height: u32) {
+        self.canvas.set_logical_size(display_width, display_height).expect("Failed to set logical size of SDL2 renderer.");
+        self.texture = self.canvas.texture_creator()
+                                  .create_texture_streaming(PixelFormatEnum::RGB24, display_width, display_height)
+                                  .expect("Failed to create SDL2 mirror texture.");
+    }
+
+    fn draw(&mut self, buffer: &[u8], palette: &Palette, rotation: Rotation, width: u32, height: u32) {
+        fill_texture(&mut self.texture, buffer, palette, rotation, width, height);
+        self.canvas.copy(&self.texture, None, None).expect("Failed to draw mirror");
+        self.canvas.present();
+    }
+}
+
+/*
+    A second window dedicated to the debugger's text views, so the game
+    display stays clean at its own aspect ratio. Text is rasterized into a
+    texture at one byte per pixel and colored with the main palette.
+*/
+struct DebugWindow {
+    canvas: render::Canvas<Window>,
+    texture: render::Texture,
+    columns: usize,
+    rows: usize,
+}
+
+impl DebugWindow {
+    const SCALE: u32 = 3;
 
+    fn new(video_subsystem: &VideoSubsystem, columns: usize, rows: usize) -> DebugWindow {
+        let width = (columns * text::CELL_WIDTH) as u32;
+        let height = (rows * text::CELL_HEIGHT) as u32;
+
+        let window = video_subsystem.window("yac8 debugger", width * DebugWindow::SCALE, height * DebugWindow::SCALE)
+                                    .resizable()
+                                    .opengl()
+                                    .build()
+                                    .unwrap();
+        let mut canvas = window.into_canvas()
+                               .build()
+                               .unwrap();
         canvas.set_logical_size(width, height).expect("Failed to set logical size of SDL2 renderer.");
 
-        canvas.set_draw_color(Color::RGB(0, 0, 0));
-        canvas.clear();
-        canvas.present();
+        let texture = canvas.texture_creator()
+                            .create_texture_streaming(PixelFormatEnum::RGB24, width, height)
+                            .expect("Failed to create SDL2 debugger texture.");
 
-        let event_pump = sdl_context.event_pump().unwrap();
+        DebugWindow {
+            canvas,
+            texture,
+            columns,
+            rows,
+        }
+    }
+
+    fn id(&self) -> u32 {
+        self.canvas.window().id()
+    }
+
+    fn draw(&mut self, lines: &[String], palette: &Palette) {
+        let pixels = text::rasterize(lines, self.columns, self.rows);
+        let width = (self.columns * text::CELL_WIDTH) as u32;
+        let height = (self.rows * text::CELL_HEIGHT) as u32;
+        fill_texture(&mut self.texture, &pixels, palette, Rotation::None, width, height);
+
+        self.canvas.copy(&self.texture, None, None).expect("Failed to draw debugger");
+        self.canvas.present();
+    }
+}
+
+/*
+    A square wave generator fed to SDL's audio callback. The device runs for
+    as long as the buzzer exists and the wave is gated by `sounding`, so the
+    output is one continuous stream (silence included) that can be recorded.
+*/
+struct SquareWave {
+    phase_inc: f32,
+    phase: f32,
+    volume: f32,
+    sounding: Arc<AtomicBool>,
+    recorder: Option<WavWriter>,
+}
+
+impl AudioCallback for SquareWave {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        let sounding = self.sounding.load(Ordering::Relaxed);
+        for sample in out.iter_mut() {
+            *sample = match (sounding, self.phase <= 0.5) {
+                (false, _) => 0.0,
+                (true, true) => self.volume,
+                (true, false) => -self.volume,
+            };
+            self.phase = (self.phase + self.phase_inc) % 1.0;
+        }
 
-        AVInterface {
-            sdl_context: sdl_context,
-            event_pump: event_pump,
-            canvas: canvas,
-            width: width,
-            height: height
+        if let Some(recorder) = self.recorder.as_mut() {
+            if let Err(e) = recorder.write_samples(out) {
+                warn!("Stopped recording audio: {}", e);
+                self.recorder = None;
+            }
         }
     }
+}
+
+/*
+    The CHIP-8 buzzer. It sounds while the sound timer is non-zero, but once
+    started it keeps sounding for at least `min_duration`. A sound timer of
+    1 or 2 only lasts a frame or two, which can be too short to hear.
+*/
+pub struct Buzzer {
+    // Audio plays for as long as the device is alive.
+    _device: AudioDevice<SquareWave>,
+    sounding: Arc<AtomicBool>,
+    min_duration: Duration,
+    started: Option<Instant>,
+}
+
+impl Buzzer {
+    const TONE_HZ: f32 = 440.0;
 
-    pub fn draw(&mut self, buffer: &[u8]) {
-        for (num, &bit) in buffer.iter().enumerate() {
-            if bit == 1 {
-                self.canvas.set_draw_color(Color::RGB(255, 255, 255));
-            } else {
-                self.canvas.set_draw_color(Color::RGB(0, 0, 0));
+    fn new(sdl_context: &Sdl, audio_config: &AudioConfig) -> Buzzer {
+        let audio_subsystem = sdl_context.audio().unwrap();
+        let desired_spec = AudioSpecDesired {
+            freq: Some(audio_config.sample_rate),
+            channels: Some(1),
+            samples: Some(audio_config.buffer_size),
+        };
+
+        let record_file = audio_config.record_to.as_ref().map(|path| {
+            File::create(path).expect("Cannot create audio recording file.")
+        });
+        let sounding = Arc::new(AtomicBool::new(false));
+
+        let device = audio_subsystem.open_playback(audio_config.device.as_deref(), &desired_spec, |spec| {
+            SquareWave {
+                phase_inc: Buzzer::TONE_HZ / spec.freq as f32,
+                phase: 0.0,
+                volume: audio_config.volume,
+                sounding: Arc::clone(&sounding),
+                recorder: record_file.map(|file| {
+                    WavWriter::new(file, spec.freq as u32).expect("Cannot write audio recording file.")
+                }),
             }
-            let y = (num as u32) / self.width;
-            let x = (num as u32) % self.width;
-            self.canvas.draw_point(Point::new(x as i32, y as i32)).expect("Failed to draw");
+        }).expect("Failed to open SDL2 audio device.");
+
+        let spec = device.spec();
+        info!("Opened audio device at {}hz with a {} sample buffer ({} requested at {}hz).",
+              spec.freq, spec.samples, audio_config.buffer_size, audio_config.sample_rate);
+        device.resume();
+
+        Buzzer {
+            _device: device,
+            sounding,
+            min_duration: audio_config.min_beep,
+            started: None,
+        }
+    }
+
+    pub fn update(&mut self, sounding: bool) {
+        match self.started {
+            None if sounding => {
+                self.sounding.store(true, Ordering::Relaxed);
+                self.started = Some(Instant::now());
+            },
+            Some(started) if !sounding && started.elapsed() >= self.min_duration => {
+                self.sounding.store(false, Ordering::Relaxed);
+                self.started = None;
+            },
+            _ => {},
         }
     }
 }
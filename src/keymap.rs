@@ -0,0 +1,161 @@
+#[cfg(feature = "frontend")]
+use sdl2::keyboard::Scancode;
+
+use crate::chip8::Chip8;
+
+/*
+    Which keyboard keys press which hex pad keys, by SDL scancode name.
+    Scancodes name physical keys by where they sit on a US keyboard, so the
+    default lays the pad out on the left of any keyboard, AZERTY and Dvorak
+    included:
+
+    1 2 3 4      1 2 3 C
+    Q W E R  ->  4 5 6 D
+    A S D F      7 8 9 E
+    Z X C V      A 0 B F
+*/
+pub struct Keymap {
+    // The scancode name for each hex key, indexed by key
+    keys: [String; 16],
+}
+
+impl Keymap {
+    // The hex pad read left to right, top to bottom
+    pub const PAD_ORDER: [u8; 16] = [0x1, 0x2, 0x3, 0xC, 0x4, 0x5, 0x6, 0xD, 0x7, 0x8, 0x9, 0xE, 0xA, 0x0, 0xB, 0xF];
+
+    pub const PRESETS: [&'static str; 2] = ["qwerty", "arrows"];
+
+    /*
+        `qwerty` is the default. `arrows` moves 2, 4, 6 and 8, the usual
+        directions, to the arrow keys, with 5 on the space bar.
+    */
+    pub fn preset(name: &str) -> Option<Keymap> {
+        let mut keymap = Keymap::default();
+        match name {
+            "qwerty" => {},
+            "arrows" => {
+                for &(key, scancode) in &[(0x2, "Up"), (0x4, "Left"), (0x6, "Right"), (0x8, "Down"), (0x5, "Space")] {
+                    keymap.bind(key, scancode);
+                }
+            },
+            _ => return None,
+        }
+        Some(keymap)
+    }
+
+    /*
+        Read a keymap file: one `KEY = "SCANCODE"` binding per line, a hex
+        pad key and an SDL scancode name such as `"Up"` or `"Keypad 8"`, with
+        `#` comments. This is a subset of TOML. Keys left out keep their
+        default. Any key but Escape can be bound, hotkeys included: a bound
+        key presses the pad instead.
+    */
+    pub fn parse(source: &str) -> Result<Keymap, String> {
+        let mut keymap = Keymap::default();
+        for (number, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |message: String| format!("line {}: {}", number + 1, message);
+            let (key, value) = line.split_once('=').ok_or_else(|| error(String::from("expected `KEY = \"SCANCODE\"`")))?;
+            let key = key.trim().trim_matches('"');
+            let key = u8::from_str_radix(key, 16).ok().filter(|&key| key < Chip8::NUM_KEYS)
+                                                 .ok_or_else(|| error(format!("`{}` isn't a hex pad key", key)))?;
+            // A quoted name can hold a `#`, as in "Keypad #"
+            let value = value.trim();
+            let scancode = match value.strip_prefix('"') {
+                Some(quoted) => quoted.split('"').next().unwrap(),
+                None => value.split('#').next().unwrap().trim(),
+            };
+            if !is_scancode_name(scancode) {
+                return Err(error(format!("`{}` isn't an SDL scancode name", scancode)));
+            }
+            // Escape has to reach the menu, or there'd be no way out of a game
+            if scancode == "Escape" {
+                return Err(error(String::from("Escape is kept for the menu")));
+            }
+            keymap.bind(key, scancode);
+        }
+        Ok(keymap)
+    }
+
+    pub fn key(&self, scancode: &str) -> Option<u8> {
+        self.keys.iter().position(|name| name == scancode).map(|key| key as u8)
+    }
+
+    /*
+        Press `key` with `scancode` from now on. A scancode only presses one
+        key, so if another key had it, that key takes over `key`'s old one.
+    */
+    pub fn bind(&mut self, key: u8, scancode: &str) {
+        let previous = std::mem::replace(&mut self.keys[key as usize], String::from(scancode));
+        if let Some(other) = (0..self.keys.len()).find(|&other| other != key as usize && self.keys[other] == scancode) {
+            self.keys[other] = previous;
+        }
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Keymap {
+        let mut keymap = Keymap { keys: Default::default() };
+        for (&key, name) in Keymap::PAD_ORDER.iter().zip("1234QWERASDFZXCV".chars()) {
+            keymap.keys[key as usize] = name.to_string();
+        }
+        keymap
+    }
+}
+
+#[cfg(feature = "frontend")]
+fn is_scancode_name(name: &str) -> bool {
+    Scancode::from_name(name).is_some()
+}
+
+// Without SDL there are no names to check against, and the terminal only ever types single characters
+#[cfg(not(feature = "frontend"))]
+fn is_scancode_name(name: &str) -> bool {
+    !name.is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_lay_out_the_pad() {
+        let qwerty = Keymap::preset("qwerty").unwrap();
+        assert_eq!((qwerty.key("1"), qwerty.key("W"), qwerty.key("V")), (Some(0x1), Some(0x5), Some(0xF)));
+        let arrows = Keymap::preset("arrows").unwrap();
+        assert_eq!((arrows.key("Up"), arrows.key("Space"), arrows.key("W")), (Some(0x2), Some(0x5), None));
+        assert!(Keymap::preset("dvorak").is_none());
+    }
+
+    #[test]
+    fn bindings_take_quoted_and_bare_names() {
+        let keymap = Keymap::parse("# The keypad\n5 = \"Keypad #\"  # a quoted # isn't a comment\n\"A\" = Up # bare\n").unwrap();
+        assert_eq!((keymap.key("Keypad #"), keymap.key("Up")), (Some(0x5), Some(0xA)));
+        // Keys left out keep their default
+        assert_eq!(keymap.key("1"), Some(0x1));
+    }
+
+    #[test]
+    fn later_bindings_win() {
+        // A name moves to the last key bound to it, and the key it left takes that key's old name
+        let keymap = Keymap::parse("1 = \"Up\"\n2 = \"Up\"").unwrap();
+        assert_eq!((keymap.key("Up"), keymap.key("2"), keymap.key("1")), (Some(0x2), Some(0x1), None));
+        // A key bound twice keeps the last name
+        let keymap = Keymap::parse("3 = \"Left\"\n3 = \"Right\"").unwrap();
+        assert_eq!((keymap.key("Left"), keymap.key("Right")), (None, Some(0x3)));
+    }
+
+    #[test]
+    fn malformed_lines_are_errors() {
+        assert_eq!(Keymap::parse("1 \"Up\"").err().unwrap(), "line 1: expected `KEY = \"SCANCODE\"`");
+        assert_eq!(Keymap::parse("\nG = \"Up\"").err().unwrap(), "line 2: `G` isn't a hex pad key");
+        assert_eq!(Keymap::parse("10 = \"Up\"").err().unwrap(), "line 1: `10` isn't a hex pad key");
+        assert_eq!(Keymap::parse("0 = \"Escape\"").err().unwrap(), "line 1: Escape is kept for the menu");
+        if cfg!(feature = "frontend") {
+            assert_eq!(Keymap::parse("0 = \"Nope\"").err().unwrap(), "line 1: `Nope` isn't an SDL scancode name");
+        }
+    }
+}
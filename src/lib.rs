@@ -0,0 +1,60 @@
+//! The emulator core: the machine and everything it needs to run, with no
+//! SDL in sight. The `yac8` binary is a frontend over it, and so are the
+//! libretro core built with the `libretro` feature and the browser build
+//! with the `wasm` feature. Depend on the crate with
+//! `default-features = false` to leave the frontend and SDL out.
+//!
+//! A frontend of its own needs little more than `chip8::Chip8`:
+//!
+//! - `Chip8::new(rom, clock_hz)`, then `set_variant`, `set_quirks`,
+//!   `set_font` and `set_seed` to set it up
+//! - `set_input` with an `input::InputSource` to feed it keys, or
+//!   `set_key` to press them directly
+//! - `try_cycle(elapsed)` to run it against a real clock, or `run_frames(n)`
+//!   to run whole 60hz frames as fast as possible, or `step_frame` to run
+//!   one and learn whether the display changed
+//! - `display` to draw, with `width`, `height`, `get`, `rows` and `pixels`,
+//!   `take_changed` to know whether there's anything new to draw, and
+//!   `sound_active` to know when to beep
+//! - `snapshot` and `restore` for save states, `state` for a plain view
+//!   of the registers, stack and memory, and `registers`, `stack` and
+//!   `memory` for the live parts
+//!
+//! `instructions` decodes opcodes on its own, `disassembler`, `lint` and
+//! `scan` look at ROMs without running them, and `assembler` writes them.
+pub mod assembler;
+pub mod checksum;
+pub mod chip8;
+pub mod compat;
+pub mod compress;
+pub mod coverage;
+pub mod disassembler;
+pub mod display;
+pub mod dynarec;
+pub mod error;
+pub mod font;
+pub mod history;
+pub mod hooks;
+pub mod input;
+pub mod instructions;
+#[cfg(feature = "libretro")]
+pub mod libretro;
+pub mod lint;
+pub mod machine_state;
+pub mod main_memory;
+pub mod quirk_usage;
+pub mod quirks;
+pub mod registers;
+pub mod rom_database;
+pub mod savestate;
+pub mod scan;
+pub mod scheduler;
+pub mod snapshot;
+pub mod stack;
+pub mod stats;
+pub mod trace;
+pub mod variant;
+pub mod watch;
+pub mod watchdog;
+#[cfg(feature = "wasm")]
+pub mod wasm;
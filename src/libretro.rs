@@ -0,0 +1,436 @@
+//! The libretro API, so yac8 can be loaded as a core by RetroArch and other
+//! libretro frontends. Build it with `cargo rustc --lib --crate-type cdylib
+//! --features libretro`; the cdylib is the core.
+//!
+//! The frontend owns the loop: every `retro_run` polls the pad, runs one
+//! frame of emulated time with `run_frames`, hands over the display as
+//! XRGB8888 and a frame's worth of buzzer as 16-bit stereo. The buzzer is
+//! the same square wave the SDL frontend plays. Save states are the
+//! uncompressed body of a yac8 save state behind its version byte, padded
+//! to a size fixed at load so the frontend can use them for rewind and
+//! netplay.
+//!
+//! The pad presses the same keys as a game controller does in the SDL
+//! frontend, and a keyboard presses the COSMAC VIP layout on 1234/QWER/
+//! ASDF/ZXCV. libretro calls a core from one thread, so the core lives in
+//! a thread local.
+
+#![allow(clippy::missing_safety_doc)]
+
+use std::cell::RefCell;
+use std::ffi::{c_void, CStr};
+use std::os::raw::{c_char, c_uint};
+use std::path::Path;
+use std::slice;
+
+use log::warn;
+
+use crate::chip8::Chip8;
+use crate::display::Display;
+use crate::quirks::Quirks;
+use crate::savestate::{self, StateReader, StateWriter};
+use crate::scheduler::Scheduler;
+use crate::snapshot::Snapshot;
+use crate::stack::Stack;
+use crate::variant::Variant;
+
+const API_VERSION: c_uint = 1;
+const CLOCK_HZ: f64 = 700.0;
+const SAMPLE_RATE: f64 = 44100.0;
+const TONE_HZ: f64 = 440.0;
+const VOLUME: i16 = i16::MAX / 4;
+// Black and white, then grays for XO-CHIP's second plane and both together
+const PALETTE: [u32; 4] = [0x0000_0000, 0x00FF_FFFF, 0x00AA_AAAA, 0x0055_5555];
+
+const ENVIRONMENT_SET_PIXEL_FORMAT: c_uint = 10;
+const PIXEL_FORMAT_XRGB8888: c_uint = 1;
+const REGION_NTSC: c_uint = 0;
+const DEVICE_JOYPAD: c_uint = 1;
+const DEVICE_KEYBOARD: c_uint = 3;
+
+// libretro joypad buttons and the hex keys they press
+const JOYPAD_KEYS: [(c_uint, u8); 10] = [(4, 0x5),   // up
+                                         (6, 0x7),   // left
+                                         (5, 0x8),   // down
+                                         (7, 0x9),   // right
+                                         (8, 0x6),   // A
+                                         (0, 0x4),   // B
+                                         (9, 0x1),   // X
+                                         (1, 0x2),   // Y
+                                         (10, 0xC),  // L
+                                         (11, 0xD)]; // R
+// The hex keys in keyboard order, and the keys (libretro keycodes are ASCII) that press them
+const PAD_ORDER: [u8; 16] = [0x1, 0x2, 0x3, 0xC, 0x4, 0x5, 0x6, 0xD, 0x7, 0x8, 0x9, 0xE, 0xA, 0x0, 0xB, 0xF];
+const KEYBOARD_LAYOUT: &[u8; 16] = b"1234qwerasdfzxcv";
+
+#[repr(C)]
+pub struct SystemInfo {
+    library_name: *const c_char,
+    library_version: *const c_char,
+    valid_extensions: *const c_char,
+    need_fullpath: bool,
+    block_extract: bool,
+}
+
+#[repr(C)]
+pub struct GameGeometry {
+    base_width: c_uint,
+    base_height: c_uint,
+    max_width: c_uint,
+    max_height: c_uint,
+    aspect_ratio: f32,
+}
+
+#[repr(C)]
+pub struct SystemTiming {
+    fps: f64,
+    sample_rate: f64,
+}
+
+#[repr(C)]
+pub struct SystemAvInfo {
+    geometry: GameGeometry,
+    timing: SystemTiming,
+}
+
+#[repr(C)]
+pub struct GameInfo {
+    path: *const c_char,
+    data: *const c_void,
+    size: usize,
+    meta: *const c_char,
+}
+
+pub type EnvironmentFn = extern "C" fn(command: c_uint, data: *mut c_void) -> bool;
+pub type VideoRefreshFn = extern "C" fn(data: *const c_void, width: c_uint, height: c_uint, pitch: usize);
+pub type AudioSampleFn = extern "C" fn(left: i16, right: i16);
+pub type AudioSampleBatchFn = extern "C" fn(data: *const i16, frames: usize) -> usize;
+pub type InputPollFn = extern "C" fn();
+pub type InputStateFn = extern "C" fn(port: c_uint, device: c_uint, index: c_uint, id: c_uint) -> i16;
+
+#[derive(Default)]
+struct Callbacks {
+    environment: Option<EnvironmentFn>,
+    video_refresh: Option<VideoRefreshFn>,
+    audio_sample_batch: Option<AudioSampleBatchFn>,
+    input_poll: Option<InputPollFn>,
+    input_state: Option<InputStateFn>,
+}
+
+struct Core {
+    machine: Chip8,
+    // Set when the program faults; the last frame stays up and nothing else runs
+    crashed: bool,
+    state_size: usize,
+    framebuffer: Vec<u32>,
+    audio: Vec<i16>,
+    phase: f64,
+}
+
+thread_local! {
+    static CALLBACKS: RefCell<Callbacks> = RefCell::new(Callbacks::default());
+    static CORE: RefCell<Option<Core>> = const { RefCell::new(None) };
+}
+
+impl Core {
+    fn new(rom: Vec<u8>, variant: Variant) -> Core {
+        let mut machine = Chip8::new(rom, CLOCK_HZ);
+        machine.set_variant(variant);
+        machine.set_quirks(if variant == Variant::Chip8 { Quirks::default() } else { variant.quirks() });
+        let mut core = Core {
+            machine,
+            crashed: false,
+            state_size: 0,
+            framebuffer: Vec::new(),
+            audio: Vec::new(),
+            phase: 0.0,
+        };
+        // The stack is empty and the display lo-res now, so leave room for both growing
+        core.state_size = core.state().len() + 2 * Stack::NUM_FRAMES + Display::HIRES_SIZE - Display::SIZE;
+        core
+    }
+
+    fn reset(&mut self) {
+        self.machine.reset();
+        self.crashed = false;
+    }
+
+    fn run(&mut self, input_state: Option<InputStateFn>) {
+        if let Some(input_state) = input_state {
+            let mut keys = [false; Chip8::NUM_KEYS as usize];
+            for &(button, key) in JOYPAD_KEYS.iter() {
+                keys[key as usize] |= input_state(0, DEVICE_JOYPAD, 0, button) != 0;
+            }
+            for (&key, &keycode) in PAD_ORDER.iter().zip(KEYBOARD_LAYOUT.iter()) {
+                keys[key as usize] |= input_state(0, DEVICE_KEYBOARD, 0, keycode as c_uint) != 0;
+            }
+            for (key, &is_pressed) in keys.iter().enumerate() {
+                self.machine.set_key(key as u8, is_pressed);
+            }
+        }
+
+        if !self.crashed {
+            if let Err(error) = self.machine.run_frames(1) {
+                warn!("The program stopped: {}", error);
+                self.crashed = true;
+            }
+        }
+
+        let display = &self.machine.display;
+        self.framebuffer.clear();
+        self.framebuffer.extend(display.pixels().iter().map(|&pixel| PALETTE[(pixel & 0x3) as usize]));
+
+        // One frame of the buzzer, continuing the wave from the last
+        let samples = (SAMPLE_RATE / Scheduler::VBLANK_RATE_HZ) as usize;
+        let sounding = self.machine.sound_active() && !self.crashed;
+        self.audio.clear();
+        for _ in 0..samples {
+            let sample = match (sounding, self.phase <= 0.5) {
+                (false, _) => 0,
+                (true, true) => VOLUME,
+                (true, false) => -VOLUME,
+            };
+            self.audio.extend_from_slice(&[sample, sample]);
+            self.phase = (self.phase + TONE_HZ / SAMPLE_RATE) % 1.0;
+        }
+    }
+
+    fn state(&self) -> Vec<u8> {
+        let mut out = StateWriter::new();
+        out.u8(savestate::VERSION);
+        self.machine.snapshot().save(&mut out);
+        out.into_bytes()
+    }
+
+    fn load_state(&mut self, data: &[u8]) -> Result<(), String> {
+        let mut input = StateReader::new(data);
+        if input.u8()? != savestate::VERSION {
+            return Err(String::from("the state is from another version of yac8"));
+        }
+        self.machine.restore(Snapshot::load(&mut input)?);
+        self.crashed = false;
+        Ok(())
+    }
+}
+
+/// A ROM's variant, by its extension: .sc8 for SUPER-CHIP and .xo8 for
+/// XO-CHIP. Anything else is plain CHIP-8.
+fn variant_for(path: *const c_char) -> Variant {
+    if path.is_null() {
+        return Variant::Chip8;
+    }
+    let path = unsafe { CStr::from_ptr(path) }.to_string_lossy();
+    match Path::new(path.as_ref()).extension().and_then(|extension| extension.to_str()) {
+        Some("sc8") => Variant::SuperChip,
+        Some("xo8") => Variant::XoChip,
+        _ => Variant::Chip8,
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_api_version() -> c_uint {
+    API_VERSION
+}
+
+#[no_mangle]
+pub extern "C" fn retro_init() {}
+
+#[no_mangle]
+pub extern "C" fn retro_deinit() {
+    CORE.with(|core| core.borrow_mut().take());
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_info(info: *mut SystemInfo) {
+    *info = SystemInfo {
+        library_name: b"yac8\0".as_ptr() as *const c_char,
+        library_version: concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char,
+        valid_extensions: b"ch8|c8|sc8|xo8\0".as_ptr() as *const c_char,
+        need_fullpath: false,
+        block_extract: false,
+    };
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_av_info(info: *mut SystemAvInfo) {
+    let (width, height) = CORE.with(|core| match core.borrow().as_ref() {
+        Some(core) => (core.machine.display.width(), core.machine.display.height()),
+        None => (64, 32),
+    });
+    *info = SystemAvInfo {
+        geometry: GameGeometry {
+            base_width: width as c_uint,
+            base_height: height as c_uint,
+            // Frames change size going in and out of SUPER-CHIP's hi-res mode
+            max_width: Display::HIRES_WIDTH as c_uint,
+            max_height: Display::HIRES_HEIGHT as c_uint,
+            aspect_ratio: width as f32 / height as f32,
+        },
+        timing: SystemTiming { fps: Scheduler::VBLANK_RATE_HZ, sample_rate: SAMPLE_RATE },
+    };
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_environment(callback: EnvironmentFn) {
+    CALLBACKS.with(|callbacks| callbacks.borrow_mut().environment = Some(callback));
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_video_refresh(callback: VideoRefreshFn) {
+    CALLBACKS.with(|callbacks| callbacks.borrow_mut().video_refresh = Some(callback));
+}
+
+// The whole frame goes to the batch callback
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample(_callback: AudioSampleFn) {}
+
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample_batch(callback: AudioSampleBatchFn) {
+    CALLBACKS.with(|callbacks| callbacks.borrow_mut().audio_sample_batch = Some(callback));
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_poll(callback: InputPollFn) {
+    CALLBACKS.with(|callbacks| callbacks.borrow_mut().input_poll = Some(callback));
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_state(callback: InputStateFn) {
+    CALLBACKS.with(|callbacks| callbacks.borrow_mut().input_state = Some(callback));
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_controller_port_device(_port: c_uint, _device: c_uint) {}
+
+#[no_mangle]
+pub extern "C" fn retro_reset() {
+    CORE.with(|core| {
+        if let Some(core) = core.borrow_mut().as_mut() {
+            core.reset();
+        }
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn retro_run() {
+    let (video_refresh, audio_sample_batch, input_poll, input_state) = CALLBACKS.with(|callbacks| {
+        let callbacks = callbacks.borrow();
+        (callbacks.video_refresh, callbacks.audio_sample_batch, callbacks.input_poll, callbacks.input_state)
+    });
+    if let Some(input_poll) = input_poll {
+        input_poll();
+    }
+
+    CORE.with(|core| {
+        let mut core = core.borrow_mut();
+        let core = match core.as_mut() {
+            Some(core) => core,
+            None => return,
+        };
+        core.run(input_state);
+
+        if let Some(video_refresh) = video_refresh {
+            let display = &core.machine.display;
+            video_refresh(core.framebuffer.as_ptr() as *const c_void, display.width() as c_uint,
+                          display.height() as c_uint, display.width() * 4);
+        }
+        if let Some(audio_sample_batch) = audio_sample_batch {
+            // The frontend may take the samples in several goes
+            let mut sent = 0;
+            let frames = core.audio.len() / 2;
+            while sent < frames {
+                let taken = audio_sample_batch(core.audio[2 * sent..].as_ptr(), frames - sent);
+                if taken == 0 {
+                    break;
+                }
+                sent += taken;
+            }
+        }
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn retro_serialize_size() -> usize {
+    CORE.with(|core| core.borrow().as_ref().map_or(0, |core| core.state_size))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_serialize(data: *mut c_void, size: usize) -> bool {
+    CORE.with(|core| {
+        let core = core.borrow();
+        let state = match core.as_ref() {
+            Some(core) => core.state(),
+            None => return false,
+        };
+        if state.len() > size {
+            return false;
+        }
+        let out = slice::from_raw_parts_mut(data as *mut u8, size);
+        out[..state.len()].copy_from_slice(&state);
+        out[state.len()..].fill(0);
+        true
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_unserialize(data: *const c_void, size: usize) -> bool {
+    let data = slice::from_raw_parts(data as *const u8, size);
+    CORE.with(|core| match core.borrow_mut().as_mut() {
+        Some(core) => core.load_state(data).map_err(|error| warn!("Cannot load the state: {}", error)).is_ok(),
+        None => false,
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_reset() {}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_set(_index: c_uint, _enabled: bool, _code: *const c_char) {}
+
+#[no_mangle]
+pub unsafe extern "C" fn retro_load_game(game: *const GameInfo) -> bool {
+    if game.is_null() || (*game).data.is_null() {
+        return false;
+    }
+    let game = &*game;
+    let rom = slice::from_raw_parts(game.data as *const u8, game.size).to_vec();
+
+    let environment = CALLBACKS.with(|callbacks| callbacks.borrow().environment);
+    let mut format = PIXEL_FORMAT_XRGB8888;
+    if let Some(environment) = environment {
+        if !environment(ENVIRONMENT_SET_PIXEL_FORMAT, &mut format as *mut c_uint as *mut c_void) {
+            warn!("The frontend doesn't support XRGB8888.");
+            return false;
+        }
+    }
+
+    let core = Core::new(rom, variant_for(game.path));
+    CORE.with(|slot| *slot.borrow_mut() = Some(core));
+    true
+}
+
+#[no_mangle]
+pub extern "C" fn retro_load_game_special(_game_type: c_uint, _info: *const GameInfo, _num_info: usize) -> bool {
+    false
+}
+
+#[no_mangle]
+pub extern "C" fn retro_unload_game() {
+    CORE.with(|core| core.borrow_mut().take());
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_region() -> c_uint {
+    REGION_NTSC
+}
+
+// Memory isn't exposed for cheats or achievements
+#[no_mangle]
+pub extern "C" fn retro_get_memory_data(_id: c_uint) -> *mut c_void {
+    std::ptr::null_mut()
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_memory_size(_id: c_uint) -> usize {
+    0
+}
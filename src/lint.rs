@@ -0,0 +1,326 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use crate::disassembler::{self, PROGRAM_OFFSET};
+use crate::instructions::{self, Instruction};
+use crate::scan::{self, Extension};
+use crate::stack::Stack;
+use crate::variant::Variant;
+
+/// Runtime lints for ROM authors, collected in strict mode and reported at
+/// exit. Each is something a working program can do on purpose, but that
+/// usually points at a bug: a value parked in VF that the next arithmetic
+/// instruction overwrites with its flag, BCD or register stores into code
+/// that has run, I pointing past the end of memory, and a stack that's
+/// nearly full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Lint {
+    VFClobbered,
+    VFArithmetic,
+    WriteIntoCode,
+    IPastMemory,
+    StackNearlyFull,
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Lint::VFClobbered => write!(f, "wrote VF as a general register, then the next instruction overwrote it with a flag"),
+            Lint::VFArithmetic => write!(f, "used VF as the destination of arithmetic, whose result is replaced by the flag"),
+            Lint::WriteIntoCode => write!(f, "stored into memory that has already run as code"),
+            Lint::IPastMemory => write!(f, "pointed I past the end of memory"),
+            Lint::StackNearlyFull => write!(f, "called a subroutine with the stack nearly full"),
+        }
+    }
+}
+
+pub struct Linter {
+    executed: Vec<bool>,
+    // Where VF was last written as a general register, until it's read or overwritten
+    vf_written_at: Option<u16>,
+    findings: BTreeMap<(u16, Lint), u64>,
+}
+
+impl Linter {
+    const STACK_WARNING_DEPTH: usize = Stack::NUM_FRAMES - 2;
+
+    pub fn new() -> Linter {
+        Linter {
+            // Every address a program counter can hold, whatever the memory size
+            executed: vec![false; u16::MAX as usize + 1],
+            vf_written_at: None,
+            findings: BTreeMap::new(),
+        }
+    }
+
+    /// Check an instruction that just ran at `pc`, given I before and after
+    /// it, how deep the stack is now and how big memory is.
+    pub fn check(&mut self, pc: u16, instruction: &Instruction, i_before: u16, i_after: u16, stack_depth: usize,
+                 memory_size: usize) {
+        for address in pc..=pc.saturating_add(1) {
+            self.executed[address as usize] = true;
+        }
+
+        let sets_flag = matches!(instruction,
+                                 Instruction::Add(..) | Instruction::Sub(..) | Instruction::NegatedSub(..)
+                                 | Instruction::ShiftRight(..) | Instruction::ShiftLeft(..) | Instruction::Draw(..));
+        if let Some(written_at) = self.vf_written_at.take() {
+            if sets_flag && !Linter::reads_vf(instruction) {
+                self.record(written_at, Lint::VFClobbered);
+            }
+        }
+        match instruction {
+            Instruction::Add(0xF, _) | Instruction::Sub(0xF, _) | Instruction::NegatedSub(0xF, _)
+            | Instruction::ShiftRight(0xF, _) | Instruction::ShiftLeft(0xF, _) => self.record(pc, Lint::VFArithmetic),
+            Instruction::LoadData(0xF, _) | Instruction::AddData(0xF, _) | Instruction::LoadRegister(0xF, _)
+            | Instruction::Or(0xF, _) | Instruction::And(0xF, _) | Instruction::Xor(0xF, _)
+            | Instruction::Random(0xF, _) | Instruction::SetRegisterFromDelay(0xF) => self.vf_written_at = Some(pc),
+            _ => {},
+        }
+
+        let written = match instruction {
+            Instruction::SetBCDRepresentation(_) => 3,
+            Instruction::StoreRegisters(high_register) => *high_register as u16 + 1,
+            _ => 0,
+        };
+        if (i_before..i_before.saturating_add(written)).any(|address| self.executed.get(address as usize) == Some(&true)) {
+            self.record(pc, Lint::WriteIntoCode);
+        }
+
+        let sets_i = matches!(instruction, Instruction::SetI(_) | Instruction::AddI(_) | Instruction::LoadSprite(_));
+        if sets_i && i_after as usize >= memory_size {
+            self.record(pc, Lint::IPastMemory);
+        }
+
+        if let Instruction::Call(_) = instruction {
+            if stack_depth >= Linter::STACK_WARNING_DEPTH {
+                self.record(pc, Lint::StackNearlyFull);
+            }
+        }
+    }
+
+    fn reads_vf(instruction: &Instruction) -> bool {
+        match *instruction {
+            Instruction::Add(x, y) | Instruction::Sub(x, y) | Instruction::NegatedSub(x, y)
+            | Instruction::ShiftRight(x, y) | Instruction::ShiftLeft(x, y) => x == 0xF || y == 0xF,
+            Instruction::Draw(x, y, _) => x == 0xF || y == 0xF,
+            _ => false,
+        }
+    }
+
+    fn record(&mut self, pc: u16, lint: Lint) {
+        *self.findings.entry((pc, lint)).or_insert(0) += 1;
+    }
+}
+
+impl Default for Linter {
+    fn default() -> Linter {
+        Linter::new()
+    }
+}
+
+impl fmt::Display for Linter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "=> Lint report")?;
+        if self.findings.is_empty() {
+            return writeln!(f, "Nothing suspicious.");
+        }
+        for (&(pc, lint), &count) in &self.findings {
+            writeln!(f, "  {:#06X} {} ({}x)", pc, lint, count)?;
+        }
+        Ok(())
+    }
+}
+
+/// Static checks for `yac8 lint`, run over the code reachable from the
+/// entry point without executing anything. Errors are things no
+/// interpreter for the variant can run; warnings are likely mistakes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+pub struct Finding {
+    pub address: u16,
+    pub severity: Severity,
+    pub check: &'static str,
+    pub message: String,
+}
+
+impl Finding {
+    pub fn to_json(&self) -> String {
+        format!("{{\"address\":{},\"severity\":\"{}\",\"check\":\"{}\",\"message\":\"{}\"}}",
+                self.address, self.severity, self.check, self.message)
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:#05X}: {}: {}: {}", self.address, self.severity, self.check, self.message)
+    }
+}
+
+// How far back from a DXYN to look for the ANNN that set up its sprite
+const SPRITE_LOOKBACK: u16 = 8;
+
+pub fn check_rom(rom: &[u8], variant: Variant) -> Vec<Finding> {
+    let reachable = disassembler::reachable(rom);
+    let rom_end = PROGRAM_OFFSET as usize + rom.len();
+    let in_rom = |address: u16| (PROGRAM_OFFSET as usize..rom_end).contains(&(address as usize));
+    let mut findings = Vec::new();
+    let mut finding = |address: u16, severity: Severity, check: &'static str, message: String| {
+        findings.push(Finding { address, severity, check, message });
+    };
+
+    let mut data_references = BTreeSet::new();
+    let mut code = vec![false; rom.len()];
+    for &address in &reachable {
+        let opcode = disassembler::opcode_at(rom, address).expect("Reachable addresses are inside the ROM.");
+        let start = (address - PROGRAM_OFFSET) as usize;
+        let length = if opcode == 0xF000 { 4 } else { 2 };
+        for byte in &mut code[start..(start + length).min(rom.len())] {
+            *byte = true;
+        }
+
+        let instruction = instructions::parse_opcode(opcode);
+        match instruction {
+            Instruction::Jump(target) | Instruction::Call(target) => {
+                let verb = if let Instruction::Jump(_) = instruction { "jumps to" } else { "calls" };
+                if !in_rom(target) {
+                    finding(address, Severity::Error, "target-out-of-range",
+                            format!("{} {:#05X}, outside the ROM", verb, target));
+                } else if target % 2 == 1 {
+                    finding(address, Severity::Warning, "odd-target",
+                            format!("{} the odd address {:#05X}", verb, target));
+                }
+            },
+            Instruction::SetI(target) => {
+                data_references.insert(target);
+            },
+            Instruction::Draw(_, _, 0) if !variant.supports(Extension::SuperChip) => {
+                finding(address, Severity::Warning, "sprite-height",
+                        format!("DXY0 draws nothing on {}", variant));
+            },
+            Instruction::Draw(_, _, height) if height > 0 => {
+                if let Some(sprite) = sprite_address(rom, &reachable, address) {
+                    if sprite as usize + height as usize > rom_end && in_rom(sprite) {
+                        finding(address, Severity::Warning, "sprite-height",
+                                format!("the {} row sprite at {:#05X} runs past the end of the ROM", height, sprite));
+                    } else if (sprite..sprite + height as u16).any(|row| reachable.contains(&(row & !1))) {
+                        finding(address, Severity::Warning, "sprite-height",
+                                format!("the {} row sprite at {:#05X} overlaps code", height, sprite));
+                    }
+                }
+            },
+            _ => match scan::extension_pattern(opcode) {
+                Some((extension, pattern)) if !variant.supports(extension) => {
+                    finding(address, Severity::Error, "invalid-opcode",
+                            format!("{} is a {} instruction, which {} doesn't have", pattern, extension, variant));
+                },
+                None if instruction == Instruction::UNKNOWN(opcode) => {
+                    finding(address, Severity::Error, "invalid-opcode", format!("{:04X} isn't an instruction", opcode));
+                },
+                _ => {},
+            },
+        }
+    }
+
+    // Runs of bytes that aren't code, aren't sprites and aren't zero padding
+    let mut offset = 0;
+    while offset < rom.len() {
+        if code[offset] {
+            offset += 1;
+            continue;
+        }
+        let start = offset;
+        while offset < rom.len() && !code[offset] {
+            offset += 1;
+        }
+        let (start_address, end_address) = (PROGRAM_OFFSET + start as u16, PROGRAM_OFFSET + offset as u16);
+        let is_data = data_references.range(start_address..end_address).next().is_some();
+        let is_padding = rom[start..offset].iter().all(|&byte| byte == 0);
+        if !is_data && !is_padding {
+            finding(start_address, Severity::Warning, "unreachable",
+                    format!("{} bytes up to {:#05X} are never reached and never used as sprite data",
+                            offset - start, end_address - 1));
+        }
+    }
+
+    findings.sort_by_key(|finding| finding.address);
+    findings
+}
+
+/// Where I points when the DXYN at `address` runs, if an ANNN sets it a
+/// few instructions earlier with nothing in between that could move it.
+fn sprite_address(rom: &[u8], reachable: &BTreeSet<u16>, address: u16) -> Option<u16> {
+    let mut previous = address;
+    for _ in 0..SPRITE_LOOKBACK {
+        previous = previous.checked_sub(2).filter(|previous| reachable.contains(previous))?;
+        match instructions::parse_opcode(disassembler::opcode_at(rom, previous)?) {
+            Instruction::SetI(sprite) => return Some(sprite),
+            Instruction::AddI(_) | Instruction::LoadSprite(_) | Instruction::StoreRegisters(_)
+            | Instruction::ReadRegisters(_) | Instruction::Call(_) | Instruction::Return
+            | Instruction::Jump(_) | Instruction::JumpFromOffset(_) => return None,
+            _ => {},
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::Chip8;
+
+    fn runtime_lints(program: Vec<u8>, variant: Variant, steps: usize) -> Vec<(u16, Lint)> {
+        let mut machine = Chip8::new(program, 700.0);
+        machine.set_variant(variant);
+        machine.enable_strict();
+        (0..steps).for_each(|_| machine.step().unwrap());
+        machine.linter().unwrap().findings.keys().cloned().collect()
+    }
+
+    #[test]
+    fn i_past_memory_depends_on_the_memory_size() {
+        // LD I, 0xFFF; LD V0, 1; ADD I, V0
+        let program = vec![0xAF, 0xFF, 0x60, 0x01, 0xF0, 0x1E];
+        assert_eq!(runtime_lints(program.clone(), Variant::Chip8, 3), vec![(0x204, Lint::IPastMemory)]);
+        assert_eq!(runtime_lints(program, Variant::XoChip, 3), vec![]);
+    }
+
+    #[test]
+    fn vf_lints_only_flag_vf() {
+        // LD VF, 1; ADD V0, V1; SUB VF, V0; then the same with V2 in VF's place
+        let program = vec![0x6F, 0x01, 0x80, 0x14, 0x8F, 0x05, 0x62, 0x01, 0x80, 0x14, 0x82, 0x05];
+        assert_eq!(runtime_lints(program, Variant::Chip8, 6),
+                   vec![(0x200, Lint::VFClobbered), (0x204, Lint::VFArithmetic)]);
+    }
+
+    #[test]
+    fn static_checks_find_bad_targets_and_opcodes() {
+        // Over some padding to an XO-CHIP opcode, then JP 0x300, past the end of the ROM
+        let rom = [0x12, 0x04, 0x00, 0x00, 0xF0, 0x01, 0x13, 0x00];
+        let checks = |variant| check_rom(&rom, variant).iter().map(|finding| (finding.address, finding.check))
+                                                          .collect::<Vec<_>>();
+        assert_eq!(checks(Variant::Chip8), vec![(0x204, "invalid-opcode"), (0x206, "target-out-of-range")]);
+        assert_eq!(checks(Variant::XoChip), vec![(0x206, "target-out-of-range")]);
+    }
+
+    #[test]
+    fn clean_programs_have_no_findings() {
+        // CLS; LD I, sprite; DRW V0, V0, 1; JP 0x206; the sprite
+        let rom = [0x00, 0xE0, 0xA2, 0x08, 0xD0, 0x01, 0x12, 0x06, 0x80];
+        assert!(check_rom(&rom, Variant::Chip8).is_empty());
+        assert_eq!(runtime_lints(rom.to_vec(), Variant::Chip8, 5), vec![]);
+    }
+}
@@ -0,0 +1,35 @@
+use crate::variant::Variant;
+
+/// The observable state of the machine at one moment, as plain values:
+/// what a debugger shows, what a dump prints and what a test compares with
+/// `assert_eq!`. Unlike a `Snapshot` it can't be restored, since it leaves
+/// out the scheduler, the random number generator and the bookkeeping
+/// behind quirks and key edges.
+///
+/// Memory and the display are the bulk of it, so they're only filled in
+/// when asked for. Memory covers the whole address space, 4K or XO-CHIP's
+/// 64K, reading 0 wherever nothing lives, and the display holds one byte per pixel, row
+/// by row.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MachineState {
+    pub variant: Variant,
+    pub instructions: u64,
+    pub frames: u64,
+    pub v: [u8; 16],
+    pub i: u16,
+    pub pc: u16,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    pub stack: Vec<u16>,
+    // Key N held in bit N
+    pub keys: u16,
+    pub waiting_on_key: bool,
+    pub memory: Option<Vec<u8>>,
+    pub display: Option<Vec<u8>>,
+}
+
+impl MachineState {
+    pub fn key_held(&self, key: u8) -> bool {
+        self.keys & (1 << key) != 0
+    }
+}
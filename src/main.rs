@@ -1,109 +1,1415 @@
+// Without SDL, the helpers only the window uses go unused
+#![cfg_attr(not(feature = "frontend"), allow(dead_code))]
+
+use std::collections::VecDeque;
 use std::fs;
-use std::time::Instant;
-
-use simple_logger;
-use sdl2::event::Event;
-use clap::{App, Arg};
-
-mod chip8;
-mod display;
-mod instructions;
-mod main_memory;
-mod registers;
-mod stack;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "frontend")]
+use sdl2::event::{Event, WindowEvent};
+#[cfg(feature = "frontend")]
+use sdl2::keyboard::Scancode;
+#[cfg(feature = "frontend")]
+use sdl2::pixels::Color;
+use clap::{value_t, values_t, ArgMatches};
+
+use yac8::{assembler, checksum, chip8, compat, disassembler, display, error, font, hooks, input, instructions, lint, machine_state,
+           main_memory, quirk_usage, quirks, registers, rom_database, savestate, scan, scheduler, snapshot, stack, stats,
+           trace, variant, watch, watchdog};
+
+mod bench;
+mod bundle;
+mod capture;
+mod cfg;
+mod cli;
+mod debug_view;
+mod debugger;
+#[cfg(feature = "frontend")]
+mod gamepads;
+mod frame_stats;
+mod html;
+#[cfg(feature = "frontend")]
+mod menu;
+#[cfg(feature = "frontend")]
+mod osd;
+mod report;
+mod script;
+mod state_dump;
+#[cfg(feature = "frontend")]
 mod interface;
+mod keymap;
+mod test_pattern;
+#[cfg(feature = "frontend")]
+mod text;
+mod tui;
+mod vectors;
+mod web_debugger;
+#[cfg(feature = "frontend")]
+mod wav;
 
+use bundle::Bundle;
+use capture::{CaptureFormat, GifRecorder};
+use chip8::Chip8;
+#[cfg(feature = "frontend")]
 use display::Display;
-use interface::AVInterface;
+use error::Chip8Error;
+use font::Font;
+use input::SharedKeys;
+#[cfg(feature = "frontend")]
+use frame_stats::FrameStats;
+use hooks::CoreEvent;
+use keymap::Keymap;
+use main_memory::MainMemory;
+#[cfg(feature = "frontend")]
+use menu::{Action as MenuAction, EscapeKey, PauseMenu};
+#[cfg(feature = "frontend")]
+use osd::Osd;
+use report::{Report, ReportFormat};
+use quirk_usage::QuirkUsage;
+use quirks::Quirks;
+#[cfg(feature = "frontend")]
+use interface::{AudioConfig, AVInterface, Fullscreen, Minimized, Rotation, ScaleMode, VideoConfig};
+use rom_database::RomDatabase;
+use savestate::{StateReader, StateWriter};
+use scheduler::Scheduler;
+use snapshot::Snapshot;
+use script::{Action, Script};
+use trace::{TraceFormat, Tracer};
+use tui::Terminal;
+use variant::Variant;
+use watch::Watch;
+use watchdog::Watchdog;
+use web_debugger::WebDebugger;
+use debugger::Debugger;
+
+// Slow enough to read along with --teach
+const TEACH_CLOCK_HZ: f64 = 2.0;
+// How far + and - move the clock, and the range they keep it in
+const CLOCK_STEP: f64 = 1.25;
+const CLOCK_RANGE: (f64, f64) = (1.0, 100_000.0);
+// How fast holding Tab runs everything, and how slow M's slow motion does
+const FAST_FORWARD: f64 = 10.0;
+const SLOW_MOTION: f64 = 0.25;
+// How often a minimized window wakes up to run the machine
+const MINIMIZED_WAKEUP_MS: u32 = 100;
+// How often a machine stalled on FX0A wakes up to keep its timers, toasts and the web debugger going
+const KEY_WAIT_WAKEUP_MS: u32 = 50;
+// Terminals only send presses, so under --tui a key counts as held for this long after each one
+const TUI_KEY_HOLD: Duration = Duration::from_millis(150);
+// How often --tui runs the machine and reads the keyboard
+const TUI_POLL: Duration = Duration::from_millis(4);
+// Ctrl-C, which raw mode delivers as a key
+const TUI_QUIT: u8 = 0x03;
+const DEBUGGER_PAUSED_POLL: Duration = Duration::from_millis(10);
+// F7 and F8 cycle through this many save slots
+const STATE_SLOTS: usize = 10;
+
+// Exit statuses for scripted runs
+const EXIT_ASSERTION_FAILED: i32 = 1;
+const EXIT_LINT_ERRORS: i32 = 1;
+const EXIT_VECTORS_CHANGED: i32 = 1;
+const EXIT_CRASHED: i32 = 2;
+const EXIT_HUNG: i32 = 3;
+const EXIT_TIMEOUT: i32 = 124;
 
 pub fn main() {
-    let matches = App::new("yac8")
-                            .version("0.1.0")
-                            .author("halfhorst")
-                            .about("Yet another CHIP-8 emulator")
-                            .arg(Arg::with_name("program_file")
-                                    .value_name("PROGRAM_FILE")
-                                    .help("A CHIP-8 ROM filepath.")
-                                    .takes_value(true)
-                                    .required(true))
-                            .arg(Arg::with_name("scan")
-                                    .short("s")
-                                    .long("scan")
-                                    .value_name("SCAN")
-                                    .help("Scan the program only, printing raw bytes and instructions.")
-                                    .takes_value(false)
-                                    .required(false))
-                            .arg(Arg::with_name("verbose")
-                                    .short("v")
-                                    .long("verbose")
-                                    .value_name("VERBOSE")
-                                    .help("Run the VM with verbose logging to the terminal.")
-                                    .takes_value(false)
-                                    .required(false))
-                            .arg(Arg::with_name("clock_speed")
-                                    .short("c")
-                                    .long("clock")
-                                    .help("The clock speed to run the CPU at in hz. Defaults to 700hz.")
-                                    .value_name("clock_speed")
-                                    .takes_value(true)
-                                    .required(false))
-                            .get_matches();
+    let matches = cli::app().get_matches();
+
+    if matches.is_present("verbose") {
+        simple_logger::init().unwrap();
+    }
 
+    match matches.subcommand() {
+        ("run", Some(sub_matches)) => std::process::exit(run(sub_matches, sub_matches.is_present("headless"))),
+        ("bench", Some(sub_matches)) | ("test", Some(sub_matches)) => std::process::exit(run(sub_matches, true)),
+        ("scan", Some(sub_matches)) => scan(sub_matches),
+        ("disasm", Some(sub_matches)) => disasm(sub_matches),
+        ("asm", Some(sub_matches)) => asm(sub_matches),
+        ("lint", Some(sub_matches)) => std::process::exit(lint(sub_matches)),
+        ("audio-devices", Some(_)) => list_audio_devices(),
+        ("vectors", Some(sub_matches)) => std::process::exit(vectors(sub_matches)),
+        ("compat", Some(sub_matches)) => compat(sub_matches),
+        _ => unreachable!("clap requires a subcommand"),
+    }
+}
+
+fn read_rom(matches: &ArgMatches) -> Vec<u8> {
+    read_program(matches).rom
+}
+
+/*
+    The program file, unpacked if it's a `.c8b` bundle, or the `--test-pattern`
+    program.
+*/
+fn read_program(matches: &ArgMatches) -> Bundle {
+    if let Some(name) = matches.value_of("test_pattern") {
+        return Bundle::plain(test_pattern::rom(name).unwrap());
+    }
     let program_file = matches.value_of("program_file").unwrap();
-    let scan = matches.is_present("scan");
-    let verbose = matches.is_present("verbose");
-    let clock_speed = match matches.value_of("clock_speed") {
-        Some(s) => {
-            match s.parse::<f64>() {
-                Ok(n) => n,
-                Err(_) => panic!("Failed to parse clock_speed")
+    let data = fs::read(program_file).expect("Cannot open or read ROM file.");
+    if !Bundle::is_bundle(&data) {
+        return Bundle::plain(data);
+    }
+    Bundle::parse(&data).unwrap_or_else(|error| {
+        clap::Error::with_description(&format!("{}: {}", program_file, error), clap::ErrorKind::InvalidValue).exit()
+    })
+}
+
+fn scan(matches: &ArgMatches) {
+    let program_file = Path::new(matches.value_of("program_file").unwrap());
+    let explain = matches.is_present("explain");
+    if program_file.is_dir() {
+        let out_dir = matches.value_of("out_dir").map(PathBuf::from).unwrap_or_else(|| program_file.join("yac8-scan"));
+        let count = scan::scan_directory(program_file, &out_dir, &rom_database(matches), explain).expect("Cannot scan ROM directory.");
+        println!("=> Scanned {} ROMs into {}.", count, out_dir.display());
+        return;
+    }
+
+    let rom = read_rom(matches);
+    for line in disassembler::listing(&rom, explain) {
+        println!("{}", line);
+    }
+    let summary = scan::Summary::of(&rom);
+    print!("\n{}\n{}{}", rom_database(matches).identify(&summary.sha1), summary, QuirkUsage::of(&rom));
+}
+
+/*
+    The ROM database given with `--rom-db`, or an empty one.
+*/
+fn rom_database(matches: &ArgMatches) -> RomDatabase {
+    let mut database = RomDatabase::new();
+    if let Some(path) = matches.value_of("rom_db") {
+        let source = fs::read_to_string(path).expect("Cannot open or read ROM database.");
+        database.load(&source).unwrap_or_else(|error| {
+            clap::Error::with_description(&format!("{}: {}", path, error), clap::ErrorKind::InvalidValue).exit()
+        });
+    }
+    database
+}
+
+fn disasm(matches: &ArgMatches) {
+    let name = Path::new(matches.value_of("program_file").unwrap()).file_stem().unwrap().to_string_lossy();
+    let rom = read_rom(matches);
+    let disassembly = if matches.is_present("dot") {
+        cfg::to_dot(&rom, &name)
+    } else if matches.is_present("html") {
+        html::to_html(&rom, &name)
+    } else {
+        disassembler::listing(&rom, matches.is_present("explain")).into_iter().map(|line| line + "\n").collect()
+    };
+    match matches.value_of("out") {
+        Some(path) => fs::write(path, disassembly).expect("Cannot write disassembly."),
+        None => print!("{}", disassembly),
+    }
+}
+
+fn asm(matches: &ArgMatches) {
+    let source_file = Path::new(matches.value_of("source_file").unwrap());
+    let source = fs::read_to_string(source_file).expect("Cannot open or read assembly source.");
+    let rom = assembler::assemble(&source).unwrap_or_else(|error| {
+        clap::Error::with_description(&format!("{}: {}", source_file.display(), error), clap::ErrorKind::InvalidValue).exit()
+    });
+    let out = matches.value_of("out").map(PathBuf::from).unwrap_or_else(|| source_file.with_extension("ch8"));
+    if out == source_file {
+        clap::Error::with_description("The ROM would overwrite the source; give it another name with --out.",
+                                      clap::ErrorKind::InvalidValue).exit();
+    }
+    fs::write(&out, &rom).expect("Cannot write ROM.");
+    println!("=> Assembled {} bytes into {}.", rom.len(), out.display());
+}
+
+/*
+    Static checks on a program, returning the process exit status: 1 if
+    anything was an error.
+*/
+fn lint(matches: &ArgMatches) -> i32 {
+    let bundle = read_program(matches);
+    let variant = matches.value_of("variant").and_then(Variant::from_name).or(bundle.variant).unwrap_or(Variant::Chip8);
+    let findings = lint::check_rom(&bundle.rom, variant);
+    let json = matches.value_of("format") == Some("json");
+    for finding in &findings {
+        if json {
+            println!("{}", finding.to_json());
+        } else {
+            println!("{}", finding);
+        }
+    }
+
+    let errors = findings.iter().filter(|finding| finding.severity == lint::Severity::Error).count();
+    if !json {
+        println!("=> {} errors, {} warnings.", errors, findings.len() - errors);
+    }
+    if errors > 0 { EXIT_LINT_ERRORS } else { 0 }
+}
+
+/*
+    Print golden test vectors, or check a file of them, returning the
+    process exit status: 1 if any vector changed.
+*/
+fn vectors(matches: &ArgMatches) -> i32 {
+    let path = match matches.value_of("check") {
+        Some(path) => path,
+        None => {
+            let variant = matches.value_of("variant").and_then(Variant::from_name);
+            for line in vectors::generate(variant, parse_arg(matches, "seed", 0), parse_arg(matches, "count", 8)) {
+                println!("{}", line);
             }
+            return 0;
         },
-        None => 700.0
     };
 
-    if verbose {
-        simple_logger::init().unwrap();
+    let file = fs::read_to_string(path).expect("Cannot open or read vector file.");
+    let changed = vectors::check(&file).unwrap_or_else(|error| {
+        clap::Error::with_description(&format!("{}: {}", path, error), clap::ErrorKind::InvalidValue).exit()
+    });
+    for (line, pattern) in &changed {
+        println!("{}:{}: {} vector changed", path, line, pattern);
     }
+    println!("=> {} of {} vectors changed.", changed.len(), file.lines().count() - 1);
+    if changed.is_empty() { 0 } else { EXIT_VECTORS_CHANGED }
+}
+
+#[cfg(feature = "frontend")]
+fn list_audio_devices() {
+    let devices = interface::audio_devices().expect("Failed to query SDL2 audio devices.");
+    if devices.is_empty() {
+        println!("=> No audio output devices found.");
+    }
+    for device in devices {
+        println!("{}", device);
+    }
+}
 
+#[cfg(not(feature = "frontend"))]
+fn list_audio_devices() {
+    without_sdl()
+}
+
+fn compat(matches: &ArgMatches) {
+    let clock_speed = parse_arg(matches, "clock_speed", 700.0);
+    let frames = parse_arg(matches, "frames", 600);
+    let rom = read_rom(matches);
+    println!("{}", QuirkUsage::of(&rom));
+    print!("{}", compat::Report(compat::check(&rom, clock_speed, frames)));
+}
+
+/*
+    Run a program in a window until it's closed, or headless, until a limit
+    is reached, returning the process exit status. `bench` and `test` are
+    headless runs with fewer options, so they come through here too.
+*/
+fn run(matches: &ArgMatches, headless: bool) -> i32 {
+    let program_file = matches.value_of("program_file").or_else(|| matches.value_of("test_pattern")).unwrap();
+    let teach = matches.is_present("teach");
+    let clock_speed = parse_arg(matches, "clock_speed", if teach { TEACH_CLOCK_HZ } else { 700.0 });
+
+    let bundle = read_program(matches);
+    let rom_sha1 = checksum::sha1(&bundle.rom);
+    let sha1 = checksum::to_hex(&rom_sha1);
     println!("=> Booting ROM [ {} ].", program_file);
-    let rom_bytes = fs::read(program_file).expect("Cannot open or read ROM file.");
-    let mut machine = chip8::Chip8::new(rom_bytes, clock_speed);
+    println!("=> SHA1 {}, CRC32 {:08x}, {}.", sha1, checksum::crc32(&bundle.rom), rom_database(matches).identify(&sha1));
+    print_bundle_info(&bundle);
 
-    if scan {
-        machine.scan_program();
-        std::process::exit(0);
+    // --variant beats a bundle's platform, --quirks beats both, then each --quirk and --display-wait apply on top
+    let (variant, mut quirks) = match matches.value_of("variant").and_then(Variant::from_name) {
+        Some(variant) => (variant, variant.quirks()),
+        None => (bundle.variant.unwrap_or(Variant::Chip8), bundle.quirks.unwrap_or_default()),
+    };
+    if let Some(preset) = matches.value_of("quirks") {
+        quirks = Quirks::preset(preset).expect("Presets are validated by clap.");
+    }
+    for setting in matches.values_of("quirk").into_iter().flatten() {
+        quirks.apply(setting).expect("Quirk settings are validated by clap.");
     }
+    quirks.display_wait |= matches.is_present("display_wait");
 
-    let mut av_interface = AVInterface::new(Display::WIDTH as u32, Display::HEIGHT as u32);
+    let report_summary = matches.is_present("report").then(|| scan::Summary::of(&bundle.rom));
+    let mut machine = Chip8::new(bundle.rom, clock_speed);
+    machine.set_variant(variant);
+    machine.set_quirks(quirks);
+    if matches.value_of("seed").is_some() {
+        machine.set_seed(parse_arg(matches, "seed", 0));
+    }
+    let font = load_font(matches.value_of("font").unwrap_or("default"));
+    let font_address = matches.value_of("font_address").map_or(MainMemory::DEFAULT_FONT_ADDRESS, |address| {
+        cli::parse_hex_address(address).expect("Font addresses are validated by clap.")
+    });
+    machine.set_font(&font, font_address).unwrap_or_else(|error| {
+        clap::Error::with_description(&error, clap::ErrorKind::InvalidValue).exit()
+    });
 
-    let mut timer = Instant::now();
+    let trace_format = if teach { Some(TraceFormat::Teach) } else { matches.value_of("trace").and_then(TraceFormat::from_name) };
+    if let Some(format) = trace_format {
+        let out: Box<dyn io::Write> = match matches.value_of("trace_file") {
+            Some(path) => Box::new(io::BufWriter::new(fs::File::create(path).expect("Cannot create trace file."))),
+            None => Box::new(io::stdout()),
+        };
+        machine.set_tracer(Tracer::new(format, out));
+    }
+
+    if let Some(path) = matches.value_of("state_hashes") {
+        machine.set_state_hashes(Box::new(io::BufWriter::new(fs::File::create(path).expect("Cannot create state hash file."))));
+    }
+
+    // Instructions are already logged as they execute
+    if log::log_enabled!(log::Level::Info) {
+        machine.add_hook(Box::new(|event| if !matches!(event, CoreEvent::InstructionExecuted { .. }) {
+            log::info!("{}", event);
+        }));
+    }
+
+    // A report includes coverage
+    if matches.is_present("coverage") || matches.is_present("report") {
+        machine.enable_coverage();
+    }
+
+    if matches.is_present("strict") {
+        machine.enable_strict();
+    } else if matches.is_present("strict_memory") {
+        machine.enable_strict_memory();
+    }
+
+    machine.set_edge_triggered_keys(matches.is_present("edge_triggered_keys"));
+
+    if let Some(path) = matches.value_of("load_state") {
+        load_state(&mut machine, &rom_sha1, path);
+    }
+
+    // The debuggers show how a crash came about without being asked
+    if matches.is_present("history") || matches.is_present("debug") || matches.is_present("web_debugger") {
+        machine.enable_history();
+    }
+
+    // The web debugger can step back
+    if matches.is_present("web_debugger") {
+        machine.enable_journal();
+    }
+
+    if matches.is_present("dynarec") {
+        machine.enable_dynarec();
+    }
+
+    if let Some(expressions) = matches.values_of("watch") {
+        for expression in expressions {
+            machine.add_watch(Watch::parse(expression).unwrap());
+        }
+    }
+
+    machine.set_limits(matches.value_of("max_instructions").map(|_| parse_arg(matches, "max_instructions", 0)),
+                       matches.value_of("max_frames").map(|_| parse_arg(matches, "max_frames", 0)));
+
+    if let Some(length) = matches.value_of("bench") {
+        return match bench::run(&mut machine, bench::Length::parse(length).expect("Lengths are validated by clap.")) {
+            Ok(report) => {
+                print!("{}", report);
+                0
+            },
+            Err(error) => {
+                report_crash(&machine, &error);
+                EXIT_CRASHED
+            },
+        };
+    }
+
+    let mut session = Session::new(matches);
+    if headless && !session.ends_by_itself(matches) {
+        clap::Error::with_description("A --headless run needs something to end it: --max-instructions, --max-frames, --timeout, \
+                                       --screenshot-at, --dump-at, --script, --debug or --web-debugger.",
+                                      clap::ErrorKind::MissingRequiredArgument).exit()
+    }
+    if let Some(path) = matches.value_of("record_gif") {
+        session.start_gif(&machine, PathBuf::from(path));
+    }
+    let exit_status = if headless {
+        run_headless(&mut machine, &mut session)
+    } else if matches.is_present("tui") {
+        run_tui(matches, &mut machine, &mut session)
+    } else {
+        run_windowed(matches, &mut machine, &mut session, &bundle.colors, &rom_sha1)
+    };
+
+    session.finish_gif();
+
+    if let Some(path) = matches.value_of("dump_screen") {
+        if let Err(error) = save_screen(&machine, Path::new(path), 1) {
+            eprintln!("=> Cannot save the screen to {}: {}.", path, error);
+        }
+    }
+
+    if let Some(path) = matches.value_of("save_state") {
+        match save_state(&machine, &rom_sha1, path) {
+            Ok(()) => println!("=> Saved the state to {}.", path),
+            Err(error) => eprintln!("=> Cannot save the state to {}: {}.", path, error),
+        }
+    }
+
+    if let Some(dynarec) = machine.dynarec() {
+        println!("=> Dynarec: {}.", dynarec);
+    }
+
+    if matches.is_present("stats") {
+        print!("{}", machine.stats());
+    }
+
+    if let Some(linter) = machine.linter() {
+        print!("{}", linter);
+    }
+
+    if let Some(coverage) = machine.coverage().filter(|_| matches.is_present("coverage")) {
+        match matches.value_of("coverage_file") {
+            Some(path) => fs::write(path, coverage.to_string()).expect("Cannot write coverage file."),
+            None => print!("{}", coverage),
+        }
+    }
+
+    if let (Some(path), Some(summary)) = (matches.value_of("report"), report_summary) {
+        let settings = vec![("clock", format!("{}hz", clock_speed)),
+                            ("variant", String::from(variant.name())),
+                            ("quirks", report::describe_quirks(&quirks)),
+                            ("mode", String::from(if headless { "headless" } else { "windowed" })),
+                            ("edge-triggered keys", matches.is_present("edge_triggered_keys").to_string())];
+        let report = Report { name: program_file, summary, identity: rom_database(matches).identify(&sha1), settings, exit_status };
+        fs::write(path, report.render(ReportFormat::from_path(Path::new(path)), &machine)).expect("Cannot write run report.");
+        println!("=> Wrote a run report to {}.", path);
+    }
+
+    exit_status
+}
+
+fn save_state(machine: &Chip8, rom_sha1: &[u8; 20], path: &str) -> io::Result<()> {
+    let mut out = StateWriter::new();
+    machine.snapshot().save(&mut out);
+    fs::write(path, savestate::encode(rom_sha1, &out.into_bytes()))
+}
+
+fn read_state(rom_sha1: &[u8; 20], path: &str) -> Result<Snapshot, String> {
+    let file = fs::read(path).map_err(|error| error.to_string())?;
+    savestate::decode(rom_sha1, &file).and_then(|body| Snapshot::load(&mut StateReader::new(&body)))
+}
+
+/*
+    Where save slot `slot` lives: slot 0 is the state file itself, and the
+    others get their number added.
+*/
+fn slot_path(state_path: &str, slot: usize) -> String {
+    if slot == 0 { String::from(state_path) } else { format!("{}{}", state_path, slot) }
+}
+
+/*
+    Save a state while playing, where a file that can't be written (say, in
+    a read-only ROM directory) is only worth a message.
+*/
+#[cfg(feature = "frontend")]
+fn quick_save(machine: &Chip8, rom_sha1: &[u8; 20], state_path: &str, slot: usize, osd: &mut Osd) {
+    let path = slot_path(state_path, slot);
+    match save_state(machine, rom_sha1, &path) {
+        Ok(()) => osd.show(format!("State saved to slot {}", slot)),
+        Err(error) => {
+            eprintln!("=> Cannot save the state to {}: {}.", path, error);
+            osd.show(String::from("Cannot save the state"));
+        },
+    }
+}
+
+/*
+    Load a state while playing, where a missing or broken file is only worth
+    a message. Returns true if the state loaded.
+*/
+#[cfg(feature = "frontend")]
+fn quick_load(machine: &mut Chip8, rom_sha1: &[u8; 20], path: &str, osd: &mut Osd) -> bool {
+    match read_state(rom_sha1, path) {
+        Ok(snapshot) => {
+            machine.restore(snapshot);
+            osd.show(String::from("State loaded"));
+            true
+        },
+        Err(error) => {
+            eprintln!("=> Cannot load the state from {}: {}.", path, error);
+            osd.show(String::from("Cannot load the state"));
+            false
+        },
+    }
+}
+
+// A faulty program stops the machine, but not the emulator
+fn report_crash(machine: &Chip8, error: &Chip8Error) {
+    if let Some(history) = machine.history() {
+        eprint!("{}", history);
+    }
+    eprintln!("=> The program crashed after {} instructions: {}", machine.instructions_executed(), error);
+}
+
+fn load_state(machine: &mut Chip8, rom_sha1: &[u8; 20], path: &str) {
+    let snapshot = read_state(rom_sha1, path).unwrap_or_else(|error| {
+        clap::Error::with_description(&format!("{}: {}", path, error), clap::ErrorKind::InvalidValue).exit()
+    });
+    machine.restore(snapshot);
+}
+
+/*
+    A built-in font by name, or else a font file.
+*/
+fn load_font(name: &str) -> Font {
+    if let Some(font) = Font::builtin(name) {
+        return font;
+    }
+    let data = fs::read(name).expect("Cannot open or read font file.");
+    Font::from_bytes(&data).unwrap_or_else(|error| {
+        clap::Error::with_description(&format!("{}: {}", name, error), clap::ErrorKind::InvalidValue).exit()
+    })
+}
+
+fn print_bundle_info(bundle: &Bundle) {
+    if let Some(name) = &bundle.name {
+        match &bundle.author {
+            Some(author) => println!("=> {} by {}.", name, author),
+            None => println!("=> {}.", name),
+        }
+    }
+    if let Some(description) = &bundle.description {
+        println!("=> {}", description);
+    }
+}
+
+/*
+    Step the machine one frame at a time in virtual time, without waiting
+    on the host clock. With screenshots, state dumps or a script scheduled,
+    the run ends after the last of them.
+*/
+fn run_headless(machine: &mut Chip8, session: &mut Session) -> i32 {
+    let stop_when_done = session.scheduled_pending();
+    if let Some(exit_status) = session.run_due(machine) {
+        return exit_status;
+    }
+
+    let mut elapsed = Scheduler::frame_duration();
     loop {
-        machine.cycle(timer.elapsed());
+        session.poll_debuggers(machine);
+        let frames = if session.debugger_paused() {
+            std::thread::sleep(DEBUGGER_PAUSED_POLL);
+            0
+        } else {
+            match machine.try_cycle(elapsed) {
+                Ok(frames) => frames,
+                Err(error) => {
+                    report_crash(machine, &error);
+                    return EXIT_CRASHED;
+                },
+            }
+        };
+        // A pause for a state dump or a watch leaves the rest of the frame to run
+        elapsed = if machine.paused() { Duration::from_secs(0) } else { Scheduler::frame_duration() };
+
+        if let Some(exit_status) = session.after_cycle(machine, frames) {
+            return exit_status;
+        }
+        // With nobody to resume it, a headless run without --debug only reports watches
+        if session.report_watch(machine) && !session.has_debugger() {
+            session.resume(machine);
+        }
+        if stop_when_done && !session.scheduled_pending() {
+            return 0;
+        }
+    }
+}
+
+/*
+    Run in the terminal instead of a window until Ctrl-C. Keys are typed
+    rather than held, so each keystroke holds its key briefly, and a key
+    held down repeats as the terminal repeats it.
+*/
+fn run_tui(matches: &ArgMatches, machine: &mut Chip8, session: &mut Session) -> i32 {
+    let keymap = matches.value_of("keymap").map_or_else(Keymap::default, load_keymap);
+    let keys = SharedKeys::default();
+    machine.set_input(Box::new(keys.clone()));
+    let mut terminal = Terminal::open().unwrap_or_else(|error| {
+        clap::Error::with_description(&format!("Cannot use this terminal: {}", error), clap::ErrorKind::InvalidValue).exit()
+    });
+    // When each key was last typed
+    let mut typed: [Option<Instant>; Chip8::NUM_KEYS as usize] = [None; Chip8::NUM_KEYS as usize];
+    let mut crash = None;
+    // Writing fails once the terminal goes away, as when an SSH connection drops
+    let mut lost_terminal = None;
+    let mut was_sounding = false;
+
+    let mut timer = Instant::now();
+    let exit_status = 'run: loop {
+        let stepped = session.poll_debuggers(machine);
+        let frames = if session.debugger_paused() {
+            0
+        } else {
+            match machine.try_cycle(timer.elapsed()) {
+                Ok(frames) => frames,
+                Err(error) => {
+                    crash = Some(error);
+                    break EXIT_CRASHED;
+                },
+            }
+        };
         timer = Instant::now();
+        if let Some(exit_status) = session.after_cycle(machine, frames) {
+            break exit_status;
+        }
+        // As headless, there are no keys to spare for resuming a watch, so watches are only reported
+        if session.report_watch(machine) {
+            session.resume(machine);
+        }
 
-        // make this a reference, no editing necessary
-        av_interface.draw(&machine.display.buffer);
+        if frames > 0 || stepped {
+            let status = format!("PC {:#06X}  frame {}  Ctrl-C quits", machine.program_counter(), machine.frames_elapsed());
+            if let Err(error) = terminal.draw(&machine.display, &status) {
+                lost_terminal = Some(error);
+                break 'run 0;
+            }
+        }
+        // The terminal bell is the only sound there is, so it rings as each tone starts
+        if machine.sound_active() && !was_sounding {
+            if let Err(error) = terminal.beep() {
+                lost_terminal = Some(error);
+                break 'run 0;
+            }
+        }
+        was_sounding = machine.sound_active();
+
+        for byte in terminal.keys() {
+            if byte == TUI_QUIT {
+                break 'run 0;
+            }
+            let name = (byte as char).to_ascii_uppercase().to_string();
+            if let Some(key) = keymap.key(&name) {
+                keys.set(key, true);
+                typed[key as usize] = Some(Instant::now());
+            }
+        }
+        for (key, at) in typed.iter_mut().enumerate() {
+            if at.is_some_and(|at| at.elapsed() >= TUI_KEY_HOLD) {
+                keys.set(key as u8, false);
+                *at = None;
+            }
+        }
+        std::thread::sleep(TUI_POLL);
+    };
 
-        av_interface.canvas.present();
+    drop(terminal);
+    if let Some(error) = crash {
+        report_crash(machine, &error);
+    }
+    if let Some(error) = lost_terminal {
+        eprintln!("=> Stopped, since the terminal can't be written to: {}.", error);
+    }
+    exit_status
+}
 
-        let event = av_interface.event_pump.poll_event();
-        match event {
-            Some(e) => {
-                match e {
-                    Event::KeyDown {scancode, ..} => {
-                        machine.update_key(scancode.unwrap().to_string(), true)
-                    },
-                    Event::KeyUp {scancode, ..} => {
-                        machine.update_key(scancode.unwrap().to_string(), false)
+#[cfg(feature = "frontend")]
+fn run_windowed(matches: &ArgMatches, machine: &mut Chip8, session: &mut Session, colors: &[[u8; 3]],
+                rom_sha1: &[u8; 20]) -> i32 {
+    // A theme replaces the ROM's own colors, --palette the theme's, and --border beats the theme's border
+    let palette = interface::palette_from_rgb(colors);
+    let theme = matches.value_of("theme").and_then(interface::theme);
+    let mut palette = matches.value_of("palette").and_then(interface::palette)
+                             .unwrap_or_else(|| theme.map_or(palette, |theme| theme.palette));
+    // Then each entry can be set on its own, background first
+    for (entry, name) in ["bg", "fg", "fg2", "fg_both"].iter().enumerate() {
+        if let Some(color) = matches.value_of(name).and_then(interface::parse_rgb) {
+            palette[entry] = color;
+        }
+    }
+    let video_config = VideoConfig {
+        rotation: Rotation::from_degrees(parse_arg(matches, "rotate", 0)).unwrap(),
+        scale_mode: ScaleMode::from_name(matches.value_of("scale_mode").unwrap_or("integer")).unwrap(),
+        window_scale: parse_arg(matches, "scale", 10),
+        fullscreen: matches.value_of("fullscreen").and_then(Fullscreen::from_name),
+        mirror_scale: matches.value_of("mirror").map(|_| parse_arg(matches, "mirror", 10)),
+        palette,
+        border: matches.value_of("border").and_then(interface::parse_rgb)
+                       .unwrap_or_else(|| theme.map_or(Color::RGB(0, 0, 0), |theme| theme.border)),
+        frame: theme.and_then(|theme| theme.frame),
+        always_on_top: matches.is_present("always_on_top"),
+        debug_panel: matches.is_present("debug_panel"),
+    };
+    let audio_config = AudioConfig {
+        sample_rate: parse_arg(matches, "audio_rate", 44_100),
+        buffer_size: parse_arg(matches, "audio_buffer", 1024),
+        volume: parse_arg::<u8>(matches, "volume", 25) as f32 / 100.0,
+        min_beep: Duration::from_millis(parse_arg(matches, "min_beep", 33)),
+        record_to: matches.value_of("record_audio").map(PathBuf::from),
+        device: matches.value_of("audio_device").map(String::from),
+    };
+    if let Some(device) = &audio_config.device {
+        let devices = interface::audio_devices().expect("Failed to query SDL2 audio devices.");
+        if !devices.contains(device) {
+            clap::Error::with_description(&format!("There's no audio device named `{}`. These are: {}.", device, devices.join(", ")),
+                                          clap::ErrorKind::InvalidValue).exit();
+        }
+    }
+
+    let mut keymap = matches.value_of("keymap").map_or_else(Keymap::default, load_keymap);
+
+    let mut av_interface = AVInterface::new(Display::WIDTH as u32, Display::HEIGHT as u32,
+                                            video_config, audio_config);
+
+    if matches.is_present("debug_window") {
+        av_interface.toggle_debug_window(debug_view::COLUMNS, debug_view::ROWS);
+    }
+
+    if let Some(exit_status) = session.run_due(machine) {
+        return exit_status;
+    }
+
+    let when_minimized = Minimized::from_name(matches.value_of("minimized").unwrap_or("throttle")).unwrap();
+    let escape = EscapeKey::from_name(matches.value_of("escape").unwrap_or("menu")).unwrap();
+    let mut is_minimized = false;
+
+    // The pause menu and F6/F9 save to and load from the state file named on the command line, or one next to
+    // the ROM, with slots 1-9 beside it
+    let state_path = matches.value_of("save_state").or_else(|| matches.value_of("load_state"))
+                            .map_or_else(|| format!("{}.state", matches.value_of("program_file")
+                                                                       .or_else(|| matches.value_of("test_pattern"))
+                                                                       .unwrap()), String::from);
+    let mut slot = 0;
+    let mut menu = PauseMenu::new();
+    let mut osd = Osd::new();
+    let keys = SharedKeys::default();
+    machine.set_input(Box::new(keys.clone()));
+    let mut redraw = false;
+    // Where a faulty program stopped the machine. The window stays open until a reset or a state load.
+    let mut crashed_at: Option<u16> = None;
+    // P holds the machine, with period to advance it a frame at a time
+    let mut paused = false;
+    // Tab held and M toggled
+    let (mut fast_forward, mut slow_motion) = (false, false);
+    let mut frame_stats = matches.is_present("frame_stats").then(FrameStats::new);
+    let run_ahead = matches.value_of("run_ahead").map(|_| parse_arg(matches, "run_ahead", 1));
+    // The display predicted by run-ahead as of the last frame
+    let mut ahead: Option<Display> = None;
+
+    let mut timer = Instant::now();
+    let exit_status = 'run: loop {
+        let stepped = session.poll_debuggers(machine);
+        // Emulated time stands still while a pause, a crash, a watch, a debugger, the menu or a minimized window holds
+        // the machine
+        let frozen = paused || crashed_at.is_some() || machine.watch_hit().is_some() || session.debugger_paused() || menu.is_open()
+                     || (is_minimized && when_minimized == Minimized::Pause);
+        // Fast-forward and slow motion run emulated time faster or slower than the wall clock
+        let speed = if fast_forward { FAST_FORWARD } else if slow_motion { SLOW_MOTION } else { 1.0 };
+        let frames = if frozen {
+            0
+        } else {
+            machine.try_cycle(timer.elapsed().mul_f64(speed)).unwrap_or_else(|error| {
+                report_crash(machine, &error);
+                crashed_at = Some(machine.program_counter());
+                redraw = true;
+                0
+            })
+        };
+        if let Some(frame_stats) = frame_stats.as_mut() {
+            frame_stats.record_emulation(timer.elapsed());
+        }
+        timer = Instant::now();
+
+        if let Some(exit_status) = session.after_cycle(machine, frames) {
+            break exit_status;
+        }
+        let watch_triggered = session.report_watch(machine);
+        redraw |= osd.expire();
+
+        // Run-ahead predicts from each new frame, and a frozen machine shows just what it has
+        if frozen {
+            ahead = None;
+        } else if frames > 0 && !is_minimized {
+            ahead = run_ahead.map(|frames_ahead| machine.run_ahead(frames_ahead));
+        }
+
+        // The display only changes as far as the viewer is concerned on vblank, and then only if something drew.
+        // Run-ahead's prediction and the debug views can change when the display doesn't
+        let new_frame = frames > 0 && (machine.display.take_changed() || ahead.is_some() || av_interface.has_debug_panel()
+                                       || av_interface.has_debug_window());
+        if (new_frame || watch_triggered || stepped || redraw) && !is_minimized {
+            let render_started = Instant::now();
+            let shown = ahead.as_ref().unwrap_or(&machine.display);
+            av_interface.draw(shown.pixels(), shown.width() as u32, shown.height() as u32);
+            if menu.is_open() {
+                let lines = menu.lines(interface::palette_name(&av_interface.palette), av_interface.is_always_on_top());
+                av_interface.draw_overlay(&lines);
+            } else {
+                let mut lines = osd.lines();
+                if let Some(pc) = crashed_at {
+                    lines.push(format!("Crashed at {:#06X}", pc));
+                } else if paused {
+                    lines.push(String::from("Paused"));
+                } else if fast_forward {
+                    lines.push(format!("Fast-forward {}x", FAST_FORWARD));
+                } else if slow_motion {
+                    lines.push(format!("Slow motion {}x", SLOW_MOTION));
+                }
+                av_interface.draw_toasts(&lines);
+            }
+            if av_interface.has_debug_panel() {
+                av_interface.draw_panel(&debug_view::panel(machine));
+            }
+            av_interface.canvas.present();
+            if let Some(frame_stats) = frame_stats.as_mut() {
+                frame_stats.record_present(render_started.elapsed());
+            }
+            redraw = false;
+
+            if av_interface.has_debug_window() {
+                av_interface.draw_debug(&debug_view::render(machine));
+            }
+        }
+        av_interface.buzzer.update(machine.sound_active() && !frozen);
+
+        // Rather than busy looping, a minimized window, a paused machine or a silent one waiting on a key sleeps until
+        // an event or the next trickle of work. Nothing on screen can change until the key arrives. A running
+        // machine sleeps until an event or the end of the frame, since nothing it does shows before then, and
+        // catches up on the frame's instructions when it wakes. Then every event that's queued is handled before
+        // the next cycle, so quick taps and releases aren't held back a frame each.
+        let until_next_frame = machine.until_next_frame().div_f64(speed).saturating_sub(timer.elapsed());
+        let until_next_frame = until_next_frame.as_micros().div_ceil(1000) as u32;
+        let mut event = if is_minimized {
+            av_interface.event_pump.wait_event_timeout(MINIMIZED_WAKEUP_MS)
+        } else if paused || (machine.waiting_on_key() && !machine.sound_active()) {
+            av_interface.event_pump.wait_event_timeout(KEY_WAIT_WAKEUP_MS)
+        } else if until_next_frame > 0 {
+            av_interface.event_pump.wait_event_timeout(until_next_frame)
+        } else {
+            av_interface.event_pump.poll_event()
+        };
+        while let Some(e) = event.take() {
+            match e {
+                // While remapping, any key can be bound, hotkeys included, so they all go to the menu
+                Event::KeyDown {repeat: true, ..} if menu.is_capturing() => {},
+                Event::KeyDown {scancode: Some(scancode), ..} if menu.is_capturing() => {
+                    redraw = true;
+                    if let Some(MenuAction::Bind(key, scancode)) = menu.handle_key(scancode) {
+                        keymap.bind(key, &scancode);
+                        if key == *Keymap::PAD_ORDER.last().unwrap() {
+                            osd.show(String::from("Keys remapped"));
+                        }
+                    }
+                },
+                // Keys bound to the pad go to it ahead of any hotkey, so a keymap can use P or Tab
+                Event::KeyDown {scancode: Some(scancode), ..} if !menu.is_open() && keymap.key(scancode.name()).is_some() => {
+                    keys.set(keymap.key(scancode.name()).unwrap(), true);
+                },
+                // Releases always go through, so a key held when the menu opens doesn't stick
+                Event::KeyUp {scancode: Some(scancode), ..} if keymap.key(scancode.name()).is_some() => {
+                    keys.set(keymap.key(scancode.name()).unwrap(), false);
+                },
+                Event::KeyDown {scancode: Some(Scancode::F11), repeat: false, ..} => {
+                    av_interface.toggle_fullscreen();
+                },
+                Event::KeyDown {scancode: Some(Scancode::F2), repeat: false, ..} => {
+                    print!("{}", capture::to_ascii(machine.display.rows()));
+                },
+                Event::KeyDown {scancode: Some(Scancode::F3), repeat: false, ..} => {
+                    let path = PathBuf::from(format!("screenshot-{}.png", machine.frames_elapsed()));
+                    match save_screen(machine, &path, session.capture_scale) {
+                        Ok(()) => osd.show(format!("Saved {}", path.display())),
+                        Err(error) => {
+                            eprintln!("=> Cannot save the screen to {}: {}.", path.display(), error);
+                            osd.show(String::from("Cannot save the screenshot"));
+                        },
+                    }
+                },
+                Event::KeyDown {scancode: Some(Scancode::F4), repeat: false, ..} => {
+                    match session.finish_gif() {
+                        Some(Ok(path)) => osd.show(format!("Saved {}", path.display())),
+                        Some(Err(_)) => osd.show(String::from("Cannot save the recording")),
+                        None => {
+                            session.start_gif(machine, PathBuf::from(format!("recording-{}.gif", machine.frames_elapsed())));
+                            osd.show(String::from("Recording"));
+                        },
+                    }
+                },
+                Event::KeyDown {scancode: Some(Scancode::F12), repeat: false, ..} => {
+                    av_interface.toggle_debug_window(debug_view::COLUMNS, debug_view::ROWS);
+                },
+                Event::KeyDown {scancode: Some(Scancode::F5), repeat: false, ..} => {
+                    session.resume(machine);
+                },
+                Event::KeyDown {scancode: Some(Scancode::F6), repeat: false, ..} => {
+                    quick_save(machine, rom_sha1, &state_path, slot, &mut osd);
+                },
+                Event::KeyDown {scancode: Some(Scancode::F9), repeat: false, ..} => {
+                    redraw = true;
+                    if quick_load(machine, rom_sha1, &slot_path(&state_path, slot), &mut osd) {
+                        crashed_at = None;
+                    }
+                },
+                Event::KeyDown {scancode: Some(scancode @ (Scancode::F7 | Scancode::F8)), ..} => {
+                    slot = (slot + if scancode == Scancode::F8 { 1 } else { STATE_SLOTS - 1 }) % STATE_SLOTS;
+                    osd.show(format!("Slot {}", slot));
+                },
+                Event::KeyDown {scancode: Some(scancode), repeat: false, ..} if menu.is_open() => {
+                    redraw = true;
+                    match menu.handle_key(scancode) {
+                        Some(MenuAction::Reset) => {
+                            machine.reset();
+                            crashed_at = None;
+                            osd.show(String::from("Reset"));
+                        },
+                        Some(MenuAction::SaveState) => {
+                            quick_save(machine, rom_sha1, &state_path, slot, &mut osd);
+                        },
+                        Some(MenuAction::LoadState) => {
+                            if quick_load(machine, rom_sha1, &slot_path(&state_path, slot), &mut osd) {
+                                crashed_at = None;
+                            }
+                        },
+                        Some(action @ MenuAction::NextPalette) | Some(action @ MenuAction::PreviousPalette) => {
+                            let offset = if action == MenuAction::NextPalette { 1 } else { -1 };
+                            av_interface.palette = interface::cycle_palette(&av_interface.palette, offset);
+                            osd.show(format!("Palette: {}", interface::palette_name(&av_interface.palette)));
+                        },
+                        Some(MenuAction::ToggleAlwaysOnTop) => match av_interface.toggle_always_on_top() {
+                            Ok(()) => osd.show(format!("Always on top: {}", if av_interface.is_always_on_top() { "on" } else { "off" })),
+                            Err(error) => osd.show(format!("Always on top: {}", error)),
+                        },
+                        Some(MenuAction::Quit) => break 'run 0,
+                        // Keys are bound above, since remapping captures every key
+                        Some(MenuAction::Bind(..)) | Some(MenuAction::Resume) | None => {},
+                    }
+                },
+                Event::KeyDown {scancode: Some(Scancode::P), repeat: false, ..} => {
+                    paused = !paused;
+                    redraw = true;
+                },
+                Event::KeyDown {scancode: Some(scancode @ (Scancode::Equals | Scancode::KpPlus)), ..}
+                | Event::KeyDown {scancode: Some(scancode @ (Scancode::Minus | Scancode::KpMinus)), ..} => {
+                    let faster = scancode == Scancode::Equals || scancode == Scancode::KpPlus;
+                    let step = if faster { CLOCK_STEP } else { 1.0 / CLOCK_STEP };
+                    machine.set_clock_speed((machine.clock_speed() * step).clamp(CLOCK_RANGE.0, CLOCK_RANGE.1));
+                    osd.show(format!("Clock {:.0}hz", machine.clock_speed()));
+                },
+                Event::KeyDown {scancode: Some(Scancode::Tab), repeat: false, ..} => {
+                    fast_forward = true;
+                    redraw = true;
+                },
+                Event::KeyUp {scancode: Some(Scancode::Tab), ..} => {
+                    fast_forward = false;
+                    redraw = true;
+                },
+                Event::KeyDown {scancode: Some(Scancode::M), repeat: false, ..} => {
+                    slow_motion = !slow_motion;
+                    redraw = true;
+                },
+                Event::KeyDown {scancode: Some(Scancode::Period), ..} if paused && crashed_at.is_none() => {
+                    if let Err(error) = machine.run_frames(1) {
+                        report_crash(machine, &error);
+                        crashed_at = Some(machine.program_counter());
                     }
-                    Event::Quit {..} => {
-                        break;
+                    redraw = true;
+                },
+                Event::KeyDown {scancode: Some(Scancode::Backspace), repeat: false, ..} => {
+                    machine.reset();
+                    crashed_at = None;
+                    redraw = true;
+                    osd.show(String::from("Reset"));
+                },
+                Event::KeyDown {scancode: Some(Scancode::Escape), repeat: false, ..} => match escape {
+                    EscapeKey::Menu => {
+                        menu.open();
+                        redraw = true;
                     },
-                    _ => {}
-                }
+                    EscapeKey::ConfirmQuit => {
+                        menu.confirm_quit();
+                        redraw = true;
+                    },
+                    EscapeKey::Quit => break 'run 0,
+                },
+                Event::Window {window_id, win_event: WindowEvent::Close, ..} if av_interface.close_window(window_id) => {
+                    break 'run 0;
+                },
+                Event::Window {window_id, win_event: WindowEvent::SizeChanged(..), ..} if av_interface.is_main_window(window_id) => {
+                    av_interface.resized();
+                    redraw = true;
+                },
+                Event::Window {window_id, win_event: WindowEvent::Minimized, ..} if av_interface.is_main_window(window_id) => {
+                    is_minimized = true;
+                },
+                Event::Window {window_id, win_event: WindowEvent::Restored, ..} if av_interface.is_main_window(window_id) => {
+                    is_minimized = false;
+                },
+                Event::ControllerDeviceAdded {which, ..} => {
+                    if let Some(name) = av_interface.gamepads.attach(which) {
+                        osd.show(format!("{} connected", name));
+                    }
+                },
+                Event::ControllerDeviceRemoved {which, ..} => {
+                    if let Some((name, held)) = av_interface.gamepads.detach(which) {
+                        for key in held {
+                            keys.set(key, false);
+                        }
+                        osd.show(format!("{} disconnected", name));
+                    }
+                },
+                Event::ControllerButtonDown {which, button, ..} if !menu.is_open() => {
+                    if let Some(key) = av_interface.gamepads.button(which, button, true) {
+                        keys.set(key, true);
+                    }
+                },
+                Event::ControllerButtonUp {which, button, ..} => {
+                    if let Some(key) = av_interface.gamepads.button(which, button, false) {
+                        keys.set(key, false);
+                    }
+                },
+                Event::Quit {..} => {
+                    break 'run 0;
+                },
+                _ => {}
+            }
+            event = av_interface.event_pump.poll_event();
+        }
+    };
+
+    // However the run ended, close the window and stop the audio (finishing any recording) before the reports
+    // and state files are written, rather than leave a frozen window up meanwhile
+    drop(av_interface);
+
+    if let Some(frame_stats) = frame_stats {
+        print!("{}", frame_stats);
+    }
+    exit_status
+}
+
+#[cfg(not(feature = "frontend"))]
+fn run_windowed(_: &ArgMatches, _: &mut Chip8, _: &mut Session, _: &[[u8; 3]], _: &[u8; 20]) -> i32 {
+    without_sdl()
+}
+
+/*
+    Built with only the `tui` feature there's no SDL to open a window or
+    play audio with, so say what still works.
+*/
+#[cfg(not(feature = "frontend"))]
+fn without_sdl() -> ! {
+    clap::Error::with_description("This yac8 was built without SDL, so it has no window or audio. Use --tui or --headless.",
+                                  clap::ErrorKind::InvalidValue).exit()
+}
+
+/*
+    The checks shared by windowed and headless runs: scheduled screenshots,
+    state dumps and scripts, instruction and frame limits, the watchdog and
+    the wall-clock timeout.
+*/
+struct Session {
+    timeout: Option<Duration>,
+    watchdog: Option<Watchdog>,
+    started: Instant,
+    screenshots: VecDeque<(u64, PathBuf)>,
+    state_dumps: VecDeque<u64>,
+    dump_dir: PathBuf,
+    script: Option<Script>,
+    assertions_passed: u64,
+    watch_reported: bool,
+    web_debugger: Option<WebDebugger>,
+    debugger: Option<Debugger>,
+    capture_scale: usize,
+    // The GIF being recorded and where it goes
+    gif: Option<(GifRecorder, PathBuf)>,
+}
+
+impl Session {
+    fn new(matches: &ArgMatches) -> Session {
+        let frames: Vec<u64> = match matches.values_of("screenshot_at") {
+            Some(_) => values_t!(matches, "screenshot_at", u64).unwrap_or_else(|e| e.exit()),
+            None => Vec::new(),
+        };
+        let paths: Vec<PathBuf> = matches.values_of("out").map(|paths| paths.map(PathBuf::from).collect())
+                                                         .unwrap_or_default();
+        if frames.len() != paths.len() {
+            clap::Error::with_description("Each --screenshot-at needs exactly one --out file.",
+                                          clap::ErrorKind::WrongNumberOfValues).exit();
+        }
+        let mut screenshots: Vec<(u64, PathBuf)> = frames.into_iter().zip(paths).collect();
+        screenshots.sort_by_key(|&(frame, _)| frame);
+        let mut state_dumps: Vec<u64> = match matches.values_of("dump_at") {
+            Some(_) => values_t!(matches, "dump_at", u64).unwrap_or_else(|e| e.exit()),
+            None => Vec::new(),
+        };
+        state_dumps.sort_unstable();
+        state_dumps.dedup();
+
+        Session {
+            timeout: matches.value_of("timeout").map(|_| Duration::from_secs_f64(parse_arg(matches, "timeout", 0.0))),
+            watchdog: matches.value_of("watchdog").map(|_| Watchdog::new(parse_arg(matches, "watchdog", 0.0))),
+            started: Instant::now(),
+            screenshots: screenshots.into(),
+            state_dumps: state_dumps.into(),
+            dump_dir: PathBuf::from(matches.value_of("dump_dir").unwrap_or(".")),
+            script: matches.value_of("script").map(load_script),
+            assertions_passed: 0,
+            watch_reported: false,
+            web_debugger: matches.value_of("web_debugger").map(|_| start_web_debugger(parse_arg(matches, "web_debugger", 0))),
+            debugger: matches.is_present("debug").then(Debugger::start),
+            capture_scale: parse_arg(matches, "capture_scale", 4),
+            gif: None,
+        }
+    }
+
+    /*
+        Serve any web debugger requests and terminal debugger commands,
+        handing a breakpoint or watch to the terminal debugger if there is
+        one. Returns true if either stepped the machine, so the display may
+        have changed.
+    */
+    fn poll_debuggers(&mut self, machine: &mut Chip8) -> bool {
+        let mut stepped = self.web_debugger.as_mut().is_some_and(|debugger| debugger.poll(machine));
+        if let Some(debugger) = self.debugger.as_mut() {
+            if machine.breakpoint_hit().is_some() || machine.watch_hit().is_some() {
+                debugger.break_in(machine);
+                machine.resume();
+                self.watch_reported = false;
+            }
+            stepped |= debugger.poll(machine);
+        }
+        stepped
+    }
+
+    fn debugger_paused(&self) -> bool {
+        self.web_debugger.as_ref().is_some_and(WebDebugger::is_paused)
+            || self.debugger.as_ref().is_some_and(Debugger::is_paused)
+    }
+
+    fn has_debugger(&self) -> bool {
+        self.debugger.is_some()
+    }
+
+    /*
+        Whether a run would end without being killed: a limit, something
+        scheduled to finish after, or a debugger for someone to drive.
+    */
+    fn ends_by_itself(&self, matches: &ArgMatches) -> bool {
+        matches.is_present("max_instructions") || matches.is_present("max_frames") || self.timeout.is_some()
+            || self.scheduled_pending() || self.debugger.is_some() || self.web_debugger.is_some()
+    }
+
+    fn start_gif(&mut self, machine: &Chip8, path: PathBuf) {
+        let recorder = GifRecorder::new(machine.display.width(), machine.display.height(), self.capture_scale);
+        self.gif = Some((recorder, path));
+    }
+
+    // Write out the GIF being recorded, if there is one, returning where it went or why it couldn't
+    fn finish_gif(&mut self) -> Option<io::Result<PathBuf>> {
+        let (recorder, path) = self.gif.take()?;
+        match fs::write(&path, recorder.finish()) {
+            Ok(()) => println!("=> Saved a recording to {}.", path.display()),
+            Err(error) => {
+                eprintln!("=> Cannot save the recording to {}: {}.", path.display(), error);
+                return Some(Err(error));
+            },
+        }
+        Some(Ok(path))
+    }
+
+    /*
+        Announce a watch that has paused the machine, once. Returns true
+        the first time it's seen.
+    */
+    fn report_watch(&mut self, machine: &Chip8) -> bool {
+        match machine.watch_hit() {
+            Some(watch) if !self.watch_reported => {
+                eprintln!("=> Watch `{}` became true after {} instructions, PC at {:#06X}.",
+                          watch, machine.instructions_executed(), machine.program_counter());
+                self.watch_reported = true;
+                true
+            },
+            _ => false,
+        }
+    }
+
+    fn resume(&mut self, machine: &mut Chip8) {
+        machine.resume();
+        self.watch_reported = false;
+    }
+
+    fn scheduled_pending(&self) -> bool {
+        !self.screenshots.is_empty() || !self.state_dumps.is_empty()
+            || self.script.as_ref().is_some_and(|script| !script.is_finished())
+    }
+
+    /*
+        Everything scheduled for this point in the run: captures, then any
+        script actions. Returns the exit status if the script ends the run.
+    */
+    fn run_due(&mut self, machine: &mut Chip8) -> Option<i32> {
+        self.save_due_captures(machine);
+        self.run_script(machine)
+    }
+
+    fn run_script(&mut self, machine: &mut Chip8) -> Option<i32> {
+        let script = self.script.as_mut()?;
+        let frame = machine.frames_elapsed();
+        let mut ran_any = false;
+
+        while let Some((due, action)) = script.next_due(frame) {
+            ran_any = true;
+            match action {
+                Action::Press { key, frames } => {
+                    machine.set_key(key, true);
+                    script.schedule(frame + frames, Action::Release(key));
+                },
+                Action::Release(key) => machine.set_key(key, false),
+                Action::Screenshot(path) => {
+                    let path = path.unwrap_or_else(|| PathBuf::from(format!("screenshot-{}.png", due)));
+                    match save_screen(machine, &path, 1) {
+                        Ok(()) => eprintln!("=> Script saved frame {} to {}.", frame, path.display()),
+                        Err(error) => eprintln!("=> Script cannot save frame {} to {}: {}.", frame, path.display(), error),
+                    }
+                },
+                Action::AssertPixel { x, y, lit } => {
+                    let pixel = ((x as usize) < machine.display.width() && (y as usize) < machine.display.height())
+                        .then(|| machine.display.get(x as usize, y as usize));
+                    if pixel != Some(lit) {
+                        eprintln!("=> Script assertion failed at frame {}: pixel {},{} == {}.", frame, x, y, lit as u8);
+                        return Some(EXIT_ASSERTION_FAILED);
+                    }
+                    self.assertions_passed += 1;
+                },
+                Action::Assert(source, expression) => {
+                    if expression.evaluate(machine) == 0 {
+                        eprintln!("=> Script assertion failed at frame {}: {}.", frame, source);
+                        return Some(EXIT_ASSERTION_FAILED);
+                    }
+                    self.assertions_passed += 1;
+                },
+                Action::Quit => {
+                    eprintln!("=> Script quit at frame {}, {} assertions passed.", frame, self.assertions_passed);
+                    return Some(0);
+                },
             }
-            None => {}
         }
+
+        if ran_any && script.is_finished() {
+            eprintln!("=> Script finished at frame {}, {} assertions passed.", frame, self.assertions_passed);
+        }
+        None
+    }
+
+    /*
+        Save every screenshot and state dump that has come due, then pause
+        the machine at the next state dump so it lands on the exact
+        instruction.
+    */
+    fn save_due_captures(&mut self, machine: &mut Chip8) {
+        while let Some((frame, _)) = self.screenshots.front() {
+            if *frame > machine.frames_elapsed() {
+                break;
+            }
+            let (frame, path) = self.screenshots.pop_front().unwrap();
+            match save_screen(machine, &path, 1) {
+                Ok(()) => eprintln!("=> Saved frame {} to {}.", frame, path.display()),
+                Err(error) => eprintln!("=> Cannot save frame {} to {}: {}.", frame, path.display(), error),
+            }
+        }
+
+        while let Some(&instructions) = self.state_dumps.front() {
+            if instructions > machine.instructions_executed() {
+                break;
+            }
+            self.state_dumps.pop_front();
+            let path = self.dump_dir.join(format!("state-{}.txt", instructions));
+            match fs::write(&path, state_dump::render(machine)) {
+                Ok(()) => eprintln!("=> Saved the state after {} instructions to {}.", instructions, path.display()),
+                Err(error) => eprintln!("=> Cannot save the state after {} instructions to {}: {}.", instructions, path.display(), error),
+            }
+        }
+        machine.set_pause_at(self.state_dumps.front().cloned());
+    }
+
+    /*
+        Called after every `Chip8::try_cycle`. Returns the exit status once the
+        run should stop.
+    */
+    fn after_cycle(&mut self, machine: &mut Chip8, frames: u32) -> Option<i32> {
+        if let Some((recorder, _)) = self.gif.as_mut() {
+            for _ in 0..frames {
+                recorder.add_frame(machine.display.pixels(), machine.display.width(), machine.display.height());
+            }
+        }
+        if self.debugger.as_ref().is_some_and(Debugger::quit_requested) {
+            return Some(0);
+        }
+        for (pc, address) in machine.take_uninitialized_reads() {
+            eprintln!("=> Warning: the instruction at {:#06X} read uninitialized memory at {:#06X}.", pc, address);
+        }
+
+        if let Some(exit_status) = self.run_due(machine) {
+            return Some(exit_status);
+        }
+
+        if machine.limit_reached() {
+            eprintln!("=> Stopped after {} instructions and {} frames.",
+                      machine.instructions_executed(), machine.frames_elapsed());
+            return Some(0);
+        }
+        if let Some(watchdog) = self.watchdog.as_mut() {
+            let pc = machine.program_counter();
+            if frames > 0 && watchdog.observe(frames, pc, machine.waiting_on_key(), machine.display.pixels()) {
+                let instruction = machine.memory().peek_opcode(pc)
+                                         .map(|opcode| instructions::parse_opcode(opcode).to_string());
+                eprintln!("=> Watchdog: no progress for {:.1}s, PC stuck at {:#06X} ({}).",
+                          watchdog.stalled_seconds(), pc, instruction.unwrap_or_else(|| String::from("outside memory")));
+                return Some(EXIT_HUNG);
+            }
+        }
+        if self.timeout.is_some_and(|timeout| self.started.elapsed() >= timeout) {
+            eprintln!("=> Timed out after {:.1}s.", self.started.elapsed().as_secs_f64());
+            return Some(EXIT_TIMEOUT);
+        }
+        None
+    }
+}
+
+fn start_web_debugger(port: u16) -> WebDebugger {
+    let debugger = WebDebugger::bind(port).unwrap_or_else(|error| {
+        clap::Error::with_description(&format!("Cannot serve the web debugger on port {}: {}", port, error),
+                                      clap::ErrorKind::InvalidValue).exit()
+    });
+    println!("=> Web debugger at http://localhost:{}/.", port);
+    debugger
+}
+
+// A preset's name, or else a keymap file
+fn load_keymap(name: &str) -> Keymap {
+    Keymap::preset(name).unwrap_or_else(|| {
+        let source = fs::read_to_string(name).unwrap_or_else(|error| {
+            clap::Error::with_description(&format!("`{}` is neither a keymap ({}) nor a readable file: {}",
+                                                   name, Keymap::PRESETS.join(", "), error),
+                                          clap::ErrorKind::InvalidValue).exit()
+        });
+        Keymap::parse(&source).unwrap_or_else(|error| {
+            clap::Error::with_description(&format!("{}: {}", name, error), clap::ErrorKind::InvalidValue).exit()
+        })
+    })
+}
+
+fn load_script(path: &str) -> Script {
+    let source = fs::read_to_string(path).expect("Cannot open or read script file.");
+    Script::parse(&source).unwrap_or_else(|error| {
+        clap::Error::with_description(&format!("{}: {}", path, error), clap::ErrorKind::InvalidValue).exit()
+    })
+}
+
+fn save_screen(machine: &Chip8, path: &Path, scale: usize) -> io::Result<()> {
+    let (width, height) = (machine.display.width(), machine.display.height());
+    let screen = capture::encode(CaptureFormat::from_path(path), &capture::scale(machine.display.pixels(), width, scale),
+                                 width * scale, height * scale);
+    fs::write(path, screen)
+}
+
+/*
+    A typed argument value, or `default` when it wasn't given. Values are
+    range checked by the validators in `cli`, so a failed parse only happens
+    for arguments that have no validator and exits with a clap error.
+*/
+fn parse_arg<T: FromStr>(matches: &ArgMatches, name: &str, default: T) -> T {
+    match matches.value_of(name) {
+        Some(_) => value_t!(matches, name, T).unwrap_or_else(|e| e.exit()),
+        None => default,
     }
 }
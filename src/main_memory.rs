@@ -1,97 +1,311 @@
-/*
-    The CHIP-8 main memory module and program counter, including offset.
+use crate::error::Chip8Error;
+use crate::font::Font;
+use crate::savestate::{StateReader, StateWriter};
 
-    In the CHIP-8 system, the program data exists in main memory beginning
-    at address 0x200. Some built-in sprite data is also stored in memory.
-
-    This module transforms addresses using the 0x200 offset, so external to
-    this module all addresses should be as-is, untransformed.
-*/
+/// The CHIP-8 main memory module and program counter, including offset.
+///
+/// In the CHIP-8 system, the program data exists in main memory beginning
+/// at address 0x200. The hex digit font lives in the interpreter's area
+/// below that, at 0x050 unless moved. Memory is 4K, or XO-CHIP's 64K, and
+/// every address here is absolute: memory is indexed by address and the
+/// program counter holds one, so 4K ends at 0xFFF and 64K at 0xFFFF.
+#[derive(Clone)]
 pub struct MainMemory {
+    // In bytes
     pub program_length: usize,
 
     memory: Vec<u8>,
+    // Which bytes of program memory the loader or the program have written
+    initialized: Vec<bool>,
+    uninitialized_reads: Option<Vec<u16>>,
+    font: Vec<u8>,
+    font_address: u16,
     program_counter: usize,
 }
 
 impl MainMemory {
-    const MEMORY_SIZE: usize = 4 * 1024;
+    pub const MEMORY_SIZE: usize = 4 * 1024;
+    pub const XO_CHIP_MEMORY_SIZE: usize = 64 * 1024;
     const PROGRAM_OFFSET: u16 = 0x200;
-    const FONT_SPRITES: [u8; 80] = [0xF0, 0x90, 0x90, 0x90, 0xF0,   // 0
-                                    0x20, 0x60, 0x20, 0x20, 0x70,   // 1
-                                    0xF0, 0x10, 0xF0, 0x80, 0xF0,   // 2
-                                    0xF0, 0x10, 0xF0, 0x10, 0xF0,   // 3
-                                    0x90, 0x90, 0xF0, 0x10, 0x10,   // 4
-                                    0xF0, 0x80, 0xF0, 0x10, 0xF0,   // 5
-                                    0xF0, 0x80, 0xF0, 0x90, 0xF0,   // 6
-                                    0xF0, 0x10, 0x20, 0x40, 0x40,   // 7
-                                    0xF0, 0x90, 0xF0, 0x90, 0xF0,   // 8
-                                    0xF0, 0x90, 0xF0, 0x10, 0xF0,   // 9
-                                    0xF0, 0x90, 0xF0, 0x90, 0x90,   // A
-                                    0xE0, 0x90, 0xE0, 0x90, 0xE0,   // B
-                                    0xF0, 0x80, 0x80, 0x80, 0xF0,   // C
-                                    0xE0, 0x90, 0x90, 0x90, 0xE0,   // D
-                                    0xF0, 0x80, 0xF0, 0x80, 0xF0,   // E
-                                    0xF0, 0x80, 0xF0, 0x80, 0x80];  // F
-
-    pub fn new(mut program_data: Vec<u8>) -> MainMemory {
-        let program_length = program_data.len() / 2;
-        program_data.resize(MainMemory::MEMORY_SIZE, 0x0);
+    pub const DEFAULT_FONT_ADDRESS: u16 = 0x050;
+
+    pub fn new(program_data: Vec<u8>) -> MainMemory {
+        let program_length = program_data.len();
+        let size = MainMemory::size_for(program_data.len());
+        let start = MainMemory::PROGRAM_OFFSET as usize;
+        let mut memory = vec![0x0; size];
+        let mut initialized = vec![false; size];
+        let end = (start + program_data.len()).min(size);
+        memory[start..end].copy_from_slice(&program_data[..end - start]);
+        initialized[start..end].fill(true);
         MainMemory {
-            memory: program_data,
-            program_counter: 0,
-            program_length: program_length,
+            memory,
+            initialized,
+            uninitialized_reads: None,
+            font: Font::builtin("default").unwrap().bytes().to_vec(),
+            font_address: MainMemory::DEFAULT_FONT_ADDRESS,
+            program_counter: start,
+            program_length,
         }
     }
 
-    pub fn fetch_opcode(&mut self) -> Option<u16> {
-        if (self.program_counter + 2) >= self.memory.len() {
-            return None;
+    /// Load `program_data` afresh, as `new` would, keeping the font and the
+    /// memory size.
+    pub fn reload(&mut self, program_data: Vec<u8>) {
+        let mut fresh = MainMemory::new(program_data);
+        fresh.font = std::mem::take(&mut self.font);
+        fresh.font_address = self.font_address;
+        fresh.set_size(self.size());
+        if self.uninitialized_reads.is_some() {
+            fresh.track_uninitialized_reads();
+        }
+        *self = fresh;
+    }
+
+    // Programs too big for 4K only fit XO-CHIP's memory
+    fn size_for(program_size: usize) -> usize {
+        if MainMemory::PROGRAM_OFFSET as usize + program_size > MainMemory::MEMORY_SIZE {
+            MainMemory::XO_CHIP_MEMORY_SIZE
+        } else {
+            MainMemory::MEMORY_SIZE
         }
+    }
+
+    pub fn save(&self, out: &mut StateWriter) {
+        let start = MainMemory::PROGRAM_OFFSET as usize;
+        out.u32(self.program_length as u32);
+        out.bytes(&self.memory[start..]);
+        out.bools(&self.initialized[start..]);
+        out.bool(self.uninitialized_reads.is_some());
+        out.bytes(&self.font);
+        out.u16(self.font_address);
+        out.u32((self.program_counter - start) as u32);
+    }
+
+    pub fn load(input: &mut StateReader) -> Result<MainMemory, String> {
+        let start = MainMemory::PROGRAM_OFFSET as usize;
+        let program_length = input.u32()? as usize;
+        let mut memory = MainMemory::new(Vec::new());
+        memory.program_length = program_length;
+        let program_memory = input.bytes()?;
+        let size = start + program_memory.len();
+        if ![MainMemory::MEMORY_SIZE, MainMemory::XO_CHIP_MEMORY_SIZE].contains(&size) {
+            return Err(format!("memory can't be {} bytes", size));
+        }
+        memory.memory = vec![0x0; start];
+        memory.memory.extend(program_memory);
+        memory.initialized = vec![false; size];
+        input.fill_bools(&mut memory.initialized[start..])?;
+        if input.bool()? {
+            memory.track_uninitialized_reads();
+        }
+        let font = Font::from_bytes(&input.bytes()?)?;
+        memory.set_font(&font, input.u16()?)?;
+        memory.program_counter = start + input.u32()? as usize;
+        if memory.program_counter >= size {
+            return Err(format!("the program counter is out of memory at {:#X}", memory.program_counter));
+        }
+        Ok(memory)
+    }
+
+    // 4K, or XO-CHIP's 64K
+    pub fn size(&self) -> usize {
+        self.memory.len()
+    }
+
+    /// Place a font at `address`. It has to fit below program memory.
+    pub fn set_font(&mut self, font: &Font, address: u16) -> Result<(), String> {
+        let end = address as usize + font.bytes().len();
+        if end > MainMemory::PROGRAM_OFFSET as usize {
+            return Err(format!("the font ({} bytes) at {:#05X} runs into program memory at {:#05X}",
+                               font.bytes().len(), address, MainMemory::PROGRAM_OFFSET));
+        }
+        self.font = font.bytes().to_vec();
+        self.font_address = address;
+        Ok(())
+    }
+
+    /// Grow memory to XO-CHIP's 64K, or back to 4K, which loses whatever
+    /// was above it. A program too big for 4K keeps the 64K it was loaded
+    /// into rather than being cut short.
+    pub fn set_size(&mut self, size: usize) {
+        let size = size.max(MainMemory::size_for(self.program_length));
+        self.memory.resize(size, 0x0);
+        self.initialized.resize(size, false);
+        self.program_counter = self.program_counter.min(size - 2);
+    }
+
+    /// Where the small sprite for a hex digit starts, as FX29 sets I.
+    pub fn digit_address(&self, digit: u8) -> u16 {
+        self.font_address + 5 * digit as u16
+    }
+
+    /// Where the big sprite for a hex digit starts, as FX30 sets I, or
+    /// None if the font doesn't have big digits.
+    pub fn big_digit_address(&self, digit: u8) -> Option<u16> {
+        (self.font.len() > Font::SMALL_SIZE).then(|| self.font_address + (Font::SMALL_SIZE + 10 * digit as usize) as u16)
+    }
+
+    /// Start noting reads and fetches of program memory that nothing has
+    /// written, usually a homebrew bug such as a missing initializer or a
+    /// jump into empty space. Each address is only noted once.
+    pub fn track_uninitialized_reads(&mut self) {
+        self.uninitialized_reads = Some(Vec::new());
+    }
+
+    pub fn take_uninitialized_reads(&mut self) -> Vec<u16> {
+        self.uninitialized_reads.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    fn note_read(&mut self, address: u16) {
+        let reads = match self.uninitialized_reads.as_mut() {
+            Some(reads) => reads,
+            None => return,
+        };
+        if address < MainMemory::PROGRAM_OFFSET {
+            return;
+        }
+        if let Some(initialized) = self.initialized.get_mut(address as usize) {
+            if !*initialized {
+                reads.push(address);
+                *initialized = true;
+            }
+        }
+    }
+
+    pub fn fetch_opcode(&mut self) -> Result<u16, Chip8Error> {
+        if (self.program_counter + 2) > self.memory.len() {
+            // Past 0xFFFF the program counter has no address of its own
+            return Err(Chip8Error::EndOfMemory(self.program_counter.min(u16::MAX as usize) as u16));
+        }
+        // Both bytes are inside memory, so even in 64K the second is at most 0xFFFF
+        let pc = self.program_counter as u16;
+        self.note_read(pc);
+        self.note_read(pc + 1);
         let big_end = self.memory[self.program_counter];
         let little_end = self.memory[self.program_counter + 1];
         let instr = ((big_end as u16) << 8) + (little_end as u16);
         self.program_counter += 2;
-        Some(instr)
+        Ok(instr)
     }
 
-    pub fn set_program_counter(&mut self, address: u16) {
-        self.program_counter = (address - MainMemory::PROGRAM_OFFSET) as usize;
+    /// Only program memory can be executed; the font below it is data.
+    pub fn set_program_counter(&mut self, address: u16) -> Result<(), Chip8Error> {
+        if address < MainMemory::PROGRAM_OFFSET || address as usize >= self.memory.len() {
+            return Err(Chip8Error::InvalidAddress(address));
+        }
+        self.program_counter = address as usize;
+        Ok(())
     }
 
     pub fn peek_program_counter(&self) -> usize {
-        self.program_counter + MainMemory::PROGRAM_OFFSET as usize
+        self.program_counter
     }
 
     pub fn skip_instruction(&mut self) {
         self.program_counter += 2;
     }
 
-    pub fn load_address(&self, address: u16) -> u8 {
-        if address > MainMemory::MEMORY_SIZE as u16 {
-            panic!("Invalid memory read at address {:#06X}", address);
-        }
+    pub fn load_address(&mut self, address: u16) -> Result<u8, Chip8Error> {
+        let data = self.peek(address).ok_or(Chip8Error::InvalidAddress(address))?;
+        self.note_read(address);
+        Ok(data)
+    }
+
+    /// A read that never panics, for looking at memory from the outside.
+    /// Addresses that hold nothing (between the font and the program, or
+    /// past the end of memory) read as None.
+    pub fn peek(&self, address: u16) -> Option<u8> {
         if address < MainMemory::PROGRAM_OFFSET {
-            MainMemory::FONT_SPRITES[address as usize]
+            address.checked_sub(self.font_address).and_then(|offset| self.font.get(offset as usize)).cloned()
         } else {
-            self.memory[(address - MainMemory::PROGRAM_OFFSET) as usize]
+            self.memory.get(address as usize).cloned()
         }
     }
 
-    pub fn write_address(&mut self, address: u16, data: u8) {
-        if address > MainMemory::MEMORY_SIZE as u16 {
-            panic!("Invalid memory read at address {:#06X}", address);
+    /// The runs of addresses, as (start, length), that read differently in
+    /// `other`, or None when the two aren't even the same size.
+    pub fn changes_from(&self, other: &MainMemory) -> Option<Vec<(u16, u16)>> {
+        if self.size() != other.size() {
+            return None;
+        }
+        let mut changes: Vec<(u16, u16)> = Vec::new();
+        for address in 0..self.size() as u32 {
+            let address = address as u16;
+            if self.peek(address) == other.peek(address) {
+                continue;
+            }
+            match changes.last_mut() {
+                Some((start, length)) if *start as u32 + *length as u32 == address as u32 && *length < u16::MAX => *length += 1,
+                _ => changes.push((address, 1)),
+            }
         }
-        self.memory[(address - MainMemory::PROGRAM_OFFSET) as usize] = data;
+        Some(changes)
     }
 
-    pub fn slice_program(&self, start: u16, end: u16) -> &[u8] {
-        if end < MainMemory::PROGRAM_OFFSET {
-            return &MainMemory::FONT_SPRITES[(start as usize)..(end as usize)];
-        } else {
-            let shifted_start = (start - MainMemory::PROGRAM_OFFSET) as usize;
-            let shifted_end = (end - MainMemory::PROGRAM_OFFSET) as usize;
-            return &self.memory[shifted_start..shifted_end]
+    pub fn peek_opcode(&self, address: u16) -> Option<u16> {
+        let high = self.peek(address)?;
+        let low = self.peek(address.checked_add(1)?)?;
+        Some(((high as u16) << 8) | low as u16)
+    }
+
+    /// Writes are only allowed to program memory; the font is read-only.
+    pub fn write_address(&mut self, address: u16, data: u8) -> Result<(), Chip8Error> {
+        if address < MainMemory::PROGRAM_OFFSET {
+            return Err(Chip8Error::InvalidAddress(address));
         }
+        let cell = self.memory.get_mut(address as usize).ok_or(Chip8Error::InvalidAddress(address))?;
+        *cell = data;
+        self.initialized[address as usize] = true;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memory_ends_at_the_top_of_the_address_space() {
+        let mut memory = MainMemory::new(vec![0x12, 0x00]);
+        assert_eq!(memory.size(), 0x1000);
+        assert_eq!(memory.write_address(0xFFF, 0x12), Ok(()));
+        assert_eq!(memory.write_address(0x1000, 0x12), Err(Chip8Error::InvalidAddress(0x1000)));
+        assert_eq!(memory.set_program_counter(0x1000), Err(Chip8Error::InvalidAddress(0x1000)));
+        memory.set_program_counter(0xFFF).unwrap();
+        assert_eq!(memory.fetch_opcode(), Err(Chip8Error::EndOfMemory(0xFFF)));
+    }
+
+    #[test]
+    fn xo_chip_runs_up_to_0xffff() {
+        let mut memory = MainMemory::new(Vec::new());
+        memory.set_size(MainMemory::XO_CHIP_MEMORY_SIZE);
+        memory.track_uninitialized_reads();
+        memory.write_address(0xFFFE, 0xAB).unwrap();
+        memory.write_address(0xFFFF, 0xCD).unwrap();
+        assert_eq!(memory.peek_opcode(0xFFFE), Some(0xABCD));
+        assert_eq!(memory.peek_opcode(0xFFFF), None);
+
+        memory.set_program_counter(0xFFFE).unwrap();
+        assert_eq!(memory.fetch_opcode(), Ok(0xABCD));
+        assert_eq!(memory.fetch_opcode(), Err(Chip8Error::EndOfMemory(0xFFFF)));
+        memory.set_program_counter(0xFFFF).unwrap();
+        assert_eq!(memory.fetch_opcode(), Err(Chip8Error::EndOfMemory(0xFFFF)));
+    }
+
+    #[test]
+    fn programs_past_4k_keep_64k() {
+        let mut program = vec![0; 0xE01];
+        program[0xE00] = 0x5A;
+        let mut memory = MainMemory::new(program);
+        assert_eq!(memory.size(), MainMemory::XO_CHIP_MEMORY_SIZE);
+        memory.set_size(MainMemory::MEMORY_SIZE);
+        assert_eq!(memory.size(), MainMemory::XO_CHIP_MEMORY_SIZE);
+        assert_eq!(memory.peek(0x1000), Some(0x5A));
+
+        let mut out = StateWriter::new();
+        memory.save(&mut out);
+        let loaded = MainMemory::load(&mut StateReader::new(&out.into_bytes())).unwrap();
+        assert_eq!((loaded.size(), loaded.peek(0x1000)), (MainMemory::XO_CHIP_MEMORY_SIZE, Some(0x5A)));
     }
 }
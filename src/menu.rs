@@ -0,0 +1,220 @@
+use sdl2::keyboard::Scancode;
+
+use crate::keymap::Keymap;
+
+/*
+    The pause menu, opened with Escape. It's a small state machine driven
+    by key presses while the game is held: arrows move the selection,
+    Enter picks, left and right cycle the palette and Escape backs out.
+    Quitting asks first. It only decides what was asked for; the frontend
+    carries it out.
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Item {
+    Resume,
+    Reset,
+    SaveState,
+    LoadState,
+    Palette,
+    AlwaysOnTop,
+    RemapKeys,
+    Quit,
+}
+
+impl Item {
+    const ALL: [Item; 8] = [Item::Resume, Item::Reset, Item::SaveState, Item::LoadState,
+                            Item::Palette, Item::AlwaysOnTop, Item::RemapKeys, Item::Quit];
+
+    fn label(&self) -> &'static str {
+        match self {
+            Item::Resume => "Resume",
+            Item::Reset => "Reset",
+            Item::SaveState => "Save state",
+            Item::LoadState => "Load state",
+            Item::Palette => "Palette",
+            Item::AlwaysOnTop => "Always on top",
+            Item::RemapKeys => "Remap keys",
+            Item::Quit => "Quit",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    Resume,
+    Reset,
+    SaveState,
+    LoadState,
+    NextPalette,
+    PreviousPalette,
+    ToggleAlwaysOnTop,
+    // Press a hex key with the named scancode
+    Bind(u8, String),
+    Quit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    Closed,
+    // The index of the selected item
+    Open(usize),
+    // How many of the pad's keys have been bound so far
+    Remapping(usize),
+    // The item to go back to, or None to go back to the game
+    ConfirmingQuit(Option<usize>),
+}
+
+/*
+    What Escape does during a game: open the menu, ask whether to quit or
+    quit straight away.
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EscapeKey {
+    Menu,
+    ConfirmQuit,
+    Quit,
+}
+
+impl EscapeKey {
+    pub fn from_name(name: &str) -> Option<EscapeKey> {
+        match name {
+            "menu" => Some(EscapeKey::Menu),
+            "confirm" => Some(EscapeKey::ConfirmQuit),
+            "quit" => Some(EscapeKey::Quit),
+            _ => None,
+        }
+    }
+}
+
+pub struct PauseMenu {
+    state: State,
+}
+
+impl PauseMenu {
+    pub fn new() -> PauseMenu {
+        PauseMenu { state: State::Closed }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.state != State::Closed
+    }
+
+    // Whether every key should come here ahead of the hotkeys, as while one is being bound
+    pub fn is_capturing(&self) -> bool {
+        matches!(self.state, State::Remapping(_))
+    }
+
+    pub fn open(&mut self) {
+        self.state = State::Open(0);
+    }
+
+    // Ask whether to quit without going through the menu
+    pub fn confirm_quit(&mut self) {
+        self.state = State::ConfirmingQuit(None);
+    }
+
+    pub fn handle_key(&mut self, scancode: Scancode) -> Option<Action> {
+        match self.state {
+            State::Closed => None,
+            State::Open(selected) => self.handle_open(selected, scancode),
+            State::Remapping(bound) => {
+                let remap_item = Item::ALL.iter().position(|&item| item == Item::RemapKeys).unwrap();
+                if scancode == Scancode::Escape {
+                    self.state = State::Open(remap_item);
+                    return None;
+                }
+                self.state = if bound + 1 == Keymap::PAD_ORDER.len() { State::Open(remap_item) } else { State::Remapping(bound + 1) };
+                Some(Action::Bind(Keymap::PAD_ORDER[bound], String::from(scancode.name())))
+            },
+            State::ConfirmingQuit(back_to) => match scancode {
+                Scancode::Return | Scancode::KpEnter | Scancode::Space | Scancode::Y => {
+                    self.state = State::Closed;
+                    Some(Action::Quit)
+                },
+                Scancode::Escape | Scancode::N => {
+                    self.state = back_to.map_or(State::Closed, State::Open);
+                    back_to.is_none().then_some(Action::Resume)
+                },
+                _ => None,
+            },
+        }
+    }
+
+    fn handle_open(&mut self, selected: usize, scancode: Scancode) -> Option<Action> {
+        let item = Item::ALL[selected];
+        match scancode {
+            Scancode::Escape => {
+                self.state = State::Closed;
+                Some(Action::Resume)
+            },
+            Scancode::Up => {
+                self.state = State::Open((selected + Item::ALL.len() - 1) % Item::ALL.len());
+                None
+            },
+            Scancode::Down => {
+                self.state = State::Open((selected + 1) % Item::ALL.len());
+                None
+            },
+            Scancode::Left if item == Item::Palette => Some(Action::PreviousPalette),
+            Scancode::Right if item == Item::Palette => Some(Action::NextPalette),
+            Scancode::Return | Scancode::KpEnter | Scancode::Space => match item {
+                Item::Palette => Some(Action::NextPalette),
+                Item::AlwaysOnTop => Some(Action::ToggleAlwaysOnTop),
+                Item::RemapKeys => {
+                    self.state = State::Remapping(0);
+                    None
+                },
+                Item::Quit => {
+                    self.state = State::ConfirmingQuit(Some(selected));
+                    None
+                },
+                Item::Resume => self.close(Action::Resume),
+                Item::Reset => self.close(Action::Reset),
+                Item::SaveState => self.close(Action::SaveState),
+                Item::LoadState => self.close(Action::LoadState),
+            },
+            _ => None,
+        }
+    }
+
+    fn close(&mut self, action: Action) -> Option<Action> {
+        self.state = State::Closed;
+        Some(action)
+    }
+
+    /*
+        The menu as lines of text, showing the current palette and whether
+        the window is always on top.
+    */
+    pub fn lines(&self, palette: &str, always_on_top: bool) -> Vec<String> {
+        match self.state {
+            State::Closed => Vec::new(),
+            State::Open(selected) => {
+                let mut lines = vec![String::from("Paused"), String::new()];
+                for (num, item) in Item::ALL.iter().enumerate() {
+                    let marker = if num == selected { '>' } else { ' ' };
+                    match item {
+                        Item::Palette => lines.push(format!("{} {}: {}", marker, item.label(), palette)),
+                        Item::AlwaysOnTop => {
+                            lines.push(format!("{} {}: {}", marker, item.label(), if always_on_top { "on" } else { "off" }))
+                        },
+                        Item::Resume | Item::Reset | Item::SaveState | Item::LoadState | Item::RemapKeys | Item::Quit => {
+                            lines.push(format!("{} {}", marker, item.label()))
+                        },
+                    }
+                }
+                lines
+            },
+            State::Remapping(bound) => vec![String::from("Remap keys"),
+                                            String::new(),
+                                            format!("Press a key for {:X}", Keymap::PAD_ORDER[bound]),
+                                            format!("({} of {})", bound + 1, Keymap::PAD_ORDER.len()),
+                                            String::new(),
+                                            String::from("Escape stops")],
+            State::ConfirmingQuit(_) => vec![String::from("Quit yac8?"),
+                                             String::new(),
+                                             String::from("Enter quits"),
+                                             String::from("Escape goes back")],
+        }
+    }
+}
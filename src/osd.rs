@@ -0,0 +1,42 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/*
+    On-screen toasts: short messages like "Palette: amber" that show over
+    the game for a moment, so a hotkey or menu choice gets feedback without
+    looking at the terminal. Only the newest few are kept.
+*/
+pub struct Osd {
+    toasts: VecDeque<(String, Instant)>,
+}
+
+impl Osd {
+    const DURATION: Duration = Duration::from_secs(2);
+    const MAX_TOASTS: usize = 3;
+
+    pub fn new() -> Osd {
+        Osd { toasts: VecDeque::new() }
+    }
+
+    pub fn show(&mut self, message: String) {
+        if self.toasts.len() == Osd::MAX_TOASTS {
+            self.toasts.pop_front();
+        }
+        self.toasts.push_back((message, Instant::now()));
+    }
+
+    /*
+        Drop the toasts that have been up long enough. Returns true if any
+        went, so the screen needs redrawing without them.
+    */
+    pub fn expire(&mut self) -> bool {
+        let before = self.toasts.len();
+        self.toasts.retain(|(_, shown)| shown.elapsed() < Osd::DURATION);
+        self.toasts.len() != before
+    }
+
+    // Oldest first
+    pub fn lines(&self) -> Vec<String> {
+        self.toasts.iter().map(|(message, _)| message.clone()).collect()
+    }
+}
@@ -0,0 +1,122 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::compat;
+use crate::disassembler;
+use crate::instructions::{self, Instruction};
+use crate::quirks::Quirks;
+use crate::variant::Variant;
+
+/// Which quirks a ROM's instructions depend on, found without running it.
+/// Like the rest of the static reports every word is treated as an
+/// instruction, so sprite data can add a few false alarms. Instructions
+/// that come out the same either way aren't counted, such as a shift of a
+/// register into itself or a BNNN with X = 0.
+///
+/// Knowing which quirks matter narrows down the presets worth trying:
+/// profiles that agree on every quirk the ROM uses will run it the same.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Quirk {
+    ShiftUsesVY,
+    LoadStoreIncrementsI,
+    JumpUsesVX,
+    LogicResetsVF,
+    BigSprite,
+}
+
+impl Quirk {
+    /// How a profile sets this quirk, in words.
+    fn setting(&self, quirks: &Quirks) -> String {
+        match self {
+            Quirk::ShiftUsesVY => String::from(if quirks.shift_uses_vy { "shift VY" } else { "shift VX" }),
+            Quirk::LoadStoreIncrementsI => String::from(if quirks.load_store_increments_i { "move I" } else { "keep I" }),
+            Quirk::JumpUsesVX => String::from(if quirks.jump_uses_vx { "XNN + VX" } else { "NNN + V0" }),
+            Quirk::LogicResetsVF => String::from(if quirks.logic_resets_vf { "reset VF" } else { "keep VF" }),
+            Quirk::BigSprite => format!("{:?}", quirks.big_sprite).to_lowercase(),
+        }
+    }
+}
+
+impl fmt::Display for Quirk {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Quirk::ShiftUsesVY => write!(f, "8XY6/8XYE shift source"),
+            Quirk::LoadStoreIncrementsI => write!(f, "FX55/FX65 I increment"),
+            Quirk::JumpUsesVX => write!(f, "BNNN offset register"),
+            Quirk::LogicResetsVF => write!(f, "8XY1/8XY2/8XY3 VF reset"),
+            Quirk::BigSprite => write!(f, "DXY0 sprite size"),
+        }
+    }
+}
+
+/// The quirk an instruction's result depends on, if any.
+fn depends_on(instruction: &Instruction) -> Option<Quirk> {
+    match *instruction {
+        Instruction::ShiftRight(x, y) | Instruction::ShiftLeft(x, y) if x != y => Some(Quirk::ShiftUsesVY),
+        Instruction::StoreRegisters(_) | Instruction::ReadRegisters(_) => Some(Quirk::LoadStoreIncrementsI),
+        Instruction::JumpFromOffset(address) if address & 0xF00 != 0 => Some(Quirk::JumpUsesVX),
+        Instruction::Or(..) | Instruction::And(..) | Instruction::Xor(..) => Some(Quirk::LogicResetsVF),
+        Instruction::Draw(_, _, 0) => Some(Quirk::BigSprite),
+        _ => None,
+    }
+}
+
+pub struct QuirkUsage {
+    // The addresses of the instructions that depend on each quirk
+    pub uses: BTreeMap<Quirk, Vec<u16>>,
+}
+
+impl QuirkUsage {
+    pub fn of(rom: &[u8]) -> QuirkUsage {
+        let mut uses: BTreeMap<Quirk, Vec<u16>> = BTreeMap::new();
+        for (num, word) in rom.chunks_exact(2).enumerate() {
+            let opcode = ((word[0] as u16) << 8) | word[1] as u16;
+            if let Some(quirk) = depends_on(&instructions::parse_opcode(opcode)) {
+                uses.entry(quirk).or_default().push(disassembler::PROGRAM_OFFSET + 2 * num as u16);
+            }
+        }
+        QuirkUsage { uses }
+    }
+
+    /// Every profile the compatibility report runs, grouped by how they set
+    /// the quirks this ROM uses. Profiles in the same group should run it
+    /// identically.
+    pub fn profile_groups(&self) -> Vec<(Vec<String>, Vec<&'static str>)> {
+        let mut profiles = vec![(compat::DEFAULT_PROFILE, Quirks::default())];
+        profiles.extend(Variant::ALL.iter().map(|variant| (variant.name(), variant.quirks())));
+
+        let mut groups: Vec<(Vec<String>, Vec<&'static str>)> = Vec::new();
+        for (profile, quirks) in profiles {
+            let settings: Vec<String> = self.uses.keys().map(|quirk| quirk.setting(&quirks)).collect();
+            match groups.iter_mut().find(|(group_settings, _)| *group_settings == settings) {
+                Some((_, members)) => members.push(profile),
+                None => groups.push((settings, vec![profile])),
+            }
+        }
+        groups
+    }
+}
+
+impl fmt::Display for QuirkUsage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.uses.is_empty() {
+            return writeln!(f, "quirk dependence: none, every profile should run this ROM the same");
+        }
+        writeln!(f, "quirk dependence:")?;
+        for (quirk, addresses) in &self.uses {
+            let shown: Vec<String> = addresses.iter().take(8).map(|address| format!("{:#05X}", address)).collect();
+            let more = if addresses.len() > shown.len() { " ..." } else { "" };
+            writeln!(f, "  {:<24} {:>4}  {}{}", quirk.to_string(), addresses.len(), shown.join(" "), more)?;
+        }
+
+        let groups = self.profile_groups();
+        if groups.len() == 1 {
+            return writeln!(f, "every profile sets these quirks the same way");
+        }
+        writeln!(f, "profiles that could behave differently:")?;
+        for (settings, profiles) in groups {
+            writeln!(f, "  {:<28} {}", profiles.join(", "), settings.join(", "))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,153 @@
+use crate::savestate::{StateReader, StateWriter};
+
+/// The behaviours that differ between CHIP-8 interpreters. ROMs are written
+/// against one interpreter or another, so running a game under the wrong
+/// set of quirks is the usual reason it misbehaves.
+///
+/// The default is what yac8 has always done, except that sprites now clip
+/// at the edges of the screen as they do on every interpreter but Octo.
+/// The presets follow the interpreters most ROMs target: the original
+/// COSMAC VIP, CHIP-48 and SUPER-CHIP 1.1 on the HP-48, and Octo's
+/// XO-CHIP.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Quirks {
+    // 8XY6/8XYE shift VY into VX instead of shifting VX in place
+    pub shift_uses_vy: bool,
+    // FX55/FX65 leave I pointing past the last register stored or loaded
+    pub load_store_increments_i: bool,
+    // BNNN jumps to XNN + VX instead of NNN + V0
+    pub jump_uses_vx: bool,
+    // 8XY1/8XY2/8XY3 reset VF to 0
+    pub logic_resets_vf: bool,
+    // DXYN stalls until the next display interrupt
+    pub display_wait: bool,
+    // DXYN in hi-res sets VF to the number of rows that collided or were clipped off the bottom, not just 1
+    pub collision_row_count: bool,
+    // What DXY0 draws outside of hi-res
+    pub big_sprite: BigSprite,
+    // DXYN wraps the part of a sprite past an edge around to the other side, rather than clipping it
+    pub wrap_sprites: bool,
+}
+
+/// DXY0 asks for a sprite with no rows. The COSMAC VIP draws nothing,
+/// SUPER-CHIP 1.1 draws an 8x16 sprite from 16 bytes at I and later
+/// interpreters, XO-CHIP included, a 16x16 one from 32 bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum BigSprite {
+    #[default]
+    Nothing,
+    Tall,
+    Large,
+}
+
+impl Quirks {
+    pub const PRESETS: [&'static str; 4] = ["chip8", "chip48", "schip", "xochip"];
+    pub const NAMES: [&'static str; 8] = ["shift-vy", "load-store-i", "jump-vx", "logic-vf", "display-wait",
+                                          "collision-rows", "wrap-sprites", "big-sprite"];
+
+    /// Change one quirk from a `NAME=VALUE` setting: `on` or `off` for the
+    /// flags, where a bare NAME means on, and `nothing`, `tall` or `large`
+    /// for big-sprite.
+    pub fn apply(&mut self, setting: &str) -> Result<(), String> {
+        let (name, value) = setting.split_once('=').unwrap_or((setting, "on"));
+        if name == "big-sprite" {
+            self.big_sprite = match value {
+                "nothing" => BigSprite::Nothing,
+                "tall" => BigSprite::Tall,
+                "large" => BigSprite::Large,
+                _ => return Err(format!("big-sprite is `nothing`, `tall` or `large`, not `{}`", value)),
+            };
+            return Ok(());
+        }
+        let flag = match name {
+            "shift-vy" => &mut self.shift_uses_vy,
+            "load-store-i" => &mut self.load_store_increments_i,
+            "jump-vx" => &mut self.jump_uses_vx,
+            "logic-vf" => &mut self.logic_resets_vf,
+            "display-wait" => &mut self.display_wait,
+            "collision-rows" => &mut self.collision_row_count,
+            "wrap-sprites" => &mut self.wrap_sprites,
+            _ => return Err(format!("unknown quirk `{}`, expected one of {}", name, Quirks::NAMES.join(", "))),
+        };
+        *flag = match value {
+            "on" => true,
+            "off" => false,
+            _ => return Err(format!("{} is `on` or `off`, not `{}`", name, value)),
+        };
+        Ok(())
+    }
+
+    // Sprite wrapping came later, so it's saved at the end of the snapshot
+    pub fn save(&self, out: &mut StateWriter) {
+        out.bools(&[self.shift_uses_vy, self.load_store_increments_i, self.jump_uses_vx, self.logic_resets_vf,
+                    self.display_wait, self.collision_row_count]);
+        out.u8(self.big_sprite as u8);
+    }
+
+    pub fn load(input: &mut StateReader) -> Result<Quirks, String> {
+        let mut flags = [false; 6];
+        input.fill_bools(&mut flags)?;
+        let big_sprite = match input.u8()? {
+            0 => BigSprite::Nothing,
+            1 => BigSprite::Tall,
+            2 => BigSprite::Large,
+            other => return Err(format!("unknown DXY0 sprite setting {}", other)),
+        };
+        Ok(Quirks {
+            shift_uses_vy: flags[0],
+            load_store_increments_i: flags[1],
+            jump_uses_vx: flags[2],
+            logic_resets_vf: flags[3],
+            display_wait: flags[4],
+            collision_row_count: flags[5],
+            big_sprite,
+            wrap_sprites: false,
+        })
+    }
+
+    pub fn preset(name: &str) -> Option<Quirks> {
+        match name {
+            "chip8" => Some(Quirks {
+                shift_uses_vy: true,
+                load_store_increments_i: true,
+                jump_uses_vx: false,
+                logic_resets_vf: true,
+                display_wait: true,
+                collision_row_count: false,
+                big_sprite: BigSprite::Nothing,
+                wrap_sprites: false,
+            }),
+            "chip48" => Some(Quirks {
+                shift_uses_vy: false,
+                load_store_increments_i: false,
+                jump_uses_vx: true,
+                logic_resets_vf: false,
+                display_wait: false,
+                collision_row_count: false,
+                big_sprite: BigSprite::Nothing,
+                wrap_sprites: false,
+            }),
+            "schip" => Some(Quirks {
+                shift_uses_vy: false,
+                load_store_increments_i: false,
+                jump_uses_vx: true,
+                logic_resets_vf: false,
+                display_wait: false,
+                collision_row_count: true,
+                big_sprite: BigSprite::Tall,
+                wrap_sprites: false,
+            }),
+            "xochip" => Some(Quirks {
+                shift_uses_vy: true,
+                load_store_increments_i: true,
+                jump_uses_vx: false,
+                logic_resets_vf: false,
+                display_wait: false,
+                collision_row_count: false,
+                big_sprite: BigSprite::Large,
+                wrap_sprites: true,
+            }),
+            _ => None,
+        }
+    }
+}
@@ -1,6 +1,8 @@
-/*
-    The CHIP-8 data registers, `I` register, and timer registers.
-*/
+use crate::error::Chip8Error;
+use crate::savestate::{StateReader, StateWriter};
+
+/// The CHIP-8 data registers, `I` register, and timer registers.
+#[derive(Clone)]
 pub struct Registers {
     data: [u8; Registers::NUM_DATA_REGISTERS as usize],
     pub i_register: u16,
@@ -9,7 +11,7 @@ pub struct Registers {
 }
 
 impl Registers {
-    const NUM_DATA_REGISTERS: u8 = 16;
+    pub const NUM_DATA_REGISTERS: u8 = 16;
 
     pub fn new() -> Registers {
         Registers {
@@ -20,19 +22,62 @@ impl Registers {
         }
     }
 
-    pub fn read_data_register(&self, register: u8) -> u8 {
-        Registers::validate_data_register(register);
-        self.data[register as usize]
+    pub fn save(&self, out: &mut StateWriter) {
+        out.bytes(&self.data);
+        out.u16(self.i_register);
+        out.u8(self.delay_timer);
+        out.u8(self.sound_timer);
+    }
+
+    pub fn load(input: &mut StateReader) -> Result<Registers, String> {
+        let mut registers = Registers::new();
+        input.fill(&mut registers.data)?;
+        registers.i_register = input.u16()?;
+        registers.delay_timer = input.u8()?;
+        registers.sound_timer = input.u8()?;
+        Ok(registers)
     }
 
-    pub fn write_data_register(&mut self, register: u8, data: u8) {
-        Registers::validate_data_register(register);
+    pub fn read_data_register(&self, register: u8) -> Result<u8, Chip8Error> {
+        Registers::validate_data_register(register)?;
+        Ok(self.data[register as usize])
+    }
+
+    pub fn write_data_register(&mut self, register: u8, data: u8) -> Result<(), Chip8Error> {
+        Registers::validate_data_register(register)?;
         self.data[register as usize] = data;
+        Ok(())
+    }
+
+    // All of V0 to VF, for callers that want the lot
+    pub fn data(&self) -> &[u8; Registers::NUM_DATA_REGISTERS as usize] {
+        &self.data
     }
 
-    pub fn validate_data_register(register: u8) {
-        if register > Registers::NUM_DATA_REGISTERS {
-            panic!("Attempting to access invalid register.");
+    pub fn validate_data_register(register: u8) -> Result<(), Chip8Error> {
+        if register >= Registers::NUM_DATA_REGISTERS {
+            return Err(Chip8Error::InvalidRegister(register));
         }
+        Ok(())
+    }
+}
+
+impl Default for Registers {
+    fn default() -> Registers {
+        Registers::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registers_past_vf_are_an_error() {
+        let mut registers = Registers::new();
+        assert_eq!(registers.write_data_register(0xF, 7), Ok(()));
+        assert_eq!(registers.read_data_register(0xF), Ok(7));
+        assert_eq!(registers.read_data_register(16), Err(Chip8Error::InvalidRegister(16)));
+        assert_eq!(registers.write_data_register(16, 1), Err(Chip8Error::InvalidRegister(16)));
     }
 }
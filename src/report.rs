@@ -0,0 +1,193 @@
+use std::path::Path;
+
+use crate::capture;
+use crate::chip8::Chip8;
+use crate::quirks::Quirks;
+use crate::scan::Summary;
+
+/*
+    A report of one run, for documenting compatibility testing of a ROM
+    collection: what the ROM is, how it was run, what it did (the counters,
+    which instructions it executed and how much of the ROM) and how the
+    screen looked at the end. Markdown shows the screen as text and HTML
+    embeds it as an image, so either is a single file to keep or share.
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReportFormat {
+    Markdown,
+    Html,
+}
+
+impl ReportFormat {
+    // HTML for `.html` and `.htm`, Markdown otherwise
+    pub fn from_path(path: &Path) -> ReportFormat {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("html") | Some("htm") => ReportFormat::Html,
+            _ => ReportFormat::Markdown,
+        }
+    }
+}
+
+pub struct Report<'a> {
+    pub name: &'a str,
+    pub summary: Summary,
+    // What the ROM database says about it
+    pub identity: String,
+    // How the machine was set up, as name and value
+    pub settings: Vec<(&'static str, String)>,
+    pub exit_status: i32,
+}
+
+struct Section {
+    title: &'static str,
+    rows: Vec<(String, String)>,
+}
+
+// How many screen pixels each display pixel takes in an HTML report
+const HTML_SCREEN_SCALE: usize = 6;
+
+/*
+    The quirks that are switched on, by field name.
+*/
+pub fn describe_quirks(quirks: &Quirks) -> String {
+    let flags = [("shift_uses_vy", quirks.shift_uses_vy),
+                 ("load_store_increments_i", quirks.load_store_increments_i),
+                 ("jump_uses_vx", quirks.jump_uses_vx),
+                 ("logic_resets_vf", quirks.logic_resets_vf),
+                 ("display_wait", quirks.display_wait),
+                 ("collision_row_count", quirks.collision_row_count),
+                 ("wrap_sprites", quirks.wrap_sprites)];
+    let mut enabled: Vec<String> = flags.iter().filter(|(_, on)| *on).map(|(name, _)| String::from(*name)).collect();
+    enabled.push(format!("big_sprite {:?}", quirks.big_sprite));
+    enabled.join(", ")
+}
+
+impl<'a> Report<'a> {
+    pub fn render(&self, format: ReportFormat, machine: &Chip8) -> String {
+        let sections = self.sections(machine);
+        match format {
+            ReportFormat::Markdown => self.to_markdown(&sections, machine),
+            ReportFormat::Html => self.to_html(&sections, machine),
+        }
+    }
+
+    fn sections(&self, machine: &Chip8) -> Vec<Section> {
+        let summary = &self.summary;
+        let rom = vec![(String::from("sha1"), summary.sha1.clone()),
+                       (String::from("crc32"), format!("{:08x}", summary.crc32)),
+                       (String::from("size"), format!("{} bytes", summary.size)),
+                       (String::from("extensions"), summary.extension_names()),
+                       (String::from("database"), self.identity.clone())];
+
+        let settings = self.settings.iter().map(|(name, value)| (String::from(*name), value.clone())).collect();
+
+        let stats = machine.stats();
+        let runtime = vec![(String::from("exit status"), self.exit_status.to_string()),
+                           (String::from("instructions"), stats.instructions.to_string()),
+                           (String::from("frames"), stats.frames.to_string()),
+                           (String::from("draws"), stats.draws.to_string()),
+                           (String::from("collisions"), stats.collisions.to_string()),
+                           (String::from("stack depth"), stats.stack_high_water.to_string()),
+                           (String::from("random calls"), stats.random_calls.to_string())];
+
+        let mut sections = vec![Section { title: "ROM", rows: rom },
+                                Section { title: "Settings", rows: settings },
+                                Section { title: "Runtime", rows: runtime }];
+
+        if let Some(coverage) = machine.coverage() {
+            let (executed, total) = coverage.executed_bytes();
+            let percent = if total == 0 { 0.0 } else { 100.0 * executed as f64 / total as f64 };
+            let mut rows = vec![(String::from("ROM bytes executed"), format!("{} of {} ({:.1}%)", executed, total, percent))];
+            rows.extend(coverage.unexecuted_ranges()
+                                .iter()
+                                .map(|(first, last)| (String::from("never executed"), format!("{:#06X}-{:#06X}", first, last))));
+            sections.push(Section { title: "Coverage", rows });
+
+            // Most executed first, ties in opcode order
+            let mut histogram: Vec<(&str, u64)> = coverage.pattern_hits().iter().map(|(&pattern, &hits)| (pattern, hits)).collect();
+            histogram.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+            let rows = histogram.iter().map(|(pattern, hits)| (String::from(*pattern), hits.to_string())).collect();
+            sections.push(Section { title: "Instructions executed", rows });
+        }
+        sections
+    }
+
+    fn to_markdown(&self, sections: &[Section], machine: &Chip8) -> String {
+        let mut lines = vec![format!("# yac8 run report: {}", self.name)];
+        for section in sections {
+            lines.push(String::new());
+            lines.push(format!("## {}", section.title));
+            lines.push(String::new());
+            lines.push(String::from("| | |"));
+            lines.push(String::from("|---|---|"));
+            for (name, value) in &section.rows {
+                lines.push(format!("| {} | {} |", name, value.replace('|', "\\|")));
+            }
+        }
+        lines.push(String::new());
+        lines.push(String::from("## Final screen"));
+        lines.push(String::new());
+        lines.push(String::from("```"));
+        lines.push(capture::to_ascii(machine.display.rows()).trim_end().to_string());
+        lines.push(String::from("```"));
+        lines.push(String::new());
+        lines.join("\n")
+    }
+
+    fn to_html(&self, sections: &[Section], machine: &Chip8) -> String {
+        let display = &machine.display;
+        let png = capture::to_png(display.pixels(), display.width(), display.height());
+        let mut html = format!("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>yac8 run report: {}</title>\n\
+                                <style>body {{ font-family: sans-serif; }} td {{ padding: 0 1em 0 0; }} \
+                                img {{ image-rendering: pixelated; }}</style>\n</head>\n<body>\n<h1>yac8 run report: {}</h1>\n",
+                               escape(self.name), escape(self.name));
+        for section in sections {
+            html.push_str(&format!("<h2>{}</h2>\n<table>\n", section.title));
+            for (name, value) in &section.rows {
+                html.push_str(&format!("<tr><td>{}</td><td>{}</td></tr>\n", escape(name), escape(value)));
+            }
+            html.push_str("</table>\n");
+        }
+        html.push_str(&format!("<h2>Final screen</h2>\n<img src=\"data:image/png;base64,{}\" width=\"{}\" height=\"{}\" alt=\"The final screen\">\n",
+                               base64(&png), display.width() * HTML_SCREEN_SCALE, display.height() * HTML_SCREEN_SCALE));
+        html.push_str("</body>\n</html>\n");
+        html
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let group = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+        for position in 0..4 {
+            if position <= chunk.len() {
+                encoded.push(ALPHABET[(group >> (18 - 6 * position) & 0x3F) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // RFC 4648's test vectors, which cover every amount of padding
+    #[test]
+    fn base64_matches_the_rfc() {
+        for (data, encoded) in [("", ""), ("f", "Zg=="), ("fo", "Zm8="), ("foo", "Zm9v"), ("foob", "Zm9vYg=="),
+                                ("fooba", "Zm9vYmE="), ("foobar", "Zm9vYmFy")] {
+            assert_eq!(base64(data.as_bytes()), encoded);
+        }
+        assert_eq!(base64(&[0xFB, 0xFF, 0xBF]), "+/+/");
+        assert_eq!(base64(&[0x00]), "AA==");
+    }
+}
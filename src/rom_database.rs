@@ -0,0 +1,106 @@
+//! Identifies ROM dumps by SHA-1 against lists of known dumps loaded at
+//! runtime, with later entries replacing earlier ones for the same hash.
+//! None is bundled, since a hash is only worth listing once it's been
+//! checked against a real dump. A list is tab separated, one dump a line:
+//!
+//! ```text
+//! sha1    status    name
+//! ```
+//!
+//! The SHA-1 is hex of the whole file, as `yac8 scan` prints it. Status is
+//! `good` for a verified dump or `bad` for a known corrupt or modified one,
+//! so users can be warned before chasing emulator bugs.
+
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DumpStatus {
+    Good,
+    Bad,
+}
+
+pub struct Entry {
+    pub name: String,
+    pub status: DumpStatus,
+}
+
+#[derive(Default)]
+pub struct RomDatabase {
+    entries: HashMap<String, Entry>,
+}
+
+impl RomDatabase {
+    pub fn new() -> RomDatabase {
+        RomDatabase::default()
+    }
+
+    /// Add the entries of a database file. Blank lines and `#` comments
+    /// are ignored.
+    pub fn load(&mut self, source: &str) -> Result<(), String> {
+        for (number, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.splitn(3, '\t').collect();
+            let (sha1, status, name) = match fields[..] {
+                [sha1, status, name] => (sha1, status, name),
+                _ => return Err(format!("line {}: expected `sha1<TAB>status<TAB>name`", number + 1)),
+            };
+            if sha1.len() != 40 || !sha1.chars().all(|digit| digit.is_ascii_hexdigit()) {
+                return Err(format!("line {}: `{}` isn't a SHA-1", number + 1, sha1));
+            }
+            let status = match status {
+                "good" => DumpStatus::Good,
+                "bad" => DumpStatus::Bad,
+                _ => return Err(format!("line {}: status must be `good` or `bad`, not `{}`", number + 1, status)),
+            };
+
+            self.entries.insert(sha1.to_ascii_lowercase(), Entry { name: name.trim().to_string(), status });
+        }
+        Ok(())
+    }
+
+    pub fn lookup(&self, sha1: &str) -> Option<&Entry> {
+        self.entries.get(&sha1.to_ascii_lowercase())
+    }
+
+    /// A one line description of a ROM, for boot messages and scan
+    /// reports.
+    pub fn identify(&self, sha1: &str) -> String {
+        match self.lookup(sha1) {
+            Some(Entry { name, status: DumpStatus::Good }) => format!("known dump: {}", name),
+            Some(Entry { name, status: DumpStatus::Bad }) => format!("known BAD dump: {}, expect problems", name),
+            None if self.entries.is_empty() => String::from("no ROM database given"),
+            None => String::from("not in the ROM database"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHA1: &str = "92a5652d382a18e89c4881ec57041fc7d885ca80";
+
+    #[test]
+    fn later_entries_replace_earlier_ones() {
+        let mut database = RomDatabase::new();
+        assert_eq!(database.identify(SHA1), "no ROM database given");
+        database.load(&format!("# comment\n\n{}\tgood\tLoop\n", SHA1)).unwrap();
+        assert_eq!(database.identify(&SHA1.to_ascii_uppercase()), "known dump: Loop");
+        database.load(&format!("{}\tbad\tLoop (hacked)", SHA1)).unwrap();
+        assert_eq!(database.identify(SHA1), "known BAD dump: Loop (hacked), expect problems");
+        assert_eq!(database.identify(&"0".repeat(40)), "not in the ROM database");
+    }
+
+    #[test]
+    fn malformed_lines_are_errors() {
+        let mut database = RomDatabase::new();
+        assert!(database.load(&format!("{} good Loop", SHA1)).is_err());
+        assert!(database.load("1234\tgood\tLoop").is_err());
+        assert_eq!(database.load(&format!("\n{}\tfine\tLoop", SHA1)).err().unwrap(),
+                   "line 2: status must be `good` or `bad`, not `fine`");
+    }
+}
@@ -0,0 +1,344 @@
+//! Save states on disk: a machine snapshot behind a small header, with the
+//! body compressed.
+//!
+//! ```text
+//! "YAC8"   magic
+//! u8       format version
+//! [u8; 20] SHA-1 of the ROM the state belongs to
+//! ...      the compressed body
+//! ```
+//!
+//! The ROM hash keeps a state from being loaded into the wrong game. Every
+//! part of the machine writes and reads its own fields through
+//! `StateWriter` and `StateReader`, in a fixed order. Numbers are
+//! big-endian, flags are one byte, and `bytes`/`flags` are a u32 length
+//! followed by that many bytes. The body of the current version is:
+//!
+//! ```text
+//! registers   bytes V0-VF, u16 I, u8 delay timer, u8 sound timer
+//! stack       u8 depth, then that many u16 return addresses
+//! memory      u32 program length in bytes, bytes memory from 0x200 to
+//!             the end of the address space (0xE00 or 0xFE00 of them),
+//!             flags written-to for the same bytes, flag tracking
+//!             uninitialized reads,
+//!             bytes font, u16 font address, u32 pc offset from 0x200
+//! display     bytes pixels (64x32, or 128x64 in hi-res), flag hi-res
+//! keys        u8 register waiting on a key (0xFF for none),
+//!             u16 presses while waiting, flags pressed,
+//!             flags pressed last frame, flags newly pressed
+//! scheduler   f64 ns per cycle, f64 ns per vblank, u128 ns elapsed,
+//!             u64 cycles, u64 vblanks
+//! machine     flag waiting on vblank, u64 instructions executed,
+//!             u64 frames elapsed, u64 random seed
+//! settings    u8 variant (index into chip8, chip48, schip, xochip),
+//!             flags shift uses VY, load/store increments I,
+//!             jump uses VX, logic resets VF, display wait,
+//!             collision row count, u8 DXY0 sprite (nothing, tall, large)
+//! schip       bytes RPL flags
+//! xochip      u8 selected bit-planes
+//! quirks      flag sprites wrap
+//! ```
+//!
+//! History:
+//! ```text
+//! 1  everything up to the settings
+//! 2  the settings, which version 1 states migrate to as plain CHIP-8
+//!    with yac8's default quirks
+//! 3  the RPL flags, which version 2 states migrate to zeroed
+//! 4  the selected bit-planes, which version 3 states migrate to as just
+//!    the first
+//! 5  sprite wrapping, which version 4 states migrate to as on, since
+//!    yac8 always wrapped sprites until then
+//! 6  memory ending where the address space does; version 5 states
+//!    stored 4K or 64K from 0x200, running 0x200 bytes past it, which
+//!    are dropped. The program length is in bytes rather than words,
+//!    which version 5 states migrate to as twice the words
+//! ```
+//!
+//! A state from an older version is upgraded one version at a time by
+//! `MIGRATIONS` before it's read, so a change to the layout means a new
+//! version, a migration from the one before and a state saved in it in
+//! `src/savestates` for the tests.
+
+use crate::chip8::Chip8;
+use crate::compress;
+use crate::quirks::Quirks;
+use crate::variant::Variant;
+
+const MAGIC: &[u8] = b"YAC8";
+pub const VERSION: u8 = 6;
+
+// MIGRATIONS[n] upgrades an uncompressed version n + 1 body to version n + 2
+const MIGRATIONS: [fn(Vec<u8>) -> Vec<u8>; VERSION as usize - 1] = [migrate_1_to_2, migrate_2_to_3, migrate_3_to_4,
+                                                                    migrate_4_to_5, migrate_5_to_6];
+
+fn migrate_1_to_2(body: Vec<u8>) -> Vec<u8> {
+    let mut out = StateWriter { data: body };
+    Variant::Chip8.save(&mut out);
+    Quirks::default().save(&mut out);
+    out.data
+}
+
+fn migrate_2_to_3(body: Vec<u8>) -> Vec<u8> {
+    let mut out = StateWriter { data: body };
+    out.bytes(&[0; Chip8::NUM_RPL_FLAGS]);
+    out.data
+}
+
+fn migrate_3_to_4(body: Vec<u8>) -> Vec<u8> {
+    let mut out = StateWriter { data: body };
+    out.u8(0x1);
+    out.data
+}
+
+fn migrate_4_to_5(body: Vec<u8>) -> Vec<u8> {
+    let mut out = StateWriter { data: body };
+    out.bool(true);
+    out.data
+}
+
+fn migrate_5_to_6(body: Vec<u8>) -> Vec<u8> {
+    // A body too broken to walk is left for loading to report
+    trim_memory(&body).unwrap_or(body)
+}
+
+fn trim_memory(body: &[u8]) -> Result<Vec<u8>, String> {
+    let mut input = StateReader::new(body);
+    // Past the registers and the stack to the memory
+    input.bytes()?;
+    input.take(4)?;
+    let depth = input.u8()?;
+    input.take(2 * depth as usize)?;
+    let start = input.position;
+    let program_words = input.u32()?;
+    let mut memory = input.bytes()?;
+    let mut initialized = input.bools()?;
+    memory.truncate(memory.len().saturating_sub(0x200));
+    initialized.truncate(initialized.len().saturating_sub(0x200));
+
+    let mut out = StateWriter { data: body[..start].to_vec() };
+    out.u32(program_words.saturating_mul(2));
+    out.bytes(&memory);
+    out.bools(&initialized);
+    out.data.extend_from_slice(&body[input.position..]);
+    Ok(out.data)
+}
+
+pub struct StateWriter {
+    data: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> StateWriter {
+        StateWriter { data: Vec::new() }
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.data
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn bool(&mut self, value: bool) {
+        self.data.push(value as u8);
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_be_bytes());
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_be_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_be_bytes());
+    }
+
+    pub fn u128(&mut self, value: u128) {
+        self.data.extend_from_slice(&value.to_be_bytes());
+    }
+
+    pub fn f64(&mut self, value: f64) {
+        self.u64(value.to_bits());
+    }
+
+    /// A length-prefixed run of bytes.
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.u32(bytes.len() as u32);
+        self.data.extend_from_slice(bytes);
+    }
+
+    pub fn bools(&mut self, values: &[bool]) {
+        self.u32(values.len() as u32);
+        self.data.extend(values.iter().map(|&value| value as u8));
+    }
+}
+
+impl Default for StateWriter {
+    fn default() -> StateWriter {
+        StateWriter::new()
+    }
+}
+
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> StateReader<'a> {
+        StateReader { data, position: 0 }
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [u8], String> {
+        let bytes = self.data.get(self.position..self.position + length)
+                             .ok_or_else(|| format!("the state ends early, at byte {}", self.position))?;
+        self.position += length;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn bool(&mut self) -> Result<bool, String> {
+        Ok(self.u8()? != 0)
+    }
+
+    pub fn u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_be_bytes(self.array()?))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_be_bytes(self.array()?))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_be_bytes(self.array()?))
+    }
+
+    pub fn u128(&mut self) -> Result<u128, String> {
+        Ok(u128::from_be_bytes(self.array()?))
+    }
+
+    pub fn f64(&mut self) -> Result<f64, String> {
+        Ok(f64::from_bits(self.u64()?))
+    }
+
+    pub fn bytes(&mut self) -> Result<Vec<u8>, String> {
+        let length = self.u32()? as usize;
+        Ok(self.take(length)?.to_vec())
+    }
+
+    pub fn bools(&mut self) -> Result<Vec<bool>, String> {
+        Ok(self.bytes()?.iter().map(|&value| value != 0).collect())
+    }
+
+    /// A fixed number of bytes or flags, for arrays whose size the machine
+    /// decides.
+    pub fn fill(&mut self, into: &mut [u8]) -> Result<(), String> {
+        let bytes = self.bytes()?;
+        if bytes.len() != into.len() {
+            return Err(format!("expected {} bytes, found {}", into.len(), bytes.len()));
+        }
+        into.copy_from_slice(&bytes);
+        Ok(())
+    }
+
+    pub fn fill_bools(&mut self, into: &mut [bool]) -> Result<(), String> {
+        let values = self.bools()?;
+        if values.len() != into.len() {
+            return Err(format!("expected {} flags, found {}", into.len(), values.len()));
+        }
+        into.copy_from_slice(&values);
+        Ok(())
+    }
+}
+
+/// A complete state file for `body`, the uncompressed state.
+pub fn encode(rom_sha1: &[u8; 20], body: &[u8]) -> Vec<u8> {
+    let mut file = MAGIC.to_vec();
+    file.push(VERSION);
+    file.extend_from_slice(rom_sha1);
+    file.extend(compress::compress(body));
+    file
+}
+
+/// The uncompressed state from a state file, in the current version's
+/// layout, if it's one yac8 can read and belongs to the ROM with hash
+/// `rom_sha1`.
+pub fn decode(rom_sha1: &[u8; 20], file: &[u8]) -> Result<Vec<u8>, String> {
+    if !file.starts_with(MAGIC) {
+        return Err(String::from("not a yac8 save state"));
+    }
+    let version = *file.get(4).ok_or("truncated save state")?;
+    if version == 0 {
+        return Err(String::from("save state version 0 isn't valid"));
+    }
+    if version > VERSION {
+        return Err(format!("save state version {} is newer than this yac8 understands", version));
+    }
+    if file.get(5..25) != Some(&rom_sha1[..]) {
+        return Err(String::from("the state was saved from a different ROM"));
+    }
+    let body = compress::decompress(&file[25..])?;
+    Ok(MIGRATIONS[version as usize - 1..].iter().fold(body, |body, migrate| migrate(body)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checksum;
+    use crate::snapshot::Snapshot;
+
+    // The ROM the fixtures in `src/savestates` were saved from, 50ms into a run
+    const ROM: [u8; 16] = [0x60, 0x05, 0x61, 0x07, 0xA2, 0x0A, 0xD0, 0x15, 0x12, 0x08, 0xF0, 0x90, 0xF0, 0x90, 0xF0, 0x00];
+
+    const FIXTURES: [&[u8]; VERSION as usize] = [include_bytes!("savestates/version-1.state"),
+                                                 include_bytes!("savestates/version-2.state"),
+                                                 include_bytes!("savestates/version-3.state"),
+                                                 include_bytes!("savestates/version-4.state"),
+                                                 include_bytes!("savestates/version-5.state"),
+                                                 include_bytes!("savestates/version-6.state")];
+
+    #[test]
+    fn every_old_version_migrates_to_the_current_one() {
+        let sha1 = checksum::sha1(&ROM);
+        let current = decode(&sha1, FIXTURES[VERSION as usize - 1]).unwrap();
+        for (version, fixture) in FIXTURES.iter().enumerate() {
+            assert_eq!(fixture[4] as usize, version + 1);
+            // The run had sprite wrapping off, which states from before version 5 migrate to as on
+            let mut expected = current.clone();
+            if version + 1 < 5 {
+                *expected.last_mut().unwrap() = 1;
+            }
+            assert_eq!(decode(&sha1, fixture).unwrap(), expected, "version {}", version + 1);
+        }
+
+        let mut machine = Chip8::new(ROM.to_vec(), 700.0);
+        machine.restore(Snapshot::load(&mut StateReader::new(&current)).unwrap());
+        let state = machine.state(true);
+        assert_eq!((state.v[0], state.v[1], state.i, state.pc), (5, 7, 0x20A, 0x208));
+        assert_eq!(state.memory.unwrap().len(), 0x1000);
+    }
+
+    #[test]
+    fn unreadable_versions_are_refused() {
+        let sha1 = checksum::sha1(&ROM);
+        let mut file = FIXTURES[VERSION as usize - 1].to_vec();
+        file[4] = 0;
+        assert_eq!(decode(&sha1, &file), Err(String::from("save state version 0 isn't valid")));
+        file[4] = VERSION + 1;
+        assert!(decode(&sha1, &file).unwrap_err().contains("newer"));
+        assert!(decode(&[0; 20], FIXTURES[0]).is_err());
+    }
+}
@@ -0,0 +1,203 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::checksum;
+use crate::disassembler;
+use crate::instructions::{self, Instruction};
+use crate::quirk_usage::QuirkUsage;
+use crate::rom_database::RomDatabase;
+
+/// Static reports on ROM files, without running them. A directory scan
+/// writes one `<rom>.txt` file per ROM, holding its summary and
+/// disassembly, plus an `index.tsv` with one summary row per ROM for
+/// auditing a whole collection in a spreadsheet.
+pub const ROM_EXTENSIONS: [&str; 4] = ["ch8", "c8", "sc8", "xo8"];
+
+/// The instruction sets a ROM can use beyond plain CHIP-8. Their opcodes
+/// mostly decode as SYS or unknown instructions here, so finding them says
+/// which interpreter the ROM was written for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Extension {
+    SuperChip,
+    XoChip,
+}
+
+impl fmt::Display for Extension {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Extension::SuperChip => write!(f, "SCHIP"),
+            Extension::XoChip => write!(f, "XO-CHIP"),
+        }
+    }
+}
+
+/// The extension and opcode pattern of an extended opcode, or None for
+/// plain CHIP-8 opcodes.
+pub fn extension_pattern(opcode: u16) -> Option<(Extension, &'static str)> {
+    match opcode {
+        0x00C0..=0x00CF => return Some((Extension::SuperChip, "00CN")),
+        0x00D0..=0x00DF => return Some((Extension::XoChip, "00DN")),
+        0x00FB => return Some((Extension::SuperChip, "00FB")),
+        0x00FC => return Some((Extension::SuperChip, "00FC")),
+        0x00FD => return Some((Extension::SuperChip, "00FD")),
+        0x00FE => return Some((Extension::SuperChip, "00FE")),
+        0x00FF => return Some((Extension::SuperChip, "00FF")),
+        0xF000 => return Some((Extension::XoChip, "F000")),
+        0xF002 => return Some((Extension::XoChip, "F002")),
+        _ => {},
+    }
+    match opcode & 0xF00F {
+        0xD000 => return Some((Extension::SuperChip, "DXY0")),
+        0x5002 => return Some((Extension::XoChip, "5XY2")),
+        0x5003 => return Some((Extension::XoChip, "5XY3")),
+        _ => {},
+    }
+    match opcode & 0xF0FF {
+        0xF001 => Some((Extension::XoChip, "FN01")),
+        0xF030 => Some((Extension::SuperChip, "FX30")),
+        0xF03A => Some((Extension::XoChip, "FX3A")),
+        0xF075 => Some((Extension::SuperChip, "FX75")),
+        0xF085 => Some((Extension::SuperChip, "FX85")),
+        _ => None,
+    }
+}
+
+/// What a ROM looks like from the outside. Every two-byte word is treated
+/// as an instruction, sprites and other data included, so the numbers are
+/// a heuristic:
+///
+/// - unknown opcodes aren't part of CHIP-8 or a known extension
+/// - suspicious instructions jump or call outside the ROM or to an odd
+///   address, or call machine code (SYS)
+/// - sprite candidates are the addresses inside the ROM that `LD I`
+///   points at
+pub struct Summary {
+    pub sha1: String,
+    pub crc32: u32,
+    pub size: usize,
+    pub words: usize,
+    pub unknown: usize,
+    pub suspicious: usize,
+    pub extensions: BTreeSet<Extension>,
+    pub sprite_candidates: BTreeSet<u16>,
+    pub histogram: BTreeMap<&'static str, usize>,
+}
+
+impl Summary {
+    pub fn of(rom: &[u8]) -> Summary {
+        let mut summary = Summary {
+            sha1: checksum::to_hex(&checksum::sha1(rom)),
+            crc32: checksum::crc32(rom),
+            size: rom.len(),
+            words: rom.len().div_ceil(2),
+            unknown: 0,
+            suspicious: 0,
+            extensions: BTreeSet::new(),
+            sprite_candidates: BTreeSet::new(),
+            histogram: BTreeMap::new(),
+        };
+        let rom_end = disassembler::PROGRAM_OFFSET as usize + rom.len();
+        let in_rom = |address: u16| (disassembler::PROGRAM_OFFSET as usize..rom_end).contains(&(address as usize));
+
+        for word in rom.chunks_exact(2) {
+            let opcode = ((word[0] as u16) << 8) | word[1] as u16;
+            if let Some((extension, pattern)) = extension_pattern(opcode) {
+                summary.extensions.insert(extension);
+                *summary.histogram.entry(pattern).or_insert(0) += 1;
+                continue;
+            }
+
+            let instruction = instructions::parse_opcode(opcode);
+            *summary.histogram.entry(instruction.pattern()).or_insert(0) += 1;
+            match instruction {
+                Instruction::UNKNOWN(_) => summary.unknown += 1,
+                Instruction::NOP(bytes) if bytes != 0x0000 => summary.suspicious += 1,
+                Instruction::Jump(address) | Instruction::Call(address) if !in_rom(address) || address % 2 == 1 => {
+                    summary.suspicious += 1
+                },
+                Instruction::SetI(address) if in_rom(address) => {
+                    summary.sprite_candidates.insert(address);
+                },
+                _ => {},
+            }
+        }
+        summary
+    }
+
+    pub const INDEX_HEADER: &'static str = "rom\tsha1\tcrc32\tsize\twords\tunknown\tsuspicious\textensions\tidentified";
+
+    pub fn index_row(&self, name: &str, database: &RomDatabase) -> String {
+        format!("{}\t{}\t{:08x}\t{}\t{}\t{}\t{}\t{}\t{}", name, self.sha1, self.crc32, self.size, self.words,
+                self.unknown, self.suspicious, self.extension_names(), database.identify(&self.sha1))
+    }
+
+    pub fn extension_names(&self) -> String {
+        let names: Vec<String> = self.extensions.iter().map(Extension::to_string).collect();
+        if names.is_empty() { String::from("none") } else { names.join(", ") }
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "sha1: {}", self.sha1)?;
+        writeln!(f, "crc32: {:08x}", self.crc32)?;
+        writeln!(f, "size: {} bytes ({} words)", self.size, self.words)?;
+        writeln!(f, "extensions: {}", self.extension_names())?;
+        writeln!(f, "unknown opcodes: {}", self.unknown)?;
+        writeln!(f, "suspicious instructions: {}", self.suspicious)?;
+
+        let candidates: Vec<String> = self.sprite_candidates.iter().map(|address| format!("{:#05X}", address)).collect();
+        writeln!(f, "sprite data candidates: {}", if candidates.is_empty() { String::from("none") } else { candidates.join(" ") })?;
+
+        // Most used first, ties in opcode order
+        let mut histogram: Vec<(&str, usize)> = self.histogram.iter().map(|(&pattern, &count)| (pattern, count)).collect();
+        histogram.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        writeln!(f, "opcode histogram:")?;
+        for (pattern, count) in histogram {
+            writeln!(f, "  {} {:>6}", pattern, count)?;
+        }
+        Ok(())
+    }
+}
+
+fn is_rom(path: &Path) -> bool {
+    path.is_file() && path.extension()
+                          .and_then(|extension| extension.to_str())
+                          .is_some_and(|extension| ROM_EXTENSIONS.iter().any(|rom| extension.eq_ignore_ascii_case(rom)))
+}
+
+/// Scan every ROM directly inside `directory` into `out_directory`,
+/// returning how many were written. Unreadable files are reported and
+/// skipped rather than ending the scan.
+pub fn scan_directory(directory: &Path, out_directory: &Path, database: &RomDatabase, explain: bool) -> io::Result<usize> {
+    let mut roms: Vec<PathBuf> = fs::read_dir(directory)?.filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                                                         .filter(|path| is_rom(path))
+                                                         .collect();
+    roms.sort();
+    fs::create_dir_all(out_directory)?;
+
+    let mut index = vec![String::from(Summary::INDEX_HEADER)];
+    for path in &roms {
+        let name = path.file_name().unwrap().to_string_lossy();
+        let rom = match fs::read(path) {
+            Ok(rom) => rom,
+            Err(error) => {
+                eprintln!("=> Skipping {}: {}", path.display(), error);
+                continue;
+            },
+        };
+
+        let summary = Summary::of(&rom);
+        let report = format!("{}\n{}\n{}{}\n\n{}\n", name, database.identify(&summary.sha1), summary,
+                             QuirkUsage::of(&rom), disassembler::listing(&rom, explain).join("\n"));
+        fs::write(out_directory.join(format!("{}.txt", name)), report)?;
+        index.push(summary.index_row(&name, database));
+    }
+
+    index.push(String::new());
+    fs::write(out_directory.join("index.tsv"), index.join("\n"))?;
+    Ok(index.len() - 2)
+}
@@ -0,0 +1,174 @@
+use std::time::Duration;
+
+use crate::savestate::{StateReader, StateWriter};
+
+/// The machine's sense of time. CHIP-8 runs off two clocks: the CPU clock,
+/// whose rate the spec leaves open, and the 60hz display interrupt (vblank)
+/// that counts down the timers and marks the end of a frame. The scheduler
+/// turns elapsed wall time into an ordered stream of these events so the
+/// core can interleave instructions with interrupts the way the hardware
+/// did, no matter how much time passes between calls.
+///
+/// Deadlines come from how many events of each kind have fired, in exact
+/// nanoseconds, so the frontend's tight loop can't accumulate rounding drift
+/// between the timers and the instruction count. A long hitch still ticks
+/// the timers once per 1/60s of emulated time, between the right instructions.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Event {
+    Cycle,
+    VBlank,
+}
+
+#[derive(Clone)]
+pub struct Scheduler {
+    nanos_per_cycle: f64,
+    nanos_per_vblank: f64,
+    now: u128,
+    cycles: u64,
+    vblanks: u64,
+}
+
+impl Scheduler {
+    pub const VBLANK_RATE_HZ: f64 = 60.0;
+
+    pub fn new(clock_speed_hz: f64) -> Scheduler {
+        Scheduler {
+            nanos_per_cycle: 1e9 / clock_speed_hz,
+            nanos_per_vblank: 1e9 / Scheduler::VBLANK_RATE_HZ,
+            now: 0,
+            cycles: 0,
+            vblanks: 0,
+        }
+    }
+
+    pub fn save(&self, out: &mut StateWriter) {
+        out.f64(self.nanos_per_cycle);
+        out.f64(self.nanos_per_vblank);
+        out.u128(self.now);
+        out.u64(self.cycles);
+        out.u64(self.vblanks);
+    }
+
+    pub fn load(input: &mut StateReader) -> Result<Scheduler, String> {
+        let scheduler = Scheduler {
+            nanos_per_cycle: input.f64()?,
+            nanos_per_vblank: input.f64()?,
+            now: input.u128()?,
+            cycles: input.u64()?,
+            vblanks: input.u64()?,
+        };
+        // A period of 0 or NaN would never let time move past the next event
+        for period in [scheduler.nanos_per_cycle, scheduler.nanos_per_vblank] {
+            if !(period.is_finite() && period > 0.0) {
+                return Err(format!("a clock period of {}ns", period));
+            }
+        }
+        Ok(scheduler)
+    }
+
+    /// The length of one frame, rounded up so that advancing by it always
+    /// fires exactly one vblank. Used to step the machine in virtual time.
+    pub fn frame_duration() -> Duration {
+        Duration::from_nanos((1e9 / Scheduler::VBLANK_RATE_HZ).ceil() as u64)
+    }
+
+    pub fn clock_speed(&self) -> f64 {
+        1e9 / self.nanos_per_cycle
+    }
+
+    /// Change the CPU clock from here on. Deadlines count from the start,
+    /// so the cycle count is rebased to what the new clock would have run
+    /// by now, which puts the next cycle within one new period.
+    pub fn set_clock_speed(&mut self, clock_speed_hz: f64) {
+        self.nanos_per_cycle = 1e9 / clock_speed_hz;
+        self.cycles = (self.now as f64 / self.nanos_per_cycle) as u64;
+    }
+
+    /// How much time the scheduler has been advanced by, in nanoseconds.
+    pub fn now(&self) -> u128 {
+        self.now
+    }
+
+    pub fn advance(&mut self, elapsed: Duration) {
+        self.now += elapsed.as_nanos();
+    }
+
+    fn deadline(count: u64, period: f64) -> u128 {
+        ((count + 1) as f64 * period).round() as u128
+    }
+
+    /// Consume `count` more cycles at once, but only if all of them are due
+    /// and the last still comes before the next vblank, so running them back
+    /// to back can't be told apart from popping them one at a time.
+    pub fn take_cycles(&mut self, count: u64) -> bool {
+        if count == 0 {
+            return true;
+        }
+        let last_cycle = Scheduler::deadline(self.cycles + count - 1, self.nanos_per_cycle);
+        let next_vblank = Scheduler::deadline(self.vblanks, self.nanos_per_vblank);
+        if last_cycle <= self.now && last_cycle < next_vblank {
+            self.cycles += count;
+            true
+        } else {
+            false
+        }
+    }
+
+    // Hand back the last `count` cycles `take_cycles` took, when fewer of them ran
+    pub fn return_cycles(&mut self, count: u64) {
+        self.cycles -= count;
+    }
+
+    /// Pop the earliest event that is due, or None once the scheduler has
+    /// caught up with the time it was advanced to. A vblank due at the same
+    /// moment as a cycle fires first.
+    pub fn next_event(&mut self) -> Option<Event> {
+        let event = self.peek_event();
+        match event {
+            Some(Event::VBlank) => self.vblanks += 1,
+            Some(Event::Cycle) => self.cycles += 1,
+            None => {},
+        }
+        event
+    }
+
+    /// How far the scheduler is from the next vblank, or nothing if one is
+    /// already due. A frontend has nothing new to show before then.
+    pub fn until_vblank(&self) -> Duration {
+        let next_vblank = Scheduler::deadline(self.vblanks, self.nanos_per_vblank);
+        Duration::from_nanos(next_vblank.saturating_sub(self.now) as u64)
+    }
+
+    /// The event `next_event` would pop, without popping it.
+    pub fn peek_event(&self) -> Option<Event> {
+        let next_cycle = Scheduler::deadline(self.cycles, self.nanos_per_cycle);
+        let next_vblank = Scheduler::deadline(self.vblanks, self.nanos_per_vblank);
+        let cycle_due = next_cycle <= self.now;
+        let vblank_due = next_vblank <= self.now;
+
+        if vblank_due && (!cycle_due || next_vblank <= next_cycle) {
+            Some(Event::VBlank)
+        } else if cycle_due {
+            Some(Event::Cycle)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn states_with_a_broken_clock_are_refused() {
+        for nanos_per_cycle in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            let mut out = StateWriter::new();
+            Scheduler { nanos_per_cycle, ..Scheduler::new(700.0) }.save(&mut out);
+            assert!(Scheduler::load(&mut StateReader::new(&out.into_bytes())).is_err());
+        }
+        let mut out = StateWriter::new();
+        Scheduler::new(700.0).save(&mut out);
+        assert!(Scheduler::load(&mut StateReader::new(&out.into_bytes())).is_ok());
+    }
+}
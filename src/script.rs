@@ -0,0 +1,170 @@
+use std::collections::VecDeque;
+use std::path::PathBuf;
+
+use crate::watch::Expression;
+
+/*
+    Automation scripts that drive a ROM alongside a run, one timed action
+    per statement. Statements are separated by `;` or new lines, and `#`
+    starts a comment:
+
+    at frame 60: press 5 for 3 frames
+    at frame 200: screenshot title.png
+    at frame 400: assert pixel 10,5 == 1; at frame 400: assert V3 > 0
+    at frame 600: quit
+
+    `press` holds a hex pad key, for one frame unless `for N frames` says
+    otherwise. `screenshot` takes an optional file name and defaults to
+    `screenshot-<frame>.png`. `assert` checks a pixel or any watch
+    expression. Actions run at the first frame boundary at or after their
+    frame, in the order they were written.
+*/
+pub enum Action {
+    Press { key: u8, frames: u64 },
+    Release(u8),
+    Screenshot(Option<PathBuf>),
+    AssertPixel { x: u16, y: u16, lit: bool },
+    Assert(String, Expression),
+    Quit,
+}
+
+pub struct Script {
+    actions: VecDeque<(u64, Action)>,
+}
+
+impl Script {
+    pub fn parse(source: &str) -> Result<Script, String> {
+        let mut script = Script { actions: VecDeque::new() };
+
+        let statements = source.lines()
+                               .map(|line| line.split('#').next().unwrap())
+                               .flat_map(|line| line.split(';'))
+                               .map(str::trim)
+                               .filter(|statement| !statement.is_empty());
+        for statement in statements {
+            let (frame, action) = Script::parse_statement(statement)
+                                         .map_err(|error| format!("`{}`: {}", statement, error))?;
+            script.schedule(frame, action);
+        }
+        Ok(script)
+    }
+
+    fn parse_statement(statement: &str) -> Result<(u64, Action), String> {
+        let (timing, action) = statement.split_once(':').ok_or("expected `at frame N: action`")?;
+        let frame = match timing.split_whitespace().collect::<Vec<&str>>()[..] {
+            ["at", "frame", frame] => frame.parse::<u64>().map_err(|_| format!("bad frame number `{}`", frame))?,
+            _ => return Err(String::from("expected `at frame N: action`")),
+        };
+
+        let action = action.trim();
+        let (verb, arguments) = action.split_once(char::is_whitespace).unwrap_or((action, ""));
+        let arguments = arguments.trim();
+        let action = match verb {
+            "press" => Script::parse_press(arguments)?,
+            "screenshot" if arguments.is_empty() => Action::Screenshot(None),
+            "screenshot" => Action::Screenshot(Some(PathBuf::from(arguments))),
+            "assert" => match arguments.strip_prefix("pixel") {
+                Some(pixel) => Script::parse_pixel(pixel)?,
+                None => Action::Assert(arguments.to_string(), Expression::parse(arguments)?),
+            },
+            "quit" if arguments.is_empty() => Action::Quit,
+            _ => return Err(format!("unknown action `{}`", action)),
+        };
+        Ok((frame, action))
+    }
+
+    // `5` or `5 for 3 frames`
+    fn parse_press(arguments: &str) -> Result<Action, String> {
+        let words: Vec<&str> = arguments.split_whitespace().collect();
+        let (key, frames) = match words[..] {
+            [key] => (key, "1"),
+            [key, "for", frames, "frame"] | [key, "for", frames, "frames"] => (key, frames),
+            _ => return Err(String::from("expected `press KEY` or `press KEY for N frames`")),
+        };
+
+        let key = u8::from_str_radix(key, 16).ok()
+                                             .filter(|&key| key < 16)
+                                             .ok_or(format!("`{}` isn't a hex pad key", key))?;
+        let frames = frames.parse::<u64>().ok()
+                                          .filter(|&frames| frames > 0)
+                                          .ok_or(format!("bad frame count `{}`", frames))?;
+        Ok(Action::Press { key, frames })
+    }
+
+    // ` 10,5 == 1`
+    fn parse_pixel(arguments: &str) -> Result<Action, String> {
+        let (position, value) = arguments.split_once("==").ok_or("expected `assert pixel X,Y == 0|1`")?;
+        let (x, y) = position.split_once(',').ok_or("expected `assert pixel X,Y == 0|1`")?;
+        let x = x.trim().parse::<u16>().map_err(|_| format!("bad x coordinate `{}`", x.trim()))?;
+        let y = y.trim().parse::<u16>().map_err(|_| format!("bad y coordinate `{}`", y.trim()))?;
+        let lit = match value.trim() {
+            "0" => false,
+            "1" => true,
+            value => return Err(format!("a pixel is 0 or 1, not `{}`", value)),
+        };
+        Ok(Action::AssertPixel { x, y, lit })
+    }
+
+    /*
+        Queue an action, after any others already due on the same frame.
+    */
+    pub fn schedule(&mut self, frame: u64, action: Action) {
+        let index = self.actions.iter().position(|&(due, _)| due > frame).unwrap_or(self.actions.len());
+        self.actions.insert(index, (frame, action));
+    }
+
+    /*
+        The next action that is due by `frame`, with the frame it was
+        scheduled for.
+    */
+    pub fn next_due(&mut self, frame: u64) -> Option<(u64, Action)> {
+        match self.actions.front() {
+            Some(&(due, _)) if due <= frame => self.actions.pop_front(),
+            _ => None,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.actions.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn statements_run_in_frame_order_then_written_order() {
+        let mut script = Script::parse("at frame 400: assert pixel 10,5 == 1; at frame 60: press 5 for 3 frames\n\
+                                        # a comment\n\
+                                        at frame 400: assert V3 > 0  # and another\n\
+                                        at frame 200: screenshot title.png\n\
+                                        at frame 600: quit").unwrap();
+        assert!(script.next_due(59).is_none());
+        assert!(matches!(script.next_due(60), Some((60, Action::Press { key: 5, frames: 3 }))));
+        assert!(matches!(script.next_due(1000), Some((200, Action::Screenshot(Some(path)))) if path == std::path::Path::new("title.png")));
+        assert!(matches!(script.next_due(1000), Some((400, Action::AssertPixel { x: 10, y: 5, lit: true }))));
+        assert!(matches!(script.next_due(1000), Some((400, Action::Assert(source, _))) if source == "V3 > 0"));
+        assert!(matches!(script.next_due(1000), Some((600, Action::Quit))));
+        assert!(script.is_finished());
+    }
+
+    #[test]
+    fn actions_take_their_defaults() {
+        let mut script = Script::parse("at frame 1: press f\nat frame 2: press A for 1 frame\nat frame 3: screenshot").unwrap();
+        assert!(matches!(script.next_due(1), Some((1, Action::Press { key: 0xF, frames: 1 }))));
+        assert!(matches!(script.next_due(2), Some((2, Action::Press { key: 0xA, frames: 1 }))));
+        assert!(matches!(script.next_due(3), Some((3, Action::Screenshot(None)))));
+    }
+
+    #[test]
+    fn malformed_statements_are_errors() {
+        for source in ["press 5", "at 60: press 5", "at frame x: quit", "at frame 1: jump", "at frame 1: quit now",
+                       "at frame 1: press 10", "at frame 1: press 5 for 0 frames", "at frame 1: press 5 for 2",
+                       "at frame 1: assert pixel 10 == 1", "at frame 1: assert pixel 10,5 == 2",
+                       "at frame 1: assert pixel -1,5 == 1", "at frame 1: assert V3 >"] {
+            assert!(Script::parse(source).is_err(), "`{}` parsed", source);
+        }
+        assert_eq!(Script::parse("at frame 1: jump").err().unwrap(), "`at frame 1: jump`: unknown action `jump`");
+    }
+}
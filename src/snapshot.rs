@@ -0,0 +1,174 @@
+use std::collections::VecDeque;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::checksum;
+use crate::chip8::Chip8;
+use crate::display::Display;
+use crate::main_memory::MainMemory;
+use crate::quirks::Quirks;
+use crate::registers::Registers;
+use crate::savestate::{StateReader, StateWriter};
+use crate::scheduler::Scheduler;
+use crate::stack::Stack;
+use crate::variant::Variant;
+
+/// Everything that decides how the machine runs from here on, taken
+/// between two scheduler events. Restoring one and running forward replays
+/// the same instructions, random numbers included, as long as the keys
+/// don't change along the way.
+#[derive(Clone)]
+pub struct Snapshot {
+    pub registers: Registers,
+    pub stack: Stack,
+    pub main_memory: MainMemory,
+    pub display: Display,
+    pub rpl_flags: [u8; Chip8::NUM_RPL_FLAGS],
+    pub planes: u8,
+    pub waiting_on_key: i8,
+    pub presses_while_waiting: u16,
+    pub key_pressed: [bool; Chip8::NUM_KEYS as usize],
+    pub key_pressed_last_frame: [bool; Chip8::NUM_KEYS as usize],
+    pub key_edges: [bool; Chip8::NUM_KEYS as usize],
+    pub scheduler: Scheduler,
+    pub waiting_on_vblank: bool,
+    pub cycles_executed: u64,
+    pub frames_elapsed: u64,
+    pub rng: StdRng,
+    pub variant: Variant,
+    pub quirks: Quirks,
+}
+
+impl Snapshot {
+    /// The random number generator's state can't be written out, so a
+    /// saved snapshot carries a seed for a new one drawn from it instead.
+    /// Loading the same state twice still replays the same numbers.
+    pub fn save(&self, out: &mut StateWriter) {
+        self.write(out, true);
+    }
+
+    /// A SHA-1 of the emulated machine, for checking that two runs went
+    /// the same way. The scheduler's clock is left out, since a windowed
+    /// run advances it by however long the host happened to take, and so
+    /// is the random number generator, which is seeded differently every
+    /// run; a program that draws random numbers shows the difference in
+    /// its registers soon enough.
+    pub fn hash(&self) -> [u8; 20] {
+        let mut out = StateWriter::new();
+        self.write(&mut out, false);
+        checksum::sha1(&out.into_bytes())
+    }
+
+    // Everything, or for hashing, everything but the clock and the generator
+    fn write(&self, out: &mut StateWriter, complete: bool) {
+        self.registers.save(out);
+        self.stack.save(out);
+        self.main_memory.save(out);
+        self.display.save(out);
+        out.u8(self.waiting_on_key as u8);
+        out.u16(self.presses_while_waiting);
+        out.bools(&self.key_pressed);
+        out.bools(&self.key_pressed_last_frame);
+        out.bools(&self.key_edges);
+        if complete {
+            self.scheduler.save(out);
+        }
+        out.bool(self.waiting_on_vblank);
+        out.u64(self.cycles_executed);
+        out.u64(self.frames_elapsed);
+        if complete {
+            out.u64(self.rng.clone().gen());
+        }
+        self.variant.save(out);
+        self.quirks.save(out);
+        out.bytes(&self.rpl_flags);
+        out.u8(self.planes);
+        out.bool(self.quirks.wrap_sprites);
+    }
+
+    pub fn load(input: &mut StateReader) -> Result<Snapshot, String> {
+        let registers = Registers::load(input)?;
+        let stack = Stack::load(input)?;
+        let main_memory = MainMemory::load(input)?;
+        let display = Display::load(input)?;
+        let waiting_on_key = input.u8()? as i8;
+        if !(-1..Chip8::NUM_KEYS as i8).contains(&waiting_on_key) {
+            return Err(format!("waiting on key V{}", waiting_on_key));
+        }
+        let presses_while_waiting = input.u16()?;
+        let mut key_pressed = [false; Chip8::NUM_KEYS as usize];
+        input.fill_bools(&mut key_pressed)?;
+        let mut key_pressed_last_frame = [false; Chip8::NUM_KEYS as usize];
+        input.fill_bools(&mut key_pressed_last_frame)?;
+        let mut key_edges = [false; Chip8::NUM_KEYS as usize];
+        input.fill_bools(&mut key_edges)?;
+        let mut snapshot = Snapshot {
+            registers,
+            stack,
+            main_memory,
+            display,
+            rpl_flags: [0; Chip8::NUM_RPL_FLAGS],
+            planes: 0,
+            waiting_on_key,
+            presses_while_waiting,
+            key_pressed,
+            key_pressed_last_frame,
+            key_edges,
+            scheduler: Scheduler::load(input)?,
+            waiting_on_vblank: input.bool()?,
+            cycles_executed: input.u64()?,
+            frames_elapsed: input.u64()?,
+            rng: StdRng::seed_from_u64(input.u64()?),
+            variant: Variant::load(input)?,
+            quirks: Quirks::load(input)?,
+        };
+        input.fill(&mut snapshot.rpl_flags)?;
+        snapshot.planes = input.u8()? & 0x3;
+        snapshot.quirks.wrap_sprites = input.bool()?;
+        Ok(snapshot)
+    }
+}
+
+/// Snapshots at regular instruction counts, the newest few kept, as
+/// starting points for replaying to an earlier instruction.
+pub struct Journal {
+    snapshots: VecDeque<Snapshot>,
+}
+
+impl Journal {
+    pub const INTERVAL: u64 = 1000;
+    pub const LENGTH: usize = 64;
+
+    pub fn new() -> Journal {
+        Journal { snapshots: VecDeque::new() }
+    }
+
+    /// Whether a snapshot is due once this many instructions have run.
+    pub fn is_due(&self, cycles_executed: u64) -> bool {
+        self.snapshots.back().is_none_or(|last| cycles_executed >= last.cycles_executed + Journal::INTERVAL)
+    }
+
+    pub fn record(&mut self, snapshot: Snapshot) {
+        if self.snapshots.len() == Journal::LENGTH {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(snapshot);
+    }
+
+    /// The newest snapshot taken at or before `cycles_executed`. Later
+    /// snapshots are dropped, since the machine is about to go back in time
+    /// and may take a different path from there.
+    pub fn rewind(&mut self, cycles_executed: u64) -> Option<Snapshot> {
+        while self.snapshots.back().is_some_and(|last| last.cycles_executed > cycles_executed) {
+            self.snapshots.pop_back();
+        }
+        self.snapshots.back().cloned()
+    }
+}
+
+impl Default for Journal {
+    fn default() -> Journal {
+        Journal::new()
+    }
+}
@@ -1,14 +1,15 @@
+use crate::error::Chip8Error;
+use crate::savestate::{StateReader, StateWriter};
 
-/*
-    The CHIP-8 stack and stack pointer.
-*/
+/// The CHIP-8 stack and stack pointer.
+#[derive(Clone)]
 pub struct Stack {
     data: [u16; Stack::NUM_FRAMES],
     pointer: usize,
 }
 
 impl Stack {
-    const NUM_FRAMES: usize = 16;
+    pub const NUM_FRAMES: usize = 16;
 
     pub fn new() -> Stack {
         Stack {
@@ -17,21 +18,52 @@ impl Stack {
         }
     }
 
-    pub fn push(&mut self, data: u16) {
+    pub fn push(&mut self, data: u16) -> Result<(), Chip8Error> {
         if self.pointer >= Stack::NUM_FRAMES {
-            panic!("Stack Overflow!");
+            return Err(Chip8Error::StackOverflow);
         }
         self.data[self.pointer] = data;
         self.pointer += 1;
+        Ok(())
     }
 
-    pub fn pop(&mut self) -> u16 {
+    /// The return addresses currently on the stack, oldest first.
+    pub fn frames(&self) -> &[u16] {
+        &self.data[..self.pointer]
+    }
+
+    pub fn save(&self, out: &mut StateWriter) {
+        out.u8(self.pointer as u8);
+        for &frame in self.frames() {
+            out.u16(frame);
+        }
+    }
+
+    pub fn load(input: &mut StateReader) -> Result<Stack, String> {
+        let mut stack = Stack::new();
+        let depth = input.u8()? as usize;
+        if depth > Stack::NUM_FRAMES {
+            return Err(format!("a stack {} frames deep", depth));
+        }
+        for _ in 0..depth {
+            stack.push(input.u16()?).expect("The depth was checked.");
+        }
+        Ok(stack)
+    }
+
+    pub fn pop(&mut self) -> Result<u16, Chip8Error> {
         if self.pointer == 0 {
-            panic!("Attempted pop from empty stack");
+            return Err(Chip8Error::StackUnderflow);
         }
 
         let val = self.data[self.pointer - 1];
         self.pointer -= 1;
-        val
+        Ok(val)
+    }
+}
+
+impl Default for Stack {
+    fn default() -> Stack {
+        Stack::new()
     }
 }
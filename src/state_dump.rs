@@ -0,0 +1,48 @@
+use crate::capture;
+use crate::chip8::Chip8;
+
+/*
+    A plain-text snapshot of the whole machine, written so that two dumps
+    taken at the same point can be compared with `diff`: one field per line,
+    then program memory in rows of 16 bytes, then the display as ASCII art.
+    The font below 0x200 never changes and is left out. Everything comes
+    from the machine's `MachineState`.
+*/
+const PROGRAM_START: usize = 0x200;
+const BYTES_PER_ROW: usize = 16;
+
+pub fn render(machine: &Chip8) -> String {
+    let state = machine.state(true);
+    let mut lines = Vec::new();
+
+    lines.push(format!("variant {}", state.variant.name()));
+    lines.push(format!("instructions {}", state.instructions));
+    lines.push(format!("frames {}", state.frames));
+    lines.push(format!("pc {:#06X}", state.pc));
+    lines.push(format!("i {:#06X}", state.i));
+    lines.push(format!("dt {:#04X}", state.delay_timer));
+    lines.push(format!("st {:#04X}", state.sound_timer));
+    for (register, value) in state.v.iter().enumerate() {
+        lines.push(format!("v{:X} {:#04X}", register, value));
+    }
+
+    let frames: Vec<String> = state.stack.iter().map(|frame| format!("{:#06X}", frame)).collect();
+    lines.push(format!("stack [{}]", frames.join(" ")));
+    let keys: Vec<String> = (0..16).filter(|&key| state.key_held(key)).map(|key| format!("{:X}", key)).collect();
+    lines.push(format!("keys [{}]", keys.join(" ")));
+    lines.push(format!("waiting_on_key {}", state.waiting_on_key));
+
+    lines.push(String::from("memory"));
+    let memory = state.memory.as_ref().expect("The state was taken with memory.");
+    for base in (PROGRAM_START..memory.len()).step_by(BYTES_PER_ROW) {
+        let bytes: Vec<String> = memory[base..(base + BYTES_PER_ROW)].iter().map(|byte| format!("{:02X}", byte)).collect();
+        lines.push(format!("{:#06X} {}", base, bytes.join(" ")));
+    }
+
+    lines.push(String::from("display"));
+    let mut dump = lines.join("\n");
+    dump.push('\n');
+    let display = state.display.as_ref().expect("The state was taken with the display.");
+    dump.push_str(&capture::to_ascii(display.chunks(machine.display.width())));
+    dump
+}
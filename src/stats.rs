@@ -0,0 +1,42 @@
+use std::fmt;
+use std::time::Duration;
+
+use crate::stack::Stack;
+
+/// Counters for a run, kept by the core as it goes. They count work done
+/// rather than describe the machine, so loading a state or stepping back
+/// leaves them alone.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Stats {
+    pub instructions: u64,
+    pub frames: u64,
+    pub draws: u64,
+    // Draws that turned at least one pixel off
+    pub collisions: u64,
+    // The deepest the stack got
+    pub stack_high_water: usize,
+    pub random_calls: u64,
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "=> Stats:")?;
+        writeln!(f, "  instructions  {}", self.instructions)?;
+        writeln!(f, "  frames        {}", self.frames)?;
+        writeln!(f, "  draws         {}", self.draws)?;
+        writeln!(f, "  collisions    {}", self.collisions)?;
+        writeln!(f, "  stack depth   {} of {}", self.stack_high_water, Stack::NUM_FRAMES)?;
+        writeln!(f, "  random calls  {}", self.random_calls)
+    }
+}
+
+/// Where the time went in the instructions executed, for `yac8 bench`.
+/// Reading the clock three times an instruction costs about as much as the
+/// cheaper phases, so compare profiles with each other rather than
+/// trusting the absolute numbers.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Profile {
+    pub fetch: Duration,
+    pub decode: Duration,
+    pub execute: Duration,
+}
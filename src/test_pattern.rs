@@ -0,0 +1,186 @@
+use crate::disassembler::PROGRAM_OFFSET;
+use crate::keymap::Keymap;
+
+/*
+    Built-in diagnostic programs for checking a new setup without a ROM:
+    scaling and the palette with a checkerboard or a fully lit screen,
+    refresh with a box that moves a pixel every frame, and the key mapping
+    with a keypad that lights up whichever keys are held. They're ordinary
+    CHIP-8 programs, assembled here, so they go through the same machine,
+    window and input path as any game.
+*/
+pub const NAMES: [&str; 4] = ["checkerboard", "all-on", "moving-sprite", "keypad"];
+
+// The keypad diagram fills the screen with a 16x8 cell per key
+const CELL_WIDTH: u16 = 16;
+const CELL_HEIGHT: u16 = 8;
+
+pub fn rom(name: &str) -> Option<Vec<u8>> {
+    match name {
+        "checkerboard" => Some(fill(&[0xAA, 0x55, 0xAA, 0x55, 0xAA, 0x55, 0xAA, 0x55])),
+        "all-on" => Some(fill(&[0xFF; 8])),
+        "moving-sprite" => Some(moving_sprite()),
+        "keypad" => Some(keypad()),
+        _ => None,
+    }
+}
+
+/*
+    A program under construction. Jumps and `LD I` to places that haven't
+    been written yet are emitted with no address and pointed at the right
+    place once it's reached.
+*/
+struct Program {
+    rom: Vec<u8>,
+}
+
+impl Program {
+    fn new() -> Program {
+        Program { rom: Vec::new() }
+    }
+
+    fn here(&self) -> u16 {
+        PROGRAM_OFFSET + self.rom.len() as u16
+    }
+
+    // Returns where the instruction went, for `resolve`
+    fn emit(&mut self, opcode: u16) -> usize {
+        self.rom.extend_from_slice(&opcode.to_be_bytes());
+        self.rom.len() - 2
+    }
+
+    // Point the address of the instruction at `at` here
+    fn resolve(&mut self, at: usize) {
+        self.point(at, self.here());
+    }
+
+    fn point(&mut self, at: usize, address: u16) {
+        self.rom[at] = self.rom[at] & 0xF0 | (address >> 8) as u8;
+        self.rom[at + 1] = address as u8;
+    }
+
+    fn data(&mut self, bytes: &[u8]) {
+        self.rom.extend_from_slice(bytes);
+    }
+}
+
+/*
+    Tile the screen with an 8x8 sprite, then stop.
+*/
+fn fill(sprite: &[u8; 8]) -> Vec<u8> {
+    let mut program = Program::new();
+    let load_sprite = program.emit(0xA000);
+    program.emit(0x6000);                       // V0 = x
+    program.emit(0x6100);                       // V1 = y
+    let tile = program.here();
+    program.emit(0xD018);
+    program.emit(0x7008);
+    program.emit(0x3040);                       // End of the row?
+    program.emit(0x1000 | tile);
+    program.emit(0x6000);
+    program.emit(0x7108);
+    program.emit(0x3120);                       // End of the screen?
+    program.emit(0x1000 | tile);
+    let end = program.here();
+    program.emit(0x1000 | end);
+
+    program.resolve(load_sprite);
+    program.data(sprite);
+    program.rom
+}
+
+/*
+    An outlined 8x8 box crossing the middle of the screen a pixel per
+    frame, wrapping around at the right edge. Uneven motion or tearing
+    shows up as the box stuttering or splitting.
+*/
+fn moving_sprite() -> Vec<u8> {
+    let mut program = Program::new();
+    let load_sprite = program.emit(0xA000);
+    program.emit(0x6000);                       // V0 = x
+    program.emit(0x610C);                       // V1 = y
+    program.emit(0x623F);                       // V2 = the mask that wraps x
+    program.emit(0x6301);
+    let frame = program.here();
+    program.emit(0xD018);
+    program.emit(0xF315);                       // Wait for the next frame
+    let wait = program.here();
+    program.emit(0xF407);
+    program.emit(0x3400);
+    program.emit(0x1000 | wait);
+    program.emit(0xD018);                       // Erase, move and wrap
+    program.emit(0x7001);
+    program.emit(0x8022);
+    program.emit(0x1000 | frame);
+
+    program.resolve(load_sprite);
+    program.data(&[0xFF, 0x81, 0x81, 0x81, 0x81, 0x81, 0x81, 0xFF]);
+    program.rom
+}
+
+/*
+    The hex keypad laid out as on the COSMAC VIP, with each key's digit
+    inverted while it's held. Whether each key is lit is kept in memory, and
+    a key's box is only drawn when that changes, so nothing flickers. Eight
+    keys' flags are loaded into V0-V7 at a time, leaving V8-VA to work with.
+*/
+fn keypad() -> Vec<u8> {
+    let mut program = Program::new();
+    let position = |index: usize| ((index as u16 % 4) * CELL_WIDTH, (index as u16 / 4) * CELL_HEIGHT);
+
+    for (index, &key) in Keymap::PAD_ORDER.iter().enumerate() {
+        let (x, y) = position(index);
+        program.emit(0x6000 | key as u16);
+        program.emit(0xF029);
+        program.emit(0x6100 | (x + 6));
+        program.emit(0x6200 | (y + 1));
+        program.emit(0xD125);
+    }
+
+    let mut load_flags = Vec::new();
+    let mut load_box = Vec::new();
+    let poll = program.here();
+    for half in 0..2 {
+        load_flags.push((program.emit(0xA000), half));
+        program.emit(0xF765);
+        for register in 0..8 {
+            let index = 8 * half + register;
+            let (x, y) = position(index);
+            let flag = (register as u16) << 8;
+            program.emit(0x6800 | Keymap::PAD_ORDER[index] as u16);
+            program.emit(0xE8A1);                   // Skip if not held
+            let to_held = program.emit(0x1000);
+            program.emit(0x3000 | flag);            // Not held: toggle if lit
+            let to_toggle = program.emit(0x1000);
+            let to_next = program.emit(0x1000);
+            program.resolve(to_held);
+            program.emit(0x3001 | flag);            // Held: toggle if not lit
+            let to_toggle_held = program.emit(0x1000);
+            let to_next_held = program.emit(0x1000);
+            program.resolve(to_toggle);
+            program.resolve(to_toggle_held);
+            program.emit(0x6901);
+            program.emit(0x8093 | flag);
+            load_box.push(program.emit(0xA000));
+            program.emit(0x6900 | (x + 4));
+            program.emit(0x6A00 | y);
+            program.emit(0xD9A7);
+            program.resolve(to_next);
+            program.resolve(to_next_held);
+        }
+        load_flags.push((program.emit(0xA000), half));
+        program.emit(0xF755);
+    }
+    program.emit(0x1000 | poll);
+
+    let flags = program.here();
+    for (at, half) in load_flags {
+        program.point(at, flags + 8 * half as u16);
+    }
+    program.data(&[0; 16]);
+    for at in load_box {
+        program.resolve(at);
+    }
+    program.data(&[0xFF; 7]);
+    program.rom
+}
@@ -0,0 +1,104 @@
+/*
+    A tiny bitmap font for drawing text with nothing but pixels, so debug
+    views don't need a font library. Each glyph is 3 pixels wide and 5 tall,
+    one row per byte with the leftmost pixel in bit 2, and sits in a 4x6
+    cell to leave a pixel of spacing. Lowercase letters are drawn as capitals
+    (except `x`, so hex literals read naturally) and anything without a
+    glyph is drawn as `?`.
+*/
+pub const CELL_WIDTH: usize = 4;
+pub const CELL_HEIGHT: usize = 6;
+
+const GLYPH_WIDTH: usize = 3;
+
+pub fn glyph(c: char) -> [u8; 5] {
+    match c.to_ascii_uppercase() {
+        _ if c == 'x' => [0, 5, 2, 5, 0],
+        '0' => [7, 5, 5, 5, 7],
+        '1' => [2, 6, 2, 2, 7],
+        '2' => [7, 1, 7, 4, 7],
+        '3' => [7, 1, 7, 1, 7],
+        '4' => [5, 5, 7, 1, 1],
+        '5' => [7, 4, 7, 1, 7],
+        '6' => [7, 4, 7, 5, 7],
+        '7' => [7, 1, 1, 2, 2],
+        '8' => [7, 5, 7, 5, 7],
+        '9' => [7, 5, 7, 1, 7],
+        'A' => [2, 5, 7, 5, 5],
+        'B' => [6, 5, 6, 5, 6],
+        'C' => [3, 4, 4, 4, 3],
+        'D' => [6, 5, 5, 5, 6],
+        'E' => [7, 4, 6, 4, 7],
+        'F' => [7, 4, 6, 4, 4],
+        'G' => [3, 4, 5, 5, 3],
+        'H' => [5, 5, 7, 5, 5],
+        'I' => [7, 2, 2, 2, 7],
+        'J' => [1, 1, 1, 5, 2],
+        'K' => [5, 5, 6, 5, 5],
+        'L' => [4, 4, 4, 4, 7],
+        'M' => [5, 7, 7, 5, 5],
+        'N' => [6, 5, 5, 5, 5],
+        'O' => [2, 5, 5, 5, 2],
+        'P' => [6, 5, 6, 4, 4],
+        'Q' => [2, 5, 5, 6, 3],
+        'R' => [6, 5, 6, 5, 5],
+        'S' => [3, 4, 2, 1, 6],
+        'T' => [7, 2, 2, 2, 2],
+        'U' => [5, 5, 5, 5, 7],
+        'V' => [5, 5, 5, 5, 2],
+        'W' => [5, 5, 7, 7, 5],
+        'X' => [5, 5, 2, 5, 5],
+        'Y' => [5, 5, 2, 2, 2],
+        'Z' => [7, 1, 2, 4, 7],
+        ' ' => [0, 0, 0, 0, 0],
+        ',' => [0, 0, 0, 2, 4],
+        '.' => [0, 0, 0, 0, 2],
+        ':' => [0, 2, 0, 2, 0],
+        ';' => [0, 2, 0, 2, 4],
+        '[' => [6, 4, 4, 4, 6],
+        ']' => [3, 1, 1, 1, 3],
+        '(' => [2, 4, 4, 4, 2],
+        ')' => [2, 1, 1, 1, 2],
+        '-' => [0, 0, 7, 0, 0],
+        '+' => [0, 2, 7, 2, 0],
+        '=' => [0, 7, 0, 7, 0],
+        '<' => [1, 2, 4, 2, 1],
+        '>' => [4, 2, 1, 2, 4],
+        '#' => [5, 7, 5, 7, 5],
+        '/' => [1, 1, 2, 4, 4],
+        '_' => [0, 0, 0, 0, 7],
+        '*' => [0, 5, 2, 5, 0],
+        '!' => [2, 2, 2, 0, 2],
+        '%' => [5, 1, 2, 4, 5],
+        '|' => [2, 2, 2, 2, 2],
+        '\'' => [2, 2, 0, 0, 0],
+        '"' => [5, 5, 0, 0, 0],
+        _ => [7, 1, 2, 0, 2],
+    }
+}
+
+/*
+    Rasterize lines of text into a `columns` by `rows` grid of cells, one
+    byte per pixel (1 for ink, 0 for background). Text past the edges of the
+    grid is cut off.
+*/
+pub fn rasterize(lines: &[String], columns: usize, rows: usize) -> Vec<u8> {
+    let width = columns * CELL_WIDTH;
+    let mut pixels = vec![0; width * rows * CELL_HEIGHT];
+
+    for (row, line) in lines.iter().take(rows).enumerate() {
+        for (column, c) in line.chars().take(columns).enumerate() {
+            for (glyph_y, bits) in glyph(c).iter().enumerate() {
+                for glyph_x in 0..GLYPH_WIDTH {
+                    if (bits >> (GLYPH_WIDTH - 1 - glyph_x)) & 1 == 1 {
+                        let x = column * CELL_WIDTH + glyph_x;
+                        let y = row * CELL_HEIGHT + glyph_y;
+                        pixels[y * width + x] = 1;
+                    }
+                }
+            }
+        }
+    }
+
+    pixels
+}
@@ -0,0 +1,163 @@
+use std::io::{self, Write};
+
+use crate::instructions::Instruction;
+use crate::registers::Registers;
+
+/// Per-instruction execution tracing. Every executed instruction is written
+/// out as one line, either as plain text or as a JSON object, so a run can be
+/// analyzed with jq, Python and friends without scraping the verbose log.
+///
+/// A JSON line looks like:
+///
+/// ```text
+/// {"cycle":7,"pc":526,"opcode":28674,"mnemonic":"ADD V0, 0x02","changed":{"V0":[4,6]}}
+/// ```
+///
+/// where each `changed` entry holds the register's value before and after
+/// the instruction executed.
+///
+/// The teaching format is for people learning CHIP-8 rather than tools: it
+/// spells each instruction out in English, with its changes underneath.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TraceFormat {
+    Text,
+    Json,
+    Teach,
+}
+
+impl TraceFormat {
+    pub fn from_name(name: &str) -> Option<TraceFormat> {
+        match name {
+            "text" => Some(TraceFormat::Text),
+            "json" => Some(TraceFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+pub struct Tracer {
+    format: TraceFormat,
+    out: Box<dyn Write>,
+}
+
+impl Tracer {
+    pub fn new(format: TraceFormat, out: Box<dyn Write>) -> Tracer {
+        Tracer {
+            format,
+            out,
+        }
+    }
+
+    pub fn record(&mut self, cycle: u64, pc: u16, instruction: &Instruction, before: &Registers,
+                  after: &Registers) -> io::Result<()> {
+        let opcode = instruction.encode();
+        let changes = Tracer::register_changes(before, after);
+
+        let line = match self.format {
+            TraceFormat::Text => {
+                let changed: Vec<String> = changes.iter()
+                                                  .map(|(name, old, new)| format!("{}: {:#X} -> {:#X}", name, old, new))
+                                                  .collect();
+                format!("{:>8} {:#06X} {:#06X} {:<16} {}", cycle, pc, opcode, instruction, changed.join(", "))
+            },
+            TraceFormat::Json => {
+                let changed: Vec<String> = changes.iter()
+                                                  .map(|(name, old, new)| format!("{}:[{},{}]", json_string(name), old, new))
+                                                  .collect();
+                format!("{{\"cycle\":{},\"pc\":{},\"opcode\":{},\"mnemonic\":{},\"changed\":{{{}}}}}",
+                        cycle, pc, opcode, json_string(&instruction.to_string()), changed.join(","))
+            },
+            TraceFormat::Teach => {
+                let mut lines = vec![format!("{:#06X}  {:04X}  {}", pc, opcode, instruction),
+                                     format!("              {}.", instruction.explain())];
+                lines.extend(changes.iter().map(|(name, old, new)| format!("              {}: {:#X} -> {:#X}", name, old, new)));
+                lines.join("\n")
+            },
+        };
+
+        writeln!(self.out, "{}", line.trim_end())
+    }
+
+    pub fn register_changes(before: &Registers, after: &Registers) -> Vec<(String, u16, u16)> {
+        let mut changes = Vec::new();
+
+        for register in 0..Registers::NUM_DATA_REGISTERS {
+            let old = before.data()[register as usize];
+            let new = after.data()[register as usize];
+            if old != new {
+                changes.push((format!("V{:X}", register), old as u16, new as u16));
+            }
+        }
+        if before.i_register != after.i_register {
+            changes.push((String::from("I"), before.i_register, after.i_register));
+        }
+        if before.delay_timer != after.delay_timer {
+            changes.push((String::from("DT"), before.delay_timer as u16, after.delay_timer as u16));
+        }
+        if before.sound_timer != after.sound_timer {
+            changes.push((String::from("ST"), before.sound_timer as u16, after.sound_timer as u16));
+        }
+
+        changes
+    }
+}
+
+// A JSON string literal, quotes included
+fn json_string(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for character in text.chars() {
+        match character {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            character if character.is_control() => quoted.push_str(&format!("\\u{:04x}", character as u32)),
+            character => quoted.push(character),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+
+    // Somewhere to trace to that the test can still read afterwards
+    #[derive(Clone, Default)]
+    struct Buffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(data)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn json_lines_hold_each_change_as_old_and_new() {
+        let buffer = Buffer::default();
+        let mut tracer = Tracer::new(TraceFormat::Json, Box::new(buffer.clone()));
+        let mut before = Registers::new();
+        before.write_data_register(0, 4).unwrap();
+        let mut after = before.clone();
+        after.write_data_register(0, 6).unwrap();
+
+        tracer.record(7, 526, &Instruction::AddData(0, 2), &before, &after).unwrap();
+        assert_eq!(String::from_utf8(buffer.0.borrow().clone()).unwrap(),
+                   "{\"cycle\":7,\"pc\":526,\"opcode\":28674,\"mnemonic\":\"ADD V0, 0x02\",\"changed\":{\"V0\":[4,6]}}\n");
+    }
+
+    #[test]
+    fn json_strings_are_escaped() {
+        assert_eq!(json_string("ADD V0, 0x02"), "\"ADD V0, 0x02\"");
+        assert_eq!(json_string("a \"b\" \\ c\n\u{1}"), "\"a \\\"b\\\" \\\\ c\\n\\u0001\"");
+    }
+}
@@ -0,0 +1,97 @@
+use std::io::{self, Read, Write};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+use crate::display::Display;
+
+/*
+    The terminal as a screen for --tui, for playing over SSH or anywhere
+    without SDL. Two rows of pixels share each line of text through the
+    half-block characters, so the 64x32 display takes 64 columns by 16
+    lines. The terminal is put in raw mode with `stty` and drawn on with
+    plain ANSI escapes, which keeps yac8 free of terminal libraries.
+    Dropping the terminal puts everything back.
+*/
+pub struct Terminal {
+    // What `stty -g` said before raw mode, to restore it
+    saved_mode: String,
+    input: Receiver<u8>,
+    // The last frame and status drawn, so they aren't redrawn unchanged
+    shown: (Vec<u8>, String),
+    out: io::Stdout,
+}
+
+impl Terminal {
+    pub fn open() -> io::Result<Terminal> {
+        let saved_mode = stty(&["-g"])?;
+        stty(&["raw", "-echo"])?;
+
+        let (sender, input) = mpsc::channel();
+        thread::spawn(move || {
+            for byte in io::stdin().lock().bytes().map_while(Result::ok) {
+                if sender.send(byte).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut out = io::stdout();
+        // The alternate screen, with the cursor hidden
+        write!(out, "\x1b[?1049h\x1b[?25l\x1b[2J")?;
+        out.flush()?;
+        Ok(Terminal { saved_mode: saved_mode.trim().to_string(), input, shown: (Vec::new(), String::new()), out })
+    }
+
+    // Every byte typed since the last call
+    pub fn keys(&self) -> Vec<u8> {
+        self.input.try_iter().collect()
+    }
+
+    pub fn draw(&mut self, display: &Display, status: &str) -> io::Result<()> {
+        if self.shown.0 == display.pixels() && self.shown.1 == status {
+            return Ok(());
+        }
+        let width = display.width();
+        // A change of resolution leaves the old frame's edges behind
+        let mut frame = String::from(if self.shown.0.len() == display.pixels().len() { "\x1b[H" } else { "\x1b[2J\x1b[H" });
+        for rows in display.pixels().chunks(2 * width) {
+            let (top, bottom) = rows.split_at(width);
+            frame.extend(top.iter().zip(bottom).map(|(&top, &bottom)| match (top != 0, bottom != 0) {
+                (true, true) => '█',
+                (true, false) => '▀',
+                (false, true) => '▄',
+                (false, false) => ' ',
+            }));
+            frame.push_str("\r\n");
+        }
+        // Clear what's left of the line in case the last status was longer
+        frame.push_str(status);
+        frame.push_str("\x1b[K");
+        self.out.write_all(frame.as_bytes())?;
+        self.out.flush()?;
+        self.shown = (display.pixels().to_vec(), String::from(status));
+        Ok(())
+    }
+
+    pub fn beep(&mut self) -> io::Result<()> {
+        self.out.write_all(b"\x07")?;
+        self.out.flush()
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let _ = write!(self.out, "\x1b[?25h\x1b[?1049l");
+        let _ = self.out.flush();
+        let _ = stty(&[&self.saved_mode]);
+    }
+}
+
+fn stty(arguments: &[&str]) -> io::Result<String> {
+    let output = Command::new("stty").args(arguments).stdin(Stdio::inherit()).output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(String::from_utf8_lossy(&output.stderr).trim().to_string()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
@@ -0,0 +1,78 @@
+use std::fmt;
+
+use crate::main_memory::MainMemory;
+use crate::quirks::Quirks;
+use crate::savestate::{StateReader, StateWriter};
+use crate::scan::Extension;
+
+/// The interpreter a program was written for. The variant decides which
+/// extension opcodes exist at all, while its quirks preset decides how the
+/// shared instructions behave. Opcodes from an extension the selected
+/// variant doesn't have are rejected with an error naming the variant that
+/// does, rather than as unknown instructions.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Variant {
+    Chip8,
+    Chip48,
+    SuperChip,
+    XoChip,
+}
+
+impl Variant {
+    pub const ALL: [Variant; 4] = [Variant::Chip8, Variant::Chip48, Variant::SuperChip, Variant::XoChip];
+    pub const NAMES: [&'static str; 4] = ["chip8", "chip48", "schip", "xochip"];
+
+    pub fn from_name(name: &str) -> Option<Variant> {
+        Variant::ALL.iter().copied().find(|variant| variant.name() == name)
+    }
+
+    pub fn save(&self, out: &mut StateWriter) {
+        out.u8(Variant::ALL.iter().position(|variant| variant == self).unwrap() as u8);
+    }
+
+    pub fn load(input: &mut StateReader) -> Result<Variant, String> {
+        let index = input.u8()?;
+        Variant::ALL.get(index as usize).copied().ok_or_else(|| format!("unknown variant {}", index))
+    }
+
+    /// The short name used on the command line and for quirks presets.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Variant::Chip8 => "chip8",
+            Variant::Chip48 => "chip48",
+            Variant::SuperChip => "schip",
+            Variant::XoChip => "xochip",
+        }
+    }
+
+    pub fn supports(&self, extension: Extension) -> bool {
+        match self {
+            Variant::Chip8 | Variant::Chip48 => false,
+            Variant::SuperChip => extension == Extension::SuperChip,
+            Variant::XoChip => true,
+        }
+    }
+
+    // XO-CHIP has 64K of memory, everything before it 4K
+    pub fn memory_size(&self) -> usize {
+        match self {
+            Variant::XoChip => MainMemory::XO_CHIP_MEMORY_SIZE,
+            _ => MainMemory::MEMORY_SIZE,
+        }
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        Quirks::preset(self.name()).expect("Every variant has a quirks preset.")
+    }
+}
+
+impl fmt::Display for Variant {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Variant::Chip8 => write!(f, "CHIP-8"),
+            Variant::Chip48 => write!(f, "CHIP-48"),
+            Variant::SuperChip => write!(f, "SUPER-CHIP"),
+            Variant::XoChip => write!(f, "XO-CHIP"),
+        }
+    }
+}
@@ -0,0 +1,209 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::chip8::Chip8;
+use crate::display::Display;
+use crate::instructions::{self, Instruction, PATTERNS};
+use crate::machine_state::MachineState;
+use crate::quirks::Quirks;
+use crate::variant::Variant;
+
+/*
+    Golden test vectors: for every opcode pattern, a few random machine
+    states, one instruction executed from each, and the state that came
+    out, as recorded from this implementation. Checked back later they're
+    an instruction-level regression suite, and as JSON Lines they're a
+    machine-readable description of yac8's behaviour that other emulators
+    can test against.
+
+    The first line is a header naming the quirks and the seed; then comes
+    a line per vector:
+
+    {"pattern":"8XY4","opcode":"0x8124","mnemonic":"ADD V1, V2","before":{...},"after":{...}}
+
+    A state has the program counter, I, V0-VF, the timers, the stack, the
+    held keys as a bitmask, whether FX0A is waiting, the 32 bytes of
+    memory at 0x300 that I points into, and the lit pixels as [x, y]. An
+    instruction that faults has `{"error": "..."}` for its after state.
+
+    Vectors are generated from a seed, so checking a file regenerates them
+    from its header and compares them vector by vector. Each pattern draws
+    from a generator of its own, seeded from the file's seed and the
+    pattern, so adding a pattern adds its vectors and leaves every other
+    line as it was. RND draws from the machine's own generator, so CXNN
+    vectors always mask with 0x00.
+*/
+const FORMAT_VERSION: u32 = 1;
+const MEMORY_WINDOW: usize = 0x300;
+const MEMORY_WINDOW_SIZE: usize = 32;
+const PIXELS_BEFORE_DRAW: usize = 24;
+
+pub fn generate(variant: Option<Variant>, seed: u64, count: usize) -> Vec<String> {
+    let quirks = variant.map_or_else(Quirks::default, |variant| variant.quirks());
+    let mut lines = vec![format!("{{\"yac8_vectors\":{},\"variant\":\"{}\",\"seed\":{},\"count\":{}}}",
+                                 FORMAT_VERSION, variant.map_or("default", |variant| variant.name()), seed, count)];
+    for pattern in PATTERNS.iter() {
+        let mut rng = StdRng::seed_from_u64(pattern_seed(seed, pattern));
+        for _ in 0..count {
+            lines.push(vector(&mut rng, pattern, variant.unwrap_or(Variant::Chip8), quirks));
+        }
+    }
+    lines
+}
+
+// The seed mixed with an FNV-1a hash of the pattern, so no two patterns share a stream
+fn pattern_seed(seed: u64, pattern: &str) -> u64 {
+    pattern.bytes().fold(seed ^ 0xCBF2_9CE4_8422_2325, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x0100_0000_01B3))
+}
+
+/*
+    Compare a vector file against what the current implementation does,
+    returning the line numbers and patterns of the vectors that changed.
+    The header says how to regenerate them.
+*/
+pub fn check(file: &str) -> Result<Vec<(usize, String)>, String> {
+    let lines: Vec<&str> = file.lines().collect();
+    let header = lines.first().ok_or("the file is empty")?;
+    if header_field(header, "yac8_vectors") != Some(FORMAT_VERSION.to_string()) {
+        return Err(String::from("not a yac8 test vector file, or from another version"));
+    }
+    let variant = match header_field(header, "variant").ok_or("the header has no variant")?.as_str() {
+        "default" => None,
+        name => Some(Variant::from_name(name).ok_or_else(|| format!("unknown variant {}", name))?),
+    };
+    let parse = |name: &str| header_field(header, name).and_then(|value| value.parse().ok())
+                                                       .ok_or(format!("the header has no {}", name));
+    let expected = generate(variant, parse("seed")?, parse("count")? as usize);
+    if lines.len() != expected.len() {
+        return Err(format!("expected {} vectors but the file has {}", expected.len() - 1, lines.len() - 1));
+    }
+
+    let mut changed = Vec::new();
+    for (num, line) in lines.iter().enumerate().skip(1) {
+        if expected.get(num).map(String::as_str) != Some(*line) {
+            changed.push((num + 1, header_field(line, "pattern").unwrap_or_else(|| String::from("?"))));
+        }
+    }
+    Ok(changed)
+}
+
+/*
+    A string or number field from one of our own JSON lines. This is no
+    JSON parser; it only reads back what `generate` writes.
+*/
+fn header_field(line: &str, name: &str) -> Option<String> {
+    let start = line.find(&format!("\"{}\":", name))? + name.len() + 3;
+    let rest = &line[start..];
+    match rest.strip_prefix('"') {
+        Some(string) => string.split('"').next().map(String::from),
+        None => rest.split([',', '}']).next().map(String::from),
+    }
+}
+
+/*
+    A random opcode matching `pattern`. Patterns like 0NNN that overlap
+    others are drawn again until they decode as the pattern asked for.
+*/
+fn opcode_for(rng: &mut StdRng, pattern: &str) -> u16 {
+    loop {
+        let opcode = pattern.chars().fold(0, |opcode, c| {
+            let nibble = c.to_digit(16).unwrap_or_else(|| rng.gen_range(0, 16));
+            opcode << 4 | nibble as u16
+        });
+        if instructions::parse_opcode(opcode).pattern() == pattern {
+            return opcode;
+        }
+    }
+}
+
+fn vector(rng: &mut StdRng, pattern: &str, variant: Variant, quirks: Quirks) -> String {
+    let mut opcode = opcode_for(rng, pattern);
+    let x = ((opcode >> 8) & 0xF) as usize;
+    let y = ((opcode >> 4) & 0xF) as usize;
+
+    let mut v = [0; 16];
+    rng.fill(&mut v[..]);
+    let mut memory = vec![0; variant.memory_size()];
+    rng.fill(&mut memory[MEMORY_WINDOW..(MEMORY_WINDOW + MEMORY_WINDOW_SIZE)]);
+    let mut display = vec![0; Display::SIZE];
+    let stack_depth = if pattern == "00EE" { rng.gen_range(1, 4) } else { rng.gen_range(0, 4) };
+
+    // Make the interesting case likely: skips that skip, keys that exist and draws that collide
+    match instructions::parse_opcode(opcode) {
+        Instruction::SkipIfEQData(..) | Instruction::SkipIfNEData(..) if rng.gen() => {
+            opcode = opcode & 0xFF00 | v[x] as u16;
+        },
+        Instruction::SkipIfEQRegister(..) | Instruction::SkipIfNERegister(..) if rng.gen() => v[y] = v[x],
+        Instruction::SkipIfPressed(_) | Instruction::SkipIfNotPressed(_) | Instruction::LoadSprite(_) => {
+            v[x] = rng.gen_range(0, 16);
+        },
+        Instruction::Random(..) => opcode &= 0xFF00,
+        Instruction::Draw(..) | Instruction::ClearScreen => {
+            for _ in 0..PIXELS_BEFORE_DRAW {
+                display[rng.gen_range(0, Display::SIZE)] = 1;
+            }
+        },
+        _ => {},
+    }
+    memory[0x200] = (opcode >> 8) as u8;
+    memory[0x201] = opcode as u8;
+
+    let before = MachineState {
+        variant,
+        instructions: 0,
+        frames: 0,
+        v,
+        i: (MEMORY_WINDOW + rng.gen_range(0, MEMORY_WINDOW_SIZE / 2)) as u16,
+        pc: 0x200,
+        delay_timer: rng.gen(),
+        sound_timer: rng.gen(),
+        stack: (0..stack_depth).map(|_| rng.gen_range(0x100, 0x800) * 2).collect(),
+        keys: rng.gen(),
+        waiting_on_key: false,
+        memory: Some(memory),
+        display: Some(display),
+    };
+
+    let mut machine = Chip8::new(Vec::new(), 700.0);
+    machine.set_variant(variant);
+    machine.set_quirks(quirks);
+    machine.set_state(&before).expect("Generated states are always valid.");
+    let after = match machine.step() {
+        Ok(()) => state_json(&machine.state(true)),
+        Err(error) => format!("{{\"error\":\"{}\"}}", error.to_string().replace('"', "'")),
+    };
+
+    let instruction = instructions::parse_opcode(opcode);
+    format!("{{\"pattern\":\"{}\",\"opcode\":\"{:#06X}\",\"mnemonic\":\"{}\",\"before\":{},\"after\":{}}}",
+            pattern, opcode, instruction, state_json(&before), after)
+}
+
+fn state_json(state: &MachineState) -> String {
+    let join = |values: Vec<String>| values.join(",");
+    let memory = state.memory.as_ref().expect("Vector states have memory.");
+    let display = state.display.as_ref().expect("Vector states have a display.");
+    let width = if display.len() == Display::HIRES_SIZE { Display::HIRES_WIDTH } else { Display::WIDTH } as usize;
+    let lit = display.iter()
+                     .enumerate()
+                     .filter(|(_, &pixel)| pixel != 0)
+                     .map(|(num, _)| format!("[{},{}]", num % width, num / width))
+                     .collect();
+    format!("{{\"pc\":{},\"i\":{},\"v\":[{}],\"dt\":{},\"st\":{},\"stack\":[{}],\"keys\":{},\"waiting_on_key\":{},\
+             \"memory\":{{\"start\":{},\"bytes\":[{}]}},\"display\":[{}]}}",
+            state.pc, state.i, join(state.v.iter().map(u8::to_string).collect()), state.delay_timer, state.sound_timer,
+            join(state.stack.iter().map(u16::to_string).collect()), state.keys, state.waiting_on_key, MEMORY_WINDOW,
+            join(memory[MEMORY_WINDOW..(MEMORY_WINDOW + MEMORY_WINDOW_SIZE)].iter().map(u8::to_string).collect()),
+            join(lit))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Regenerate with `yac8 vectors --count 2 > src/vectors.jsonl` after a deliberate change in behaviour
+    #[test]
+    fn instructions_match_the_golden_vectors() {
+        let changed = check(include_str!("vectors.jsonl")).unwrap();
+        assert!(changed.is_empty(), "vectors changed at lines {:?}", changed);
+    }
+}
@@ -0,0 +1,116 @@
+//! A WebAssembly build of the core, for running yac8 in a browser. Build
+//! it with `cargo rustc --lib --crate-type cdylib --target
+//! wasm32-unknown-unknown --no-default-features --features wasm`;
+//! `web/index.html` is a page that loads the module and plays on a canvas.
+//!
+//! Like the libretro core, these are plain C ABI exports rather than
+//! wasm-bindgen glue, so the page instantiates the module directly and
+//! reads its memory. It copies a ROM into the buffer `rom_buffer` hands
+//! out and calls `load_rom`, then on every animation frame calls `cycle`
+//! with the milliseconds since the last and, when it says a frame ran,
+//! draws `framebuffer`: RGBA, `framebuffer_width` by `framebuffer_height`,
+//! ready for an `ImageData`. `key_event` presses and releases hex keys.
+//!
+//! The browser calls in from one thread, so the machine lives in a
+//! thread local.
+
+use std::cell::RefCell;
+use std::time::Duration;
+
+use crate::chip8::Chip8;
+
+const CLOCK_HZ: f64 = 700.0;
+// Black and white, then grays for XO-CHIP's second plane and both together, as RGBA in memory order
+const PALETTE: [u32; 4] = [0xFF00_0000, 0xFFFF_FFFF, 0xFFAA_AAAA, 0xFF55_5555];
+
+struct Core {
+    machine: Chip8,
+    // Set when the program faults; the last frame stays up and nothing else runs
+    crashed: bool,
+    framebuffer: Vec<u32>,
+}
+
+thread_local! {
+    static ROM: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+    static CORE: RefCell<Option<Core>> = const { RefCell::new(None) };
+}
+
+// Room for a ROM of `size` bytes, for the page to copy one into before `load_rom`
+#[no_mangle]
+pub extern "C" fn rom_buffer(size: usize) -> *mut u8 {
+    ROM.with(|rom| {
+        let mut rom = rom.borrow_mut();
+        rom.clear();
+        rom.resize(size, 0);
+        rom.as_mut_ptr()
+    })
+}
+
+/// Start the ROM in the buffer. There's no entropy source without
+/// wasm-bindgen, so the page passes a seed for CXNN, from `Math.random`.
+#[no_mangle]
+pub extern "C" fn load_rom(seed: u32) {
+    let rom = ROM.with(|rom| rom.borrow().clone());
+    let mut machine = Chip8::new(rom, CLOCK_HZ);
+    machine.set_seed(seed as u64);
+    let core = Core { machine, crashed: false, framebuffer: Vec::new() };
+    CORE.with(|slot| *slot.borrow_mut() = Some(core));
+}
+
+// Run for `elapsed_ms` of real time, returning the frames that ran; draw when it isn't 0
+#[no_mangle]
+pub extern "C" fn cycle(elapsed_ms: f64) -> u32 {
+    CORE.with(|core| {
+        let mut core = core.borrow_mut();
+        let core = match core.as_mut() {
+            Some(core) if !core.crashed => core,
+            _ => return 0,
+        };
+        match core.machine.try_cycle(Duration::from_secs_f64(elapsed_ms.max(0.0) / 1000.0)) {
+            Ok(frames) => frames,
+            Err(_) => {
+                core.crashed = true;
+                0
+            },
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn framebuffer() -> *const u32 {
+    CORE.with(|core| {
+        let mut core = core.borrow_mut();
+        let core = match core.as_mut() {
+            Some(core) => core,
+            None => return std::ptr::null(),
+        };
+        let pixels = core.machine.display.pixels();
+        core.framebuffer.clear();
+        core.framebuffer.extend(pixels.iter().map(|&pixel| PALETTE[(pixel & 0x3) as usize]));
+        core.framebuffer.as_ptr()
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn framebuffer_width() -> usize {
+    CORE.with(|core| core.borrow().as_ref().map_or(0, |core| core.machine.display.width()))
+}
+
+#[no_mangle]
+pub extern "C" fn framebuffer_height() -> usize {
+    CORE.with(|core| core.borrow().as_ref().map_or(0, |core| core.machine.display.height()))
+}
+
+#[no_mangle]
+pub extern "C" fn key_event(key: u8, is_pressed: bool) {
+    CORE.with(|core| {
+        if let Some(core) = core.borrow_mut().as_mut() {
+            core.machine.set_key(key & 0xF, is_pressed);
+        }
+    });
+}
+
+#[no_mangle]
+pub extern "C" fn sound_active() -> bool {
+    CORE.with(|core| core.borrow().as_ref().is_some_and(|core| core.machine.sound_active() && !core.crashed))
+}
@@ -0,0 +1,346 @@
+use std::fmt;
+
+use crate::chip8::Chip8;
+
+/// Watch expressions for the debugger, e.g. `V3 + V4 > 10` or
+/// `[0x400] != 0`. They are checked after every instruction and trigger
+/// when they go from false to true.
+///
+/// The language has the registers V0-VF, I, DT, ST and PC, decimal and 0x
+/// hex numbers, memory reads with `[address]`, parentheses, unary ! and -,
+/// and C's binary operators with C's precedence, tightest first:
+///
+/// ```text
+/// * / %   + -   << >>   < <= > >=   == !=   &   ^   |   &&   ||
+/// ```
+///
+/// Everything is evaluated as a signed 64-bit integer, so comparisons give
+/// 0 or 1 and any non-zero result counts as true. Dividing by zero and
+/// reading outside of memory both give 0.
+pub struct Watch {
+    source: String,
+    expression: Expression,
+    was_true: bool,
+}
+
+impl Watch {
+    pub fn parse(source: &str) -> Result<Watch, String> {
+        Ok(Watch {
+            source: source.trim().to_string(),
+            expression: Expression::parse(source)?,
+            was_true: false,
+        })
+    }
+
+    /// Evaluate against the machine, returning true only if the expression
+    /// has just become true.
+    pub fn check(&mut self, machine: &Chip8) -> bool {
+        let is_true = self.expression.evaluate(machine) != 0;
+        let became_true = is_true && !self.was_true;
+        self.was_true = is_true;
+        became_true
+    }
+}
+
+impl fmt::Display for Watch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operator {
+    Or,
+    And,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    BitOr,
+    BitXor,
+    BitAnd,
+    ShiftLeft,
+    ShiftRight,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+}
+
+impl Operator {
+    const LOWEST_PRECEDENCE: u8 = 0;
+
+    fn from_symbol(symbol: &str) -> Option<Operator> {
+        match symbol {
+            "||" => Some(Operator::Or),
+            "&&" => Some(Operator::And),
+            "==" => Some(Operator::Equal),
+            "!=" => Some(Operator::NotEqual),
+            "<" => Some(Operator::Less),
+            "<=" => Some(Operator::LessEqual),
+            ">" => Some(Operator::Greater),
+            ">=" => Some(Operator::GreaterEqual),
+            "|" => Some(Operator::BitOr),
+            "^" => Some(Operator::BitXor),
+            "&" => Some(Operator::BitAnd),
+            "<<" => Some(Operator::ShiftLeft),
+            ">>" => Some(Operator::ShiftRight),
+            "+" => Some(Operator::Add),
+            "-" => Some(Operator::Subtract),
+            "*" => Some(Operator::Multiply),
+            "/" => Some(Operator::Divide),
+            "%" => Some(Operator::Remainder),
+            _ => None,
+        }
+    }
+
+    fn precedence(&self) -> u8 {
+        match self {
+            Operator::Or => 0,
+            Operator::And => 1,
+            Operator::BitOr => 2,
+            Operator::BitXor => 3,
+            Operator::BitAnd => 4,
+            Operator::Equal | Operator::NotEqual => 5,
+            Operator::Less | Operator::LessEqual | Operator::Greater | Operator::GreaterEqual => 6,
+            Operator::ShiftLeft | Operator::ShiftRight => 7,
+            Operator::Add | Operator::Subtract => 8,
+            Operator::Multiply | Operator::Divide | Operator::Remainder => 9,
+        }
+    }
+
+    fn apply(&self, left: i64, right: i64) -> i64 {
+        match self {
+            Operator::Or => (left != 0 || right != 0) as i64,
+            Operator::And => (left != 0 && right != 0) as i64,
+            Operator::Equal => (left == right) as i64,
+            Operator::NotEqual => (left != right) as i64,
+            Operator::Less => (left < right) as i64,
+            Operator::LessEqual => (left <= right) as i64,
+            Operator::Greater => (left > right) as i64,
+            Operator::GreaterEqual => (left >= right) as i64,
+            Operator::BitOr => left | right,
+            Operator::BitXor => left ^ right,
+            Operator::BitAnd => left & right,
+            Operator::ShiftLeft => left.wrapping_shl(right as u32),
+            Operator::ShiftRight => left.wrapping_shr(right as u32),
+            Operator::Add => left.wrapping_add(right),
+            Operator::Subtract => left.wrapping_sub(right),
+            Operator::Multiply => left.wrapping_mul(right),
+            Operator::Divide => left.checked_div(right).unwrap_or(0),
+            Operator::Remainder => left.checked_rem(right).unwrap_or(0),
+        }
+    }
+}
+
+pub enum Expression {
+    Number(i64),
+    DataRegister(u8),
+    IRegister,
+    DelayTimer,
+    SoundTimer,
+    ProgramCounter,
+    Memory(Box<Expression>),
+    Not(Box<Expression>),
+    Negate(Box<Expression>),
+    Binary(Operator, Box<Expression>, Box<Expression>),
+}
+
+impl Expression {
+    pub fn parse(source: &str) -> Result<Expression, String> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser { tokens, position: 0 };
+        let expression = parser.expression(Operator::LOWEST_PRECEDENCE)?;
+        match parser.peek() {
+            Some(token) => Err(format!("unexpected `{}`", token)),
+            None => Ok(expression),
+        }
+    }
+
+    pub fn evaluate(&self, machine: &Chip8) -> i64 {
+        let registers = machine.registers();
+        match self {
+            Expression::Number(value) => *value,
+            Expression::DataRegister(register) => registers.read_data_register(*register).unwrap_or(0) as i64,
+            Expression::IRegister => registers.i_register as i64,
+            Expression::DelayTimer => registers.delay_timer as i64,
+            Expression::SoundTimer => registers.sound_timer as i64,
+            Expression::ProgramCounter => machine.program_counter() as i64,
+            Expression::Memory(address) => {
+                let address = address.evaluate(machine);
+                if (0..=u16::MAX as i64).contains(&address) {
+                    machine.memory().peek(address as u16).unwrap_or(0) as i64
+                } else {
+                    0
+                }
+            },
+            Expression::Not(operand) => (operand.evaluate(machine) == 0) as i64,
+            Expression::Negate(operand) => operand.evaluate(machine).wrapping_neg(),
+            Expression::Binary(operator, left, right) => operator.apply(left.evaluate(machine), right.evaluate(machine)),
+        }
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<String>, String> {
+    let characters: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut position = 0;
+
+    while position < characters.len() {
+        let character = characters[position];
+        if character.is_whitespace() {
+            position += 1;
+        } else if character.is_ascii_alphanumeric() {
+            let start = position;
+            while position < characters.len() && characters[position].is_ascii_alphanumeric() {
+                position += 1;
+            }
+            tokens.push(characters[start..position].iter().collect());
+        } else {
+            let pair: String = characters[position..characters.len().min(position + 2)].iter().collect();
+            let single = character.to_string();
+            if pair.len() == 2 && Operator::from_symbol(&pair).is_some() {
+                tokens.push(pair);
+                position += 2;
+            } else if Operator::from_symbol(&single).is_some() || "!()[]".contains(character) {
+                tokens.push(single);
+                position += 1;
+            } else {
+                return Err(format!("unexpected character `{}`", character));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<String>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.position).map(|token| token.as_str())
+    }
+
+    fn next(&mut self) -> Result<String, String> {
+        let token = self.tokens.get(self.position).cloned().ok_or("unexpected end of expression")?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), String> {
+        match self.next()? {
+            ref token if token == expected => Ok(()),
+            token => Err(format!("expected `{}` but found `{}`", expected, token)),
+        }
+    }
+
+    // Precedence climbing over the binary operators
+    fn expression(&mut self, min_precedence: u8) -> Result<Expression, String> {
+        let mut left = self.unary()?;
+        while let Some(operator) = self.peek().and_then(Operator::from_symbol) {
+            if operator.precedence() < min_precedence {
+                break;
+            }
+            self.position += 1;
+            let right = self.expression(operator.precedence() + 1)?;
+            left = Expression::Binary(operator, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expression, String> {
+        let token = self.next()?;
+        match token.as_str() {
+            "!" => Ok(Expression::Not(Box::new(self.unary()?))),
+            "-" => Ok(Expression::Negate(Box::new(self.unary()?))),
+            "(" => {
+                let inner = self.expression(Operator::LOWEST_PRECEDENCE)?;
+                self.expect(")")?;
+                Ok(inner)
+            },
+            "[" => {
+                let address = self.expression(Operator::LOWEST_PRECEDENCE)?;
+                self.expect("]")?;
+                Ok(Expression::Memory(Box::new(address)))
+            },
+            _ => Parser::operand(&token),
+        }
+    }
+
+    fn operand(token: &str) -> Result<Expression, String> {
+        let upper = token.to_ascii_uppercase();
+        match upper.as_str() {
+            "I" => return Ok(Expression::IRegister),
+            "DT" => return Ok(Expression::DelayTimer),
+            "ST" => return Ok(Expression::SoundTimer),
+            "PC" => return Ok(Expression::ProgramCounter),
+            _ => {},
+        }
+
+        let number = match upper.strip_prefix("0X") {
+            Some(hex) => i64::from_str_radix(hex, 16),
+            None => upper.parse::<i64>(),
+        };
+        if let Ok(value) = number {
+            return Ok(Expression::Number(value));
+        }
+        match upper.strip_prefix('V') {
+            Some(register) if register.len() == 1 => {
+                u8::from_str_radix(register, 16).map(Expression::DataRegister)
+                                                .map_err(|_| format!("unknown operand `{}`", token))
+            },
+            _ => Err(format!("unknown operand `{}`", token)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // V0 = 5, then jump in place
+    fn evaluate(source: &str) -> i64 {
+        let mut machine = Chip8::new(vec![0x60, 0x05, 0x12, 0x02], 700.0);
+        machine.step().unwrap();
+        Expression::parse(source).unwrap().evaluate(&machine)
+    }
+
+    #[test]
+    fn operators_bind_with_c_precedence() {
+        assert_eq!(evaluate("1 + 2 * 3"), 7);
+        assert_eq!(evaluate("(1 + 2) * 3"), 9);
+        assert_eq!(evaluate("10 - 2 - 3"), 5);
+        assert_eq!(evaluate("1 << 2 + 1"), 8);
+        assert_eq!(evaluate("1 | 2 & 0"), 1);
+        assert_eq!(evaluate("6 ^ 3 == 3"), 7);
+        assert_eq!(evaluate("2 > 1 == 1"), 1);
+        assert_eq!(evaluate("0 && 1 || 1"), 1);
+        assert_eq!(evaluate("!0 + -1"), 0);
+        assert_eq!(evaluate("--3"), 3);
+    }
+
+    #[test]
+    fn numbers_registers_and_memory() {
+        assert_eq!(evaluate("0x1F + 0X01 + 10"), 42);
+        assert_eq!(evaluate("V0 == 5 && v1 == 0"), 1);
+        assert_eq!(evaluate("pc"), 0x202);
+        assert_eq!(evaluate("I + DT + ST"), 0);
+        assert_eq!(evaluate("[0x200] == 0x60 && [0x201] == V0"), 1);
+        assert_eq!(evaluate("[0x10000] + [-1]"), 0);
+        assert_eq!(evaluate("7 / 0 + 7 % 0"), 0);
+    }
+
+    #[test]
+    fn malformed_expressions_are_errors() {
+        for source in ["", "1 +", "(1", "[0x200", "1 2", "V10", "VG", "X", "1 $ 2", "()", "1 )"] {
+            assert!(Expression::parse(source).is_err(), "`{}` parsed", source);
+        }
+        assert_eq!(Expression::parse("1 2").err().unwrap(), "unexpected `2`");
+        assert_eq!(Expression::parse("1 +").err().unwrap(), "unexpected end of expression");
+    }
+}
@@ -0,0 +1,50 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use crate::scheduler::Scheduler;
+
+/// Notices a ROM that has stopped making progress, e.g. one that crashed
+/// into a `JP` to itself. The machine is observed at frame boundaries and
+/// counts as stalled while both the program counter and the display stay
+/// exactly the same. Waiting on FX0A for a key isn't a stall. Time is
+/// measured in emulated frames so a batch run trips at the same point on
+/// any host.
+pub struct Watchdog {
+    limit_frames: u64,
+    stalled_frames: u64,
+    last_pc: u16,
+    last_display: u64,
+}
+
+impl Watchdog {
+    pub fn new(seconds: f64) -> Watchdog {
+        Watchdog {
+            limit_frames: (seconds * Scheduler::VBLANK_RATE_HZ).ceil() as u64,
+            stalled_frames: 0,
+            last_pc: 0,
+            last_display: 0,
+        }
+    }
+
+    /// Record the machine's state after `frames` more frames have elapsed.
+    /// Returns true once it has been stalled for longer than the limit.
+    pub fn observe(&mut self, frames: u32, pc: u16, waiting_on_key: bool, display: &[u8]) -> bool {
+        let mut hasher = DefaultHasher::new();
+        display.hash(&mut hasher);
+        let display_hash = hasher.finish();
+
+        if waiting_on_key || pc != self.last_pc || display_hash != self.last_display {
+            self.stalled_frames = 0;
+        } else {
+            self.stalled_frames += frames as u64;
+        }
+        self.last_pc = pc;
+        self.last_display = display_hash;
+
+        self.stalled_frames >= self.limit_frames
+    }
+
+    pub fn stalled_seconds(&self) -> f64 {
+        self.stalled_frames as f64 / Scheduler::VBLANK_RATE_HZ
+    }
+}
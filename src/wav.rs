@@ -0,0 +1,66 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+
+/*
+    A minimal writer for mono, 16-bit PCM WAV files. The RIFF and data chunk
+    sizes aren't known until recording stops, so the header is written with
+    placeholders and patched when the writer is dropped.
+*/
+pub struct WavWriter {
+    out: BufWriter<File>,
+    samples_written: u32,
+}
+
+impl WavWriter {
+    const HEADER_SIZE: u32 = 44;
+    const BYTES_PER_SAMPLE: u16 = 2;
+
+    pub fn new(file: File, sample_rate: u32) -> io::Result<WavWriter> {
+        let mut out = BufWriter::new(file);
+        let byte_rate = sample_rate * WavWriter::BYTES_PER_SAMPLE as u32;
+
+        out.write_all(b"RIFF")?;
+        out.write_all(&0u32.to_le_bytes())?;
+        out.write_all(b"WAVE")?;
+        out.write_all(b"fmt ")?;
+        out.write_all(&16u32.to_le_bytes())?;      // fmt chunk size
+        out.write_all(&1u16.to_le_bytes())?;       // PCM
+        out.write_all(&1u16.to_le_bytes())?;       // mono
+        out.write_all(&sample_rate.to_le_bytes())?;
+        out.write_all(&byte_rate.to_le_bytes())?;
+        out.write_all(&WavWriter::BYTES_PER_SAMPLE.to_le_bytes())?;
+        out.write_all(&(8 * WavWriter::BYTES_PER_SAMPLE).to_le_bytes())?;
+        out.write_all(b"data")?;
+        out.write_all(&0u32.to_le_bytes())?;
+
+        Ok(WavWriter {
+            out,
+            samples_written: 0,
+        })
+    }
+
+    pub fn write_samples(&mut self, samples: &[f32]) -> io::Result<()> {
+        for &sample in samples {
+            let pcm = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            self.out.write_all(&pcm.to_le_bytes())?;
+        }
+        self.samples_written += samples.len() as u32;
+        Ok(())
+    }
+
+    fn finalize(&mut self) -> io::Result<()> {
+        let data_size = self.samples_written * WavWriter::BYTES_PER_SAMPLE as u32;
+
+        self.out.seek(SeekFrom::Start(4))?;
+        self.out.write_all(&(WavWriter::HEADER_SIZE - 8 + data_size).to_le_bytes())?;
+        self.out.seek(SeekFrom::Start(40))?;
+        self.out.write_all(&data_size.to_le_bytes())?;
+        self.out.flush()
+    }
+}
+
+impl Drop for WavWriter {
+    fn drop(&mut self) {
+        let _ = self.finalize();
+    }
+}
@@ -0,0 +1,299 @@
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::{Duration, Instant};
+
+use log::info;
+
+use crate::chip8::Chip8;
+use crate::instructions;
+
+/*
+    A debugger in the browser, served from inside the emulator so it works
+    anywhere without native UI dependencies. It listens on localhost only
+    and is polled from the main loop between cycles, so it sees the machine
+    exactly as the frontend does. Connections are read without blocking,
+    so a slow client holds up nothing but its own request. Requests must
+    name this server in Host, and in Origin if they have one, so neither
+    another site's page nor a rebound DNS name can drive it. The page is a
+    thin client over a small HTTP API that scripts can use too:
+
+    GET  /state     registers, stack, display and disassembly as JSON
+    POST /pause     hold the machine
+    POST /resume    let it run again
+    POST /step      execute one instruction while paused
+    POST /back      go back one instruction while paused
+*/
+pub struct WebDebugger {
+    listener: TcpListener,
+    port: u16,
+    // Accepted, but the request hasn't all arrived yet
+    connections: Vec<Connection>,
+    paused: bool,
+    last_error: Option<String>,
+}
+
+struct Connection {
+    stream: TcpStream,
+    request: Vec<u8>,
+    opened: Instant,
+}
+
+// A request that hasn't fully arrived by then is dropped
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+// Requests are only a request line and headers, so anything bigger is junk
+const MAX_REQUEST_SIZE: usize = 16 * 1024;
+
+// Disassembly lines shown before and after the program counter
+const LINES_BEFORE: u16 = 6;
+const LINES_AFTER: u16 = 14;
+
+impl WebDebugger {
+    pub fn bind(port: u16) -> io::Result<WebDebugger> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        listener.set_nonblocking(true)?;
+        let port = listener.local_addr()?.port();
+        Ok(WebDebugger { listener, port, connections: Vec::new(), paused: false, last_error: None })
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /*
+        Answer every request that has arrived, without blocking on any
+        that haven't. Returns true if a request stepped the machine.
+    */
+    pub fn poll(&mut self, machine: &mut Chip8) -> bool {
+        while let Ok((stream, _)) = self.listener.accept() {
+            match stream.set_nonblocking(true) {
+                Ok(()) => self.connections.push(Connection { stream, request: Vec::new(), opened: Instant::now() }),
+                Err(error) => info!("Web debugger request failed: {}", error),
+            }
+        }
+
+        let mut stepped = false;
+        for mut connection in std::mem::take(&mut self.connections) {
+            let result = match connection.read() {
+                Ok(true) => self.handle(&mut connection, machine),
+                Ok(false) if connection.opened.elapsed() < REQUEST_TIMEOUT => {
+                    self.connections.push(connection);
+                    continue;
+                },
+                Ok(false) => Err(io::Error::new(io::ErrorKind::TimedOut, "the request never finished")),
+                Err(error) => Err(error),
+            };
+            match result {
+                Ok(step) => stepped |= step,
+                Err(error) => info!("Web debugger request failed: {}", error),
+            }
+        }
+        stepped
+    }
+
+    fn handle(&mut self, connection: &mut Connection, machine: &mut Chip8) -> io::Result<bool> {
+        let request = String::from_utf8_lossy(&connection.request);
+        let mut request_line = request.lines().next().unwrap_or("").split_whitespace();
+        let method = request_line.next().unwrap_or("");
+        let path = request_line.next().unwrap_or("");
+
+        let mut stepped = false;
+        let (status, content_type, body) = match (method, path) {
+            _ if !is_from_here(&request, self.port) => ("403 Forbidden", "text/plain", String::from("Forbidden.")),
+            ("GET", "/") => ("200 OK", "text/html; charset=utf-8", String::from(PAGE)),
+            ("GET", "/state") => ("200 OK", "application/json", self.state(machine)),
+            ("POST", "/pause") => {
+                self.paused = true;
+                ("200 OK", "application/json", self.state(machine))
+            },
+            ("POST", "/resume") => {
+                self.paused = false;
+                self.last_error = None;
+                ("200 OK", "application/json", self.state(machine))
+            },
+            ("POST", "/step") => {
+                self.paused = true;
+                if self.last_error.is_none() {
+                    if let Err(error) = machine.step() {
+                        self.last_error = Some(error.to_string());
+                    }
+                    stepped = true;
+                }
+                ("200 OK", "application/json", self.state(machine))
+            },
+            ("POST", "/back") => {
+                self.paused = true;
+                match machine.step_back() {
+                    Ok(()) => self.last_error = None,
+                    Err(error) => self.last_error = Some(error),
+                }
+                stepped = true;
+                ("200 OK", "application/json", self.state(machine))
+            },
+            _ => ("404 Not Found", "text/plain", String::from("Not found.")),
+        };
+        write!(connection.stream, "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+               status, content_type, body.len(), body)?;
+        Ok(stepped)
+    }
+
+    fn state(&self, machine: &Chip8) -> String {
+        let registers = machine.registers();
+        let data: Vec<String> = registers.data().iter().map(u8::to_string).collect();
+        let stack: Vec<String> = machine.stack().frames().iter().map(u16::to_string).collect();
+        let display: String = machine.display.pixels().iter().map(|&pixel| if pixel != 0 { '1' } else { '0' }).collect();
+
+        let pc = machine.program_counter();
+        let start = pc.saturating_sub(2 * LINES_BEFORE);
+        let disassembly: Vec<String> = (0..LINES_BEFORE + LINES_AFTER).map(|line| start + 2 * line)
+                                                                      .filter_map(|address| {
+                                                                          let opcode = machine.memory().peek_opcode(address)?;
+                                                                          Some(format!("{{\"address\":{},\"opcode\":{},\"text\":\"{}\"}}",
+                                                                                       address, opcode, instructions::parse_opcode(opcode)))
+                                                                      })
+                                                                      .collect();
+        let error = match &self.last_error {
+            Some(error) => format!("\"{}\"", error.replace('\\', "\\\\").replace('"', "\\\"")),
+            None => String::from("null"),
+        };
+
+        format!("{{\"pc\":{},\"i\":{},\"dt\":{},\"st\":{},\"v\":[{}],\"stack\":[{}],\"instructions\":{},\"frames\":{},\
+                 \"paused\":{},\"error\":{},\"width\":{},\"height\":{},\"display\":\"{}\",\"disassembly\":[{}]}}",
+                pc, registers.i_register, registers.delay_timer, registers.sound_timer, data.join(","), stack.join(","),
+                machine.instructions_executed(), machine.frames_elapsed(), self.paused, error,
+                machine.display.width(), machine.display.height(), display, disassembly.join(","))
+    }
+}
+
+impl Connection {
+    /*
+        Read whatever has arrived, returning true once the whole request
+        has, or the client has stopped sending.
+    */
+    fn read(&mut self) -> io::Result<bool> {
+        let mut buffer = [0; 1024];
+        loop {
+            match self.stream.read(&mut buffer) {
+                Ok(0) => return Ok(true),
+                Ok(read) => self.request.extend_from_slice(&buffer[..read]),
+                Err(error) if error.kind() == io::ErrorKind::WouldBlock => break,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                Err(error) => return Err(error),
+            }
+            if self.request.len() > MAX_REQUEST_SIZE {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "the request is too big"));
+            }
+        }
+        Ok(self.request.windows(4).any(|window| window == b"\r\n\r\n"))
+    }
+}
+
+/*
+    Whether a request was meant for this server on `port`: its Host has
+    to be localhost or 127.0.0.1 on that port, and its Origin, which
+    browsers send for cross-site requests, has to be one of those too.
+*/
+fn is_from_here(request: &str, port: u16) -> bool {
+    let hosts = [format!("127.0.0.1:{}", port), format!("localhost:{}", port)];
+    let is_here = |host: &str| hosts.iter().any(|here| host.eq_ignore_ascii_case(here));
+    header(request, "Host").is_some_and(is_here)
+        && header(request, "Origin").is_none_or(|origin| origin.strip_prefix("http://").is_some_and(is_here))
+}
+
+fn header<'a>(request: &'a str, name: &str) -> Option<&'a str> {
+    request.lines()
+           .skip(1)
+           .take_while(|line| !line.is_empty())
+           .filter_map(|line| line.split_once(':'))
+           .find(|(field, _)| field.trim().eq_ignore_ascii_case(name))
+           .map(|(_, value)| value.trim())
+}
+
+const PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>yac8 debugger</title>
+<style>
+body { background: #111; color: #ddd; font-family: monospace; display: flex; gap: 24px; padding: 12px; }
+canvas { image-rendering: pixelated; width: 640px; height: 320px; border: 1px solid #444; }
+button { font-family: monospace; margin-right: 6px; }
+td { padding: 0 8px 0 0; }
+.current { background: #335; }
+.error { color: #f66; }
+</style>
+</head>
+<body>
+<div>
+<canvas id="screen"></canvas>
+<p><button onclick="post('pause')">Pause</button><button onclick="post('resume')">Resume</button><button onclick="post('back')">Back</button><button onclick="post('step')">Step</button></p>
+<p id="status"></p>
+<p id="error" class="error"></p>
+</div>
+<div><table id="registers"></table><p id="stack"></p></div>
+<div><table id="disassembly"></table></div>
+<script>
+const hex = (value, digits) => value.toString(16).toUpperCase().padStart(digits, "0");
+
+function render(state) {
+    const canvas = document.getElementById("screen");
+    canvas.width = state.width;
+    canvas.height = state.height;
+    const context = canvas.getContext("2d");
+    const image = context.createImageData(state.width, state.height);
+    for (let pixel = 0; pixel < state.display.length; pixel++) {
+        const lit = state.display[pixel] === "1" ? 255 : 0;
+        image.data.set([lit, lit, lit, 255], pixel * 4);
+    }
+    context.putImageData(image, 0, 0);
+
+    document.getElementById("status").textContent =
+        (state.paused ? "paused" : "running") + " after " + state.instructions + " instructions, " + state.frames + " frames";
+    document.getElementById("error").textContent = state.error || "";
+
+    let registers = "";
+    for (let row = 0; row < 8; row++) {
+        registers += "<tr><td>V" + hex(row, 1) + " " + hex(state.v[row], 2) + "</td><td>V" + hex(row + 8, 1) + " " + hex(state.v[row + 8], 2) + "</td></tr>";
+    }
+    registers += "<tr><td>PC " + hex(state.pc, 3) + "</td><td>I " + hex(state.i, 3) + "</td></tr>";
+    registers += "<tr><td>DT " + hex(state.dt, 2) + "</td><td>ST " + hex(state.st, 2) + "</td></tr>";
+    document.getElementById("registers").innerHTML = registers;
+    document.getElementById("stack").textContent = "stack: " + (state.stack.map(frame => hex(frame, 3)).join(" ") || "empty");
+
+    document.getElementById("disassembly").innerHTML = state.disassembly.map(line =>
+        "<tr" + (line.address === state.pc ? " class=\"current\"" : "") + "><td>" + hex(line.address, 3) +
+        "</td><td>" + hex(line.opcode, 4) + "</td><td>" + line.text + "</td></tr>").join("");
+}
+
+function post(command) {
+    fetch("/" + command, { method: "POST" }).then(response => response.json()).then(render);
+}
+
+function refresh() {
+    fetch("/state").then(response => response.json()).then(render).finally(() => setTimeout(refresh, 100));
+}
+refresh();
+</script>
+</body>
+</html>
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_requests_for_this_server_are_answered() {
+        assert!(is_from_here("POST /step HTTP/1.1\r\nHost: 127.0.0.1:8000\r\n\r\n", 8000));
+        assert!(is_from_here("POST /step HTTP/1.1\r\nhost: LOCALHOST:8000\r\nOrigin: http://localhost:8000\r\n\r\n", 8000));
+
+        // Missing, or another port or name, as DNS rebinding would give
+        assert!(!is_from_here("POST /step HTTP/1.1\r\n\r\n", 8000));
+        assert!(!is_from_here("POST /step HTTP/1.1\r\nHost: 127.0.0.1:8001\r\n\r\n", 8000));
+        assert!(!is_from_here("POST /step HTTP/1.1\r\nHost: evil.example:8000\r\n\r\n", 8000));
+        // Another site's page posting here
+        assert!(!is_from_here("POST /step HTTP/1.1\r\nHost: localhost:8000\r\nOrigin: https://evil.example\r\n\r\n", 8000));
+        assert!(!is_from_here("POST /step HTTP/1.1\r\nHost: localhost:8000\r\nOrigin: null\r\n\r\n", 8000));
+        // A header in the body doesn't count
+        assert!(!is_from_here("POST /step HTTP/1.1\r\n\r\nHost: localhost:8000", 8000));
+    }
+}